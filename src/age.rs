@@ -0,0 +1,211 @@
+//! Disk usage broken down by file age, to tell how much data is cold
+//!
+//! Every counted file falls into one age bucket according to its last
+//! modification time. The buckets are split at a few ages, by default one
+//! week, one month and one year, and are tallied for each immediate child of
+//! the root as well as for the whole analysis.
+
+use crate::format::format_duration;
+use crate::{analyze_with_hooks, root_child, AnalysisConfig, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Ages at which the default buckets are split: 7 days, 30 days and a year
+pub const DEFAULT_BOUNDARIES: [Duration; 3] = [
+    Duration::from_secs(7 * DAY),
+    Duration::from_secs(30 * DAY),
+    Duration::from_secs(365 * DAY),
+];
+
+/// Usage of an entry split by age
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeUsage {
+    /// Path of the entry
+    pub path: PathBuf,
+    /// Total disk usage in bytes
+    pub size: u64,
+    /// Disk usage in bytes per bucket, youngest first
+    pub buckets: Vec<u64>,
+}
+
+/// Usage by age for an analysis and each immediate child of its root
+#[derive(Debug, Clone)]
+pub struct AgeReport {
+    /// Ages at which the buckets are split, ascending
+    pub boundaries: Vec<Duration>,
+    /// Usage of the whole analysis, for the root path
+    pub total: AgeUsage,
+    /// Usage of the immediate children of the root, largest first
+    pub entries: Vec<AgeUsage>,
+}
+
+impl AgeReport {
+    /// Labels of the buckets, like "<7d", "7d-30d", "30d-1y" and ">1y"
+    pub fn labels(&self) -> Vec<String> {
+        bucket_labels(&self.boundaries)
+    }
+}
+
+/// Labels of the buckets split at `boundaries`
+pub fn bucket_labels(boundaries: &[Duration]) -> Vec<String> {
+    let names: Vec<String> = boundaries.iter().map(|&b| format_duration(b)).collect();
+    let Some((first, last)) = names.first().zip(names.last()) else {
+        return vec!["all".to_string()];
+    };
+    std::iter::once(format!("<{}", first))
+        .chain(
+            names
+                .windows(2)
+                .map(|pair| format!("{}-{}", pair[0], pair[1])),
+        )
+        .chain(std::iter::once(format!(">{}", last)))
+        .collect()
+}
+
+/// Index of the bucket holding a file of `age`
+fn bucket(boundaries: &[Duration], age: Duration) -> usize {
+    boundaries.partition_point(|&boundary| boundary <= age)
+}
+
+/// Running totals per immediate child of the root
+struct AgeTally<'a> {
+    root: &'a Path,
+    boundaries: &'a [Duration],
+    now: SystemTime,
+    entries: Mutex<HashMap<PathBuf, Vec<u64>>>,
+}
+
+impl EntryVisitor for AgeTally<'_> {
+    fn visit_file(&self, path: &Path, metadata: &std::fs::Metadata, size: u64) {
+        let Some(child) = root_child(self.root, path) else {
+            return;
+        };
+        // Files modified in the future count as new
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| self.now.duration_since(modified).ok())
+            .unwrap_or_default();
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let buckets = entries
+            .entry(child)
+            .or_insert_with(|| vec![0; self.boundaries.len() + 1]);
+        buckets[bucket(self.boundaries, age)] += size;
+    }
+}
+
+/// Analyze `path`, splitting the usage of each file by its age at the given
+/// `boundaries`
+pub fn analyze_by_age(
+    path: &Path,
+    config: &AnalysisConfig,
+    boundaries: &[Duration],
+) -> Result<AgeReport> {
+    let mut boundaries = boundaries.to_vec();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let tally = AgeTally {
+        root: path,
+        boundaries: &boundaries,
+        now: SystemTime::now(),
+        entries: Mutex::new(HashMap::new()),
+    };
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+
+    let mut entries: Vec<AgeUsage> = tally
+        .entries
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|(path, buckets)| AgeUsage {
+            path,
+            size: buckets.iter().sum(),
+            buckets,
+        })
+        .collect();
+    entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    let mut buckets = vec![0; boundaries.len() + 1];
+    for entry in &entries {
+        for (total, size) in buckets.iter_mut().zip(&entry.buckets) {
+            *total += size;
+        }
+    }
+
+    Ok(AgeReport {
+        boundaries,
+        total: AgeUsage {
+            path: result.root_path,
+            size: result.total_size,
+            buckets,
+        },
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bucket_labels() {
+        assert_eq!(
+            bucket_labels(&DEFAULT_BOUNDARIES),
+            ["<1w", "1w-30d", "30d-1y", ">1y"]
+        );
+        assert_eq!(bucket_labels(&[]), ["all"]);
+        let day = Duration::from_secs(DAY);
+        assert_eq!(bucket(&DEFAULT_BOUNDARIES, day), 0);
+        assert_eq!(bucket(&DEFAULT_BOUNDARIES, day * 7), 1);
+        assert_eq!(bucket(&DEFAULT_BOUNDARIES, day * 400), 3);
+    }
+
+    #[test]
+    fn test_analyze_by_age() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let day = Duration::from_secs(DAY);
+
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        for (name, age_days) in [("a/new", 1), ("a/old", 400), ("month", 20)] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, vec![1u8; 4096]).unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - day * age_days).unwrap();
+        }
+
+        let report = analyze_by_age(
+            temp_dir.path(),
+            &AnalysisConfig::default(),
+            &DEFAULT_BOUNDARIES,
+        )
+        .unwrap();
+        let a = report
+            .entries
+            .iter()
+            .find(|e| e.path == temp_dir.path().join("a"))
+            .unwrap();
+        assert!(a.buckets[0] > 0 && a.buckets[3] > 0);
+        assert_eq!(a.buckets[1] + a.buckets[2], 0);
+        assert_eq!(a.size, a.buckets.iter().sum::<u64>());
+        assert_eq!(report.total.buckets.iter().sum::<u64>(), report.total.size);
+        assert!(report.total.buckets[1] > 0);
+    }
+}
@@ -0,0 +1,263 @@
+//! Webhook alerts for `dutop alert`
+//!
+//! Roots are scanned and checked against [`Thresholds`]; when one is over
+//! them, a summary with the offending paths and the largest directories is
+//! posted to a webhook, either as generic JSON or as a Slack message. When
+//! scanning periodically, a root is reported once when it crosses the limits
+//! and again only after it went back under them.
+
+use crate::format::{format_rfc3339, format_size_auto};
+use crate::threshold::{Thresholds, Violation};
+use crate::{analyze_disk_usage, AnalysisConfig, AnalysisResult, CancellationToken};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long posting to a webhook may take
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// How often the cancellation token is checked between scans
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Shape of the body posted to the webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlertFormat {
+    /// The alert as a JSON object, for generic receivers
+    #[default]
+    Json,
+    /// A message for Slack incoming webhooks (also understood by Mattermost)
+    Slack,
+}
+
+impl FromStr for AlertFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "slack" => Ok(Self::Slack),
+            _ => anyhow::bail!("Invalid alert format: {} (expected json or slack)", s),
+        }
+    }
+}
+
+/// Where and how alerts are posted
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    /// URL receiving the alerts with a POST request
+    pub url: String,
+    /// Shape of the request body
+    pub format: AlertFormat,
+}
+
+impl Webhook {
+    /// Post an alert about `result` being over `thresholds`
+    pub fn post(
+        &self,
+        result: &AnalysisResult,
+        violations: &[Violation],
+        thresholds: &Thresholds,
+    ) -> Result<()> {
+        let body = payload(result, violations, thresholds, self.format).to_string();
+        let agent = ureq::Agent::new_with_config(
+            ureq::Agent::config_builder()
+                .timeout_global(Some(TIMEOUT))
+                .build(),
+        );
+        agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(body)
+            .with_context(|| format!("Failed to post alert to {}", self.url))?;
+        Ok(())
+    }
+}
+
+/// Body of the alert about `result` being over `thresholds`
+pub fn payload(
+    result: &AnalysisResult,
+    violations: &[Violation],
+    thresholds: &Thresholds,
+    format: AlertFormat,
+) -> Value {
+    let hostname = crate::output::hostname();
+    match format {
+        AlertFormat::Json => {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let violations: Vec<Value> = violations
+                .iter()
+                .map(|v| {
+                    json!({
+                        "path": v.path.display().to_string(),
+                        "size": v.size,
+                        "size_human": format_size_auto(v.size),
+                        "filesystem_percentage": v.percent,
+                    })
+                })
+                .collect();
+            let top_directories: Vec<Value> = result
+                .top_directories
+                .iter()
+                .map(|d| {
+                    json!({
+                        "path": d.path.display().to_string(),
+                        "size": d.size,
+                        "size_human": format_size_auto(d.size),
+                        "file_count": d.file_count,
+                    })
+                })
+                .collect();
+            json!({
+                "hostname": hostname,
+                "timestamp": format_rfc3339(timestamp),
+                "path": result.root_path.display().to_string(),
+                "total_size": result.total_size,
+                "total_size_human": format_size_auto(result.total_size),
+                "threshold": {
+                    "max_size": thresholds.max_size,
+                    "max_percent": thresholds.max_percent,
+                },
+                "violations": violations,
+                "top_directories": top_directories,
+            })
+        }
+        AlertFormat::Slack => {
+            let mut text = format!(
+                ":warning: *{}*{} is over {}",
+                result.root_path.display(),
+                hostname.map(|h| format!(" on {}", h)).unwrap_or_default(),
+                thresholds
+            );
+            for violation in violations {
+                text.push_str(&format!("\n• `{}`", violation));
+            }
+            if !result.top_directories.is_empty() {
+                text.push_str("\nLargest directories:");
+                for dir in &result.top_directories {
+                    text.push_str(&format!(
+                        "\n• `{} {}`",
+                        format_size_auto(dir.size),
+                        dir.path.display()
+                    ));
+                }
+            }
+            json!({ "text": text })
+        }
+    }
+}
+
+/// Posts an alert when a root crosses its thresholds, and again only after it
+/// went back under them
+#[derive(Debug)]
+pub struct Alerter {
+    webhook: Webhook,
+    thresholds: Thresholds,
+    /// Roots already reported, so that an alert is not repeated on every scan
+    alerted: HashSet<PathBuf>,
+}
+
+impl Alerter {
+    /// Alert through `webhook` when a root is over `thresholds`
+    pub fn new(webhook: Webhook, thresholds: Thresholds) -> Self {
+        Self {
+            webhook,
+            thresholds,
+            alerted: HashSet::new(),
+        }
+    }
+
+    /// Check the latest scan of a root, posting an alert if it just crossed
+    /// the thresholds
+    ///
+    /// A failed post is logged and retried on the next check.
+    pub fn check(&mut self, result: &AnalysisResult) {
+        let root = &result.root_path;
+        let violations = self.thresholds.check(result);
+        if violations.is_empty() {
+            if self.alerted.remove(root) {
+                log::info!("{} is back under the threshold", root.display());
+            }
+        } else if !self.alerted.contains(root) {
+            match self.webhook.post(result, &violations, &self.thresholds) {
+                Ok(()) => {
+                    log::info!("Alert sent for {}", root.display());
+                    self.alerted.insert(root.clone());
+                }
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+    }
+}
+
+/// Scan every root in `roots` once per `interval` until `cancel` is triggered,
+/// posting through `alerter` when one crosses its thresholds
+pub fn alert_loop(
+    roots: &[PathBuf],
+    config: &AnalysisConfig,
+    top_n: usize,
+    alerter: &mut Alerter,
+    interval: Duration,
+    cancel: &CancellationToken,
+) {
+    while !cancel.is_cancelled() {
+        let started = Instant::now();
+        for root in roots {
+            match analyze_disk_usage(root, config, top_n) {
+                Ok(result) => alerter.check(&result),
+                Err(e) => log::warn!("Failed to scan {}: {:#}", root.display(), e),
+            }
+        }
+
+        let next = started + interval;
+        while !cancel.is_cancelled() && Instant::now() < next {
+            thread::sleep(POLL_INTERVAL.min(next.saturating_duration_since(Instant::now())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirectoryEntry;
+
+    #[test]
+    fn test_payload() {
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 3 << 30,
+            top_directories: vec![DirectoryEntry {
+                path: PathBuf::from("/srv/data"),
+                size: 2 << 30,
+                file_count: 12,
+                dir_count: 1,
+                modified: None,
+            }],
+            ..Default::default()
+        };
+        let thresholds = Thresholds {
+            max_size: Some(1 << 30),
+            max_percent: None,
+        };
+        let violations = thresholds.check(&result);
+
+        let body = payload(&result, &violations, &thresholds, AlertFormat::Json);
+        assert_eq!(body["path"], "/srv");
+        assert_eq!(body["threshold"]["max_size"], 1 << 30);
+        assert_eq!(body["violations"].as_array().unwrap().len(), 2);
+        assert_eq!(body["violations"][1]["path"], "/srv/data");
+        assert_eq!(body["top_directories"][0]["file_count"], 12);
+
+        let body = payload(&result, &violations, &thresholds, AlertFormat::Slack);
+        let text = body["text"].as_str().unwrap();
+        assert!(text.starts_with(":warning: */srv*"));
+        assert!(text.contains("is over 1.0 G"));
+        assert!(text.contains("\n• `2.0 G /srv/data`"));
+        assert!("teams".parse::<AlertFormat>().is_err());
+    }
+}
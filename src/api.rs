@@ -0,0 +1,404 @@
+//! JSON API for `dutop serve --http`
+//!
+//! `GET /scan?path=...&depth=...&top=...` starts a scan in the background and
+//! answers `202 Accepted` with the job, or `200 OK` when an identical scan is
+//! still running or finished within the cache lifetime. `GET /results/{id}`
+//! returns the job with, once finished, the result in the format of
+//! `--format json` (see `docs/json-schema.md`). `GET /jobs` lists the jobs.
+//!
+//! Only paths inside the roots given on the command line can be scanned, and
+//! a few scans at most run at once; further requests are answered `503 Service
+//! Unavailable` until one finishes.
+
+use crate::http::{Request, Response};
+use crate::output::{write_json, JsonContext};
+use crate::{analyze_disk_usage, AnalysisConfig, AnalysisResult};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Finished jobs kept before the oldest ones are forgotten
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// Scans running at once, beyond which new ones are refused
+const MAX_RUNNING_SCANS: usize = 4;
+
+/// Number of top entries returned when the request does not say
+const DEFAULT_TOP: usize = 10;
+
+/// What a job scans; identical requests share a job while it is cached
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScanKey {
+    path: PathBuf,
+    depth: Option<usize>,
+    top: usize,
+}
+
+#[derive(Debug)]
+enum JobState {
+    Running,
+    Done(Arc<AnalysisResult>),
+    Failed(String),
+}
+
+#[derive(Debug)]
+struct Job {
+    key: ScanKey,
+    state: JobState,
+    started_at: SystemTime,
+    finished_at: Option<SystemTime>,
+}
+
+/// Background scan jobs and their cached results
+pub struct Api {
+    roots: Vec<PathBuf>,
+    config: AnalysisConfig,
+    cache_ttl: Duration,
+    jobs: Mutex<BTreeMap<u64, Job>>,
+}
+
+impl Api {
+    /// Serve scans of paths inside `roots` with `config`, reusing results
+    /// for `cache_ttl` after they finish
+    pub fn new(roots: Vec<PathBuf>, config: AnalysisConfig, cache_ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            roots,
+            config,
+            cache_ttl,
+            jobs: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Answer a request to the API
+    pub fn handle(self: &Arc<Self>, request: &Request) -> Response {
+        if request.method != "GET" {
+            return Response::text(405, "Method not allowed\n");
+        }
+        let path = request.path.trim_end_matches('/');
+        match path.split('/').collect::<Vec<_>>().as_slice() {
+            ["", "scan"] => self.scan(request),
+            ["", "results", id] => match id.parse().ok().and_then(|id| self.job_json(id)) {
+                Some(job) => json_response(200, &job),
+                None => json_error(404, "No such job"),
+            },
+            ["", "jobs"] => {
+                let jobs = self.lock_jobs();
+                let list: Vec<Value> = jobs.iter().map(|(id, job)| summary(*id, job)).collect();
+                json_response(200, &json!({ "jobs": list }))
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    fn scan(self: &Arc<Self>, request: &Request) -> Response {
+        let Some(path) = request.param("path") else {
+            return json_error(400, "Missing path parameter");
+        };
+        let depth = match request.param("depth").map(str::parse).transpose() {
+            Ok(depth) => depth,
+            Err(_) => return json_error(400, "Invalid depth parameter"),
+        };
+        let top = match request.param("top").map(str::parse).transpose() {
+            Ok(top) => top.unwrap_or(DEFAULT_TOP),
+            Err(_) => return json_error(400, "Invalid top parameter"),
+        };
+        // Paths outside the roots are refused before touching the filesystem,
+        // so whether they exist is not told, and again once links are resolved
+        if !self.is_served(&lexical(Path::new(path))) {
+            return json_error(403, "Path is outside of the served roots");
+        }
+        let path = match Path::new(path).canonicalize() {
+            Ok(path) => path,
+            Err(e) => return json_error(404, &format!("Cannot access {}: {}", path, e)),
+        };
+        if !self.is_served(&path) {
+            return json_error(403, "Path is outside of the served roots");
+        }
+
+        let key = ScanKey { path, depth, top };
+        match self.find_or_start(key) {
+            Some((id, created)) => {
+                let job = self.job_json(id).unwrap_or(Value::Null);
+                json_response(if created { 202 } else { 200 }, &job)
+            }
+            None => json_error(503, "Too many scans running, try again later"),
+        }
+    }
+
+    /// Whether `path` is inside one of the served roots
+    fn is_served(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Return the job for `key` that is running or still cached, or start a
+    /// new one unless [`MAX_RUNNING_SCANS`] are running already
+    fn find_or_start(self: &Arc<Self>, key: ScanKey) -> Option<(u64, bool)> {
+        let mut jobs = self.lock_jobs();
+        let now = SystemTime::now();
+        let cached = jobs.iter().rev().find(|(_, job)| {
+            job.key == key
+                && match (&job.state, job.finished_at) {
+                    (JobState::Running, _) => true,
+                    (JobState::Done(_), Some(finished)) => {
+                        now.duration_since(finished).unwrap_or_default() < self.cache_ttl
+                    }
+                    _ => false,
+                }
+        });
+        if let Some((id, _)) = cached {
+            return Some((*id, false));
+        }
+        let running = jobs
+            .values()
+            .filter(|job| matches!(job.state, JobState::Running))
+            .count();
+        if running >= MAX_RUNNING_SCANS {
+            return None;
+        }
+
+        let id = jobs.keys().next_back().map_or(1, |id| id + 1);
+        jobs.insert(
+            id,
+            Job {
+                key: key.clone(),
+                state: JobState::Running,
+                started_at: now,
+                finished_at: None,
+            },
+        );
+        forget_old_jobs(&mut jobs);
+        drop(jobs);
+
+        let api = Arc::clone(self);
+        thread::spawn(move || {
+            let config = api.job_config(&key);
+            let state = match analyze_disk_usage(&key.path, &config, key.top) {
+                Ok(result) => JobState::Done(Arc::new(result)),
+                Err(e) => JobState::Failed(format!("{:#}", e)),
+            };
+            if let Some(job) = api.lock_jobs().get_mut(&id) {
+                job.state = state;
+                job.finished_at = Some(SystemTime::now());
+            }
+        });
+        Some((id, true))
+    }
+
+    /// The job with `id` including its result, if it exists
+    fn job_json(&self, id: u64) -> Option<Value> {
+        let jobs = self.lock_jobs();
+        let job = jobs.get(&id)?;
+        let mut value = summary(id, job);
+        match &job.state {
+            JobState::Done(result) => {
+                let config = self.job_config(&job.key);
+                let context = JsonContext {
+                    config: Some(&config),
+                    started_at: Some(job.started_at),
+                    compact: true,
+                };
+                let mut out = Vec::new();
+                value["result"] = match write_json(&mut out, result, &context) {
+                    Ok(()) => serde_json::from_slice(&out).unwrap_or(Value::Null),
+                    Err(_) => Value::Null,
+                };
+            }
+            JobState::Failed(error) => value["error"] = json!(error),
+            JobState::Running => {}
+        }
+        Some(value)
+    }
+
+    /// Configuration of the scan for `key`
+    fn job_config(&self, key: &ScanKey) -> AnalysisConfig {
+        AnalysisConfig {
+            max_depth: key.depth.or(self.config.max_depth),
+            ..self.config.clone()
+        }
+    }
+
+    fn lock_jobs(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, Job>> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// `path` with its `.` and `..` components resolved, without following links
+fn lexical(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// The job without its result
+fn summary(id: u64, job: &Job) -> Value {
+    let status = match job.state {
+        JobState::Running => "running",
+        JobState::Done(_) => "done",
+        JobState::Failed(_) => "failed",
+    };
+    json!({
+        "id": id,
+        "status": status,
+        "url": format!("/results/{}", id),
+        "path": job.key.path.display().to_string(),
+        "depth": job.key.depth,
+        "top": job.key.top,
+        "started_at": rfc3339(job.started_at),
+        "finished_at": job.finished_at.map(rfc3339),
+    })
+}
+
+/// Drop the oldest finished jobs beyond [`MAX_FINISHED_JOBS`]
+fn forget_old_jobs(jobs: &mut BTreeMap<u64, Job>) {
+    let finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| !matches!(job.state, JobState::Running))
+        .map(|(id, _)| *id)
+        .collect();
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(MAX_FINISHED_JOBS))
+    {
+        jobs.remove(id);
+    }
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    crate::format::format_rfc3339(
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    )
+}
+
+fn json_response(status: u16, value: &Value) -> Response {
+    let mut body = serde_json::to_vec(value).unwrap_or_default();
+    body.push(b'\n');
+    Response {
+        status,
+        content_type: "application/json",
+        body,
+    }
+}
+
+fn json_error(status: u16, message: &str) -> Response {
+    json_response(status, &json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn get(api: &Arc<Api>, target: &str) -> (u16, Value) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let request = Request {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        let response = api.handle(&request);
+        let value = serde_json::from_slice(&response.body).unwrap_or(Value::Null);
+        (response.status, value)
+    }
+
+    #[test]
+    fn test_scan_job() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("data")).unwrap();
+        fs::write(root.join("data/file"), vec![0u8; 8192]).unwrap();
+        let api = Api::new(
+            vec![root.clone()],
+            AnalysisConfig::default(),
+            Duration::from_secs(60),
+        );
+
+        let scan = format!("/scan?path={}&depth=3", root.display());
+        let (status, job) = get(&api, &scan);
+        assert_eq!(status, 202);
+        let url = job["url"].as_str().unwrap().to_string();
+
+        let mut result = Value::Null;
+        for _ in 0..500 {
+            let (status, job) = get(&api, &url);
+            assert_eq!(status, 200);
+            if job["status"] != "running" {
+                result = job;
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(result["status"], "done");
+        assert_eq!(result["depth"], 3);
+        assert_eq!(result["result"]["file_count"], 1);
+        assert_eq!(result["result"]["config"]["max_depth"], 3);
+
+        // The same scan is answered from the cache
+        let (status, job) = get(&api, &scan);
+        assert_eq!((status, job["url"].as_str()), (200, Some(url.as_str())));
+        assert_eq!(get(&api, "/jobs").1["jobs"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_scan_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let api = Api::new(
+            vec![root.join("served")],
+            AnalysisConfig::default(),
+            Duration::ZERO,
+        );
+
+        assert_eq!(get(&api, "/scan").0, 400);
+        let outside = format!("/scan?path={}", root.display());
+        assert_eq!(get(&api, &outside).0, 403);
+        // Whether a path outside the roots exists is not told
+        assert_eq!(get(&api, "/scan?path=/does/not/exist").0, 403);
+        let escape = format!("/scan?path={}/served/../secret", root.display());
+        assert_eq!(get(&api, &escape).0, 403);
+        let missing = format!("/scan?path={}/served/missing", root.display());
+        assert_eq!(get(&api, &missing).0, 404);
+        let depth = format!("/scan?path={}&depth=deep", root.display());
+        assert_eq!(get(&api, &depth).0, 400);
+        assert_eq!(get(&api, "/results/42").0, 404);
+
+        // Beyond the running scans allowed, new ones are refused
+        fs::create_dir(root.join("served")).unwrap();
+        for _ in 0..MAX_RUNNING_SCANS {
+            let key = ScanKey {
+                path: root.join("elsewhere"),
+                depth: None,
+                top: 1,
+            };
+            let id = api.lock_jobs().keys().next_back().map_or(1, |id| id + 1);
+            api.lock_jobs().insert(
+                id,
+                Job {
+                    key,
+                    state: JobState::Running,
+                    started_at: SystemTime::now(),
+                    finished_at: None,
+                },
+            );
+        }
+        let served = format!("/scan?path={}/served", root.display());
+        assert_eq!(get(&api, &served).0, 503);
+    }
+}
@@ -0,0 +1,347 @@
+//! Looking inside archives without extracting them
+//!
+//! Tar (optionally gzip-compressed) and zip archives are listed entry by entry
+//! and turned into a virtual subtree rooted at the archive, with paths such as
+//! `backup.tar/home/user/video.mp4`. Each entry's apparent size is its
+//! uncompressed size, while the archive's disk usage is shared among its
+//! entries in proportion to those sizes, so totals keep adding up.
+//!
+//! Listing a plain tar seeks past the contents of each entry, and zip archives
+//! are read from their central directory; compressed tarballs have to be
+//! decompressed in full.
+
+use crate::{get_disk_usage, AnalysisResult, DirectoryEntry, DirectoryNode, FileNode};
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// A file stored in an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Path of the file within the archive
+    pub path: PathBuf,
+    /// Uncompressed size in bytes
+    pub size: u64,
+}
+
+/// Supported archive formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl Format {
+    /// Recognize an archive from its file name
+    fn detect(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` names an archive that can be listed (`.tar`, `.tar.gz`, `.tgz`, `.zip`)
+pub fn is_archive(path: &Path) -> bool {
+    Format::detect(path).is_some()
+}
+
+/// List the files stored in the archive at `path`
+pub fn list_entries(path: &Path) -> Result<Vec<ArchiveEntry>> {
+    let format = Format::detect(path)
+        .with_context(|| format!("Not a supported archive: {}", path.display()))?;
+    let file =
+        File::open(path).with_context(|| format!("Failed to open archive: {}", path.display()))?;
+
+    let entries = match format {
+        Format::Tar => {
+            let mut archive = tar::Archive::new(BufReader::new(file));
+            tar_entries(archive.entries_with_seek()?)
+        }
+        Format::TarGz => {
+            let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+            tar_entries(archive.entries()?)
+        }
+        Format::Zip => zip_entries(file),
+    };
+    entries.with_context(|| format!("Failed to read archive: {}", path.display()))
+}
+
+fn tar_entries<R: Read>(entries: tar::Entries<'_, R>) -> Result<Vec<ArchiveEntry>> {
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            files.push(ArchiveEntry {
+                path: entry.path()?.into_owned(),
+                size: entry.size(),
+            });
+        }
+    }
+    Ok(files)
+}
+
+fn zip_entries(file: File) -> Result<Vec<ArchiveEntry>> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        // Names escaping the archive root are skipped rather than trusted
+        if let (true, Some(path)) = (entry.is_file(), entry.enclosed_name()) {
+            files.push(ArchiveEntry {
+                path,
+                size: entry.size(),
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Build the virtual subtree for the archive at `path`, which uses `disk_size` bytes
+///
+/// Each file gets the share of `disk_size` matching its share of the
+/// uncompressed size; the rounding remainder stays with the archive node.
+pub fn archive_tree(path: &Path, disk_size: u64) -> Result<DirectoryNode> {
+    let entries = list_entries(path)?;
+    let uncompressed: u64 = entries.iter().map(|e| e.size).sum();
+    let scale = if uncompressed > 0 {
+        disk_size as f64 / uncompressed as f64
+    } else {
+        0.0
+    };
+
+    let mut root = VirtualDir::default();
+    for entry in entries {
+        let size = (entry.size as f64 * scale) as u64;
+        root.insert(&entry.path, size, entry.size);
+    }
+
+    let mut node = root.into_node(path.to_path_buf());
+    node.size = disk_size.max(node.size);
+    Ok(node)
+}
+
+/// Analyze the contents of the archive at `path` as if it were a directory
+pub fn analyze_archive(path: &Path, top_n: usize) -> Result<AnalysisResult> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("Failed to access path: {}", path.display()))?;
+    let tree = archive_tree(path, get_disk_usage(path, &metadata))?;
+    Ok(tree_result(tree, top_n))
+}
+
+/// Turn a virtual tree into an analysis of its root, keeping the `top_n` largest entries
+pub(crate) fn tree_result(tree: DirectoryNode, top_n: usize) -> AnalysisResult {
+    let mut directories: Vec<DirectoryEntry> = tree
+        .children
+        .iter()
+        .map(|child| DirectoryEntry {
+            path: child.path.clone(),
+            size: child.size,
+            file_count: child.file_count,
+            dir_count: child.dir_count + 1,
+            modified: None,
+        })
+        .chain(tree.files.iter().map(|file| DirectoryEntry {
+            path: tree.path.join(&file.name),
+            size: file.size,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        }))
+        .collect();
+    directories.sort_unstable_by_key(|d| std::cmp::Reverse(d.size));
+    directories.truncate(top_n);
+
+    AnalysisResult {
+        root_path: tree.path.clone(),
+        total_size: tree.size,
+        total_files: tree.file_count,
+        total_dirs: tree.dir_count,
+        top_directories: directories,
+        tree: Some(tree),
+        ..Default::default()
+    }
+}
+
+/// A directory implied by the paths of the files below it (archive entries, object keys)
+#[derive(Default)]
+pub(crate) struct VirtualDir {
+    dirs: BTreeMap<OsString, VirtualDir>,
+    files: Vec<FileNode>,
+}
+
+impl VirtualDir {
+    /// Add the directory at `path`, relative to this directory, with its parents
+    #[cfg(any(windows, test))]
+    pub(crate) fn insert_dir(&mut self, path: &Path) {
+        path.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_os_string()),
+                _ => None,
+            })
+            .fold(self, |dir, name| dir.dirs.entry(name).or_default());
+    }
+
+    /// Add the file at `path`, relative to this directory, creating its parents
+    pub(crate) fn insert(&mut self, path: &Path, size: u64, apparent_size: u64) {
+        let mut names: Vec<OsString> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_os_string()),
+                _ => None,
+            })
+            .collect();
+        let Some(name) = names.pop() else {
+            return;
+        };
+        let dir = names
+            .into_iter()
+            .fold(self, |dir, name| dir.dirs.entry(name).or_default());
+        dir.files.push(FileNode {
+            name,
+            size,
+            apparent_size,
+        });
+    }
+
+    /// Convert into a directory node rooted at `path`, with totals and sorted children
+    pub(crate) fn into_node(self, path: PathBuf) -> DirectoryNode {
+        let mut children: Vec<DirectoryNode> = self
+            .dirs
+            .into_iter()
+            .map(|(name, dir)| dir.into_node(path.join(name)))
+            .collect();
+        let mut files = self.files;
+        children.sort_unstable_by_key(|c| std::cmp::Reverse(c.size));
+        files.sort_unstable_by_key(|f| std::cmp::Reverse(f.size));
+
+        DirectoryNode {
+            size: children.iter().map(|c| c.size).sum::<u64>()
+                + files.iter().map(|f| f.size).sum::<u64>(),
+            file_count: children.iter().map(|c| c.file_count).sum::<usize>() + files.len(),
+            dir_count: children.iter().map(|c| c.dir_count + 1).sum(),
+            path,
+            children,
+            files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn write_tar(path: &Path, files: &[(&str, usize)]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, size) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(*size as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, &vec![0u8; *size][..])
+                .unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_tar_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("backup.tar");
+        write_tar(
+            &path,
+            &[("a/b/big", 30_000), ("a/small", 1_000), ("top", 9_000)],
+        );
+
+        let tree = archive_tree(&path, 40_000).unwrap();
+        assert_eq!(tree.size, 40_000);
+        assert_eq!(tree.file_count, 3);
+        assert_eq!(tree.dir_count, 2);
+        assert_eq!(tree.children[0].path, path.join("a"));
+        assert_eq!(tree.children[0].size, 31_000);
+        assert_eq!(tree.children[0].children[0].files[0].apparent_size, 30_000);
+        assert_eq!(tree.files[0].name, "top");
+    }
+
+    #[test]
+    fn test_zip_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("docs.ZIP");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("dir/", options).unwrap();
+        writer.start_file("dir/readme.txt", options).unwrap();
+        writer.write_all(&[b'x'; 5_000]).unwrap();
+        writer.finish().unwrap();
+
+        let entries = list_entries(&path).unwrap();
+        assert_eq!(
+            entries,
+            vec![ArchiveEntry {
+                path: PathBuf::from("dir/readme.txt"),
+                size: 5_000
+            }]
+        );
+    }
+
+    #[test]
+    fn test_analyze_gzipped_tar() {
+        let temp_dir = TempDir::new().unwrap();
+        let tar_path = temp_dir.path().join("plain.tar");
+        write_tar(
+            &tar_path,
+            &[("x/one", 4_000), ("x/two", 4_000), ("three", 100)],
+        );
+        let path = temp_dir.path().join("backup.tgz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(&fs::read(&tar_path).unwrap()).unwrap();
+        encoder.finish().unwrap();
+
+        let result = analyze_archive(&path, 10).unwrap();
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.top_directories[0].path, path.join("x"));
+        assert_eq!(result.top_directories[0].file_count, 2);
+        assert!(!is_archive(&temp_dir.path().join("notes.txt")));
+    }
+
+    #[test]
+    fn test_expanded_archive_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("backups")).unwrap();
+        write_tar(
+            &root.join("backups/old.tar"),
+            &[("a/one", 2_000), ("a/two", 2_000), ("three", 2_000)],
+        );
+
+        let config = crate::AnalysisConfig {
+            build_tree: true,
+            expand_archives: true,
+            ..Default::default()
+        };
+        let result = crate::analyze_disk_usage(&root, &config, 10).unwrap();
+        // The archive counts as a directory holding its entries, everywhere
+        assert_eq!((result.total_files, result.total_dirs), (3, 3));
+        assert_eq!(result.top_directories[0].file_count, 3);
+        let backups = &result.tree.unwrap().children[0];
+        assert_eq!(backups.file_count, 3);
+        assert_eq!(backups.file_count, backups.children[0].file_count);
+    }
+}
@@ -0,0 +1,93 @@
+//! Running analyses from async code (requires the `tokio` feature)
+
+use crate::{analyze_with_hooks, AnalysisConfig, AnalysisResult, CancellationToken, ScanHooks};
+use crate::{ProgressCallback, ScanProgress};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Forwards progress notifications to the channel
+struct ChannelProgress(UnboundedSender<ScanProgress>);
+
+impl ProgressCallback for ChannelProgress {
+    fn on_progress(&self, progress: &ScanProgress) {
+        // Progress is advisory; a closed receiver just means nobody is listening
+        let _ = self.0.send(progress.clone());
+    }
+}
+
+/// Cancels the scan when the future awaiting it is dropped
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Analyzes disk usage like [`crate::analyze_disk_usage`] on tokio's blocking thread pool
+///
+/// When `progress` is given, it receives the same throttled notifications as a
+/// [`ProgressCallback`]. Dropping the returned future cancels the scan.
+pub async fn analyze_disk_usage_async(
+    path: impl Into<PathBuf>,
+    config: AnalysisConfig,
+    top_n: usize,
+    progress: Option<UnboundedSender<ScanProgress>>,
+) -> Result<AnalysisResult> {
+    let path = path.into();
+    // Cancelling after the scan has finished has no effect
+    let guard = CancelOnDrop(CancellationToken::new());
+    let cancel = guard.0.clone();
+
+    let result = tokio::task::spawn_blocking(move || {
+        let progress = progress.map(ChannelProgress);
+        let hooks = ScanHooks {
+            progress: progress.as_ref().map(|p| p as &dyn ProgressCallback),
+            cancel: Some(&cancel),
+            ..Default::default()
+        };
+        analyze_with_hooks(&path, &config, top_n, &hooks)
+    })
+    .await
+    .context("Analysis task failed")?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_analyze_disk_usage_async() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/file.txt"), "data").unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let result = runtime
+            .block_on(analyze_disk_usage_async(
+                temp_dir.path(),
+                AnalysisConfig::default(),
+                10,
+                Some(tx),
+            ))
+            .unwrap();
+
+        assert_eq!(result.total_files, 1);
+        assert!(!result.cancelled);
+
+        // The final notification always arrives
+        let mut last = None;
+        while let Ok(progress) = rx.try_recv() {
+            last = Some(progress);
+        }
+        assert_eq!(last.unwrap().files_scanned, 1);
+    }
+}
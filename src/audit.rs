@@ -0,0 +1,223 @@
+//! Permission audit alongside a disk usage scan (Unix only)
+//!
+//! The scan that adds up sizes already has every file's metadata at hand, so
+//! checking its mode and owner costs no second walk. World-writable files and
+//! files owned by someone other than the expected owners are flagged from a
+//! minimum size up, since large ones are where planted or leaked data hides;
+//! setuid and setgid files are flagged whatever their size.
+
+use crate::{analyze_with_hooks, AnalysisConfig, AnalysisResult, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SETUID: u32 = 0o4000;
+const SETGID: u32 = 0o2000;
+const WORLD_WRITABLE: u32 = 0o002;
+
+/// Why a file was flagged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issue {
+    WorldWritable,
+    Setuid,
+    Setgid,
+    UnexpectedOwner,
+}
+
+impl Issue {
+    /// Name used in reports, like "world-writable"
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Issue::WorldWritable => "world-writable",
+            Issue::Setuid => "setuid",
+            Issue::Setgid => "setgid",
+            Issue::UnexpectedOwner => "unexpected-owner",
+        }
+    }
+}
+
+/// A flagged file
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub path: PathBuf,
+    /// Disk usage in bytes
+    pub size: u64,
+    /// Permission bits, like 0o4755
+    pub mode: u32,
+    /// Numeric ID of the owner
+    pub uid: u32,
+    /// Every reason the file was flagged for
+    pub issues: Vec<Issue>,
+}
+
+impl Finding {
+    /// Name of the owner, falling back to the numeric ID
+    pub fn owner(&self) -> String {
+        user_name(self.uid).unwrap_or_else(|| self.uid.to_string())
+    }
+}
+
+/// Disk usage of a root with the files flagged by the audit
+#[derive(Debug)]
+pub struct AuditReport {
+    /// Usage of the root and its largest entries
+    pub result: AnalysisResult,
+    /// Flagged files, largest first
+    pub findings: Vec<Finding>,
+    /// Users allowed to own files
+    pub owners: Vec<u32>,
+    /// Size from which world-writable files and unexpected owners are flagged
+    pub min_size: u64,
+}
+
+impl AuditReport {
+    /// Number of findings with `issue`
+    pub fn count(&self, issue: Issue) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.issues.contains(&issue))
+            .count()
+    }
+}
+
+struct AuditTally<'a> {
+    owners: &'a [u32],
+    min_size: u64,
+    findings: Mutex<Vec<Finding>>,
+}
+
+impl EntryVisitor for AuditTally<'_> {
+    fn visit_file(&self, path: &Path, metadata: &std::fs::Metadata, size: u64) {
+        let mode = metadata.mode() & 0o7777;
+        let mut issues = Vec::new();
+        if size >= self.min_size {
+            if mode & WORLD_WRITABLE != 0 {
+                issues.push(Issue::WorldWritable);
+            }
+            if !self.owners.contains(&metadata.uid()) {
+                issues.push(Issue::UnexpectedOwner);
+            }
+        }
+        if mode & SETUID != 0 {
+            issues.push(Issue::Setuid);
+        }
+        if mode & SETGID != 0 {
+            issues.push(Issue::Setgid);
+        }
+        if issues.is_empty() {
+            return;
+        }
+        let finding = Finding {
+            path: path.to_path_buf(),
+            size,
+            mode,
+            uid: metadata.uid(),
+            issues,
+        };
+        let mut findings = self.findings.lock().unwrap_or_else(|e| e.into_inner());
+        findings.push(finding);
+    }
+}
+
+/// Numeric ID of the user `name`, which may be a number itself
+pub fn resolve_user(name: &str) -> Result<u32> {
+    if let Ok(uid) = name.parse() {
+        return Ok(uid);
+    }
+    match uzers::get_user_by_name(name) {
+        Some(user) => Ok(user.uid()),
+        None => anyhow::bail!("Unknown user: {}", name),
+    }
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    uzers::get_user_by_uid(uid).map(|user| user.name().to_string_lossy().into_owned())
+}
+
+/// Analyze `path` keeping the `top_n` largest entries, and flag its
+/// setuid and setgid files and, from `min_size` bytes up, its world-writable
+/// files and those not owned by one of `owners`
+///
+/// Without `owners`, the owner of `path` and root are expected.
+pub fn audit(
+    path: &Path,
+    config: &AnalysisConfig,
+    top_n: usize,
+    min_size: u64,
+    owners: &[u32],
+) -> Result<AuditReport> {
+    let owners = if owners.is_empty() {
+        let mut owners = vec![0, std::fs::metadata(path)?.uid()];
+        owners.dedup();
+        owners
+    } else {
+        owners.to_vec()
+    };
+    let tally = AuditTally {
+        owners: &owners,
+        min_size,
+        findings: Mutex::new(Vec::new()),
+    };
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let result = analyze_with_hooks(path, config, top_n, &hooks)?;
+
+    let mut findings = tally
+        .findings
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    findings.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    Ok(AuditReport {
+        result,
+        findings,
+        owners,
+        min_size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_audit() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let chmod = |name: &str, len: usize, mode: u32| {
+            fs::write(root.join(name), vec![1u8; len]).unwrap();
+            fs::set_permissions(root.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        };
+        chmod("open-big", 64 << 10, 0o666);
+        chmod("open-small", 10, 0o666);
+        chmod("tool", 10, 0o4755);
+        chmod("plain", 64 << 10, 0o644);
+
+        let uid = fs::metadata(&root).unwrap().uid();
+        let report = audit(&root, &AnalysisConfig::default(), 10, 32 << 10, &[]).unwrap();
+        assert!(report.owners.contains(&uid));
+        assert_eq!(report.result.total_files, 4);
+        assert_eq!(report.findings.len(), 2);
+        assert_eq!(report.findings[0].path, root.join("open-big"));
+        assert_eq!(report.findings[0].issues, [Issue::WorldWritable]);
+        assert_eq!(report.findings[1].issues, [Issue::Setuid]);
+        assert_eq!(report.findings[1].mode, 0o4755);
+
+        // Nobody owns these files
+        let report = audit(
+            &root,
+            &AnalysisConfig::default(),
+            10,
+            32 << 10,
+            &[u32::MAX - 1],
+        )
+        .unwrap();
+        assert_eq!(report.count(Issue::UnexpectedOwner), 2);
+        assert_eq!(resolve_user("1234").unwrap(), 1234);
+    }
+}
@@ -0,0 +1,272 @@
+//! Reusing the totals of unchanged directories from a previous scan
+//!
+//! Creating, removing or renaming an entry updates the modification time of
+//! its directory, so a directory whose modification time and size match the
+//! cached ones still holds the same entries. Its files are then counted from
+//! the cache instead of being stat'ed again, while its subdirectories are still
+//! visited and checked the same way. A file rewritten in place does not touch
+//! its directory, so its new size only shows once the directory changes or the
+//! cache is rebuilt.
+//!
+//! Only directories holding plain files and subdirectories are cached: symbolic
+//! links, special files, hard links, cloud-only files and entries that could
+//! not be read are always checked again.
+
+use crate::{AnalysisConfig, RemoteFs};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the cache format written by this build
+const FORMAT_VERSION: u32 = 1;
+
+/// Directory holding the cache files, if a cache directory is known
+pub fn default_dir() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(dir.join("dutop"))
+}
+
+/// Cache file in `dir` for scans of `root` with `config`
+///
+/// Options changing which files are counted get a cache of their own.
+pub fn cache_file(dir: &Path, root: &Path, config: &AnalysisConfig) -> PathBuf {
+    let key = format!(
+        "{}\0{:?}\0{}\0{:?}\0{}\0{}\0{}\0{}",
+        fs::canonicalize(root)
+            .unwrap_or_else(|_| root.to_path_buf())
+            .display(),
+        config.exclude_patterns,
+        config.one_file_system,
+        config.min_file_size,
+        config.count_all_types,
+        config.alternate_streams,
+        config.remote_fs == RemoteFs::Skip,
+        config.include_virtual,
+    );
+    let hash = blake3::hash(key.as_bytes()).to_hex();
+    dir.join(format!("{}.json", &hash[..16]))
+}
+
+/// Whether directory totals can be reused for a scan with `config`
+///
+/// Age windows move with the current time, link targets and `.gitignore`
+/// files can change without touching their directory, and per-file listings,
+/// link checks and clone detection need every file.
+pub(crate) fn supports(config: &AnalysisConfig) -> bool {
+    !config.follow_links
+        && config.modified_before.is_none()
+        && config.modified_after.is_none()
+        && !config.respect_gitignore
+        && !config.tree_files
+        && !config.expand_archives
+        && !config.check_links
+        && !config.detect_clones
+        && !config.track_all_inodes
+}
+
+/// What identifies an unchanged directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Stamp {
+    secs: u64,
+    nanos: u32,
+    size: u64,
+}
+
+impl Stamp {
+    pub(crate) fn new(modified: Option<SystemTime>, size: u64) -> Option<Self> {
+        let since_epoch = modified?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            secs: since_epoch.as_secs(),
+            nanos: since_epoch.subsec_nanos(),
+            size,
+        })
+    }
+}
+
+/// Totals of the files stored directly in a directory, and its subdirectories
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CachedDir {
+    pub stamp: Stamp,
+    pub size: u64,
+    pub file_count: usize,
+    /// Most recent modification time of the files, in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    pub subdirs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CacheFile {
+    version: u32,
+    dirs: HashMap<String, CachedDir>,
+}
+
+/// [`CacheFile`] borrowing the directories being saved
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    version: u32,
+    dirs: HashMap<&'a str, &'a CachedDir>,
+}
+
+/// Directory totals read from a previous scan, and recorded during this one
+#[derive(Debug, Default)]
+pub(crate) struct ScanCache {
+    previous: HashMap<String, CachedDir>,
+    current: Mutex<HashMap<String, CachedDir>>,
+}
+
+impl ScanCache {
+    /// Load the cache at `path`; a missing, damaged or outdated file yields an
+    /// empty cache
+    pub(crate) fn load(path: &Path) -> Self {
+        let previous = File::open(path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, CacheFile>(BufReader::new(file)).ok())
+            .filter(|cache| cache.version == FORMAT_VERSION)
+            .map(|cache| cache.dirs)
+            .unwrap_or_default();
+        log::debug!(
+            "Loaded {} cached directories from {}",
+            previous.len(),
+            path.display()
+        );
+        Self {
+            previous,
+            current: Mutex::default(),
+        }
+    }
+
+    /// Totals of `dir` from the previous scan, if it has not changed since
+    pub(crate) fn lookup(&self, dir: &Path, stamp: Stamp) -> Option<&CachedDir> {
+        self.previous
+            .get(dir.to_str()?)
+            .filter(|cached| cached.stamp == stamp)
+    }
+
+    /// Remember the totals of `dir`; paths and names that are not valid UTF-8
+    /// are left out of the cache
+    pub(crate) fn record(
+        &self,
+        dir: &Path,
+        stamp: Stamp,
+        (size, file_count, modified): (u64, usize, Option<u64>),
+        subdirs: &[OsString],
+    ) {
+        let (Some(path), Some(subdirs)) = (
+            dir.to_str(),
+            subdirs
+                .iter()
+                .map(|name| name.to_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>(),
+        ) else {
+            return;
+        };
+        let cached = CachedDir {
+            stamp,
+            size,
+            file_count,
+            modified,
+            subdirs,
+        };
+        self.current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path.to_string(), cached);
+    }
+
+    /// Write the directories recorded during this scan to `path`, replacing
+    /// the previous cache
+    ///
+    /// A `partial` scan, interrupted or not finished yet, also keeps the
+    /// previous totals of the directories it has not reached.
+    pub(crate) fn save(&self, path: &Path, partial: bool) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+        let current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let mut dirs: HashMap<&str, &CachedDir> = HashMap::new();
+        if partial {
+            dirs.extend(self.previous.iter().map(|(path, dir)| (path.as_str(), dir)));
+        }
+        dirs.extend(current.iter().map(|(path, dir)| (path.as_str(), dir)));
+        let cache = CacheFileRef {
+            version: FORMAT_VERSION,
+            dirs,
+        };
+
+        // Write next to the cache and rename, so a crash never leaves half a file
+        let temp = path.with_extension("json.tmp");
+        let file = File::create(&temp)
+            .with_context(|| format!("Failed to create cache file: {}", temp.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &cache)?;
+        writer.flush()?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("Failed to write cache file: {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_disk_usage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("photos/2024")).unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("photos/2024/a.jpg"), vec![1u8; 20_000]).unwrap();
+        fs::write(root.join("docs/notes.txt"), vec![1u8; 4_000]).unwrap();
+
+        let cache_dir = temp_dir.path().join("cache");
+        let mut config = AnalysisConfig::default();
+        let path = cache_file(&cache_dir, &root, &config);
+        config.cache = Some(path.clone());
+
+        let first = analyze_disk_usage(&root, &config, 10).unwrap();
+        assert!(path.exists());
+        let cached = ScanCache::load(&path);
+        assert_eq!(cached.previous.len(), 3);
+
+        // Scanning again from the cache reports the same totals, cloud-only
+        // files included
+        let again = analyze_disk_usage(&root, &config, 10).unwrap();
+        assert_eq!(again.total_size, first.total_size);
+        assert_eq!(
+            (again.cloud_files, again.cloud_size),
+            (first.cloud_files, first.cloud_size)
+        );
+
+        // A file rewritten in place is not noticed until its directory changes...
+        fs::write(root.join("photos/2024/a.jpg"), vec![1u8; 60_000]).unwrap();
+        let second = analyze_disk_usage(&root, &config, 10).unwrap();
+        assert_eq!(second.total_size, first.total_size);
+        assert_eq!((second.total_files, second.total_dirs), (2, 3));
+
+        // ...while adding a file is
+        fs::write(root.join("photos/2024/b.jpg"), vec![1u8; 8_000]).unwrap();
+        let third = analyze_disk_usage(&root, &config, 10).unwrap();
+        assert!(third.total_size >= first.total_size + 40_000 + 8_000);
+        assert_eq!(third.total_files, 3);
+
+        // Options changing what is counted use another cache
+        let other = AnalysisConfig {
+            exclude_patterns: vec!["docs".to_string()],
+            ..Default::default()
+        };
+        assert_ne!(cache_file(&cache_dir, &root, &other), path);
+    }
+}
@@ -0,0 +1,274 @@
+//! Checkpoints letting an interrupted scan continue where it stopped
+//!
+//! While walking, the completely aggregated directories, at any depth, are
+//! recorded with their totals; once a directory completes, the directories
+//! below it are folded into its entry, so only the outermost completed ones
+//! are kept. A thread of its own writes them to the checkpoint file at regular
+//! intervals, in the snapshot format. A later scan of the same root with the
+//! same checkpoint file takes the totals of those directories from it instead
+//! of walking them again, and the file is removed once a scan completes.
+//!
+//! Checkpoints only hold totals, so scans building a tree do not write them.
+//! Hard links between a resumed directory and one walked after resuming are
+//! counted twice, and the counts of errors, symbolic links and hard links only
+//! cover what was walked after resuming.
+
+use crate::snapshot::{load_snapshot, write_snapshot};
+use crate::walk::DirectoryStats;
+use crate::{AnalysisConfig, AnalysisResult, DirectoryEntry};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Minimum time between two checkpoints
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Checkpoint file in `dir` for scans of `root` with `config`
+///
+/// Every option shaping the results gets a checkpoint of its own; age windows
+/// only count by their presence, since they are relative to the current time.
+pub fn checkpoint_file(dir: &Path, root: &Path, config: &AnalysisConfig) -> PathBuf {
+    let options = AnalysisConfig {
+        num_threads: None,
+        entry_timeout: None,
+        modified_before: config.modified_before.map(|_| UNIX_EPOCH),
+        modified_after: config.modified_after.map(|_| UNIX_EPOCH),
+        cache: None,
+        checkpoint: None,
+        ..config.clone()
+    };
+    let key = format!(
+        "{}\0{:?}",
+        fs::canonicalize(root)
+            .unwrap_or_else(|_| root.to_path_buf())
+            .display(),
+        options
+    );
+    let hash = blake3::hash(key.as_bytes()).to_hex();
+    dir.join(format!("{}.checkpoint", &hash[..16]))
+}
+
+/// Records the completed directories of a walk and writes them periodically
+pub(crate) struct Checkpointer {
+    path: PathBuf,
+    root: PathBuf,
+    /// Totals of the directories taken from the previous checkpoint, by path
+    resumed: HashMap<PathBuf, DirectoryEntry>,
+    /// Completed directories, without those below another completed one
+    completed: Mutex<BTreeMap<PathBuf, DirectoryEntry>>,
+    /// Whether the walk is over, which the writer is woken up for
+    finished: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Checkpointer {
+    /// Checkpoint `root` to `path`, resuming from the checkpoint already
+    /// there when it belongs to the same root
+    pub(crate) fn new(path: &Path, root: &Path) -> Self {
+        let resumed: HashMap<PathBuf, DirectoryEntry> = match load_snapshot(path) {
+            Ok(snapshot) if snapshot.result.root_path == root => snapshot
+                .result
+                .top_directories
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect(),
+            Ok(_) => HashMap::new(),
+            Err(e) => {
+                if path.exists() {
+                    log::warn!("Ignoring checkpoint {}: {:#}", path.display(), e);
+                }
+                HashMap::new()
+            }
+        };
+        if !resumed.is_empty() {
+            log::info!(
+                "Resuming the scan of {} with {} directories done",
+                root.display(),
+                resumed.len()
+            );
+        }
+
+        Self {
+            path: path.to_path_buf(),
+            root: root.to_path_buf(),
+            completed: Mutex::new(
+                resumed
+                    .iter()
+                    .map(|(path, entry)| (path.clone(), entry.clone()))
+                    .collect(),
+            ),
+            resumed,
+            finished: Mutex::new(false),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Totals of `dir` if it was completed before resuming
+    pub(crate) fn resumed(&self, dir: &Path) -> Option<&DirectoryEntry> {
+        self.resumed.get(dir)
+    }
+
+    /// Whether directories were taken from a previous checkpoint
+    pub(crate) fn has_resumed(&self) -> bool {
+        !self.resumed.is_empty()
+    }
+
+    /// Record the totals of `dir`, completely aggregated, in place of the
+    /// directories below it
+    ///
+    /// Directories whose paths are not valid UTF-8 are left out, as the
+    /// snapshot format could not match them with their directory again.
+    pub(crate) fn add(&self, dir: &Path, stats: &DirectoryStats) {
+        if dir.to_str().is_none() {
+            return;
+        }
+        let mut completed = self.completed.lock().unwrap_or_else(|e| e.into_inner());
+        // Paths sort component by component, so those below `dir` follow it
+        let below: Vec<PathBuf> = completed
+            .range::<Path, _>((Bound::Excluded(dir), Bound::Unbounded))
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(dir))
+            .cloned()
+            .collect();
+        for path in below {
+            completed.remove(&path);
+        }
+        completed.insert(
+            dir.to_path_buf(),
+            DirectoryEntry {
+                path: dir.to_path_buf(),
+                size: stats.size,
+                file_count: stats.file_count,
+                // The directory itself is counted alongside its descendants
+                dir_count: stats.dir_count + 1,
+                modified: stats.modified,
+            },
+        );
+    }
+
+    /// Wait for the next checkpoint to be due, returning `false` instead once
+    /// the walk is over
+    pub(crate) fn wait(&self) -> bool {
+        let finished = self.finished.lock().unwrap_or_else(|e| e.into_inner());
+        let (finished, _) = self
+            .wake
+            .wait_timeout_while(finished, CHECKPOINT_INTERVAL, |finished| !*finished)
+            .unwrap_or_else(|e| e.into_inner());
+        !*finished
+    }
+
+    /// Stop waiting for checkpoints, the walk being over
+    pub(crate) fn finish(&self) {
+        *self.finished.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.wake.notify_all();
+    }
+
+    /// Write the directories completed so far to the checkpoint file
+    pub(crate) fn save(&self) -> Result<()> {
+        // Only the copy is written, leaving the walk free to record more
+        let entries: Vec<DirectoryEntry> = self
+            .completed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        let result = AnalysisResult {
+            root_path: self.root.clone(),
+            total_size: entries.iter().map(|e| e.size).sum(),
+            total_files: entries.iter().map(|e| e.file_count).sum(),
+            total_dirs: entries.iter().map(|e| e.dir_count).sum(),
+            cancelled: true,
+            modified: entries.iter().filter_map(|e| e.modified).max(),
+            top_directories: entries,
+            ..Default::default()
+        };
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+        // Write next to the checkpoint and rename, so a crash never leaves half a file
+        let temp = self.path.with_extension("checkpoint.tmp");
+        let file = File::create(&temp)
+            .with_context(|| format!("Failed to create checkpoint: {}", temp.display()))?;
+        let mut out = BufWriter::new(file);
+        write_snapshot(&mut out, &result)?;
+        out.flush()?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write checkpoint: {}", self.path.display()))?;
+        log::debug!("Checkpoint written to {}", self.path.display());
+        Ok(())
+    }
+
+    /// Remove the checkpoint once the scan completed
+    pub(crate) fn remove(&self) {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                log::warn!("Cannot remove checkpoint {}: {}", self.path.display(), e);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_disk_usage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("nas");
+        fs::create_dir_all(root.join("media/done/old")).unwrap();
+        fs::create_dir_all(root.join("media/todo")).unwrap();
+        fs::write(root.join("media/done/old/a.bin"), vec![1u8; 10_000]).unwrap();
+        fs::write(root.join("media/todo/b.bin"), vec![1u8; 10_000]).unwrap();
+
+        let config = AnalysisConfig::default();
+        let path = checkpoint_file(temp_dir.path(), &root, &config);
+        // The walk of "media/done" completed before the interruption, folding
+        // in the directory below it
+        let checkpoint = Checkpointer::new(&path, &root);
+        let stats = |size, file_count, dir_count| DirectoryStats {
+            size,
+            file_count,
+            dir_count,
+            ..Default::default()
+        };
+        checkpoint.add(&root.join("media/done/old"), &stats(10_000, 1, 0));
+        checkpoint.add(&root.join("media/done-too"), &stats(10_000, 1, 0));
+        checkpoint.add(&root.join("media/done"), &stats(5_000_000, 42, 1));
+        checkpoint.save().unwrap();
+        let saved = load_snapshot(&path).unwrap().result;
+        assert_eq!(saved.top_directories.len(), 2);
+        assert_eq!(saved.top_directories[0].path, root.join("media/done"));
+        assert_eq!(saved.top_directories[0].dir_count, 2);
+
+        let config = AnalysisConfig {
+            checkpoint: Some(path.clone()),
+            ..config
+        };
+        let result = analyze_disk_usage(&root, &config, 10).unwrap();
+        assert_eq!(result.total_files, 43);
+        assert_eq!(
+            (result.total_dirs, result.top_directories[0].dir_count),
+            (4, 4)
+        );
+        assert!(result.total_size > 5_000_000);
+        // A completed scan leaves no checkpoint behind
+        assert!(!path.exists());
+
+        // Another root never resumes from it
+        checkpoint.save().unwrap();
+        let other = Checkpointer::new(&path, &temp_dir.path().join("other"));
+        assert!(!other.has_resumed());
+    }
+}
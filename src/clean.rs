@@ -0,0 +1,416 @@
+//! Reclaimable build output, dependency and cache directories
+//!
+//! Directories are recognized by name and location, confirmed where the name
+//! alone is ambiguous (a `target/` next to a `Cargo.toml`, a `.venv` holding a
+//! `pyvenv.cfg`). Any directory marked with a `CACHEDIR.TAG` counts as a cache.
+//! Nothing below a recognized directory is examined further.
+
+use crate::{analyze_disk_usage, AnalysisConfig, DirectoryNode};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Kinds of reclaimable directories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    /// Compiler and bundler output (`target/`, ...)
+    BuildOutput,
+    /// Installed project dependencies (`node_modules/`, ...)
+    Dependencies,
+    /// Python virtual environments
+    VirtualEnv,
+    /// Bytecode and tool caches kept next to the sources (`__pycache__/`, ...)
+    ToolCache,
+    /// Per-user package manager caches (pip, npm, gradle, ...)
+    PackageCache,
+    /// Docker image layers and container filesystems
+    Docker,
+    /// Other directories tagged with `CACHEDIR.TAG`
+    Cache,
+}
+
+impl Category {
+    /// Every category, in declaration order
+    pub const ALL: [Category; 7] = [
+        Category::BuildOutput,
+        Category::Dependencies,
+        Category::VirtualEnv,
+        Category::ToolCache,
+        Category::PackageCache,
+        Category::Docker,
+        Category::Cache,
+    ];
+
+    /// Short name used on the command line (e.g. `build-output`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::BuildOutput => "build-output",
+            Category::Dependencies => "dependencies",
+            Category::VirtualEnv => "virtualenv",
+            Category::ToolCache => "tool-cache",
+            Category::PackageCache => "package-cache",
+            Category::Docker => "docker",
+            Category::Cache => "cache",
+        }
+    }
+
+    /// Human-readable name
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::BuildOutput => "Build output",
+            Category::Dependencies => "Dependencies",
+            Category::VirtualEnv => "Virtual environments",
+            Category::ToolCache => "Tool caches",
+            Category::PackageCache => "Package caches",
+            Category::Docker => "Docker",
+            Category::Cache => "Caches",
+        }
+    }
+}
+
+impl std::str::FromStr for Category {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Category::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+            .with_context(|| {
+                let names: Vec<&str> = Category::ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "Unknown category {} (expected one of: {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Scripting language of a generated deletion script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptFormat {
+    /// POSIX shell, using `rm -rf`
+    Sh,
+    /// PowerShell, using `Remove-Item -Recurse`
+    PowerShell,
+}
+
+/// A directory that can most likely be deleted and rebuilt or downloaded again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Path to the directory
+    pub path: PathBuf,
+    /// What kind of directory it is
+    pub category: Category,
+    /// The rule that recognized it (e.g. `cargo target`)
+    pub kind: &'static str,
+    /// Disk usage in bytes
+    pub size: u64,
+    /// Number of files below it
+    pub file_count: usize,
+}
+
+/// Reclaimable directories below a root
+#[derive(Debug, Default)]
+pub struct CleanReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Total size of the analyzed tree in bytes
+    pub total_size: u64,
+    /// Candidates sorted by size, largest first
+    pub candidates: Vec<Candidate>,
+}
+
+impl CleanReport {
+    /// Total size of all candidates in bytes
+    pub fn reclaimable(&self) -> u64 {
+        self.candidates.iter().map(|c| c.size).sum()
+    }
+
+    /// Total size and number of candidates per category, largest first
+    pub fn by_category(&self) -> Vec<(Category, u64, usize)> {
+        let mut totals: Vec<(Category, u64, usize)> = Vec::new();
+        for candidate in &self.candidates {
+            match totals.iter_mut().find(|(c, _, _)| *c == candidate.category) {
+                Some((_, size, count)) => {
+                    *size += candidate.size;
+                    *count += 1;
+                }
+                None => totals.push((candidate.category, candidate.size, 1)),
+            }
+        }
+        totals.sort_by_key(|&(category, size, _)| (std::cmp::Reverse(size), category));
+        totals
+    }
+
+    /// Keep the candidates of at least `min_size` bytes in one of `categories` (any when empty)
+    pub fn retain(&mut self, categories: &[Category], min_size: u64) {
+        self.candidates.retain(|c| {
+            c.size >= min_size && (categories.is_empty() || categories.contains(&c.category))
+        });
+    }
+}
+
+/// How a directory is recognized
+struct Rule {
+    kind: &'static str,
+    category: Category,
+    /// Trailing path components the directory must have (e.g. `.cache/pip`)
+    suffix: &'static str,
+    /// File that must exist, relative to the directory (`../` for its parent)
+    marker: Option<&'static str>,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        kind: "cargo target",
+        category: Category::BuildOutput,
+        suffix: "target",
+        marker: Some("../Cargo.toml"),
+    },
+    Rule {
+        kind: "maven target",
+        category: Category::BuildOutput,
+        suffix: "target",
+        marker: Some("../pom.xml"),
+    },
+    Rule {
+        kind: "gradle build",
+        category: Category::BuildOutput,
+        suffix: "build",
+        marker: Some("../build.gradle"),
+    },
+    Rule {
+        kind: "gradle build",
+        category: Category::BuildOutput,
+        suffix: "build",
+        marker: Some("../build.gradle.kts"),
+    },
+    Rule {
+        kind: "next.js build",
+        category: Category::BuildOutput,
+        suffix: ".next",
+        marker: None,
+    },
+    Rule {
+        kind: "node_modules",
+        category: Category::Dependencies,
+        suffix: "node_modules",
+        marker: None,
+    },
+    Rule {
+        kind: "python venv",
+        category: Category::VirtualEnv,
+        suffix: ".venv",
+        marker: Some("pyvenv.cfg"),
+    },
+    Rule {
+        kind: "python venv",
+        category: Category::VirtualEnv,
+        suffix: "venv",
+        marker: Some("pyvenv.cfg"),
+    },
+    Rule {
+        kind: "tox",
+        category: Category::VirtualEnv,
+        suffix: ".tox",
+        marker: None,
+    },
+    Rule {
+        kind: "__pycache__",
+        category: Category::ToolCache,
+        suffix: "__pycache__",
+        marker: None,
+    },
+    Rule {
+        kind: "pytest cache",
+        category: Category::ToolCache,
+        suffix: ".pytest_cache",
+        marker: None,
+    },
+    Rule {
+        kind: "mypy cache",
+        category: Category::ToolCache,
+        suffix: ".mypy_cache",
+        marker: None,
+    },
+    Rule {
+        kind: "pip cache",
+        category: Category::PackageCache,
+        suffix: ".cache/pip",
+        marker: None,
+    },
+    Rule {
+        kind: "pip cache",
+        category: Category::PackageCache,
+        suffix: "Library/Caches/pip",
+        marker: None,
+    },
+    Rule {
+        kind: "pip cache",
+        category: Category::PackageCache,
+        suffix: "AppData/Local/pip/Cache",
+        marker: None,
+    },
+    Rule {
+        kind: "npm cache",
+        category: Category::PackageCache,
+        suffix: ".npm/_cacache",
+        marker: None,
+    },
+    Rule {
+        kind: "yarn cache",
+        category: Category::PackageCache,
+        suffix: ".cache/yarn",
+        marker: None,
+    },
+    Rule {
+        kind: "gradle cache",
+        category: Category::PackageCache,
+        suffix: ".gradle/caches",
+        marker: None,
+    },
+    Rule {
+        kind: "cargo registry",
+        category: Category::PackageCache,
+        suffix: ".cargo/registry",
+        marker: None,
+    },
+    Rule {
+        kind: "docker overlay",
+        category: Category::Docker,
+        suffix: "docker/overlay2",
+        marker: None,
+    },
+];
+
+/// Recognize a reclaimable directory at `path`
+pub fn classify(path: &Path) -> Option<(Category, &'static str)> {
+    let rule = RULES.iter().find(|rule| {
+        path.ends_with(rule.suffix)
+            && rule
+                .marker
+                .map_or(true, |marker| match marker.strip_prefix("../") {
+                    Some(sibling) => path.with_file_name(sibling).exists(),
+                    None => path.join(marker).exists(),
+                })
+    });
+    match rule {
+        Some(rule) => Some((rule.category, rule.kind)),
+        None if path.join("CACHEDIR.TAG").is_file() => Some((Category::Cache, "CACHEDIR.TAG")),
+        None => None,
+    }
+}
+
+/// Find the reclaimable directories below `path`
+///
+/// The root itself is never reported.
+pub fn find_reclaimable(path: &Path, config: &AnalysisConfig) -> Result<CleanReport> {
+    let config = AnalysisConfig {
+        build_tree: true,
+        ..config.clone()
+    };
+    let result = analyze_disk_usage(path, &config, 0)?;
+    let tree = result.tree.context("Analysis did not produce a tree")?;
+
+    let mut candidates = Vec::new();
+    for child in &tree.children {
+        collect(child, &mut candidates);
+    }
+    candidates.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    Ok(CleanReport {
+        root_path: result.root_path,
+        total_size: result.total_size,
+        candidates,
+    })
+}
+
+fn collect(node: &DirectoryNode, candidates: &mut Vec<Candidate>) {
+    match classify(&node.path) {
+        Some((category, kind)) => candidates.push(Candidate {
+            path: node.path.clone(),
+            category,
+            kind,
+            size: node.size,
+            file_count: node.file_count,
+        }),
+        None => {
+            for child in &node.children {
+                collect(child, candidates);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, size: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; size]).unwrap();
+    }
+
+    #[test]
+    fn test_find_reclaimable() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        write(&root.join("app/Cargo.toml"), 10);
+        write(&root.join("app/target/debug/app"), 40_000);
+        write(
+            &root.join("web/node_modules/a/node_modules/b/index.js"),
+            20_000,
+        );
+        write(&root.join("py/.venv/pyvenv.cfg"), 10);
+        write(&root.join("py/src/__pycache__/mod.pyc"), 5_000);
+        write(&root.join("py/src/mod.py"), 100);
+        write(&root.join("thumbs/CACHEDIR.TAG"), 43);
+        // A `target` without a Cargo.toml or pom.xml beside it is left alone
+        write(&root.join("docs/target/page.html"), 8_000);
+
+        let report = find_reclaimable(&root, &AnalysisConfig::default()).unwrap();
+        let found: Vec<(PathBuf, &str)> = report
+            .candidates
+            .iter()
+            .map(|c| (c.path.strip_prefix(&root).unwrap().to_path_buf(), c.kind))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (PathBuf::from("app/target"), "cargo target"),
+                (PathBuf::from("web/node_modules"), "node_modules"),
+                (PathBuf::from("py/src/__pycache__"), "__pycache__"),
+                (PathBuf::from("py/.venv"), "python venv"),
+                (PathBuf::from("thumbs"), "CACHEDIR.TAG"),
+            ]
+        );
+        assert_eq!(report.by_category()[0].0, Category::BuildOutput);
+        assert_eq!(
+            report.reclaimable(),
+            report.candidates.iter().map(|c| c.size).sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn test_classify_by_location() {
+        assert_eq!(
+            classify(Path::new("/home/me/.cache/pip")),
+            Some((Category::PackageCache, "pip cache"))
+        );
+        assert_eq!(
+            classify(Path::new("/var/lib/docker/overlay2")),
+            Some((Category::Docker, "docker overlay"))
+        );
+        assert_eq!(classify(Path::new("/home/me/pip")), None);
+    }
+
+    #[test]
+    fn test_category_names() {
+        for category in Category::ALL {
+            assert_eq!(category.name().parse::<Category>().unwrap(), category);
+        }
+        assert!("bogus".parse::<Category>().is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! User configuration file and named profiles
+//!
+//! Defaults are read from `config.toml` in the user's configuration directory
+//! (`$XDG_CONFIG_HOME/dutop`, `~/.config/dutop` or `%APPDATA%\dutop`). Settings
+//! at the top level of the file always apply; a `[profiles.NAME]` table
+//! selected on the command line is layered on top of them, and explicit
+//! command-line flags take precedence over both. Exclude patterns accumulate
+//! across all layers instead of replacing each other.
+//!
+//! ```toml
+//! exclude = ["node_modules", ".git"]
+//! threads = 4
+//!
+//! [profiles.work]
+//! exclude = ["**/target"]
+//! format = "json"
+//! color = false
+//! top = 25
+//! ```
+//!
+//! Color themes and their `[themes.NAME]` tables are described in
+//! [`crate::theme`].
+
+use crate::theme::{self, ColorBy, Theme, ThemeSpec};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings from one layer of the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Exclude patterns added to those given on the command line
+    pub exclude: Vec<String>,
+    /// Output format name, as accepted by `--format`
+    pub format: Option<String>,
+    /// Whether colored output is allowed
+    pub color: Option<bool>,
+    /// Whether tables, bars and trees are drawn with plain ASCII
+    pub ascii: Option<bool>,
+    /// Number of threads to use
+    pub threads: Option<usize>,
+    /// Number of top directories to display
+    pub top: Option<usize>,
+    /// Name of the color theme
+    pub theme: Option<String>,
+    /// What the table and the tree are colored by: size, age or type
+    pub color_by: Option<String>,
+    /// Color themes defined in the file, by name
+    pub themes: BTreeMap<String, ThemeSpec>,
+}
+
+impl Settings {
+    /// Layer `other` on top of these settings
+    pub fn merge(&mut self, other: &Settings) {
+        self.exclude.extend(other.exclude.iter().cloned());
+        self.format = other.format.clone().or(self.format.take());
+        self.color = other.color.or(self.color);
+        self.ascii = other.ascii.or(self.ascii);
+        self.threads = other.threads.or(self.threads);
+        self.top = other.top.or(self.top);
+        self.theme = other.theme.clone().or(self.theme.take());
+        self.color_by = other.color_by.clone().or(self.color_by.take());
+        self.themes.extend(other.themes.clone());
+    }
+
+    /// Color theme called `name`, or the one of these settings, or the default
+    pub fn theme(&self, name: Option<&str>) -> Result<Theme> {
+        match name.or(self.theme.as_deref()) {
+            Some(name) => theme::resolve(name, &self.themes),
+            None => Ok(Theme::default()),
+        }
+    }
+
+    /// Coloring mode of these settings, size by default
+    pub fn color_by(&self) -> Result<ColorBy> {
+        match &self.color_by {
+            Some(mode) => mode.parse().context("Invalid color_by in config file"),
+            None => Ok(ColorBy::Size),
+        }
+    }
+}
+
+/// Contents of a configuration file
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    /// Settings that always apply
+    #[serde(flatten)]
+    pub defaults: Settings,
+    /// Named profiles selectable with `--profile`
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+impl ConfigFile {
+    /// Parse a configuration file from its TOML source
+    pub fn parse(source: &str) -> Result<Self> {
+        Ok(toml::from_str(source)?)
+    }
+
+    /// Read and parse the configuration file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("Invalid config file: {}", path.display()))
+    }
+
+    /// Effective settings with `profile` (if any) applied over the defaults
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings> {
+        let mut settings = self.defaults.clone();
+        if let Some(name) = profile {
+            let overrides = self
+                .profiles
+                .get(name)
+                .with_context(|| format!("Unknown profile: {}", name))?;
+            settings.merge(overrides);
+        }
+        Ok(settings)
+    }
+}
+
+/// Location of the user's configuration file, if a configuration directory is known
+pub fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(dir.join("dutop").join("config.toml"))
+}
+
+/// Load the settings for `profile` from the configuration file at `path`
+///
+/// A missing file yields empty settings, unless a profile was requested.
+pub fn load_settings(path: &Path, profile: Option<&str>) -> Result<Settings> {
+    match fs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match profile {
+            Some(name) => anyhow::bail!(
+                "Unknown profile: {} (no config file at {})",
+                name,
+                path.display()
+            ),
+            None => Ok(Settings::default()),
+        },
+        _ => ConfigFile::load(path)?.settings(profile),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        exclude = ["node_modules"]
+        threads = 4
+        top = 15
+
+        [profiles.work]
+        exclude = ["**/target"]
+        format = "json"
+        color = false
+        ascii = true
+        top = 25
+    "#;
+
+    #[test]
+    fn test_defaults_without_profile() {
+        let config = ConfigFile::parse(SOURCE).unwrap();
+        let settings = config.settings(None).unwrap();
+
+        assert_eq!(settings.exclude, vec!["node_modules"]);
+        assert_eq!(settings.threads, Some(4));
+        assert_eq!(settings.top, Some(15));
+        assert_eq!(settings.format, None);
+        assert_eq!(settings.color, None);
+        assert_eq!(settings.ascii, None);
+    }
+
+    #[test]
+    fn test_profile_overrides_defaults() {
+        let config = ConfigFile::parse(SOURCE).unwrap();
+        let settings = config.settings(Some("work")).unwrap();
+
+        assert_eq!(settings.exclude, vec!["node_modules", "**/target"]);
+        assert_eq!(settings.threads, Some(4));
+        assert_eq!(settings.top, Some(25));
+        assert_eq!(settings.format.as_deref(), Some("json"));
+        assert_eq!(settings.color, Some(false));
+        assert_eq!(settings.ascii, Some(true));
+    }
+
+    #[test]
+    fn test_theme_settings() {
+        let config = ConfigFile::parse(
+            r##"
+            theme = "solarized"
+
+            [themes.mine]
+            high = "#ff0000"
+
+            [profiles.work]
+            theme = "mine"
+            color_by = "age"
+            "##,
+        )
+        .unwrap();
+
+        let settings = config.settings(None).unwrap();
+        let builtin = |name| Theme::builtin(name).unwrap();
+        assert_eq!(settings.theme(None).unwrap(), builtin("solarized"));
+        assert_eq!(settings.theme(Some("mono")).unwrap(), builtin("mono"));
+        assert_eq!(settings.color_by().unwrap(), ColorBy::Size);
+
+        let settings = config.settings(Some("work")).unwrap();
+        let theme = settings.theme(None).unwrap();
+        assert_eq!(theme.high, theme::Color::Rgb(255, 0, 0));
+        assert_eq!(theme.low, Theme::default().low);
+        assert_eq!(settings.color_by().unwrap(), ColorBy::Age);
+        assert!(settings.theme(Some("neon")).is_err());
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let config = ConfigFile::parse(SOURCE).unwrap();
+        assert!(config.settings(Some("home")).is_err());
+    }
+
+    #[test]
+    fn test_load_settings_missing_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+
+        assert_eq!(load_settings(&path, None).unwrap(), Settings::default());
+        assert!(load_settings(&path, Some("work")).is_err());
+
+        fs::write(&path, "threads = \"many\"").unwrap();
+        assert!(load_settings(&path, None).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Directories ranked by the number of entries they hold
+//!
+//! Millions of tiny files in one directory (a Maildir, a thumbnail cache, a
+//! session store) use up inodes and slow down every tool listing them long
+//! before they show up in a ranking by size. Only the direct children of each
+//! directory are counted, so the report points at the directory holding the
+//! files rather than at all of its parents.
+//!
+//! Each worker thread of the walk counts into a map of its own, and the maps
+//! are merged once the walk is over, so counting takes no lock shared between
+//! threads.
+
+use crate::{analyze_with_hooks, AnalysisConfig, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Direct contents of one directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirCount {
+    /// Files directly in the directory
+    pub files: u64,
+    /// Subdirectories directly in the directory
+    pub dirs: u64,
+    /// Disk usage of those files in bytes
+    pub size: u64,
+    /// Apparent size of those files in bytes
+    pub apparent_size: u64,
+}
+
+impl DirCount {
+    /// Entries in the directory, each using an inode
+    pub fn entries(&self) -> u64 {
+        self.files + self.dirs
+    }
+
+    /// Average apparent size of the files in bytes
+    pub fn average_file_size(&self) -> u64 {
+        self.apparent_size.checked_div(self.files).unwrap_or(0)
+    }
+
+    fn merge(&mut self, other: &DirCount) {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.size += other.size;
+        self.apparent_size += other.apparent_size;
+    }
+}
+
+/// Directories below a root with the most direct entries
+#[derive(Debug, Clone)]
+pub struct CountsReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Every directory holding entries, most entries first
+    pub entries: Vec<(PathBuf, DirCount)>,
+    /// Files counted in the whole analysis
+    pub total_files: u64,
+    /// Directories counted in the whole analysis, the root excluded
+    pub total_dirs: u64,
+}
+
+/// A value per worker thread of the walk, each only locked by its own thread
+pub(crate) struct PerWorker<T> {
+    /// One slot per thread of the pool, and one for threads outside of it
+    slots: Vec<Mutex<T>>,
+}
+
+impl<T> PerWorker<T> {
+    /// Slots for the threads of a walk with `config`, starting from `init`
+    pub(crate) fn new(config: &AnalysisConfig, init: impl Fn() -> T) -> Self {
+        let threads = config
+            .num_threads
+            .unwrap_or_else(rayon::current_num_threads);
+        Self {
+            slots: (0..=threads).map(|_| Mutex::new(init())).collect(),
+        }
+    }
+
+    /// Run `f` on the value of the current thread
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let slot =
+            rayon::current_thread_index().map_or(0, |index| 1 + index % (self.slots.len() - 1));
+        f(&mut self.slots[slot].lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// The values of every thread
+    pub(crate) fn into_values(self) -> impl Iterator<Item = T> {
+        self.slots
+            .into_iter()
+            .map(|slot| slot.into_inner().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Running counts of the direct entries of each directory
+pub(crate) struct ParentTally {
+    counts: PerWorker<HashMap<PathBuf, DirCount>>,
+}
+
+impl ParentTally {
+    pub(crate) fn new(config: &AnalysisConfig) -> Self {
+        Self {
+            counts: PerWorker::new(config, HashMap::new),
+        }
+    }
+
+    fn add(&self, path: &Path, add: impl FnOnce(&mut DirCount)) {
+        let Some(parent) = path.parent() else {
+            return;
+        };
+        self.counts.with(|counts| match counts.get_mut(parent) {
+            Some(count) => add(count),
+            None => add(counts.entry(parent.to_path_buf()).or_default()),
+        });
+    }
+
+    /// Count the file at `path`, using `size` bytes on disk out of `apparent_size`
+    pub(crate) fn add_file(&self, path: &Path, size: u64, apparent_size: u64) {
+        self.add(path, |count| {
+            count.files += 1;
+            count.size += size;
+            count.apparent_size += apparent_size;
+        });
+    }
+
+    /// Count the directory at `path`
+    pub(crate) fn add_dir(&self, path: &Path) {
+        self.add(path, |count| count.dirs += 1);
+    }
+
+    /// Counts of every directory holding entries
+    pub(crate) fn into_counts(self) -> HashMap<PathBuf, DirCount> {
+        let mut values = self.counts.into_values();
+        let mut merged = values.next().unwrap_or_default();
+        for counts in values {
+            for (path, count) in counts {
+                match merged.get_mut(&path) {
+                    Some(total) => total.merge(&count),
+                    None => {
+                        merged.insert(path, count);
+                    }
+                }
+            }
+        }
+        merged
+    }
+}
+
+impl EntryVisitor for ParentTally {
+    fn visit_file(&self, path: &Path, metadata: &std::fs::Metadata, size: u64) {
+        self.add_file(path, size, metadata.len());
+    }
+
+    fn visit_dir(&self, path: &Path) {
+        self.add_dir(path);
+    }
+}
+
+/// Analyze `path`, counting the files and directories directly in each directory
+pub fn count_entries(path: &Path, config: &AnalysisConfig) -> Result<CountsReport> {
+    let tally = ParentTally::new(config);
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+
+    let mut entries: Vec<(PathBuf, DirCount)> = tally.into_counts().into_iter().collect();
+    entries.sort_by(|a, b| {
+        b.1.entries()
+            .cmp(&a.1.entries())
+            .then_with(|| b.1.size.cmp(&a.1.size))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    Ok(CountsReport {
+        root_path: result.root_path,
+        total_files: entries.iter().map(|(_, count)| count.files).sum(),
+        total_dirs: entries.iter().map(|(_, count)| count.dirs).sum(),
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_count_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("mail/cur")).unwrap();
+        for i in 0..5 {
+            fs::write(root.join(format!("mail/cur/{}", i)), vec![1u8; 2000]).unwrap();
+        }
+        fs::create_dir(root.join("mail/new")).unwrap();
+        fs::write(root.join("big"), vec![1u8; 100_000]).unwrap();
+
+        let report = count_entries(&root, &AnalysisConfig::default()).unwrap();
+        assert_eq!(report.total_files, 6);
+        assert_eq!(report.total_dirs, 3);
+
+        let (path, cur) = &report.entries[0];
+        assert_eq!(path, &root.join("mail/cur"));
+        assert_eq!(cur.files, 5);
+        assert_eq!(cur.average_file_size(), 2000);
+
+        let mail = report.entries.iter().find(|(p, _)| p == &root.join("mail"));
+        let mail = &mail.unwrap().1;
+        assert_eq!((mail.files, mail.dirs, mail.average_file_size()), (0, 2, 0));
+        // Empty directories hold no entries and are left out
+        assert!(!report
+            .entries
+            .iter()
+            .any(|(p, _)| p == &root.join("mail/new")));
+    }
+}
@@ -0,0 +1,571 @@
+//! Recurring scans for `dutop daemon`
+//!
+//! The daemon reads its roots and what to do with every scan from a TOML
+//! file: snapshots are saved and rotated, sizes are recorded in the history
+//! database and old scans pruned from it, and the latest results feed the
+//! Prometheus metrics and webhook alerts. The JSON API of `dutop serve --http`
+//! can run alongside. Every section is optional.
+//!
+//! ```toml
+//! roots = ["/srv", "/home"]
+//! interval = "6h"
+//! top = 20
+//! exclude = ["*.tmp"]
+//! one_file_system = true
+//!
+//! [snapshots]
+//! dir = "/var/lib/dutop/snapshots"
+//! keep = 28
+//!
+//! [history]
+//! path = "/var/lib/dutop/history.db"
+//! depth = 2
+//! max_age = "1y"
+//!
+//! [prometheus]
+//! listen = ":9184"
+//!
+//! [http]
+//! listen = "127.0.0.1:8080"
+//! cache_ttl = "5m"
+//!
+//! [alert]
+//! webhook = "https://hooks.slack.com/services/..."
+//! format = "slack"
+//! threshold = "500G"
+//! percent_threshold = 90
+//! ```
+
+use crate::format::{parse_duration, parse_size};
+use crate::history::History;
+use crate::metrics::Metrics;
+use crate::output::OutputFile;
+use crate::threshold::Thresholds;
+use crate::{analyze_disk_usage, AnalysisConfig, AnalysisResult, CancellationToken};
+use anyhow::{Context, Result};
+use serde::{de, Deserialize, Deserializer};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Time between scans when neither the command line nor the file sets one
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+/// How often the cancellation token is checked between scans
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Contents of a daemon configuration file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Directories scanned on every run
+    pub roots: Vec<PathBuf>,
+    /// Time between the starts of two runs
+    #[serde(default, deserialize_with = "duration")]
+    pub interval: Option<Duration>,
+    /// Number of largest entries kept for metrics and alerts
+    #[serde(default = "default_top")]
+    pub top: usize,
+    /// Exclude patterns, as given to `--exclude`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Do not cross filesystem boundaries
+    #[serde(default)]
+    pub one_file_system: bool,
+    /// Follow symbolic links
+    #[serde(default)]
+    pub follow_links: bool,
+    /// Number of threads to use for each scan
+    pub threads: Option<usize>,
+    /// Where snapshots of every scan are saved
+    pub snapshots: Option<SnapshotSettings>,
+    /// History database recording every scan
+    pub history: Option<HistorySettings>,
+    /// Prometheus metrics server
+    pub prometheus: Option<PrometheusSettings>,
+    /// JSON API server
+    pub http: Option<HttpSettings>,
+    /// Webhook alerts
+    pub alert: Option<AlertSettings>,
+}
+
+/// The `[snapshots]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotSettings {
+    /// Directory holding one subdirectory of snapshots per root
+    pub dir: PathBuf,
+    /// Number of snapshots kept per root, the oldest being deleted first
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+/// The `[history]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistorySettings {
+    /// Database file (default: the one of `dutop history`)
+    pub path: Option<PathBuf>,
+    /// Levels of directories below each root that are recorded
+    #[serde(default = "default_depth")]
+    pub depth: usize,
+    /// Scans older than this are deleted from the database
+    #[serde(default, deserialize_with = "duration")]
+    pub max_age: Option<Duration>,
+}
+
+/// The `[prometheus]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusSettings {
+    /// Address serving `/metrics`, e.g. `:9184`
+    pub listen: String,
+}
+
+/// The `[http]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpSettings {
+    /// Address serving the JSON API, e.g. `127.0.0.1:8080`
+    pub listen: String,
+    /// How long results are reused for identical requests
+    #[serde(default, deserialize_with = "duration")]
+    pub cache_ttl: Option<Duration>,
+}
+
+/// The `[alert]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertSettings {
+    /// URL receiving the alerts with a POST request
+    pub webhook: String,
+    /// Body of the request, `json` (default) or `slack`
+    pub format: Option<String>,
+    /// Alert when a root or one of its top entries is over this size
+    #[serde(default, deserialize_with = "size")]
+    pub threshold: Option<u64>,
+    /// Alert when a root or one of its top entries takes more than this
+    /// percentage of its filesystem
+    pub percent_threshold: Option<f64>,
+}
+
+impl AlertSettings {
+    /// The limits triggering an alert
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            max_size: self.threshold,
+            max_percent: self.percent_threshold,
+        }
+    }
+}
+
+fn default_top() -> usize {
+    20
+}
+
+fn default_keep() -> usize {
+    10
+}
+
+fn default_depth() -> usize {
+    2
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| parse_duration(&text).map_err(de::Error::custom))
+        .transpose()
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| parse_size(&text).map_err(de::Error::custom))
+        .transpose()
+}
+
+impl DaemonConfig {
+    /// Parse a daemon configuration from its TOML source
+    pub fn parse(source: &str) -> Result<Self> {
+        let config: Self = toml::from_str(source)?;
+        if config.roots.is_empty() {
+            anyhow::bail!("No roots to scan");
+        }
+        if let Some(alert) = &config.alert {
+            if alert.thresholds().is_empty() {
+                anyhow::bail!("The alert section needs a threshold or percent_threshold");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read and parse the daemon configuration file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("Invalid config file: {}", path.display()))
+    }
+
+    /// Options of every scan
+    pub fn analysis_config(&self) -> AnalysisConfig {
+        AnalysisConfig {
+            exclude_patterns: self.exclude.clone(),
+            one_file_system: self.one_file_system,
+            follow_links: self.follow_links,
+            num_threads: self.threads,
+            // Snapshots and history want the directories below the top entries
+            build_tree: self.snapshots.is_some() || self.history.is_some(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Scans the configured roots and hands every result to the enabled subsystems
+struct Daemon {
+    config: DaemonConfig,
+    roots: Vec<PathBuf>,
+    analysis: AnalysisConfig,
+    history: Option<History>,
+    metrics: Option<Arc<Metrics>>,
+    #[cfg(feature = "webhook")]
+    alerter: Option<crate::alert::Alerter>,
+}
+
+/// Run the daemon described by `config`, scanning once per `interval` until
+/// `cancel` is triggered
+///
+/// Servers are started before the first scan; failing to bind one of them,
+/// to access a root or to open the history database is an error, while a
+/// failed scan or snapshot is logged and retried on the next run.
+pub fn run(config: DaemonConfig, interval: Duration, cancel: &CancellationToken) -> Result<()> {
+    let mut daemon = Daemon::start(config)?;
+    while !cancel.is_cancelled() {
+        let started = Instant::now();
+        for root in daemon.roots.clone() {
+            daemon.scan(&root);
+        }
+        log::info!(
+            "Scanned {} roots in {:?}",
+            daemon.roots.len(),
+            started.elapsed()
+        );
+
+        let next = started + interval;
+        while !cancel.is_cancelled() && Instant::now() < next {
+            thread::sleep(POLL_INTERVAL.min(next.saturating_duration_since(Instant::now())));
+        }
+    }
+    Ok(())
+}
+
+impl Daemon {
+    fn start(config: DaemonConfig) -> Result<Self> {
+        let roots = config
+            .roots
+            .iter()
+            .map(|root| {
+                root.canonicalize()
+                    .with_context(|| format!("Failed to access path: {}", root.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        #[cfg(feature = "webhook")]
+        let alerter = match &config.alert {
+            Some(alert) => {
+                let webhook = crate::alert::Webhook {
+                    url: alert.webhook.clone(),
+                    format: alert.format.as_deref().unwrap_or("json").parse()?,
+                };
+                Some(crate::alert::Alerter::new(webhook, alert.thresholds()))
+            }
+            None => None,
+        };
+        #[cfg(not(feature = "webhook"))]
+        if config.alert.is_some() {
+            anyhow::bail!("Alerts need dutop to be built with the webhook feature");
+        }
+
+        let history = match &config.history {
+            Some(settings) => {
+                let path = settings
+                    .path
+                    .clone()
+                    .or_else(crate::history::default_path)
+                    .context("Cannot determine where to store the history database")?;
+                Some(History::open(&path)?)
+            }
+            None => None,
+        };
+
+        // Bind everything first so a taken port fails before anything runs
+        let prometheus = match &config.prometheus {
+            Some(settings) => Some(bind(&settings.listen)?),
+            None => None,
+        };
+        let api = match &config.http {
+            Some(settings) => Some(bind(&settings.listen)?),
+            None => None,
+        };
+        let analysis = config.analysis_config();
+
+        let metrics = prometheus.map(|(addr, listener)| {
+            let metrics = Arc::new(Metrics::new());
+            let served = Arc::clone(&metrics);
+            log::info!("Serving metrics on http://{}/metrics", addr);
+            spawn_server(listener, move |request| {
+                crate::metrics::handle(&served, request)
+            });
+            metrics
+        });
+        if let (Some((addr, listener)), Some(settings)) = (api, &config.http) {
+            let cache_ttl = settings.cache_ttl.unwrap_or(Duration::from_secs(5 * 60));
+            let api = crate::api::Api::new(
+                roots.clone(),
+                AnalysisConfig {
+                    build_tree: false,
+                    ..analysis.clone()
+                },
+                cache_ttl,
+            );
+            log::info!("Serving the JSON API on http://{}/", addr);
+            spawn_server(listener, move |request| api.handle(request));
+        }
+
+        Ok(Self {
+            config,
+            roots,
+            analysis,
+            history,
+            metrics,
+            #[cfg(feature = "webhook")]
+            alerter,
+        })
+    }
+
+    /// Scan `root` and pass the result on
+    fn scan(&mut self, root: &Path) {
+        let started = Instant::now();
+        let result = analyze_disk_usage(root, &self.analysis, self.config.top);
+        if let Some(metrics) = &self.metrics {
+            metrics.record(root, result.as_ref(), started.elapsed());
+        }
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                log::warn!("Failed to scan {}: {:#}", root.display(), e);
+                return;
+            }
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        if let Some(settings) = &self.config.snapshots {
+            let dir = settings.dir.join(snapshot_dir_name(root));
+            let saved = save_snapshot(&dir, &result, timestamp)
+                .and_then(|()| rotate_snapshots(&dir, settings.keep));
+            if let Err(e) = saved {
+                log::warn!("Failed to save snapshot of {}: {:#}", root.display(), e);
+            }
+        }
+        if let (Some(history), Some(settings)) = (&mut self.history, &self.config.history) {
+            let recorded = history
+                .record(&result, timestamp, settings.depth)
+                .and_then(|_| match settings.max_age {
+                    Some(age) => history.prune(timestamp.saturating_sub(age.as_secs())),
+                    None => Ok(0),
+                });
+            if let Err(e) = recorded {
+                log::warn!(
+                    "Failed to record {} in the history: {:#}",
+                    root.display(),
+                    e
+                );
+            }
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(alerter) = &mut self.alerter {
+            alerter.check(&result);
+        }
+    }
+}
+
+fn bind(addr: &str) -> Result<(std::net::SocketAddr, std::net::TcpListener)> {
+    let addr = crate::http::parse_listen_addr(addr)?;
+    Ok((addr, crate::http::bind(addr)?))
+}
+
+fn spawn_server<H>(listener: std::net::TcpListener, handler: H)
+where
+    H: Fn(&crate::http::Request) -> crate::http::Response + Send + Sync + 'static,
+{
+    thread::spawn(move || {
+        if let Err(e) = crate::http::serve(listener, handler) {
+            log::error!("Server stopped: {:#}", e);
+        }
+    });
+}
+
+/// Name of the directory holding the snapshots of `root`, e.g. `srv_data`
+/// for `/srv/data`
+fn snapshot_dir_name(root: &Path) -> String {
+    let name: String = root
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.trim_matches('_') {
+        "" => "root".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Save `result` as `TIMESTAMP.json` in `dir`, readable with `dutop load`
+fn save_snapshot(dir: &Path, result: &AnalysisResult, timestamp: u64) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    let mut file = OutputFile::create(&dir.join(format!("{}.json", timestamp)))?;
+    crate::snapshot::write_snapshot(&mut file, result)?;
+    file.commit()
+}
+
+/// Delete all but the `keep` latest snapshots in `dir`
+fn rotate_snapshots(dir: &Path, keep: usize) -> Result<()> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let timestamp = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|stem| stem.parse::<u64>().ok());
+        if let Some(timestamp) = timestamp {
+            snapshots.push((timestamp, path));
+        }
+    }
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(keep);
+    for (_, path) in &snapshots[..excess] {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to delete old snapshot: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// A systemd service unit running `exe` with `args`
+pub fn systemd_unit(exe: &Path, args: &[String]) -> String {
+    let quote = |arg: &str| {
+        if arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+            format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+        } else {
+            arg.to_string()
+        }
+    };
+    let command: Vec<String> = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .map(|arg| quote(&arg))
+        .collect();
+    format!(
+        "[Unit]
+Description=dutop disk usage daemon
+Wants=network-online.target
+After=local-fs.target network-online.target
+
+[Service]
+Type=simple
+ExecStart={}
+Restart=on-failure
+RestartSec=30
+Nice=10
+IOSchedulingClass=idle
+
+[Install]
+WantedBy=multi-user.target
+",
+        command.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_config() {
+        let config = DaemonConfig::parse(
+            r#"
+            roots = ["/srv"]
+            interval = "6h"
+
+            [history]
+            max_age = "1y"
+
+            [alert]
+            webhook = "http://localhost/hook"
+            threshold = "1.5G"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.interval, Some(Duration::from_secs(6 * 3600)));
+        assert_eq!(config.top, 20);
+        assert!(config.analysis_config().build_tree);
+        let history = config.history.unwrap();
+        assert_eq!((history.path, history.depth), (None, 2));
+        assert_eq!(history.max_age, Some(Duration::from_secs(365 * 86400)));
+        assert_eq!(config.alert.unwrap().threshold, Some(1_610_612_736));
+
+        assert!(DaemonConfig::parse("roots = []").is_err());
+        assert!(DaemonConfig::parse("roots = [\"/\"]\nintervl = \"1h\"").is_err());
+        assert!(DaemonConfig::parse("roots = [\"/\"]\n[alert]\nwebhook = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_rotate_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir
+            .path()
+            .join(snapshot_dir_name(Path::new("/srv/data")));
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv/data"),
+            ..Default::default()
+        };
+        for timestamp in [100, 300, 200, 1000] {
+            save_snapshot(&dir, &result, timestamp).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "kept").unwrap();
+        rotate_snapshots(&dir, 2).unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["1000.json", "300.json", "notes.txt"]);
+        assert!(dir.ends_with("srv_data"));
+        assert_eq!(snapshot_dir_name(Path::new("/")), "root");
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(
+            Path::new("/usr/local/bin/dutop"),
+            &[
+                "daemon".into(),
+                "--daemon-config".into(),
+                "/etc/dutop/my daemon.toml".into(),
+            ],
+        );
+        assert!(unit.contains(
+            "\nExecStart=/usr/local/bin/dutop daemon --daemon-config \"/etc/dutop/my daemon.toml\"\n"
+        ));
+        assert!(unit.contains("\n[Install]\nWantedBy=multi-user.target\n"));
+    }
+}
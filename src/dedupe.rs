@@ -0,0 +1,201 @@
+//! Duplicate file detection
+//!
+//! Files are first grouped by size, which rules out most of them without any
+//! I/O. Remaining candidates are compared by a BLAKE3 hash of their first
+//! block, and only files that still collide are hashed in full. Hashing runs
+//! in parallel on the analysis thread pool. Hard links are never reported as
+//! duplicates of each other since the walker counts each inode once.
+
+use crate::{analyze_disk_usage, build_thread_pool, AnalysisConfig};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Number of leading bytes hashed to weed out files that differ early
+const PREFIX_LEN: u64 = 16 * 1024;
+
+/// Files with identical contents
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    /// Size of each file in bytes
+    pub size: u64,
+    /// Paths of the identical files, sorted
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Bytes that could be reclaimed by keeping a single copy
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Find groups of identical files of at least `min_size` bytes below `path`
+///
+/// Groups are sorted by the space they waste, largest first.
+pub fn find_duplicates(
+    path: &Path,
+    config: &AnalysisConfig,
+    min_size: u64,
+) -> Result<Vec<DuplicateGroup>> {
+    let scan_config = AnalysisConfig {
+        build_tree: true,
+        tree_files: true,
+        // Only real files can be hashed
+        expand_archives: false,
+        ..config.clone()
+    };
+    let result = analyze_disk_usage(path, &scan_config, 0)?;
+    let Some(tree) = result.tree else {
+        return Ok(Vec::new());
+    };
+
+    // Empty files are all identical and waste nothing
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for node in tree.iter() {
+        for file in &node.files {
+            if file.apparent_size >= min_size.max(1) {
+                by_size
+                    .entry(file.apparent_size)
+                    .or_default()
+                    .push(node.path.join(&file.name));
+            }
+        }
+    }
+    let candidates: Vec<(u64, PathBuf)> = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+        .collect();
+    log::info!("Hashing {} files with duplicate sizes", candidates.len());
+
+    let pool = build_thread_pool(config)?;
+    let hash_all = || {
+        let by_prefix = regroup(candidates, |path| hash_file(path, Some(PREFIX_LEN)));
+        // Files no longer than the prefix were hashed completely already
+        let (complete, partial): (Vec<_>, Vec<_>) = by_prefix
+            .into_iter()
+            .partition(|group| group.size <= PREFIX_LEN);
+        let partial = partial
+            .into_iter()
+            .flat_map(|group| {
+                let size = group.size;
+                group.paths.into_iter().map(move |path| (size, path))
+            })
+            .collect();
+        let mut groups = complete;
+        groups.extend(regroup(partial, |path| hash_file(path, None)));
+        groups
+    };
+    let mut groups = match &pool {
+        Some(pool) => pool.install(hash_all),
+        None => hash_all(),
+    };
+
+    for group in &mut groups {
+        group.paths.sort();
+    }
+    groups.sort_by(|a, b| {
+        b.wasted()
+            .cmp(&a.wasted())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+    Ok(groups)
+}
+
+/// Split files of equal size into groups with equal keys, dropping singletons
+fn regroup<K, F>(files: Vec<(u64, PathBuf)>, key: F) -> Vec<DuplicateGroup>
+where
+    K: Hash + Eq + Send,
+    F: Fn(&Path) -> io::Result<K> + Sync,
+{
+    let keyed: Vec<((u64, K), PathBuf)> = files
+        .into_par_iter()
+        .filter_map(|(size, path)| match key(&path) {
+            Ok(key) => Some(((size, key), path)),
+            Err(e) => {
+                log::debug!("Error hashing {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+
+    let mut groups: HashMap<(u64, K), Vec<PathBuf>> = HashMap::new();
+    for (key, path) in keyed {
+        groups.entry(key).or_default().push(path);
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, _), paths)| DuplicateGroup { size, paths })
+        .collect()
+}
+
+/// Hash the contents of a file, or only its first `limit` bytes
+fn hash_file(path: &Path, limit: Option<u64>) -> io::Result<blake3::Hash> {
+    let file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    match limit {
+        Some(limit) => io::copy(&mut file.take(limit), &mut hasher)?,
+        None => io::copy(&mut { file }, &mut hasher)?,
+    };
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+
+        // Same size and prefix, different tail
+        let mut large = vec![7u8; 64 * 1024];
+        fs::write(root.join("large1"), &large).unwrap();
+        fs::write(root.join("a/large2"), &large).unwrap();
+        large[60_000] = 8;
+        fs::write(root.join("a/b/large3"), &large).unwrap();
+
+        fs::write(root.join("small1"), "same").unwrap();
+        fs::write(root.join("a/b/small2"), "same").unwrap();
+        fs::write(root.join("a/small3"), "diff").unwrap();
+        fs::write(root.join("empty1"), "").unwrap();
+        fs::write(root.join("empty2"), "").unwrap();
+
+        let groups = find_duplicates(root, &AnalysisConfig::default(), 0).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].paths,
+            vec![root.join("a/large2"), root.join("large1")]
+        );
+        assert_eq!(groups[0].wasted(), 64 * 1024);
+        assert_eq!(
+            groups[1].paths,
+            vec![root.join("a/b/small2"), root.join("small1")]
+        );
+
+        let groups = find_duplicates(root, &AnalysisConfig::default(), 1024).unwrap();
+        assert_eq!(groups.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_are_not_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("file"), "content").unwrap();
+        fs::hard_link(temp_dir.path().join("file"), temp_dir.path().join("link")).unwrap();
+
+        let groups = find_duplicates(temp_dir.path(), &AnalysisConfig::default(), 0).unwrap();
+        assert!(groups.is_empty());
+    }
+}
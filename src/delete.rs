@@ -0,0 +1,73 @@
+//! Deleting files and directories found during analysis
+//!
+//! Symbolic links are removed themselves and never followed, so deleting a
+//! directory cannot reach files outside of it.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What happens to a deleted entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Removal {
+    /// Remove it permanently
+    #[default]
+    Delete,
+    /// Move it to the system trash (recycle bin)
+    Trash,
+}
+
+/// Delete the file, symlink or directory tree at `path`
+pub fn remove(path: &Path, removal: Removal) -> io::Result<()> {
+    if removal == Removal::Trash {
+        return trash::delete(path).map_err(io::Error::other);
+    }
+
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        // Does not follow symlinks found inside the tree
+        fs::remove_dir_all(path)
+    } else if metadata.is_symlink() && cfg!(windows) {
+        // Directory symlinks and junctions are removed as directories on Windows
+        fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_remove_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let tree = temp_dir.path().join("tree");
+        fs::create_dir_all(tree.join("a/b")).unwrap();
+        fs::write(tree.join("a/b/file"), b"data").unwrap();
+        fs::write(temp_dir.path().join("single"), b"data").unwrap();
+
+        remove(&tree, Removal::Delete).unwrap();
+        remove(&temp_dir.path().join("single"), Removal::Delete).unwrap();
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_does_not_follow_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("keep"), b"data").unwrap();
+        let tree = temp_dir.path().join("tree");
+        fs::create_dir(&tree).unwrap();
+        std::os::unix::fs::symlink(&outside, tree.join("link")).unwrap();
+        std::os::unix::fs::symlink(&outside, temp_dir.path().join("top-link")).unwrap();
+
+        remove(&tree, Removal::Delete).unwrap();
+        remove(&temp_dir.path().join("top-link"), Removal::Delete).unwrap();
+        assert!(!tree.exists());
+        assert!(outside.join("keep").exists());
+    }
+}
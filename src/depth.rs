@@ -0,0 +1,231 @@
+//! Tree depth, path lengths and directory fan-out
+//!
+//! Deep trees, long paths and directories with hundreds of thousands of direct
+//! entries are what breaks backup and sync tools: Windows and many archive
+//! formats cap path lengths, and listing a huge directory is slow on most
+//! filesystems. Depth counts the components below the root, so its direct
+//! children are at depth 1; path lengths are in bytes of the full path.
+
+use crate::counts::{ParentTally, PerWorker};
+use crate::top::TopN;
+use crate::{analyze_with_hooks, AnalysisConfig, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Direct entries from which a directory is reported for its fan-out
+pub const DEFAULT_FAN_OUT: u64 = 100_000;
+
+/// A file or directory with its depth and path length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathStat {
+    pub path: PathBuf,
+    /// Components below the root
+    pub depth: usize,
+    /// Length of the full path in bytes
+    pub length: usize,
+}
+
+/// Depth and fan-out statistics of the tree below a root
+#[derive(Debug, Clone)]
+pub struct DepthReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Files counted
+    pub file_count: u64,
+    /// Directories counted, the root excluded
+    pub dir_count: u64,
+    /// Depth of the deepest file or directory
+    pub max_depth: usize,
+    /// Average depth of the files
+    pub average_depth: f64,
+    /// Deepest files and directories, deepest first
+    pub deepest: Vec<PathStat>,
+    /// Files and directories with the longest paths, longest first
+    pub longest: Vec<PathStat>,
+    /// Most direct entries in a single directory
+    pub max_fan_out: u64,
+    /// Directories with at least `fan_out_threshold` direct entries, most first
+    pub fan_out: Vec<(PathBuf, u64)>,
+    /// Direct entries from which a directory is listed in `fan_out`
+    pub fan_out_threshold: u64,
+}
+
+/// Statistics of the part of the walk done by one thread
+struct DepthState {
+    file_count: u64,
+    dir_count: u64,
+    max_depth: usize,
+    file_depths: u64,
+    deepest: TopN<PathStat>,
+    longest: TopN<PathStat>,
+}
+
+impl DepthState {
+    fn new(top_n: usize) -> Self {
+        Self {
+            file_count: 0,
+            dir_count: 0,
+            max_depth: 0,
+            file_depths: 0,
+            deepest: TopN::new(top_n),
+            longest: TopN::new(top_n),
+        }
+    }
+
+    fn merge(mut self, other: DepthState) -> Self {
+        self.file_count += other.file_count;
+        self.dir_count += other.dir_count;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.file_depths += other.file_depths;
+        self.deepest = self.deepest.merge(other.deepest);
+        self.longest = self.longest.merge(other.longest);
+        self
+    }
+}
+
+/// Running statistics of the walk, per worker thread, and the direct entries
+/// of each directory
+struct DepthTally<'a> {
+    root: &'a Path,
+    states: PerWorker<DepthState>,
+    fan_out: ParentTally,
+}
+
+impl<'a> DepthTally<'a> {
+    fn new(root: &'a Path, config: &AnalysisConfig, top_n: usize) -> Self {
+        Self {
+            root,
+            states: PerWorker::new(config, || DepthState::new(top_n)),
+            fan_out: ParentTally::new(config),
+        }
+    }
+
+    fn add(&self, path: &Path, is_file: bool) {
+        let depth = path
+            .strip_prefix(self.root)
+            .map_or(0, |relative| relative.components().count());
+        let length = path.as_os_str().len();
+        self.states.with(|state| {
+            if is_file {
+                state.file_count += 1;
+                state.file_depths += depth as u64;
+            } else {
+                state.dir_count += 1;
+            }
+            state.max_depth = state.max_depth.max(depth);
+            // Most paths are neither among the deepest nor the longest, and
+            // are not copied
+            let stat = || PathStat {
+                path: path.to_path_buf(),
+                depth,
+                length,
+            };
+            if state.deepest.accepts(depth as u64) {
+                state.deepest.push(depth as u64, stat());
+            }
+            if state.longest.accepts(length as u64) {
+                state.longest.push(length as u64, stat());
+            }
+        });
+    }
+}
+
+impl EntryVisitor for DepthTally<'_> {
+    fn visit_file(&self, path: &Path, metadata: &std::fs::Metadata, size: u64) {
+        self.add(path, true);
+        self.fan_out.add_file(path, size, metadata.len());
+    }
+
+    fn visit_dir(&self, path: &Path) {
+        self.add(path, false);
+        self.fan_out.add_dir(path);
+    }
+}
+
+/// Analyze `path`, keeping the `top_n` deepest and longest paths and the
+/// directories with at least `fan_out_threshold` direct entries
+pub fn analyze_depth(
+    path: &Path,
+    config: &AnalysisConfig,
+    top_n: usize,
+    fan_out_threshold: u64,
+) -> Result<DepthReport> {
+    let tally = DepthTally::new(path, config, top_n);
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+    let state = tally
+        .states
+        .into_values()
+        .fold(DepthState::new(top_n), DepthState::merge);
+    let children = tally.fan_out.into_counts();
+
+    let max_fan_out = children
+        .values()
+        .map(|count| count.entries())
+        .max()
+        .unwrap_or(0);
+    let mut fan_out: Vec<(PathBuf, u64)> = children
+        .into_iter()
+        .map(|(path, count)| (path, count.entries()))
+        .filter(|&(_, entries)| entries >= fan_out_threshold)
+        .collect();
+    fan_out.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    Ok(DepthReport {
+        root_path: result.root_path,
+        file_count: state.file_count,
+        dir_count: state.dir_count,
+        max_depth: state.max_depth,
+        average_depth: if state.file_count > 0 {
+            state.file_depths as f64 / state.file_count as f64
+        } else {
+            0.0
+        },
+        deepest: state.deepest.into_sorted_vec(),
+        longest: state.longest.into_sorted_vec(),
+        max_fan_out,
+        fan_out,
+        fan_out_threshold,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_analyze_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::write(root.join("a/b/c/deep"), b"data").unwrap();
+        fs::write(root.join("a/a-file-with-a-rather-long-name"), b"data").unwrap();
+        fs::create_dir(root.join("wide")).unwrap();
+        for i in 0..4 {
+            fs::write(root.join(format!("wide/{}", i)), b"data").unwrap();
+        }
+
+        let report = analyze_depth(&root, &AnalysisConfig::default(), 2, 4).unwrap();
+        assert_eq!((report.file_count, report.dir_count), (6, 4));
+        assert_eq!(report.max_depth, 4);
+        // Depths 4, 2 and four times 2
+        assert!((report.average_depth - 14.0 / 6.0).abs() < 1e-9);
+        assert_eq!(report.deepest.len(), 2);
+        assert_eq!(report.deepest[0].path, root.join("a/b/c/deep"));
+        assert_eq!(
+            report.longest[0].path,
+            root.join("a/a-file-with-a-rather-long-name")
+        );
+        assert_eq!(report.max_fan_out, 4);
+        assert_eq!(report.fan_out, [(root.join("wide"), 4)]);
+    }
+}
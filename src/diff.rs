@@ -0,0 +1,177 @@
+//! Comparing two analyses of the same directory
+
+use crate::AnalysisResult;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How a directory changed between two analyses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Only present in the newer analysis
+    Added,
+    /// Only present in the older analysis
+    Removed,
+    /// Present in both with a different size
+    Changed,
+}
+
+/// Size change of a single directory
+#[derive(Debug, Clone)]
+pub struct DirectoryDelta {
+    /// Path relative to the analyzed root
+    pub path: PathBuf,
+    /// Size in the older analysis (0 when added)
+    pub old_size: u64,
+    /// Size in the newer analysis (0 when removed)
+    pub new_size: u64,
+    pub kind: ChangeKind,
+}
+
+impl DirectoryDelta {
+    /// Growth in bytes, negative when the directory shrank
+    pub fn delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+}
+
+/// Compare two analyses, returning changed directories sorted by absolute growth
+///
+/// Directories are matched by their path relative to each analysis root, so a
+/// snapshot can be compared against a scan of the same tree mounted elsewhere.
+/// When both analyses include the full tree every directory is compared;
+/// otherwise only the top-level entries are. Added or removed directories are
+/// reported once, without their descendants.
+pub fn diff_results(old: &AnalysisResult, new: &AnalysisResult) -> Vec<DirectoryDelta> {
+    let use_tree = old.tree.is_some() && new.tree.is_some();
+    let old_sizes = relative_sizes(old, use_tree);
+    let new_sizes = relative_sizes(new, use_tree);
+
+    let mut deltas = Vec::new();
+    for (path, &new_size) in &new_sizes {
+        match old_sizes.get(path) {
+            Some(&old_size) if old_size != new_size => deltas.push(DirectoryDelta {
+                path: path.clone(),
+                old_size,
+                new_size,
+                kind: ChangeKind::Changed,
+            }),
+            Some(_) => {}
+            None if parent_present(path, &old_sizes) => deltas.push(DirectoryDelta {
+                path: path.clone(),
+                old_size: 0,
+                new_size,
+                kind: ChangeKind::Added,
+            }),
+            None => {}
+        }
+    }
+    for (path, &old_size) in &old_sizes {
+        if !new_sizes.contains_key(path) && parent_present(path, &new_sizes) {
+            deltas.push(DirectoryDelta {
+                path: path.clone(),
+                old_size,
+                new_size: 0,
+                kind: ChangeKind::Removed,
+            });
+        }
+    }
+
+    deltas.sort_by(|a, b| {
+        b.delta()
+            .unsigned_abs()
+            .cmp(&a.delta().unsigned_abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    deltas
+}
+
+/// Sizes of every directory below the root, keyed by path relative to the root
+fn relative_sizes(result: &AnalysisResult, use_tree: bool) -> HashMap<PathBuf, u64> {
+    let relative = |path: &Path| {
+        path.strip_prefix(&result.root_path)
+            .unwrap_or(path)
+            .to_path_buf()
+    };
+
+    match result.tree.as_ref().filter(|_| use_tree) {
+        Some(tree) => tree
+            .iter()
+            .skip(1)
+            .map(|node| (relative(&node.path), node.size))
+            .collect(),
+        None => result
+            .top_directories
+            .iter()
+            .map(|entry| (relative(&entry.path), entry.size))
+            .collect(),
+    }
+}
+
+/// Whether the parent of `path` exists in `sizes` (the root always does)
+fn parent_present(path: &Path, sizes: &HashMap<PathBuf, u64>) -> bool {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sizes.contains_key(parent),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_disk_usage, AnalysisConfig};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn scan(path: &Path) -> AnalysisResult {
+        let config = AnalysisConfig {
+            build_tree: true,
+            ..Default::default()
+        };
+        analyze_disk_usage(path, &config, 10).unwrap()
+    }
+
+    #[test]
+    fn test_diff_reports_growth_and_new_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("logs/old")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("gone/inner")).unwrap();
+        fs::write(temp_dir.path().join("logs/a.log"), vec![0u8; 4096]).unwrap();
+        fs::write(temp_dir.path().join("gone/inner/x"), vec![0u8; 4096]).unwrap();
+        let before = scan(temp_dir.path());
+
+        fs::write(temp_dir.path().join("logs/old/b.log"), vec![0u8; 64 * 1024]).unwrap();
+        fs::remove_dir_all(temp_dir.path().join("gone")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("cache/deep")).unwrap();
+        fs::write(temp_dir.path().join("cache/deep/c"), vec![0u8; 8192]).unwrap();
+        let after = scan(temp_dir.path());
+
+        let deltas = diff_results(&before, &after);
+        let find = |path: &str| deltas.iter().find(|d| d.path == Path::new(path));
+
+        let logs = find("logs").unwrap();
+        assert_eq!(logs.kind, ChangeKind::Changed);
+        assert!(logs.delta() > 0);
+        assert_eq!(deltas[0].path, Path::new("logs"));
+
+        assert_eq!(find("cache").unwrap().kind, ChangeKind::Added);
+        assert_eq!(find("gone").unwrap().kind, ChangeKind::Removed);
+        assert!(find("gone").unwrap().delta() < 0);
+        // Descendants of added and removed directories are not repeated
+        assert!(find("cache/deep").is_none());
+        assert!(find("gone/inner").is_none());
+    }
+
+    #[test]
+    fn test_diff_without_tree_uses_top_level_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("data")).unwrap();
+        let before = analyze_disk_usage(temp_dir.path(), &AnalysisConfig::default(), 10).unwrap();
+        fs::write(temp_dir.path().join("data/file"), vec![0u8; 4096]).unwrap();
+        let after = scan(temp_dir.path());
+
+        let deltas = diff_results(&before, &after);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].path, Path::new("data"));
+        assert_eq!(deltas[0].old_size, 0);
+    }
+}
@@ -0,0 +1,479 @@
+//! Disk usage of Docker images, containers, volumes and build cache
+//!
+//! The numbers come from the daemon's `/system/df` endpoint (the data behind
+//! `docker system df -v`), requested over the Docker socket with a minimal
+//! HTTP/1.0 client. The daemon is found through `DOCKER_HOST` (`unix://`,
+//! `tcp://` or `npipe://`) and otherwise at the platform's default socket.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+/// Default daemon socket on Unix
+#[cfg(unix)]
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+/// Default daemon pipe on Windows
+#[cfg(windows)]
+const DEFAULT_PIPE: &str = r"\\.\pipe\docker_engine";
+
+/// Disk usage reported by the Docker daemon, each list sorted by size, largest first
+#[derive(Debug, Clone, Default)]
+pub struct DockerUsage {
+    /// Total size of all image layers, counting shared layers once
+    pub layers_size: u64,
+    /// Images with their layer sizes
+    pub images: Vec<Image>,
+    /// Containers with the size of their writable layer
+    pub containers: Vec<Container>,
+    /// Named and anonymous volumes
+    pub volumes: Vec<Volume>,
+    /// Total size of the build cache
+    pub build_cache_size: u64,
+}
+
+impl DockerUsage {
+    /// Space used by images, container layers, volumes and build cache together
+    pub fn total_size(&self) -> u64 {
+        self.layers_size
+            + self.containers.iter().map(|c| c.size_rw).sum::<u64>()
+            + self.volumes.iter().filter_map(|v| v.size).sum::<u64>()
+            + self.build_cache_size
+    }
+}
+
+/// An image and its layers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// Image ID (`sha256:...`)
+    pub id: String,
+    /// Repository tags (e.g. `nginx:latest`), empty for dangling images
+    pub tags: Vec<String>,
+    /// Size of all layers of the image
+    pub size: u64,
+    /// Size of the layers shared with other images
+    pub shared_size: u64,
+    /// Number of containers using the image
+    pub containers: usize,
+}
+
+impl Image {
+    /// Size of the layers used by this image only
+    pub fn unique_size(&self) -> u64 {
+        self.size.saturating_sub(self.shared_size)
+    }
+
+    /// First tag, or the short ID of an untagged image
+    pub fn name(&self) -> String {
+        self.tags
+            .first()
+            .cloned()
+            .unwrap_or_else(|| short_id(&self.id))
+    }
+}
+
+/// A container and its writable layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    /// Container ID
+    pub id: String,
+    /// Container name, without the leading `/`
+    pub name: String,
+    /// Image the container was created from
+    pub image: String,
+    /// Size of the files written by the container
+    pub size_rw: u64,
+    /// Size of the writable layer together with its image
+    pub size_root_fs: u64,
+    /// State (`running`, `exited`, ...)
+    pub state: String,
+}
+
+/// A volume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    /// Volume name
+    pub name: String,
+    /// Directory holding the volume's data on the host
+    pub mountpoint: PathBuf,
+    /// Size of the volume's data, when the driver reports it
+    pub size: Option<u64>,
+    /// Number of containers using the volume
+    pub ref_count: usize,
+}
+
+/// Query the Docker daemon for its disk usage
+pub fn disk_usage() -> Result<DockerUsage> {
+    let body = request("/system/df")?;
+    parse_system_df(&body)
+}
+
+/// Directory on the host holding the data of the volume `name`
+pub fn volume_mountpoint(name: &str) -> Result<PathBuf> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || "_.-".contains(c);
+    if name.is_empty() || !name.chars().all(valid) {
+        anyhow::bail!("Invalid volume name: {}", name);
+    }
+    let body = request(&format!("/volumes/{}", name))?;
+    let volume: RawVolume = serde_json::from_slice(&body).context("Invalid volume response")?;
+    Ok(PathBuf::from(volume.mountpoint))
+}
+
+/// Where the Docker daemon listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Endpoint {
+    Unix(PathBuf),
+    Tcp(String),
+    Pipe(PathBuf),
+}
+
+impl Endpoint {
+    /// The endpoint from `DOCKER_HOST`, or the platform default
+    fn from_env() -> Result<Self> {
+        match std::env::var("DOCKER_HOST") {
+            Ok(host) if !host.is_empty() => Self::parse(&host),
+            #[cfg(unix)]
+            _ => Ok(Endpoint::Unix(PathBuf::from(DEFAULT_SOCKET))),
+            #[cfg(windows)]
+            _ => Ok(Endpoint::Pipe(PathBuf::from(DEFAULT_PIPE))),
+            #[cfg(not(any(unix, windows)))]
+            _ => anyhow::bail!("Set DOCKER_HOST to the address of the Docker daemon"),
+        }
+    }
+
+    /// Parse a `DOCKER_HOST` value
+    fn parse(host: &str) -> Result<Self> {
+        if let Some(path) = host.strip_prefix("unix://") {
+            Ok(Endpoint::Unix(PathBuf::from(path)))
+        } else if let Some(address) = host.strip_prefix("tcp://") {
+            Ok(Endpoint::Tcp(address.trim_end_matches('/').to_string()))
+        } else if let Some(pipe) = host.strip_prefix("npipe://") {
+            Ok(Endpoint::Pipe(PathBuf::from(pipe.replace('/', "\\"))))
+        } else {
+            anyhow::bail!("Unsupported DOCKER_HOST: {}", host)
+        }
+    }
+
+    /// Send `request` and read the whole response
+    fn exchange(&self, request: &str) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                exchange(std::os::unix::net::UnixStream::connect(path)?, request)
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => Err(io::ErrorKind::Unsupported.into()),
+            Endpoint::Tcp(address) => exchange(TcpStream::connect(address)?, request),
+            Endpoint::Pipe(path) => {
+                let pipe = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)?;
+                exchange(pipe, request)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+            Endpoint::Tcp(address) => write!(f, "tcp://{}", address),
+            Endpoint::Pipe(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn exchange(mut stream: impl Read + Write, request: &str) -> io::Result<Vec<u8>> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(response)
+}
+
+/// GET `path` from the daemon and return the response body
+fn request(path: &str) -> Result<Vec<u8>> {
+    let endpoint = Endpoint::from_env()?;
+    log::debug!("Requesting {} from {}", path, endpoint);
+    // HTTP/1.0 makes the daemon close the connection after an unchunked body
+    let request = format!("GET {} HTTP/1.0\r\nHost: docker\r\n\r\n", path);
+    let response = endpoint
+        .exchange(&request)
+        .with_context(|| format!("Cannot connect to the Docker daemon at {}", endpoint))?;
+    response_body(&response)
+}
+
+/// Split an HTTP response, returning the body of a successful one
+fn response_body(response: &[u8]) -> Result<Vec<u8>> {
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("Malformed response from the Docker daemon")?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .context("Malformed response from the Docker daemon")?;
+    if status != 200 {
+        #[derive(Deserialize)]
+        struct ErrorBody {
+            message: String,
+        }
+        let message = serde_json::from_slice::<ErrorBody>(body)
+            .map(|e| e.message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(body).trim().to_string());
+        anyhow::bail!("Docker daemon returned {}: {}", status, message);
+    }
+    Ok(body.to_vec())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SystemDf {
+    #[serde(default)]
+    layers_size: i64,
+    #[serde(default)]
+    images: Option<Vec<RawImage>>,
+    #[serde(default)]
+    containers: Option<Vec<RawContainer>>,
+    #[serde(default)]
+    volumes: Option<Vec<RawVolume>>,
+    #[serde(default)]
+    build_cache: Option<Vec<RawBuildCache>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawImage {
+    id: String,
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+    #[serde(default)]
+    size: i64,
+    #[serde(default)]
+    shared_size: i64,
+    #[serde(default)]
+    containers: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawContainer {
+    id: String,
+    #[serde(default)]
+    names: Option<Vec<String>>,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    size_rw: i64,
+    #[serde(default)]
+    size_root_fs: i64,
+    #[serde(default)]
+    state: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawVolume {
+    name: String,
+    mountpoint: String,
+    #[serde(default)]
+    usage_data: Option<RawUsageData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawUsageData {
+    size: i64,
+    ref_count: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawBuildCache {
+    #[serde(default)]
+    size: i64,
+}
+
+/// Parse the body of a `/system/df` response
+///
+/// The daemon uses -1 for sizes it did not compute; they count as zero, or as
+/// unknown for volumes.
+fn parse_system_df(body: &[u8]) -> Result<DockerUsage> {
+    let df: SystemDf = serde_json::from_slice(body).context("Invalid disk usage response")?;
+    let size = |value: i64| value.max(0) as u64;
+
+    let mut images: Vec<Image> = df
+        .images
+        .unwrap_or_default()
+        .into_iter()
+        .map(|image| Image {
+            id: image.id,
+            tags: image
+                .repo_tags
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|tag| tag != "<none>:<none>")
+                .collect(),
+            size: size(image.size),
+            shared_size: size(image.shared_size),
+            containers: image.containers.max(0) as usize,
+        })
+        .collect();
+    let mut containers: Vec<Container> = df
+        .containers
+        .unwrap_or_default()
+        .into_iter()
+        .map(|container| Container {
+            name: container
+                .names
+                .and_then(|names| names.into_iter().next())
+                .map(|name| name.trim_start_matches('/').to_string())
+                .unwrap_or_else(|| short_id(&container.id)),
+            id: container.id,
+            image: container.image,
+            size_rw: size(container.size_rw),
+            size_root_fs: size(container.size_root_fs),
+            state: container.state,
+        })
+        .collect();
+    let mut volumes: Vec<Volume> = df
+        .volumes
+        .unwrap_or_default()
+        .into_iter()
+        .map(|volume| Volume {
+            name: volume.name,
+            mountpoint: PathBuf::from(volume.mountpoint),
+            size: volume
+                .usage_data
+                .as_ref()
+                .filter(|usage| usage.size >= 0)
+                .map(|usage| usage.size as u64),
+            ref_count: volume
+                .usage_data
+                .map_or(0, |usage| usage.ref_count.max(0) as usize),
+        })
+        .collect();
+
+    images.sort_by_key(|i| std::cmp::Reverse(i.size));
+    containers.sort_by_key(|c| std::cmp::Reverse(c.size_rw));
+    volumes.sort_by_key(|v| std::cmp::Reverse(v.size));
+
+    Ok(DockerUsage {
+        layers_size: size(df.layers_size),
+        images,
+        containers,
+        volumes,
+        build_cache_size: df
+            .build_cache
+            .unwrap_or_default()
+            .iter()
+            .map(|entry| size(entry.size))
+            .sum(),
+    })
+}
+
+/// The first 12 hex digits of an ID, as shown by the docker CLI
+fn short_id(id: &str) -> String {
+    let id = id.strip_prefix("sha256:").unwrap_or(id);
+    id.chars().take(12).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSTEM_DF: &str = r#"{
+        "LayersSize": 300000,
+        "Images": [
+            {"Id": "sha256:aaaaaaaaaaaaaaaaaaaa", "RepoTags": ["small:1"], "Size": 50000, "SharedSize": 40000, "Containers": 0},
+            {"Id": "sha256:bbbbbbbbbbbbbbbbbbbb", "RepoTags": null, "Size": 250000, "SharedSize": -1, "Containers": 1}
+        ],
+        "Containers": [
+            {"Id": "cccccccccccccccc", "Names": ["/web"], "Image": "small:1", "SizeRw": 1200, "SizeRootFs": 51200, "State": "running"}
+        ],
+        "Volumes": [
+            {"Name": "db", "Mountpoint": "/var/lib/docker/volumes/db/_data", "UsageData": {"Size": 7000, "RefCount": 1}},
+            {"Name": "remote", "Mountpoint": "", "UsageData": {"Size": -1, "RefCount": 0}}
+        ],
+        "BuildCache": [{"Size": 800}, {"Size": 200}]
+    }"#;
+
+    #[test]
+    fn test_parse_system_df() {
+        let usage = parse_system_df(SYSTEM_DF.as_bytes()).unwrap();
+
+        assert_eq!(usage.images[0].name(), "bbbbbbbbbbbb");
+        assert_eq!(usage.images[0].unique_size(), 250_000);
+        assert_eq!(usage.images[1].name(), "small:1");
+        assert_eq!(usage.images[1].unique_size(), 10_000);
+        assert_eq!(usage.containers[0].name, "web");
+        assert_eq!(usage.volumes[0].size, Some(7_000));
+        assert_eq!(usage.volumes[1].size, None);
+        assert_eq!(usage.build_cache_size, 1_000);
+        assert_eq!(usage.total_size(), 300_000 + 1_200 + 7_000 + 1_000);
+    }
+
+    #[test]
+    fn test_response_body() {
+        let ok = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}";
+        assert_eq!(response_body(ok).unwrap(), b"{}");
+
+        let missing = b"HTTP/1.0 404 Not Found\r\n\r\n{\"message\":\"get nope: no such volume\"}";
+        let error = response_body(missing).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Docker daemon returned 404: get nope: no such volume"
+        );
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            Endpoint::parse("unix:///run/user/1000/docker.sock").unwrap(),
+            Endpoint::Unix(PathBuf::from("/run/user/1000/docker.sock"))
+        );
+        assert_eq!(
+            Endpoint::parse("tcp://10.0.0.5:2375").unwrap(),
+            Endpoint::Tcp("10.0.0.5:2375".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("npipe:////./pipe/docker_engine").unwrap(),
+            Endpoint::Pipe(PathBuf::from(r"\\.\pipe\docker_engine"))
+        );
+        assert!(Endpoint::parse("ssh://host").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_request_over_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let socket = temp_dir.path().join("docker.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let len = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\n\r\n{\"LayersSize\": 42}")
+                .unwrap();
+            String::from_utf8_lossy(&request[..len]).into_owned()
+        });
+
+        let endpoint = Endpoint::Unix(socket);
+        let response = endpoint
+            .exchange("GET /system/df HTTP/1.0\r\n\r\n")
+            .unwrap();
+        let usage = parse_system_df(&response_body(&response).unwrap()).unwrap();
+        assert_eq!(usage.layers_size, 42);
+        assert!(server.join().unwrap().starts_with("GET /system/df "));
+    }
+}
@@ -0,0 +1,141 @@
+//! Empty and nearly empty directories
+//!
+//! A directory is empty when no file is counted anywhere below it, so a tree
+//! of nested directories without files is reported once, at its top. Files
+//! left out of the scan (excluded or under `min_size`) do not count.
+
+use crate::{analyze_disk_usage, AnalysisConfig, DirectoryEntry, DirectoryNode};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Empty and tiny directories below a root
+#[derive(Debug, Default)]
+pub struct EmptyReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Topmost directories without any files below them, outside the tiny ones, sorted by path
+    pub empty: Vec<PathBuf>,
+    /// Number of empty directories, including those nested in `empty`
+    pub empty_count: usize,
+    /// Topmost directories holding files but less than the threshold in total, sorted by path
+    pub tiny: Vec<DirectoryEntry>,
+    /// Size under which a directory counts as tiny
+    pub tiny_threshold: Option<u64>,
+}
+
+impl EmptyReport {
+    /// Total size of the tiny directories in bytes
+    pub fn tiny_size(&self) -> u64 {
+        self.tiny.iter().map(|d| d.size).sum()
+    }
+}
+
+/// Find the empty directories below `path`, and those smaller than
+/// `tiny_threshold` bytes when given
+///
+/// The root itself is never reported.
+pub fn find_empty(
+    path: &Path,
+    config: &AnalysisConfig,
+    tiny_threshold: Option<u64>,
+) -> Result<EmptyReport> {
+    let config = AnalysisConfig {
+        build_tree: true,
+        ..config.clone()
+    };
+    let result = analyze_disk_usage(path, &config, 0)?;
+    let tree = result.tree.context("Analysis did not produce a tree")?;
+
+    let mut report = EmptyReport {
+        root_path: result.root_path,
+        tiny_threshold,
+        ..Default::default()
+    };
+    for child in &tree.children {
+        collect(child, &mut report);
+    }
+    report.empty.sort();
+    report.tiny.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(report)
+}
+
+fn collect(node: &DirectoryNode, report: &mut EmptyReport) {
+    if node.file_count == 0 {
+        // Every directory below an empty one is empty as well
+        report.empty.push(node.path.clone());
+        report.empty_count += 1 + node.dir_count;
+        return;
+    }
+    if report
+        .tiny_threshold
+        .is_some_and(|threshold| node.size < threshold)
+    {
+        report.tiny.push(DirectoryEntry {
+            path: node.path.clone(),
+            size: node.size,
+            file_count: node.file_count,
+            dir_count: node.dir_count,
+            modified: None,
+        });
+        // Still look for empty directories inside it
+        report.empty_count += count_empty(node);
+        return;
+    }
+    for child in &node.children {
+        collect(child, report);
+    }
+}
+
+/// Number of empty directories below `node`
+fn count_empty(node: &DirectoryNode) -> usize {
+    node.children
+        .iter()
+        .map(|child| {
+            if child.file_count == 0 {
+                1 + child.dir_count
+            } else {
+                count_empty(child)
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("dead/a/b")).unwrap();
+        fs::create_dir_all(root.join("dead/c")).unwrap();
+        fs::create_dir_all(root.join("big/empty")).unwrap();
+        fs::write(root.join("big/data"), vec![0u8; 64 * 1024]).unwrap();
+        fs::create_dir_all(root.join("small/nothing")).unwrap();
+        fs::write(root.join("small/note"), b"hi").unwrap();
+
+        let report = find_empty(&root, &AnalysisConfig::default(), None).unwrap();
+        assert_eq!(
+            report.empty,
+            vec![
+                root.join("big/empty"),
+                root.join("dead"),
+                root.join("small/nothing")
+            ]
+        );
+        assert_eq!(report.empty_count, 6);
+        assert!(report.tiny.is_empty());
+
+        let report = find_empty(&root, &AnalysisConfig::default(), Some(32 * 1024)).unwrap();
+        assert_eq!(
+            report.empty,
+            vec![root.join("big/empty"), root.join("dead")]
+        );
+        assert_eq!(report.empty_count, 6);
+        assert_eq!(report.tiny.len(), 1);
+        assert_eq!(report.tiny[0].path, root.join("small"));
+    }
+}
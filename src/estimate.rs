@@ -0,0 +1,394 @@
+//! Estimating disk usage from a sample of the directories
+//!
+//! Every directory is a stratum of its own: a fraction of its subdirectories
+//! (at least [`MIN_SAMPLE`]) is drawn at random and estimated the same way,
+//! level after level, and the files stored directly in it are counted exactly.
+//! The subdirectories left out are assumed to hold as much as the drawn ones
+//! on average. The 95% confidence intervals follow from the spread between the
+//! drawn subtrees and the uncertainty within each of them, as for any
+//! multistage sample. Where a few subdirectories hold most of the data, the
+//! intervals get wide, and a sample missing all of them still falls short.
+//!
+//! Hard links are counted once per name, and symbolic links are not followed.
+
+use crate::exclude::Exclusions;
+use crate::{
+    build_thread_pool, get_device_id, get_disk_usage, remote_mounts, skipped_mounts,
+    with_storage_threads, AnalysisConfig,
+};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Directories listed at least per level, so each level yields a variance
+pub const MIN_SAMPLE: usize = 4;
+
+/// z-score of a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// An estimated quantity with the half-width of its 95% confidence interval
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub margin: f64,
+}
+
+impl Estimate {
+    /// Lower bound of the confidence interval
+    pub fn low(&self) -> f64 {
+        (self.value - self.margin).max(0.0)
+    }
+
+    /// Upper bound of the confidence interval
+    pub fn high(&self) -> f64 {
+        self.value + self.margin
+    }
+}
+
+/// Estimated usage of an immediate child of the root
+#[derive(Debug, Clone)]
+pub struct EstimateEntry {
+    pub path: PathBuf,
+    /// Disk usage in bytes
+    pub size: Estimate,
+    pub file_count: Estimate,
+    pub dir_count: Estimate,
+}
+
+/// Estimated usage of a path and of each immediate child of it
+#[derive(Debug, Clone)]
+pub struct EstimateReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Disk usage in bytes
+    pub size: Estimate,
+    pub file_count: Estimate,
+    pub dir_count: Estimate,
+    /// Estimates per immediate child of the root, largest first
+    pub entries: Vec<EstimateEntry>,
+    /// Directories that were actually listed
+    pub sampled_dirs: usize,
+    /// Time the sampling took
+    pub elapsed: Duration,
+}
+
+/// An estimated total and its variance
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    value: f64,
+    variance: f64,
+}
+
+impl Tally {
+    fn exact(value: f64) -> Self {
+        Tally {
+            value,
+            variance: 0.0,
+        }
+    }
+
+    /// Total of a directory holding `own` itself, with `drawn` estimated out of
+    /// `population` subtrees
+    fn two_stage(own: f64, population: usize, drawn: &[Tally]) -> Tally {
+        if drawn.is_empty() {
+            return Tally::exact(own);
+        }
+        let (m, n) = (population as f64, drawn.len() as f64);
+        let mean = drawn.iter().map(|t| t.value).sum::<f64>() / n;
+        // Spread between the drawn subtrees, then the uncertainty within them
+        let mut variance = 0.0;
+        if drawn.len() > 1 && drawn.len() < population {
+            let sample_variance =
+                drawn.iter().map(|t| (t.value - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            variance += m * m * (1.0 - n / m) * sample_variance / n;
+        }
+        variance += m / n * drawn.iter().map(|t| t.variance).sum::<f64>();
+        Tally {
+            value: own + m * mean,
+            variance,
+        }
+    }
+
+    fn merge(self, other: Tally) -> Tally {
+        Tally {
+            value: self.value + other.value,
+            variance: self.variance + other.variance,
+        }
+    }
+
+    fn estimate(self) -> Estimate {
+        Estimate {
+            value: self.value,
+            margin: Z_95 * self.variance.sqrt(),
+        }
+    }
+}
+
+/// Sizes, files and directories below one directory, and the number of
+/// directories listed
+#[derive(Debug, Clone, Copy, Default)]
+struct SubtreeTally {
+    size: Tally,
+    files: Tally,
+    dirs: Tally,
+    sampled: usize,
+}
+
+/// What listing one directory found
+#[derive(Default)]
+struct Listing {
+    size: u64,
+    files: usize,
+    subdirs: Vec<PathBuf>,
+}
+
+/// Deterministic generator drawing the samples (splitmix64)
+struct Rng(u64);
+
+impl Rng {
+    fn seeded(path: &Path) -> Self {
+        let hash = blake3::hash(path.as_os_str().as_encoded_bytes());
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&hash.as_bytes()[..8]);
+        Rng(u64::from_le_bytes(seed))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut x = self.0;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+
+    /// Move `count` randomly chosen items to the front of `items`
+    fn choose<T>(&mut self, items: &mut [T], count: usize) {
+        for i in 0..count.min(items.len()) {
+            let j = i + (self.next() % (items.len() - i) as u64) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+struct Sampler<'a> {
+    root: &'a Path,
+    fraction: f64,
+    max_depth: usize,
+    root_device: Option<u64>,
+    exclusions: &'a Exclusions,
+    /// Mount points left out, relative to the root
+    skipped_mounts: Vec<PathBuf>,
+}
+
+impl Sampler<'_> {
+    /// List `dir`, adding up the disk usage of its files
+    fn list(&self, dir: &Path) -> Listing {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Listing::default();
+        };
+        let mut listing = Listing::default();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let relative = path.strip_prefix(self.root).unwrap_or(&path);
+            if self.exclusions.is_excluded(relative)
+                || self.skipped_mounts.iter().any(|mount| mount == relative)
+            {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if let Some(root_device) = self.root_device {
+                if get_device_id(&metadata).is_some_and(|device| device != root_device) {
+                    continue;
+                }
+            }
+            if metadata.is_dir() {
+                listing.subdirs.push(path);
+            } else if metadata.is_file() {
+                listing.size += get_disk_usage(&path, &metadata);
+                listing.files += 1;
+            }
+        }
+        listing
+    }
+
+    /// Estimate the contents of `dir`, found at `depth` below the root
+    fn sample_dir(&self, dir: &Path, depth: usize) -> SubtreeTally {
+        // Directories below max_depth are neither listed nor counted, like the walk
+        if depth >= self.max_depth {
+            return SubtreeTally::default();
+        }
+        let listing = self.list(dir);
+        let own = SubtreeTally {
+            size: Tally::exact(listing.size as f64),
+            files: Tally::exact(listing.files as f64),
+            dirs: Tally::exact(listing.subdirs.len() as f64),
+            sampled: 1,
+        };
+        if depth + 1 >= self.max_depth || listing.subdirs.is_empty() {
+            return own;
+        }
+
+        let mut subdirs = listing.subdirs;
+        let population = subdirs.len();
+        let drawn = ((population as f64 * self.fraction).ceil() as usize)
+            .max(MIN_SAMPLE)
+            .min(population);
+        Rng::seeded(dir).choose(&mut subdirs, drawn);
+        subdirs.truncate(drawn);
+        let subtrees: Vec<SubtreeTally> = subdirs
+            .par_iter()
+            .map(|subdir| self.sample_dir(subdir, depth + 1))
+            .collect();
+
+        let combine = |own: Tally, metric: fn(&SubtreeTally) -> Tally| {
+            let drawn: Vec<Tally> = subtrees.iter().map(metric).collect();
+            Tally::two_stage(own.value, population, &drawn)
+        };
+        SubtreeTally {
+            size: combine(own.size, |t| t.size),
+            files: combine(own.files, |t| t.files),
+            dirs: combine(own.dirs, |t| t.dirs),
+            sampled: 1 + subtrees.iter().map(|t| t.sampled).sum::<usize>(),
+        }
+    }
+}
+
+/// Estimate the disk usage of `path` by listing `fraction` of its
+/// directories at every level (at least [`MIN_SAMPLE`] per level and child)
+pub fn estimate_usage(
+    path: &Path,
+    config: &AnalysisConfig,
+    fraction: f64,
+) -> Result<EstimateReport> {
+    if !path.is_dir() {
+        anyhow::bail!("Path is not a directory: {}", path.display());
+    }
+    let started = Instant::now();
+    let exclusions = Exclusions::new(&config.exclude_patterns)?;
+    let remote = remote_mounts(path, config);
+    let sampler = Sampler {
+        root: path,
+        fraction: fraction.clamp(0.0, 1.0),
+        max_depth: config.max_depth.unwrap_or(usize::MAX),
+        root_device: if config.one_file_system {
+            fs::metadata(path).ok().and_then(|m| get_device_id(&m))
+        } else {
+            None
+        },
+        exclusions: &exclusions,
+        skipped_mounts: skipped_mounts(path, config, &remote),
+    };
+
+    // Files directly in the root are counted exactly, children sampled
+    let root = if sampler.max_depth > 0 {
+        sampler.list(path)
+    } else {
+        Listing::default()
+    };
+    let sample = || -> Vec<(PathBuf, SubtreeTally)> {
+        root.subdirs
+            .par_iter()
+            .map(|dir| (dir.clone(), sampler.sample_dir(dir, 1)))
+            .collect()
+    };
+    let subtrees = match build_thread_pool(&with_storage_threads(config, path, &remote))? {
+        Some(pool) => pool.install(sample),
+        None => sample(),
+    };
+
+    let mut total = SubtreeTally {
+        size: Tally::exact(root.size as f64),
+        files: Tally::exact(root.files as f64),
+        dirs: Tally::exact(subtrees.len() as f64),
+        sampled: 1,
+    };
+    let mut entries: Vec<EstimateEntry> = subtrees
+        .into_iter()
+        .map(|(path, tally)| {
+            total.size = total.size.merge(tally.size);
+            total.files = total.files.merge(tally.files);
+            total.dirs = total.dirs.merge(tally.dirs);
+            total.sampled += tally.sampled;
+            EstimateEntry {
+                path,
+                size: tally.size.estimate(),
+                file_count: tally.files.estimate(),
+                // The child itself is counted alongside its descendants
+                dir_count: Estimate {
+                    value: tally.dirs.value + 1.0,
+                    ..tally.dirs.estimate()
+                },
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.size
+            .value
+            .total_cmp(&a.size.value)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    Ok(EstimateReport {
+        root_path: path.to_path_buf(),
+        size: total.size.estimate(),
+        file_count: total.files.estimate(),
+        dir_count: total.dirs.estimate(),
+        entries,
+        sampled_dirs: total.sampled,
+        elapsed: started.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_disk_usage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_estimate_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // Identical directories, so any sample extrapolates exactly
+        for i in 0..40 {
+            let dir = root.join("photos").join(format!("{:02}", i));
+            fs::create_dir_all(dir.join("raw")).unwrap();
+            fs::write(dir.join("a.jpg"), vec![1u8; 10_000]).unwrap();
+            fs::write(dir.join("raw/a.cr2"), vec![1u8; 30_000]).unwrap();
+        }
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/notes.txt"), vec![1u8; 5_000]).unwrap();
+        fs::write(root.join("top.bin"), vec![1u8; 5_000]).unwrap();
+
+        let config = AnalysisConfig::default();
+        let exact = analyze_disk_usage(root, &config, 0).unwrap();
+        let report = estimate_usage(root, &config, 0.1).unwrap();
+        assert_eq!(report.size.value, exact.total_size as f64);
+        assert_eq!(report.file_count.value, exact.total_files as f64);
+        assert_eq!(report.dir_count.value, exact.total_dirs as f64);
+        assert_eq!(report.size.margin, 0.0);
+        assert_eq!(report.entries[0].path, root.join("photos"));
+        assert_eq!(report.entries[0].dir_count.value, 81.0);
+        // photos, 4 of its 40 directories and their raw/, docs, and the root
+        assert_eq!(report.sampled_dirs, 11);
+
+        // Different sizes leave some uncertainty
+        for i in 0..40 {
+            let file = root.join("photos").join(format!("{:02}/b.jpg", i));
+            fs::write(file, vec![1u8; 8_192 * (i + 1)]).unwrap();
+        }
+        let report = estimate_usage(root, &config, 0.1).unwrap();
+        let photos = &report.entries[0];
+        assert!(photos.size.margin > 0.0);
+        let full = estimate_usage(root, &config, 1.0).unwrap();
+        assert_eq!(
+            full.size.value,
+            analyze_disk_usage(root, &config, 0).unwrap().total_size as f64
+        );
+        assert_eq!(full.size.margin, 0.0);
+    }
+}
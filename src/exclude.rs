@@ -0,0 +1,135 @@
+//! Exclusion patterns
+//!
+//! Patterns follow `.gitignore` anchoring rules: a pattern without a slash
+//! matches the name of an entry at any depth, while a pattern containing a
+//! slash matches the path relative to the scan root. A leading slash only
+//! serves to anchor the pattern, and a leading `**/` matches at any depth.
+//! `*` never matches across a `/`.
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::path::Path;
+
+/// Read exclusion patterns from a file, one per line
+///
+/// Blank lines and lines starting with `#` are skipped; a leading `\#`
+/// stands for a literal `#`. Trailing whitespace is ignored.
+pub fn read_exclude_file(path: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read exclude file: {}", path.display()))?;
+    Ok(parse_exclude_list(&contents))
+}
+
+fn parse_exclude_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.strip_prefix('\\')
+                .filter(|l| l.starts_with('#'))
+                .unwrap_or(line)
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Compiled exclusion patterns
+pub(crate) struct Exclusions {
+    /// Patterns matched against the entry name
+    names: GlobSet,
+    /// Patterns matched against the path relative to the root
+    paths: GlobSet,
+}
+
+impl Exclusions {
+    pub(crate) fn new(patterns: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            let trimmed = pattern.trim_end_matches('/');
+            let (target, glob) = match trimmed.strip_prefix('/') {
+                Some(anchored) => (&mut paths, anchored),
+                None if trimmed.contains('/') => (&mut paths, trimmed),
+                None => (&mut names, trimmed),
+            };
+            let glob = GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .context(format!("Invalid glob pattern: {}", pattern))?;
+            target.add(glob);
+        }
+
+        Ok(Self {
+            names: names.build()?,
+            paths: paths.build()?,
+        })
+    }
+
+    /// Whether the entry at `relative` (a path below the scan root) is excluded
+    pub(crate) fn is_excluded(&self, relative: &Path) -> bool {
+        if !self.names.is_empty() {
+            if let Some(name) = relative.file_name() {
+                if self.names.is_match(name) {
+                    return true;
+                }
+            }
+        }
+        !self.paths.is_empty() && self.paths.is_match(relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclusions(patterns: &[&str]) -> Exclusions {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Exclusions::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn test_name_patterns_match_at_any_depth() {
+        let ex = exclusions(&["*.log", "node_modules"]);
+        assert!(ex.is_excluded(Path::new("app.log")));
+        assert!(ex.is_excluded(Path::new("var/app.log")));
+        assert!(ex.is_excluded(Path::new("web/node_modules")));
+        assert!(!ex.is_excluded(Path::new("node_modules_backup")));
+        assert!(!ex.is_excluded(Path::new("logs")));
+    }
+
+    #[test]
+    fn test_path_patterns_are_anchored() {
+        let ex = exclusions(&["build/cache", "/tmp/", "**/target/debug"]);
+        assert!(ex.is_excluded(Path::new("build/cache")));
+        assert!(!ex.is_excluded(Path::new("sub/build/cache")));
+        assert!(ex.is_excluded(Path::new("tmp")));
+        assert!(!ex.is_excluded(Path::new("sub/tmp")));
+        assert!(ex.is_excluded(Path::new("target/debug")));
+        assert!(ex.is_excluded(Path::new("crates/core/target/debug")));
+        assert!(!ex.is_excluded(Path::new("target/release")));
+    }
+
+    #[test]
+    fn test_star_does_not_cross_directories() {
+        let ex = exclusions(&["src/*.rs"]);
+        assert!(ex.is_excluded(Path::new("src/main.rs")));
+        assert!(!ex.is_excluded(Path::new("src/bin/tool.rs")));
+    }
+
+    #[test]
+    fn test_parse_exclude_list() {
+        let contents = "# build output\ntarget/\n\n  \n*.log   \n\\#notes#\n**/cache\n";
+        assert_eq!(
+            parse_exclude_list(contents),
+            vec!["target/", "*.log", "#notes#", "**/cache"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(Exclusions::new(&["[".to_string()]).is_err());
+    }
+}
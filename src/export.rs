@@ -0,0 +1,538 @@
+//! Exporting complete scans for analysis with external tools
+//!
+//! The SQLite export writes one row per directory, and optionally per file,
+//! so that large scans can be queried with plain SQL:
+//!
+//! ```sql
+//! SELECT path, size FROM directories WHERE depth = 2 ORDER BY size DESC LIMIT 20;
+//! SELECT d.path, f.name, f.size FROM files f JOIN directories d ON d.id = f.directory_id
+//!     WHERE f.size > 1e9;
+//! ```
+//!
+//! The Parquet export (with the `arrow` feature) writes the same records to a
+//! single table, one row per directory or file, for data lake ingestion.
+//!
+//! Paths and names are stored as text, with non-UTF-8 names converted lossily.
+
+use crate::{AnalysisResult, DirectoryNode};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, Statement, Transaction};
+use std::fs;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE scan (
+        root TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        total_size INTEGER NOT NULL,
+        file_count INTEGER NOT NULL,
+        dir_count INTEGER NOT NULL,
+        cancelled INTEGER NOT NULL
+    );
+    CREATE TABLE directories (
+        id INTEGER PRIMARY KEY,
+        parent_id INTEGER REFERENCES directories(id),
+        path TEXT NOT NULL,
+        name TEXT NOT NULL,
+        depth INTEGER NOT NULL,
+        size INTEGER NOT NULL,
+        file_count INTEGER NOT NULL,
+        dir_count INTEGER NOT NULL
+    );
+    CREATE TABLE files (
+        id INTEGER PRIMARY KEY,
+        directory_id INTEGER NOT NULL REFERENCES directories(id),
+        name TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        apparent_size INTEGER NOT NULL
+    );
+    CREATE TABLE errors (
+        path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        message TEXT NOT NULL
+    );
+";
+
+/// Indexes created once all rows are inserted, which is faster than maintaining them
+const INDEXES: &str = "
+    CREATE INDEX directories_by_parent ON directories(parent_id);
+    CREATE INDEX directories_by_path ON directories(path);
+    CREATE INDEX files_by_directory ON files(directory_id);
+";
+
+/// Write `result` to a new SQLite database at `path`, replacing any existing file
+///
+/// Every directory of the tree is exported along with the files listed in
+/// it. Without a tree only the root and its top entries are written.
+pub fn export_sqlite(result: &AnalysisResult, path: &Path, timestamp: u64) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("Failed to replace {}", path.display()));
+        }
+        _ => {}
+    }
+    let mut conn = Connection::open(path)
+        .with_context(|| format!("Failed to create database: {}", path.display()))?;
+    write_sqlite(&mut conn, result, timestamp)
+        .with_context(|| format!("Failed to write database: {}", path.display()))
+}
+
+/// Create the export tables in `conn` and fill them from `result`
+pub fn write_sqlite(conn: &mut Connection, result: &AnalysisResult, timestamp: u64) -> Result<()> {
+    // The database is written once from scratch, so a crash only loses the export
+    conn.pragma_update(None, "journal_mode", "OFF")?;
+    conn.pragma_update(None, "synchronous", "OFF")?;
+
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    tx.execute(
+        "INSERT INTO scan (root, timestamp, total_size, file_count, dir_count, cancelled)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            result.root_path.to_string_lossy(),
+            timestamp as i64,
+            result.total_size as i64,
+            result.total_files as i64,
+            result.total_dirs as i64,
+            result.cancelled
+        ],
+    )?;
+
+    {
+        let mut writer = Writer::new(&tx)?;
+        match &result.tree {
+            Some(tree) => writer.node(tree, None, 0)?,
+            None => {
+                let root = writer.directory(
+                    None,
+                    &result.root_path,
+                    0,
+                    result.total_size,
+                    result.total_files,
+                    result.total_dirs,
+                )?;
+                for entry in &result.top_directories {
+                    writer.directory(
+                        Some(root),
+                        &entry.path,
+                        1,
+                        entry.size,
+                        entry.file_count,
+                        entry.dir_count,
+                    )?;
+                }
+            }
+        }
+
+        let mut insert_error =
+            tx.prepare("INSERT INTO errors (path, kind, message) VALUES (?1, ?2, ?3)")?;
+        for error in &result.errors {
+            insert_error.execute(params![
+                error.path.to_string_lossy(),
+                error.kind.as_str(),
+                error.message
+            ])?;
+        }
+    }
+
+    tx.execute_batch(INDEXES)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Prepared statements inserting the tree
+struct Writer<'conn> {
+    insert_dir: Statement<'conn>,
+    insert_file: Statement<'conn>,
+}
+
+impl<'conn> Writer<'conn> {
+    fn new(tx: &'conn Transaction<'_>) -> Result<Self> {
+        Ok(Self {
+            insert_dir: tx.prepare(
+                "INSERT INTO directories
+                 (parent_id, path, name, depth, size, file_count, dir_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?,
+            insert_file: tx.prepare(
+                "INSERT INTO files (directory_id, name, size, apparent_size)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?,
+        })
+    }
+
+    fn node(&mut self, node: &DirectoryNode, parent: Option<i64>, depth: usize) -> Result<()> {
+        let id = self.directory(
+            parent,
+            &node.path,
+            depth,
+            node.size,
+            node.file_count,
+            node.dir_count,
+        )?;
+        for file in &node.files {
+            self.insert_file.execute(params![
+                id,
+                file.name.to_string_lossy(),
+                file.size as i64,
+                file.apparent_size as i64
+            ])?;
+        }
+        for child in &node.children {
+            self.node(child, Some(id), depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn directory(
+        &mut self,
+        parent: Option<i64>,
+        path: &Path,
+        depth: usize,
+        size: u64,
+        file_count: usize,
+        dir_count: usize,
+    ) -> Result<i64> {
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let id = self.insert_dir.insert(params![
+            parent,
+            path.to_string_lossy(),
+            name,
+            depth as i64,
+            size as i64,
+            file_count as i64,
+            dir_count as i64
+        ])?;
+        Ok(id)
+    }
+}
+
+/// Rows buffered before they are written out as one record batch
+#[cfg(feature = "arrow")]
+const PARQUET_BATCH_ROWS: usize = 64 * 1024;
+
+/// Write `result` to a Parquet file at `path`, replacing any existing file
+///
+/// Each row is a directory or a file, identified by the `kind` column. Every
+/// row also carries the scanned root and the scan time so that exports from
+/// many hosts can be queried together. Integers are signed, as many readers
+/// do not support unsigned Parquet types.
+#[cfg(feature = "arrow")]
+pub fn export_parquet(result: &AnalysisResult, path: &Path, timestamp: u64) -> Result<()> {
+    let file =
+        fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = ParquetWriter::new(file, result, timestamp)?;
+    match &result.tree {
+        Some(tree) => writer.node(tree, None, 0)?,
+        None => {
+            writer.directory(
+                None,
+                &result.root_path,
+                0,
+                result.total_size,
+                result.total_files,
+                result.total_dirs,
+            )?;
+            for entry in &result.top_directories {
+                writer.directory(
+                    Some(&result.root_path),
+                    &entry.path,
+                    1,
+                    entry.size,
+                    entry.file_count,
+                    entry.dir_count,
+                )?;
+            }
+        }
+    }
+    writer
+        .close()
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Column builders of the Parquet export, flushed every [`PARQUET_BATCH_ROWS`] rows
+#[cfg(feature = "arrow")]
+struct ParquetWriter {
+    writer: parquet::arrow::ArrowWriter<fs::File>,
+    schema: arrow_schema::SchemaRef,
+    root: String,
+    timestamp: i64,
+    rows: usize,
+    roots: arrow_array::builder::StringBuilder,
+    timestamps: arrow_array::builder::Int64Builder,
+    kinds: arrow_array::builder::StringBuilder,
+    paths: arrow_array::builder::StringBuilder,
+    parents: arrow_array::builder::StringBuilder,
+    names: arrow_array::builder::StringBuilder,
+    depths: arrow_array::builder::Int32Builder,
+    sizes: arrow_array::builder::Int64Builder,
+    apparent_sizes: arrow_array::builder::Int64Builder,
+    file_counts: arrow_array::builder::Int64Builder,
+    dir_counts: arrow_array::builder::Int64Builder,
+}
+
+#[cfg(feature = "arrow")]
+impl ParquetWriter {
+    fn new(file: fs::File, result: &AnalysisResult, timestamp: u64) -> Result<Self> {
+        use arrow_schema::{DataType, Field, Schema};
+        use parquet::basic::Compression;
+        use parquet::file::properties::WriterProperties;
+
+        let schema = std::sync::Arc::new(Schema::new(vec![
+            Field::new("root", DataType::Utf8, false),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("path", DataType::Utf8, false),
+            Field::new("parent", DataType::Utf8, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("depth", DataType::Int32, false),
+            Field::new("size", DataType::Int64, false),
+            Field::new("apparent_size", DataType::Int64, true),
+            Field::new("file_count", DataType::Int64, true),
+            Field::new("dir_count", DataType::Int64, true),
+        ]));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = parquet::arrow::ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+        Ok(Self {
+            writer,
+            schema,
+            root: result.root_path.to_string_lossy().into_owned(),
+            timestamp: timestamp as i64,
+            rows: 0,
+            roots: Default::default(),
+            timestamps: Default::default(),
+            kinds: Default::default(),
+            paths: Default::default(),
+            parents: Default::default(),
+            names: Default::default(),
+            depths: Default::default(),
+            sizes: Default::default(),
+            apparent_sizes: Default::default(),
+            file_counts: Default::default(),
+            dir_counts: Default::default(),
+        })
+    }
+
+    fn node(&mut self, node: &DirectoryNode, parent: Option<&Path>, depth: usize) -> Result<()> {
+        self.directory(
+            parent,
+            &node.path,
+            depth,
+            node.size,
+            node.file_count,
+            node.dir_count,
+        )?;
+        for file in &node.files {
+            self.push(
+                "file",
+                &node.path.join(&file.name),
+                Some(&node.path),
+                depth + 1,
+                file.size,
+            );
+            self.apparent_sizes.append_value(file.apparent_size as i64);
+            self.file_counts.append_null();
+            self.dir_counts.append_null();
+            self.row_done()?;
+        }
+        for child in &node.children {
+            self.node(child, Some(&node.path), depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn directory(
+        &mut self,
+        parent: Option<&Path>,
+        path: &Path,
+        depth: usize,
+        size: u64,
+        file_count: usize,
+        dir_count: usize,
+    ) -> Result<()> {
+        self.push("directory", path, parent, depth, size);
+        self.apparent_sizes.append_null();
+        self.file_counts.append_value(file_count as i64);
+        self.dir_counts.append_value(dir_count as i64);
+        self.row_done()
+    }
+
+    /// Append the columns shared by directories and files
+    fn push(&mut self, kind: &str, path: &Path, parent: Option<&Path>, depth: usize, size: u64) {
+        self.roots.append_value(&self.root);
+        self.timestamps.append_value(self.timestamp);
+        self.kinds.append_value(kind);
+        self.paths.append_value(path.to_string_lossy());
+        self.parents
+            .append_option(parent.map(|parent| parent.to_string_lossy()));
+        self.names.append_value(
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy(),
+        );
+        self.depths.append_value(depth as i32);
+        self.sizes.append_value(size as i64);
+    }
+
+    fn row_done(&mut self) -> Result<()> {
+        self.rows += 1;
+        if self.rows >= PARQUET_BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        use arrow_array::ArrayRef;
+        use std::sync::Arc;
+
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.roots.finish()),
+            Arc::new(self.timestamps.finish()),
+            Arc::new(self.kinds.finish()),
+            Arc::new(self.paths.finish()),
+            Arc::new(self.parents.finish()),
+            Arc::new(self.names.finish()),
+            Arc::new(self.depths.finish()),
+            Arc::new(self.sizes.finish()),
+            Arc::new(self.apparent_sizes.finish()),
+            Arc::new(self.file_counts.finish()),
+            Arc::new(self.dir_counts.finish()),
+        ];
+        let batch = arrow_array::RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn close(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_disk_usage, AnalysisConfig};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_export_sqlite() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/big"), vec![0u8; 8192]).unwrap();
+        fs::write(root.join("a/small"), b"x").unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            tree_files: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(&root, &config, 0).unwrap();
+        let db = temp_dir.path().join("scan.db");
+        // An existing file is replaced
+        fs::write(&db, b"not a database").unwrap();
+        export_sqlite(&result, &db, 1234).unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let (timestamp, total): (i64, i64) = conn
+            .query_row("SELECT timestamp, total_size FROM scan", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(timestamp, 1234);
+        assert_eq!(total as u64, result.total_size);
+
+        let (depth, parent_name): (i64, String) = conn
+            .query_row(
+                "SELECT d.depth, p.name FROM directories d
+                 JOIN directories p ON p.id = d.parent_id WHERE d.name = 'b'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((depth, parent_name.as_str()), (2, "a"));
+
+        let files: Vec<(String, String)> = conn
+            .prepare(
+                "SELECT d.name, f.name FROM files f
+                 JOIN directories d ON d.id = f.directory_id ORDER BY f.name",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            files,
+            [
+                ("b".to_string(), "big".to_string()),
+                ("a".to_string(), "small".to_string())
+            ]
+        );
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_export_parquet() {
+        use arrow_array::{Array, Int64Array, StringArray};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a/file"), vec![0u8; 8192]).unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            tree_files: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(&root, &config, 0).unwrap();
+        let path = temp_dir.path().join("scan.parquet");
+        export_parquet(&result, &path, 1234).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 3);
+
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let kinds = column("kind");
+        let kinds = kinds.as_any().downcast_ref::<StringArray>().unwrap();
+        let names = column("name");
+        let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+        let rows: Vec<_> = kinds
+            .iter()
+            .zip(names.iter())
+            .flat_map(|(k, n)| k.zip(n))
+            .collect();
+        assert_eq!(
+            rows,
+            [("directory", "data"), ("directory", "a"), ("file", "file")]
+        );
+
+        let file_counts = column("file_count");
+        let file_counts = file_counts.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(file_counts.value(0), 1);
+        assert!(file_counts.is_null(2));
+        let timestamps = column("timestamp");
+        let timestamps = timestamps.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert!(timestamps.iter().all(|t| t == Some(1234)));
+    }
+}
@@ -1,20 +1,18 @@
-//! Size formatting utilities for human-readable output
+//! Size formatting utilities for human-readable output, and parsing of
+//! human-readable sizes and durations
+
+use std::time::Duration;
 
 /// Unit system for size formatting
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum UnitSystem {
     /// Binary units (1024-based): KiB, MiB, GiB, TiB
+    #[default]
     Binary,
     /// SI units (1000-based): KB, MB, GB, TB
     Si,
 }
 
-impl Default for UnitSystem {
-    fn default() -> Self {
-        UnitSystem::Binary
-    }
-}
-
 /// Format a size in bytes to a human-readable string
 ///
 /// # Arguments
@@ -57,6 +55,103 @@ pub fn format_size_auto(bytes: u64) -> String {
     format_size(bytes, UnitSystem::Binary, 1)
 }
 
+/// How sizes are printed in tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeFormat {
+    /// Rounded with a unit, like "4.5 G"
+    #[default]
+    Human,
+    /// Exact byte counts, with digits grouped by thousands when a separator is set
+    Bytes(Option<char>),
+}
+
+impl SizeFormat {
+    /// Text of `bytes` in this format
+    pub fn format(self, bytes: u64) -> String {
+        match self {
+            Self::Human => format_size_auto(bytes),
+            Self::Bytes(None) => bytes.to_string(),
+            Self::Bytes(Some(separator)) => group_digits(bytes, separator),
+        }
+    }
+}
+
+/// Format `number` with its digits grouped by thousands, like "1,234,567"
+pub fn group_digits(number: u64, separator: char) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::with_capacity(digits.len() * 2);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Thousands separator of the current locale, from `LC_ALL`, `LC_NUMERIC` or `LANG`
+pub fn locale_thousands_separator() -> char {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    thousands_separator(&locale)
+}
+
+/// Thousands separator used by `locale`, like "de_DE.UTF-8"
+///
+/// Only the common conventions are known; anything else gets a comma.
+pub fn thousands_separator(locale: &str) -> char {
+    let name = locale.split(['.', '@']).next().unwrap_or_default();
+    let language = name.split(['_', '-']).next().unwrap_or_default();
+    match (language, name) {
+        (_, "de_CH" | "it_CH" | "fr_CH" | "rm_CH" | "de_LI") => '\'',
+        ("de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro" | "hr" | "sl", _) => {
+            '.'
+        }
+        (
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "uk" | "hu"
+            | "bg" | "et" | "lt" | "lv",
+            _,
+        ) => '\u{a0}',
+        _ => ',',
+    }
+}
+
+/// Format a signed size change with an explicit sign, like "+1.5 G" or "-300 B"
+pub fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_size_auto(delta.unsigned_abs()))
+}
+
+/// Format a size the way `du -h` does, like "512", "4.0K" or "12M"
+///
+/// Sizes are rounded up, with one decimal below 10 of a unit.
+pub fn format_size_du(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    loop {
+        if size < 10.0 {
+            let tenths = (size * 10.0).ceil() / 10.0;
+            if tenths < 10.0 {
+                return format!("{:.1}{}", tenths, UNITS[unit]);
+            }
+        }
+        let whole = size.ceil();
+        if whole < 1024.0 || unit == UNITS.len() - 1 {
+            return format!("{}{}", whole, UNITS[unit]);
+        }
+        size /= 1024.0;
+        unit += 1;
+    }
+}
+
 /// Calculate percentage and format as string
 pub fn format_percentage(part: u64, total: u64) -> String {
     if total == 0 {
@@ -67,6 +162,205 @@ pub fn format_percentage(part: u64, total: u64) -> String {
     format!("{:>3.0}%", percentage)
 }
 
+/// Format a Unix timestamp in seconds as a UTC date and time, like "2024-03-09 14:05"
+pub fn format_timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_date(secs);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+/// Format a Unix timestamp in seconds as an RFC 3339 UTC time, like "2024-03-09T14:05:00Z"
+pub fn format_rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_date(secs);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Civil date (year, month, day) from a Unix timestamp (Howard Hinnant's algorithm)
+fn civil_date(secs: u64) -> (i64, i64, i64) {
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Render values as a sparkline of block characters, scaled between their minimum and maximum
+pub fn sparkline(values: &[u64]) -> String {
+    sparkline_with(values, &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'])
+}
+
+/// Render values as a sparkline of eight `levels`, lowest first, scaled
+/// between their minimum and maximum
+pub fn sparkline_with(values: &[u64], levels: &[char; 8]) -> String {
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&value| {
+            let level = if max > min {
+                ((value - min) as f64 / (max - min) as f64 * 7.0).round() as usize
+            } else {
+                3
+            };
+            levels[level]
+        })
+        .collect()
+}
+
+/// Parse a size like "10M", "1.5G" or "4096"
+///
+/// Suffixes are binary (1K = 1024 bytes) and may be followed by `B` or `iB`;
+/// a bare number is taken as bytes.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", text))?;
+
+    let unit = unit.trim_start().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let exponent = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => {
+            return Err(format!(
+                "invalid size unit in '{}' (expected K, M, G, T or P)",
+                text
+            ))
+        }
+    };
+
+    let bytes = number * 1024f64.powi(exponent);
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("size '{}' is too large", text));
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// A size given in bytes or as a share of a total, e.g. for `--threshold`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeCutoff {
+    /// A number of bytes
+    Bytes(u64),
+    /// A percentage of the total
+    Percent(f64),
+}
+
+impl SizeCutoff {
+    /// The cutoff in bytes for a listing adding up to `total`
+    pub fn bytes(self, total: u64) -> u64 {
+        match self {
+            SizeCutoff::Bytes(bytes) => bytes,
+            SizeCutoff::Percent(percent) => (total as f64 * percent / 100.0).ceil() as u64,
+        }
+    }
+}
+
+/// Parse a cutoff like "1G" (see [`parse_size`]) or "5%"
+pub fn parse_size_cutoff(text: &str) -> Result<SizeCutoff, String> {
+    if text.trim().ends_with('%') {
+        parse_percent(text).map(SizeCutoff::Percent)
+    } else {
+        parse_size(text).map(SizeCutoff::Bytes)
+    }
+}
+
+/// Parse a percentage from 0 to 100 like "90" or "12.5%"
+pub fn parse_percent(text: &str) -> Result<f64, String> {
+    let number = text.trim();
+    let number = number.strip_suffix('%').unwrap_or(number);
+    match number.trim().parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("invalid percentage '{}' (expected 0 to 100)", text)),
+    }
+}
+
+/// Parse a duration like "90d", "12h" or "2w"
+///
+/// Supported units are `s`, `m` (minutes), `h`, `d`, `w` and `y` (365 days).
+/// A bare number is taken as days.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", text))?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{}' (expected s, m, h, d, w or y)",
+                unit
+            ))
+        }
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too large", text))
+}
+
+/// Format a duration in the largest unit of [`parse_duration`] that divides it
+/// exactly, e.g. "6h" or "90s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let unit = [
+        ("y", 365 * 24 * 60 * 60),
+        ("w", 7 * 24 * 60 * 60),
+        ("d", 24 * 60 * 60),
+        ("h", 60 * 60),
+        ("m", 60),
+    ]
+    .into_iter()
+    .find(|&(_, size)| seconds > 0 && seconds % size == 0);
+    match unit {
+        Some((unit, size)) => format!("{}{}", seconds / size, unit),
+        None => format!("{}s", seconds),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -103,4 +397,100 @@ mod tests {
         assert_eq!(format_percentage(0, 100), "  0%");
         assert_eq!(format_percentage(100, 100), "100%");
     }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1.5G"), Ok(1536 * 1024 * 1024));
+        assert_eq!(parse_size("2KiB"), Ok(2048));
+        assert_eq!(parse_size("3 kb"), Ok(3072));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("-1K").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90d"), Ok(Duration::from_secs(90 * 86400)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("1y"), Ok(Duration::from_secs(365 * 86400)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30 * 86400)));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("-5d").is_err());
+
+        assert_eq!(format_duration(Duration::from_secs(6 * 3600)), "6h");
+        assert_eq!(format_duration(Duration::from_secs(14 * 86400)), "2w");
+        assert_eq!(format_duration(Duration::from_secs(730 * 86400)), "2y");
+        assert_eq!(format_duration(Duration::from_secs(30 * 86400)), "30d");
+        assert_eq!(format_duration(Duration::from_secs(90)), "90s");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+    }
+
+    #[test]
+    fn test_size_format_bytes() {
+        assert_eq!(SizeFormat::Bytes(None).format(1234567), "1234567");
+        assert_eq!(SizeFormat::Bytes(Some(',')).format(1234567), "1,234,567");
+        assert_eq!(SizeFormat::Bytes(Some('.')).format(123456), "123.456");
+        assert_eq!(SizeFormat::Bytes(Some(',')).format(999), "999");
+        assert_eq!(SizeFormat::Human.format(1024), "1.0 K");
+        assert_eq!(thousands_separator("de_DE.UTF-8"), '.');
+        assert_eq!(thousands_separator("de_CH.UTF-8"), '\'');
+        assert_eq!(thousands_separator("fr_FR"), '\u{a0}');
+        assert_eq!(thousands_separator("en_US.UTF-8"), ',');
+        assert_eq!(thousands_separator("C"), ',');
+    }
+
+    #[test]
+    fn test_format_size_du() {
+        assert_eq!(format_size_du(0), "0");
+        assert_eq!(format_size_du(1023), "1023");
+        assert_eq!(format_size_du(4096), "4.0K");
+        assert_eq!(format_size_du(4097), "4.1K");
+        assert_eq!(format_size_du(10 * 1024 - 1), "10K");
+        assert_eq!(format_size_du(12 * 1024 * 1024 + 1), "13M");
+        assert_eq!(format_size_du((1 << 30) - 1), "1.0G");
+        assert_eq!(format_size_du(3 << 30), "3.0G");
+    }
+
+    #[test]
+    fn test_parse_size_cutoff() {
+        assert_eq!(parse_size_cutoff("1G"), Ok(SizeCutoff::Bytes(1 << 30)));
+        assert_eq!(parse_size_cutoff("5%"), Ok(SizeCutoff::Percent(5.0)));
+        assert_eq!(SizeCutoff::Percent(5.0).bytes(1000), 50);
+        assert_eq!(SizeCutoff::Percent(2.5).bytes(101), 3);
+        assert_eq!(SizeCutoff::Bytes(7).bytes(1000), 7);
+        assert!(parse_size_cutoff("150%").is_err());
+        assert!(parse_size_cutoff("x%").is_err());
+        assert_eq!(parse_percent("90"), Ok(90.0));
+        assert_eq!(parse_percent("12.5%"), Ok(12.5));
+        assert!(parse_percent("101").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00");
+        assert_eq!(format_timestamp(1_709_993_100), "2024-03-09 14:05");
+        assert_eq!(format_rfc3339(1_709_993_107), "2024-03-09T14:05:07Z");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0, 50, 100]), "▁▅█");
+        assert_eq!(sparkline(&[7, 7]), "▄▄");
+        assert_eq!(sparkline(&[]), "");
+        let digits = ['0', '1', '2', '3', '4', '5', '6', '7'];
+        assert_eq!(sparkline_with(&[0, 50, 100], &digits), "047");
+    }
+
+    #[test]
+    fn test_format_size_delta() {
+        assert_eq!(format_size_delta(1536), "+1.5 K");
+        assert_eq!(format_size_delta(-300), "-300 B");
+        assert_eq!(format_size_delta(0), "+0 B");
+    }
 }
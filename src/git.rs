@@ -0,0 +1,219 @@
+//! Disk usage of git repositories, split between repository data, build
+//! artifacts and the working tree
+//!
+//! A repository is any directory holding a `.git` entry, noticed as the walk
+//! meets it. Worktrees and submodules have a `.git` file pointing elsewhere, so
+//! they show no repository data of their own. Artifacts are the reclaimable
+//! directories recognized by [`crate::clean`]. Repositories nested in another
+//! one are reported on their own and left out of the outer one.
+
+use crate::{analyze_with_hooks, clean, AnalysisConfig, DirectoryNode, EntryVisitor, ScanHooks};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Usage of a single repository
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoUsage {
+    /// Path to the working tree
+    pub path: PathBuf,
+    /// Everything below `.git` (objects, LFS, index, ...)
+    pub git: u64,
+    /// Part of `git` taken by Git LFS objects
+    pub lfs: u64,
+    /// Number of loose objects, which `git gc` would pack
+    pub loose_objects: usize,
+    /// Build output, dependencies and caches
+    pub artifacts: u64,
+    /// Everything else: the checked out files
+    pub working_tree: u64,
+}
+
+impl RepoUsage {
+    /// Total disk usage of the repository, nested repositories excluded
+    pub fn size(&self) -> u64 {
+        self.git + self.artifacts + self.working_tree
+    }
+}
+
+/// Repositories found below a root
+#[derive(Debug, Default)]
+pub struct GitReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Total size of the analyzed tree in bytes
+    pub total_size: u64,
+    /// Repositories sorted by size, largest first
+    pub repos: Vec<RepoUsage>,
+}
+
+/// Directories holding a `.git` entry, as the walk meets them
+#[derive(Default)]
+struct RepoFinder {
+    roots: Mutex<HashSet<PathBuf>>,
+}
+
+impl RepoFinder {
+    fn found(&self, entry: &Path) {
+        if let (Some(name), Some(parent)) = (entry.file_name(), entry.parent()) {
+            if name == ".git" {
+                let mut roots = self.roots.lock().unwrap_or_else(|e| e.into_inner());
+                roots.insert(parent.to_path_buf());
+            }
+        }
+    }
+}
+
+impl EntryVisitor for RepoFinder {
+    fn visit_file(&self, path: &Path, _metadata: &std::fs::Metadata, _size: u64) {
+        self.found(path);
+    }
+
+    fn visit_dir(&self, path: &Path) {
+        self.found(path);
+    }
+}
+
+/// Find the git repositories below `path`, the root included
+pub fn analyze_git_repos(path: &Path, config: &AnalysisConfig) -> Result<GitReport> {
+    let finder = RepoFinder::default();
+    let hooks = ScanHooks {
+        visitor: Some(&finder),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: true,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+    let tree = result.tree.context("Analysis did not produce a tree")?;
+    let roots = finder.roots.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    let mut repos = Vec::new();
+    collect(&tree, &roots, &mut repos);
+    repos.sort_by(|a, b| b.size().cmp(&a.size()).then_with(|| a.path.cmp(&b.path)));
+
+    Ok(GitReport {
+        root_path: result.root_path,
+        total_size: result.total_size,
+        repos,
+    })
+}
+
+/// Immediate subdirectory of `node` called `name`
+pub(crate) fn child<'a>(node: &'a DirectoryNode, name: &str) -> Option<&'a DirectoryNode> {
+    node.children
+        .iter()
+        .find(|child| child.path.file_name() == Some(OsStr::new(name)))
+}
+
+fn collect(node: &DirectoryNode, roots: &HashSet<PathBuf>, repos: &mut Vec<RepoUsage>) {
+    if roots.contains(&node.path) {
+        measure(node, roots, repos);
+    } else {
+        for child in &node.children {
+            collect(child, roots, repos);
+        }
+    }
+}
+
+/// Add the usage of the repository at `node`, and of any nested in it among
+/// the repository `roots`
+fn measure(node: &DirectoryNode, roots: &HashSet<PathBuf>, repos: &mut Vec<RepoUsage>) {
+    let mut usage = RepoUsage {
+        path: node.path.clone(),
+        ..Default::default()
+    };
+    if let Some(git) = child(node, ".git") {
+        usage.git = git.size;
+        usage.lfs = child(git, "lfs").map_or(0, |lfs| lfs.size);
+        // Loose objects live in directories named after the first two hex
+        // digits of their hash, next to `pack/` and `info/`
+        usage.loose_objects = child(git, "objects").map_or(0, |objects| {
+            objects
+                .children
+                .iter()
+                .filter(|dir| {
+                    dir.path
+                        .file_name()
+                        .and_then(OsStr::to_str)
+                        .is_some_and(|name| {
+                            name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit())
+                        })
+                })
+                .map(|dir| dir.file_count)
+                .sum()
+        });
+    }
+
+    let mut nested = 0;
+    let mut pending: Vec<&DirectoryNode> = node
+        .children
+        .iter()
+        .filter(|child| child.path.file_name() != Some(OsStr::new(".git")))
+        .collect();
+    while let Some(dir) = pending.pop() {
+        if roots.contains(&dir.path) {
+            nested += dir.size;
+            measure(dir, roots, repos);
+        } else if clean::classify(&dir.path).is_some() {
+            usage.artifacts += dir.size;
+        } else {
+            pending.extend(&dir.children);
+        }
+    }
+
+    usage.working_tree = node.size - usage.git - usage.artifacts - nested;
+    repos.push(usage);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, size: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![1u8; size]).unwrap();
+    }
+
+    #[test]
+    fn test_analyze_git_repos() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        write(&root.join("app/.git/objects/ab/cdef"), 8_000);
+        write(&root.join("app/.git/objects/pack/pack-1.pack"), 40_000);
+        write(&root.join("app/.git/lfs/objects/aa/bb/blob"), 20_000);
+        write(&root.join("app/Cargo.toml"), 100);
+        write(&root.join("app/src/main.rs"), 4_000);
+        write(&root.join("app/target/debug/app"), 60_000);
+        write(&root.join("app/vendor/lib/.git/HEAD"), 100);
+        write(&root.join("app/vendor/lib/lib.rs"), 12_000);
+        write(&root.join("notes/todo.txt"), 4_000);
+        // A worktree, whose `.git` is a file pointing to the main repository
+        write(&root.join("app-fix/.git"), 40);
+        write(&root.join("app-fix/src/main.rs"), 4_000);
+
+        let report = analyze_git_repos(&root, &AnalysisConfig::default()).unwrap();
+        assert_eq!(report.repos.len(), 3);
+
+        let app = &report.repos[0];
+        assert_eq!(app.path, root.join("app"));
+        assert!(app.git >= 68_000 && app.lfs >= 20_000 && app.lfs < app.git);
+        assert_eq!(app.loose_objects, 1);
+        assert!(app.artifacts >= 60_000);
+        assert!(app.working_tree >= 4_100 && app.working_tree < 12_000);
+
+        let lib = &report.repos[1];
+        assert_eq!(lib.path, root.join("app/vendor/lib"));
+        assert!(lib.working_tree >= 12_000);
+        let worktree = &report.repos[2];
+        assert_eq!(worktree.path, root.join("app-fix"));
+        assert_eq!(worktree.git, 0);
+        let app_size = report.repos.iter().map(RepoUsage::size).sum::<u64>();
+        assert!(app_size < report.total_size);
+    }
+}
@@ -0,0 +1,157 @@
+//! Files reachable through several hard links
+//!
+//! The walker counts every inode once, skipping the links it reaches after the
+//! first. This module lists those inodes together with every link found below
+//! the root, so the space that deduplication saved can be attributed.
+
+use crate::{
+    analyze_with_hooks, get_inode_key, get_link_count, AnalysisConfig, EntryVisitor, ScanHooks,
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// An inode reached through more than one path during the scan
+#[derive(Debug, Clone)]
+pub struct HardLinkGroup {
+    /// Disk usage of the file in bytes
+    pub size: u64,
+    /// Number of links to the file on its filesystem, including any outside the scanned tree
+    pub link_count: u64,
+    /// Paths of the links found below the root, sorted
+    pub paths: Vec<PathBuf>,
+}
+
+impl HardLinkGroup {
+    /// Bytes that would have been counted again without deduplication
+    pub fn saved(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Hard-linked files below a root, with the totals of the analysis
+#[derive(Debug, Default)]
+pub struct HardLinkReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Total size of all files in bytes, each inode counted once
+    pub total_size: u64,
+    /// Groups sorted by the space they save, largest first
+    pub groups: Vec<HardLinkGroup>,
+    /// Additional links skipped over all groups
+    pub hard_links: usize,
+    /// Disk usage saved over all groups in bytes
+    pub hard_link_savings: u64,
+}
+
+/// Links found so far, keyed by inode
+#[derive(Default)]
+struct LinkTally {
+    inodes: Mutex<HashMap<(u64, u64), HardLinkGroup>>,
+}
+
+impl LinkTally {
+    fn add(&self, path: &Path, metadata: &Metadata, size: u64) {
+        let mut inodes = self.inodes.lock().unwrap_or_else(|e| e.into_inner());
+        inodes
+            .entry(get_inode_key(metadata))
+            .or_insert_with(|| HardLinkGroup {
+                size,
+                link_count: get_link_count(metadata),
+                paths: Vec::new(),
+            })
+            .paths
+            .push(path.to_path_buf());
+    }
+}
+
+impl EntryVisitor for LinkTally {
+    fn visit_file(&self, path: &Path, metadata: &Metadata, size: u64) {
+        // Files with a single link can never be reached again
+        if get_link_count(metadata) > 1 {
+            self.add(path, metadata, size);
+        }
+    }
+
+    fn visit_hard_link(&self, path: &Path, metadata: &Metadata, size: u64) {
+        self.add(path, metadata, size);
+    }
+}
+
+/// Analyze `path`, collecting the files reached through several hard links
+pub fn find_hard_links(path: &Path, config: &AnalysisConfig) -> Result<HardLinkReport> {
+    let tally = LinkTally::default();
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+
+    let mut groups: Vec<HardLinkGroup> = tally
+        .inodes
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_values()
+        .filter(|group| group.paths.len() > 1)
+        .collect();
+    for group in &mut groups {
+        group.paths.sort();
+    }
+    groups.sort_by(|a, b| {
+        b.saved()
+            .cmp(&a.saved())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    Ok(HardLinkReport {
+        root_path: result.root_path,
+        total_size: result.total_size,
+        groups,
+        hard_links: result.hard_links,
+        hard_link_savings: result.hard_link_savings,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_hard_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("a")).unwrap();
+        fs::write(root.join("big"), vec![1u8; 64 * 1024]).unwrap();
+        fs::hard_link(root.join("big"), root.join("a/big1")).unwrap();
+        fs::hard_link(root.join("big"), root.join("a/big2")).unwrap();
+        fs::write(root.join("small"), "data").unwrap();
+        fs::hard_link(root.join("small"), root.join("a/small")).unwrap();
+        fs::write(root.join("single"), "data").unwrap();
+
+        let report = find_hard_links(root, &AnalysisConfig::default()).unwrap();
+
+        assert_eq!(report.groups.len(), 2);
+        let big = &report.groups[0];
+        assert_eq!(
+            big.paths,
+            vec![root.join("a/big1"), root.join("a/big2"), root.join("big")]
+        );
+        assert_eq!(big.link_count, 3);
+        assert_eq!(big.saved(), big.size * 2);
+        assert_eq!(report.groups[1].paths.len(), 2);
+
+        assert_eq!(report.hard_links, 3);
+        assert_eq!(
+            report.hard_link_savings,
+            report.groups.iter().map(HardLinkGroup::saved).sum::<u64>()
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Distribution of file sizes on a logarithmic scale
+//!
+//! Files are sorted into buckets by their apparent size, each bucket 16 times
+//! larger than the previous one, from under 4K to over 1G. Every bucket holds
+//! the number of files and their disk usage, for the whole analysis and for
+//! each immediate child of the root.
+
+use crate::format::{format_size, UnitSystem};
+use crate::{analyze_with_hooks, root_child, AnalysisConfig, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Apparent sizes at which the buckets are split
+pub const BOUNDARIES: [u64; 6] = [4 << 10, 64 << 10, 1 << 20, 16 << 20, 256 << 20, 1 << 30];
+
+/// Labels of the buckets, like "<4K", "4K-64K" and ">1G"
+pub fn bucket_labels() -> Vec<String> {
+    let names: Vec<String> = BOUNDARIES
+        .iter()
+        .map(|&size| format_size(size, UnitSystem::Binary, 0).replace(' ', ""))
+        .collect();
+    std::iter::once(format!("<{}", names[0]))
+        .chain(
+            names
+                .windows(2)
+                .map(|pair| format!("{}-{}", pair[0], pair[1])),
+        )
+        .chain(std::iter::once(format!(">{}", names[names.len() - 1])))
+        .collect()
+}
+
+/// Number of files and their disk usage per size bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// Number of files per bucket, smallest first
+    pub counts: Vec<usize>,
+    /// Disk usage in bytes per bucket, smallest first
+    pub bytes: Vec<u64>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BOUNDARIES.len() + 1],
+            bytes: vec![0; BOUNDARIES.len() + 1],
+        }
+    }
+}
+
+impl Histogram {
+    /// Count a file of `apparent_size` taking `size` bytes on disk
+    pub fn add(&mut self, apparent_size: u64, size: u64) {
+        let bucket = BOUNDARIES.partition_point(|&boundary| boundary <= apparent_size);
+        self.counts[bucket] += 1;
+        self.bytes[bucket] += size;
+    }
+
+    /// Add up the buckets of `other`
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        for (bytes, other) in self.bytes.iter_mut().zip(&other.bytes) {
+            *bytes += other;
+        }
+    }
+
+    /// Total number of files
+    pub fn file_count(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Total disk usage in bytes
+    pub fn size(&self) -> u64 {
+        self.bytes.iter().sum()
+    }
+}
+
+/// File size distribution of an analysis and of each immediate child of its root
+#[derive(Debug, Clone)]
+pub struct HistogramReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Distribution over every file
+    pub total: Histogram,
+    /// Distribution per immediate child of the root, largest first
+    pub entries: Vec<(PathBuf, Histogram)>,
+}
+
+/// Running histograms per immediate child of the root
+struct HistogramTally<'a> {
+    root: &'a Path,
+    entries: Mutex<HashMap<PathBuf, Histogram>>,
+}
+
+impl EntryVisitor for HistogramTally<'_> {
+    fn visit_file(&self, path: &Path, metadata: &std::fs::Metadata, size: u64) {
+        let Some(child) = root_child(self.root, path) else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.entry(child).or_default().add(metadata.len(), size);
+    }
+}
+
+/// Analyze `path`, sorting every file into a size bucket
+pub fn analyze_histogram(path: &Path, config: &AnalysisConfig) -> Result<HistogramReport> {
+    let tally = HistogramTally {
+        root: path,
+        entries: Mutex::new(HashMap::new()),
+    };
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+
+    let mut entries: Vec<(PathBuf, Histogram)> = tally
+        .entries
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .collect();
+    entries.sort_by(|a, b| b.1.size().cmp(&a.1.size()).then_with(|| a.0.cmp(&b.0)));
+
+    let mut total = Histogram::default();
+    for (_, histogram) in &entries {
+        total.merge(histogram);
+    }
+
+    Ok(HistogramReport {
+        root_path: result.root_path,
+        total,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(
+            bucket_labels(),
+            ["<4K", "4K-64K", "64K-1M", "1M-16M", "16M-256M", "256M-1G", ">1G"]
+        );
+
+        let mut histogram = Histogram::default();
+        histogram.add(0, 0);
+        histogram.add(4095, 4096);
+        histogram.add(4096, 4096);
+        histogram.add(2 << 30, 2 << 30);
+        assert_eq!(histogram.counts, [2, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(histogram.bytes[0], 4096);
+        assert_eq!(histogram.file_count(), 4);
+        assert_eq!(histogram.size(), (2 << 30) + 8192);
+    }
+
+    #[test]
+    fn test_analyze_histogram() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/small"), vec![1u8; 100]).unwrap();
+        fs::write(temp_dir.path().join("a/medium"), vec![1u8; 100 << 10]).unwrap();
+        fs::write(temp_dir.path().join("top"), vec![1u8; 10]).unwrap();
+
+        let report = analyze_histogram(temp_dir.path(), &AnalysisConfig::default()).unwrap();
+        assert_eq!(report.total.counts[0], 2);
+        assert_eq!(report.total.counts[2], 1);
+        assert_eq!(report.entries[0].0, temp_dir.path().join("a"));
+        assert_eq!(report.entries[0].1.file_count(), 2);
+        assert_eq!(report.entries.len(), 2);
+    }
+}
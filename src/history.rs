@@ -0,0 +1,290 @@
+//! Growth tracking across repeated scans
+//!
+//! Every recorded scan stores the size of its root and of the directories
+//! below it, down to a fixed depth, in a local SQLite database. Running
+//! `dutop history record` from cron builds a timeline per directory that
+//! `dutop history show` turns into growth rates.
+//!
+//! Paths are stored as text, with non-UTF-8 names converted lossily.
+
+use crate::{AnalysisResult, DirectoryNode};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the database layout, kept in `PRAGMA user_version`
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS scans (
+        id INTEGER PRIMARY KEY,
+        root TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        total_size INTEGER NOT NULL,
+        file_count INTEGER NOT NULL,
+        dir_count INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sizes (
+        scan_id INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        file_count INTEGER NOT NULL,
+        PRIMARY KEY (scan_id, path)
+    );
+    CREATE INDEX IF NOT EXISTS sizes_by_path ON sizes(path);
+";
+
+/// Size of a directory at the time of one scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryPoint {
+    /// When the scan was recorded, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Disk usage in bytes
+    pub size: u64,
+    /// Number of files below the directory
+    pub file_count: usize,
+}
+
+/// Average growth in bytes per day between the first and last point (negative when shrinking)
+///
+/// Returns `None` with fewer than two points or when they were all taken at the same time.
+pub fn growth_per_day(points: &[HistoryPoint]) -> Option<f64> {
+    let (first, last) = (points.first()?, points.last()?);
+    let days = last.timestamp.checked_sub(first.timestamp)? as f64 / 86400.0;
+    (days > 0.0).then(|| (last.size as f64 - first.size as f64) / days)
+}
+
+/// Default location of the history database (e.g. `~/.local/share/dutop/history.db`)
+pub fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(dir.join("dutop").join("history.db"))
+}
+
+/// A history database
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Open the database at `path`, creating it and its directory if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open history database: {}", path.display()))?;
+        Self::init(conn)
+            .with_context(|| format!("Failed to open history database: {}", path.display()))
+    }
+
+    /// Open a database that lives in memory only
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "Database was written by a newer version of dutop (schema {})",
+                version
+            );
+        }
+        conn.execute_batch(SCHEMA)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        Ok(Self { conn })
+    }
+
+    /// Store `result`, taken at `timestamp`, with the directories up to
+    /// `max_depth` levels below its root
+    ///
+    /// Without a tree only the root and its top entries are stored.
+    /// Returns the number of directories stored.
+    pub fn record(
+        &mut self,
+        result: &AnalysisResult,
+        timestamp: u64,
+        max_depth: usize,
+    ) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO scans (root, timestamp, total_size, file_count, dir_count)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                result.root_path.to_string_lossy(),
+                timestamp as i64,
+                result.total_size as i64,
+                result.total_files as i64,
+                result.total_dirs as i64
+            ],
+        )?;
+        let scan_id = tx.last_insert_rowid();
+
+        let mut rows = vec![(
+            result.root_path.clone(),
+            result.total_size,
+            result.total_files,
+        )];
+        match &result.tree {
+            Some(tree) => {
+                for child in &tree.children {
+                    collect(child, 1, max_depth, &mut rows);
+                }
+            }
+            None => rows.extend(
+                result
+                    .top_directories
+                    .iter()
+                    .filter(|_| max_depth > 0)
+                    .map(|d| (d.path.clone(), d.size, d.file_count)),
+            ),
+        }
+
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO sizes (scan_id, path, size, file_count)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (path, size, file_count) in &rows {
+                insert.execute(params![
+                    scan_id,
+                    path.to_string_lossy(),
+                    *size as i64,
+                    *file_count as i64
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(rows.len())
+    }
+
+    /// Delete the scans recorded before `timestamp` with their sizes,
+    /// returning how many were deleted
+    pub fn prune(&mut self, timestamp: u64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM scans WHERE timestamp < ?1",
+            params![timestamp as i64],
+        )?)
+    }
+
+    /// The recorded sizes of `path`, oldest first, limited to the latest `limit` scans
+    pub fn timeline(&self, path: &Path, limit: usize) -> Result<Vec<HistoryPoint>> {
+        let mut query = self.conn.prepare(
+            "SELECT scans.timestamp, sizes.size, sizes.file_count
+             FROM sizes JOIN scans ON scans.id = sizes.scan_id
+             WHERE sizes.path = ?1
+             ORDER BY scans.timestamp DESC, scans.id DESC
+             LIMIT ?2",
+        )?;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut points = query
+            .query_map(params![path.to_string_lossy(), limit], |row| {
+                Ok(HistoryPoint {
+                    timestamp: row.get::<_, i64>(0)?.max(0) as u64,
+                    size: row.get::<_, i64>(1)?.max(0) as u64,
+                    file_count: row.get::<_, i64>(2)?.max(0) as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        points.reverse();
+        Ok(points)
+    }
+}
+
+fn collect(
+    node: &DirectoryNode,
+    depth: usize,
+    max_depth: usize,
+    rows: &mut Vec<(PathBuf, u64, usize)>,
+) {
+    if depth > max_depth {
+        return;
+    }
+    rows.push((node.path.clone(), node.size, node.file_count));
+    for child in &node.children {
+        collect(child, depth + 1, max_depth, rows);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(path: &str, size: u64, children: Vec<DirectoryNode>) -> DirectoryNode {
+        DirectoryNode {
+            path: PathBuf::from(path),
+            size,
+            file_count: 1,
+            dir_count: children.len(),
+            children,
+            files: Vec::new(),
+        }
+    }
+
+    fn scan(data_size: u64) -> AnalysisResult {
+        let tree = node(
+            "/srv",
+            data_size + 10,
+            vec![node(
+                "/srv/data",
+                data_size,
+                vec![node("/srv/data/deep", data_size, Vec::new())],
+            )],
+        );
+        AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: tree.size,
+            tree: Some(tree),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_and_timeline() {
+        let mut history = History::open_in_memory().unwrap();
+        assert_eq!(history.record(&scan(1_000), 86400, 1).unwrap(), 2);
+        history.record(&scan(3_000), 3 * 86400, 1).unwrap();
+        history.record(&scan(5_000), 5 * 86400, 1).unwrap();
+
+        let points = history.timeline(Path::new("/srv/data"), 10).unwrap();
+        let sizes: Vec<u64> = points.iter().map(|p| p.size).collect();
+        assert_eq!(sizes, [1_000, 3_000, 5_000]);
+        assert_eq!(growth_per_day(&points), Some(1_000.0));
+
+        // Deeper directories are not stored
+        assert!(history
+            .timeline(Path::new("/srv/data/deep"), 10)
+            .unwrap()
+            .is_empty());
+
+        let latest = history.timeline(Path::new("/srv"), 2).unwrap();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].timestamp, 3 * 86400);
+
+        assert_eq!(history.prune(3 * 86400).unwrap(), 1);
+        let points = history.timeline(Path::new("/srv/data"), 10).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp, 3 * 86400);
+    }
+
+    #[test]
+    fn test_reopen_database() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("nested/history.db");
+        History::open(&path)
+            .unwrap()
+            .record(&scan(1), 0, 0)
+            .unwrap();
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.timeline(Path::new("/srv"), 10).unwrap().len(), 1);
+        assert!(growth_per_day(&history.timeline(Path::new("/srv"), 10).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Minimal HTTP/1.1 server for `dutop serve`
+//!
+//! Only what scrapers and dashboards need is supported: requests without a
+//! body, answered on a thread per connection and closed after the response.
+
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest accepted request line or header line
+const MAX_LINE: u64 = 8 * 1024;
+/// Most header lines accepted in a request
+const MAX_HEADERS: usize = 100;
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// Method, e.g. `GET`
+    pub method: String,
+    /// Decoded path without the query string, e.g. `/metrics`
+    pub path: String,
+    /// Decoded query parameters in order of appearance
+    pub query: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Value of the first query parameter called `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parse a request line, e.g. `GET /scan?path=%2Fsrv HTTP/1.1`
+    fn parse_line(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?;
+        parts
+            .next()
+            .filter(|version| version.starts_with("HTTP/"))?;
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key), percent_decode(value))
+            })
+            .collect();
+        Some(Self {
+            method,
+            path: percent_decode(path),
+            query,
+        })
+    }
+
+    /// Read a request from `reader`, ignoring its headers
+    fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed HTTP request");
+
+        let request = Self::parse_line(&read_line(reader)?).ok_or_else(invalid)?;
+        for _ in 0..MAX_HEADERS {
+            if read_line(reader)?.is_empty() {
+                return Ok(request);
+            }
+        }
+        Err(invalid())
+    }
+}
+
+/// An HTTP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// Status code
+    pub status: u16,
+    /// Value of the `Content-Type` header
+    pub content_type: &'static str,
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// A `200 OK` response
+    pub fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    /// A plain text response with `status`
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into().into_bytes(),
+        }
+    }
+
+    /// A `404 Not Found` response
+    pub fn not_found() -> Self {
+        Self::text(404, "Not found\n")
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+/// Parse a listen address such as `:9184`, `127.0.0.1:8080` or `localhost:8080`
+///
+/// A missing host listens on all interfaces.
+pub fn parse_listen_addr(text: &str) -> Result<SocketAddr> {
+    let text = text.trim();
+    let with_host;
+    let text = if text.starts_with(':') {
+        with_host = format!("0.0.0.0{}", text);
+        &with_host
+    } else {
+        text
+    };
+    text.to_socket_addrs()
+        .with_context(|| format!("Invalid listen address: {}", text))?
+        .next()
+        .with_context(|| format!("Invalid listen address: {}", text))
+}
+
+/// Bind to `addr`
+pub fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))
+}
+
+/// Answer every connection to `listener` with `handler`, forever
+pub fn serve<H>(listener: TcpListener, handler: H) -> Result<()>
+where
+    H: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let handler = Arc::clone(&handler);
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, handler.as_ref()) {
+                log::debug!("HTTP connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    handler: &(impl Fn(&Request) -> Response + ?Sized),
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match Request::read(&mut BufReader::new(&stream)) {
+        Ok(request) => {
+            log::debug!("{} {}", request.method, request.path);
+            handler(&request)
+        }
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Response::text(400, "Bad request\n"),
+        Err(e) => return Err(e),
+    };
+    response.write_to(&mut stream)
+}
+
+/// Read one line without its line ending, failing on overly long lines
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Malformed HTTP request",
+        ));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Decode `%XX` escapes and `+` as a space, keeping malformed escapes as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(byte) = hex {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let mut input =
+            "GET /scan?path=%2Fsrv%2Fmy+data&depth=2&flag HTTP/1.1\r\nHost: x\r\n\r\n".as_bytes();
+        let request = Request::read(&mut input).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/scan");
+        assert_eq!(request.param("path"), Some("/srv/my data"));
+        assert_eq!(request.param("depth"), Some("2"));
+        assert_eq!(request.param("flag"), Some(""));
+        assert_eq!(request.param("missing"), None);
+
+        let mut input = "garbage\r\n\r\n".as_bytes();
+        assert!(Request::read(&mut input).is_err());
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(
+            parse_listen_addr(":9184").unwrap(),
+            "0.0.0.0:9184".parse().unwrap()
+        );
+        assert_eq!(
+            parse_listen_addr("127.0.0.1:8080").unwrap(),
+            "127.0.0.1:8080".parse().unwrap()
+        );
+        assert!(parse_listen_addr("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            serve(listener, |request| match request.path.as_str() {
+                "/hello" => Response::ok("text/plain", "hi"),
+                _ => Response::not_found(),
+            })
+        });
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/hello");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nhi"));
+        assert!(get("/other").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
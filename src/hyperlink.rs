@@ -0,0 +1,126 @@
+//! OSC 8 hyperlinks in the terminal output
+//!
+//! Terminals supporting the OSC 8 escape sequence turn the text between its
+//! opening and closing sequence into a link, so a click on a row of the table
+//! or the tree opens the entry in the file manager. Terminals that do not
+//! support it are supposed to ignore it, but some print it as garbage, so
+//! links are only written where support is known.
+
+use std::path::Path;
+
+/// When entries are written as hyperlinks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HyperlinkChoice {
+    /// When writing to a terminal known to support them
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl HyperlinkChoice {
+    /// Whether output going to a terminal when `is_terminal` is set, or
+    /// elsewhere, has hyperlinks
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            HyperlinkChoice::Always => true,
+            HyperlinkChoice::Never => false,
+            HyperlinkChoice::Auto => is_terminal && supported(),
+        }
+    }
+}
+
+/// Whether the terminal dutop runs in is known to support hyperlinks
+pub fn supported() -> bool {
+    let var = |name| std::env::var(name).unwrap_or_default();
+    supported_by(
+        &var("TERM"),
+        &var("TERM_PROGRAM"),
+        &var("VTE_VERSION"),
+        std::env::var_os("WT_SESSION").is_some() || std::env::var_os("KONSOLE_VERSION").is_some(),
+    )
+}
+
+/// Whether a terminal with these `TERM`, `TERM_PROGRAM` and `VTE_VERSION`
+/// values supports hyperlinks, as Windows Terminal and Konsole, found by their
+/// own variables and flagged by `known`, always do
+fn supported_by(term: &str, program: &str, vte_version: &str, known: bool) -> bool {
+    if term == "dumb" {
+        return false;
+    }
+    // VTE terminals (GNOME Terminal, Tilix, ...) support them from 0.50
+    let vte = vte_version
+        .parse::<u32>()
+        .is_ok_and(|version| version >= 5000);
+    known
+        || vte
+        || matches!(
+            program,
+            "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper" | "rio"
+        )
+        || ["kitty", "foot", "alacritty", "wezterm", "ghostty"]
+            .iter()
+            .any(|name| term.contains(name))
+}
+
+/// `file://` URL of the absolute `path`, with the bytes URLs do not allow
+/// percent-encoded
+pub fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    // Windows paths like C:\Users or \\?\C:\Users become file:///C:/Users
+    let path = path
+        .strip_prefix(r"\\?\")
+        .unwrap_or(&path)
+        .replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+/// `text` as a hyperlink to `path`
+///
+/// The escape sequences take no room on the screen, so `text` can be padded
+/// or truncated before being linked, but not after.
+pub fn link(text: &str, path: &Path) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", file_url(path), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_url() {
+        assert_eq!(file_url(Path::new("/srv/data")), "file:///srv/data");
+        assert_eq!(
+            file_url(Path::new("/home/me/My Files/naïve#1")),
+            "file:///home/me/My%20Files/na%C3%AFve%231"
+        );
+        assert_eq!(
+            link("data", Path::new("/srv/data")),
+            "\x1b]8;;file:///srv/data\x1b\\data\x1b]8;;\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_supported_by() {
+        assert!(supported_by("xterm-kitty", "", "", false));
+        assert!(supported_by("xterm-256color", "iTerm.app", "", false));
+        assert!(supported_by("xterm-256color", "", "7600", false));
+        assert!(supported_by("xterm-256color", "", "", true));
+        assert!(!supported_by("xterm-256color", "", "4800", false));
+        assert!(!supported_by("xterm-256color", "Apple_Terminal", "", false));
+        assert!(!supported_by("dumb", "WezTerm", "", true));
+        assert!(!HyperlinkChoice::Auto.enabled(false));
+        assert!(HyperlinkChoice::Always.enabled(false));
+    }
+}
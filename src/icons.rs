@@ -0,0 +1,211 @@
+//! Icons telling the kinds of entries apart in the table and the tree
+//!
+//! Directories are recognized the way [`crate::clean`] and
+//! [`crate::projects`] recognize them (a `node_modules/`, a cache tagged with
+//! `CACHEDIR.TAG`, a directory holding a `Cargo.toml` or a `.git`), media
+//! directories by their usual names, and files by their extension. Nerd Font
+//! icons need a patched font in the terminal; emoji work with any font that has
+//! them but take two columns.
+
+use crate::{clean, projects, types};
+use anyhow::Result;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Set of icons used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconStyle {
+    /// Glyphs of the Nerd Fonts
+    NerdFont,
+    Emoji,
+}
+
+impl FromStr for IconStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nerd" => Ok(IconStyle::NerdFont),
+            "emoji" => Ok(IconStyle::Emoji),
+            _ => anyhow::bail!("Invalid icon style: {} (expected nerd or emoji)", s),
+        }
+    }
+}
+
+/// Kind of entry an icon stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Folder,
+    GitRepo,
+    NodeProject,
+    RustProject,
+    PythonProject,
+    GoProject,
+    JavaProject,
+    /// Installed dependencies, like `node_modules/`
+    Dependencies,
+    /// Build output, like a cargo `target/`
+    BuildOutput,
+    /// Caches and virtual environments that can be rebuilt
+    Cache,
+    /// Docker image layers and container filesystems
+    Docker,
+    /// Movies, music or pictures
+    Media,
+    File,
+    Video,
+    Audio,
+    Image,
+    Archive,
+}
+
+/// Names of directories usually holding media, compared without case
+const MEDIA_DIRS: &[&str] = &[
+    "movies", "videos", "music", "pictures", "photos", "dcim", "podcasts",
+];
+
+/// Names of cache directories not recognized by [`clean::classify`]
+const CACHE_DIRS: &[&str] = &[".cache", "cache", "caches"];
+
+impl EntryKind {
+    /// Kind of the directory at `path`
+    pub fn of_dir(path: &Path) -> Self {
+        if let Some((category, _)) = clean::classify(path) {
+            return match category {
+                clean::Category::BuildOutput => EntryKind::BuildOutput,
+                clean::Category::Dependencies => EntryKind::Dependencies,
+                clean::Category::Docker => EntryKind::Docker,
+                clean::Category::VirtualEnv
+                | clean::Category::ToolCache
+                | clean::Category::PackageCache
+                | clean::Category::Cache => EntryKind::Cache,
+            };
+        }
+        let kinds = projects::detect(path);
+        // The language of a project says more than it being a repository
+        let kind = kinds.iter().find_map(|&kind| match kind {
+            "npm" => Some(EntryKind::NodeProject),
+            "cargo" => Some(EntryKind::RustProject),
+            "python" => Some(EntryKind::PythonProject),
+            "go" => Some(EntryKind::GoProject),
+            "maven" | "gradle" => Some(EntryKind::JavaProject),
+            _ => None,
+        });
+        if let Some(kind) = kind {
+            return kind;
+        }
+        if kinds.contains(&"git") {
+            return EntryKind::GitRepo;
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if MEDIA_DIRS.contains(&name.as_str()) {
+            EntryKind::Media
+        } else if CACHE_DIRS.contains(&name.as_str()) {
+            EntryKind::Cache
+        } else {
+            EntryKind::Folder
+        }
+    }
+
+    /// Kind of the entry at `path`, taken for a directory unless it is a file
+    pub fn of_path(path: &Path) -> Self {
+        if path.is_file() {
+            Self::of_file(path)
+        } else {
+            Self::of_dir(path)
+        }
+    }
+
+    /// Kind of the file at `path`, told by its extension
+    pub fn of_file(path: &Path) -> Self {
+        let extension = types::extension(path).unwrap_or_default();
+        match types::Category::from_extension(&extension) {
+            types::Category::Video => EntryKind::Video,
+            types::Category::Audio => EntryKind::Audio,
+            types::Category::Image => EntryKind::Image,
+            types::Category::Archive | types::Category::DiskImage => EntryKind::Archive,
+            _ => EntryKind::File,
+        }
+    }
+
+    /// Icon of this kind in `style`
+    pub fn icon(self, style: IconStyle) -> &'static str {
+        match style {
+            IconStyle::NerdFont => match self {
+                EntryKind::Folder => "\u{f07b}",
+                EntryKind::GitRepo => "\u{e702}",
+                EntryKind::NodeProject => "\u{e718}",
+                EntryKind::RustProject => "\u{e7a8}",
+                EntryKind::PythonProject => "\u{e73c}",
+                EntryKind::GoProject => "\u{e627}",
+                EntryKind::JavaProject => "\u{e738}",
+                EntryKind::Dependencies => "\u{e5fa}",
+                EntryKind::BuildOutput => "\u{f085}",
+                EntryKind::Cache => "\u{f1da}",
+                EntryKind::Docker => "\u{e7b0}",
+                EntryKind::Media => "\u{f008}",
+                EntryKind::File => "\u{f15b}",
+                EntryKind::Video => "\u{f03d}",
+                EntryKind::Audio => "\u{f001}",
+                EntryKind::Image => "\u{f03e}",
+                EntryKind::Archive => "\u{f410}",
+            },
+            IconStyle::Emoji => match self {
+                EntryKind::Folder => "📁",
+                EntryKind::GitRepo => "🌿",
+                EntryKind::NodeProject => "🟢",
+                EntryKind::RustProject => "🦀",
+                EntryKind::PythonProject => "🐍",
+                EntryKind::GoProject => "🐹",
+                EntryKind::JavaProject => "☕",
+                EntryKind::Dependencies => "📚",
+                EntryKind::BuildOutput => "🔨",
+                EntryKind::Cache => "🧹",
+                EntryKind::Docker => "🐳",
+                EntryKind::Media => "🎬",
+                EntryKind::File => "📄",
+                EntryKind::Video => "📼",
+                EntryKind::Audio => "🎵",
+                EntryKind::Image => "📷",
+                EntryKind::Archive => "📦",
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_entry_kinds() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let dir = |name: &str, marker: Option<&str>| {
+            let path = root.join(name);
+            fs::create_dir_all(&path).unwrap();
+            if let Some(marker) = marker {
+                fs::write(path.join(marker), b"").unwrap();
+            }
+            EntryKind::of_dir(&path)
+        };
+        assert_eq!(dir("site", Some("package.json")), EntryKind::NodeProject);
+        assert_eq!(dir("site/node_modules", None), EntryKind::Dependencies);
+        assert_eq!(dir("dotfiles", Some(".git")), EntryKind::GitRepo);
+        assert_eq!(dir("Music", None), EntryKind::Media);
+        assert_eq!(dir(".cache", None), EntryKind::Cache);
+        assert_eq!(dir("tagged", Some("CACHEDIR.TAG")), EntryKind::Cache);
+        assert_eq!(dir("docs", None), EntryKind::Folder);
+
+        assert_eq!(EntryKind::of_file(Path::new("clip.MKV")), EntryKind::Video);
+        assert_eq!(EntryKind::of_file(Path::new("notes")), EntryKind::File);
+        assert_eq!(EntryKind::Video.icon(IconStyle::Emoji), "📼");
+        assert_eq!("nerd".parse::<IconStyle>().unwrap(), IconStyle::NerdFont);
+        assert!("fancy".parse::<IconStyle>().is_err());
+    }
+}
@@ -0,0 +1,104 @@
+//! Remembering which inodes have already been counted
+//!
+//! The exact set grows with every file that may be a hard link. For scans of
+//! hundreds of millions of files the approximate mode uses a Bloom filter of
+//! fixed size instead: memory stays bounded, at the price of a small chance of
+//! taking a file that was never seen for an extra link to one that was, and
+//! leaving it out of the totals.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Size of the Bloom filter in bits (16 MiB), which keeps false positives
+/// around 1% up to roughly 14 million tracked inodes
+const BLOOM_BITS: usize = 1 << 27;
+/// Number of bits set per inode
+const BLOOM_HASHES: u64 = 7;
+
+/// Inodes seen during a walk, as `(device, inode)` pairs
+pub(crate) enum SeenInodes {
+    Exact(Mutex<HashSet<(u64, u64)>>),
+    Approximate(BloomFilter),
+}
+
+impl SeenInodes {
+    pub(crate) fn new(approximate: bool) -> Self {
+        if approximate {
+            SeenInodes::Approximate(BloomFilter::new(BLOOM_BITS))
+        } else {
+            SeenInodes::Exact(Mutex::new(HashSet::new()))
+        }
+    }
+
+    /// Record `key`, returning whether it had not been seen before
+    pub(crate) fn insert(&self, key: (u64, u64)) -> bool {
+        match self {
+            SeenInodes::Exact(set) => set.lock().unwrap_or_else(|e| e.into_inner()).insert(key),
+            SeenInodes::Approximate(filter) => filter.insert(key),
+        }
+    }
+}
+
+/// Lock-free Bloom filter over `(device, inode)` pairs
+pub(crate) struct BloomFilter {
+    words: Box<[AtomicU64]>,
+    mask: u64,
+}
+
+impl BloomFilter {
+    /// Filter of `bits` bits, rounded up to a power of two
+    fn new(bits: usize) -> Self {
+        let bits = bits.next_power_of_two().max(64);
+        Self {
+            words: (0..bits / 64).map(|_| AtomicU64::new(0)).collect(),
+            mask: bits as u64 - 1,
+        }
+    }
+
+    /// Set the bits of `key`, returning whether any of them was still clear
+    ///
+    /// Two names of the same inode reached at the same moment may both be
+    /// reported as new.
+    fn insert(&self, (device, inode): (u64, u64)) -> bool {
+        // Double hashing derives every probe from two independent hashes
+        let first = mix(inode ^ mix(device));
+        let step = mix(first) | 1;
+        let mut added = false;
+        for i in 0..BLOOM_HASHES {
+            let bit = first.wrapping_add(i.wrapping_mul(step)) & self.mask;
+            let flag = 1 << (bit % 64);
+            let previous = self.words[(bit / 64) as usize].fetch_or(flag, Ordering::Relaxed);
+            added |= previous & flag == 0;
+        }
+        added
+    }
+}
+
+/// The splitmix64 finalizer, scattering nearby inode numbers across the filter
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let filter = BloomFilter::new(1 << 20);
+        let keys = (0..10_000u64).map(|inode| (42, inode));
+        let added = keys.clone().filter(|&key| filter.insert(key)).count();
+        // Sized for far more keys, so collisions are very unlikely
+        assert!(added >= 9_990);
+        assert!(keys.clone().all(|key| !filter.insert(key)));
+
+        let exact = SeenInodes::new(false);
+        assert!(exact.insert((1, 2)));
+        assert!(!exact.insert((1, 2)));
+        assert!(exact.insert((2, 2)));
+    }
+}
@@ -4,16 +4,87 @@
 //! traversal options, exclusion patterns, and output formatting.
 
 use anyhow::{Context, Result};
-use rayon::prelude::*;
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
-use walkdir::{DirEntry, WalkDir};
+use std::time::{Duration, SystemTime};
 
+pub mod age;
+#[cfg(feature = "webhook")]
+pub mod alert;
+pub mod api;
+pub mod archive;
+#[cfg(feature = "tokio")]
+mod async_api;
+#[cfg(unix)]
+pub mod audit;
+pub mod cache;
+pub mod cancel;
+pub mod checkpoint;
+pub mod clean;
+pub mod config;
+pub mod counts;
+pub mod daemon;
+pub mod dedupe;
+pub mod delete;
+pub mod depth;
+pub mod diff;
+pub mod docker;
+pub mod empty;
+pub mod estimate;
+mod exclude;
+pub mod export;
 pub mod format;
+pub mod git;
+pub mod hardlinks;
+pub mod histogram;
+pub mod history;
+pub mod http;
+pub mod hyperlink;
+pub mod icons;
+mod inodes;
+#[cfg(target_os = "macos")]
+mod macos;
+pub mod metrics;
+#[cfg(any(windows, test))]
+mod mft;
+pub mod mounts;
 pub mod output;
+#[cfg(unix)]
+pub mod owners;
+pub mod progress;
+pub mod projects;
+pub mod remote;
+pub mod render;
+pub mod report;
+#[cfg(feature = "object-store")]
+pub mod s3;
+pub mod snapshot;
+pub mod stats;
+#[cfg(target_os = "linux")]
+mod statx;
+pub mod stream;
+pub mod theme;
+pub mod threshold;
+mod top;
+mod trie;
+pub mod tui;
+pub mod types;
+mod walk;
+pub mod watch;
+mod watchdog;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(feature = "tokio")]
+pub use async_api::analyze_disk_usage_async;
+pub use cancel::CancellationToken;
+pub use exclude::read_exclude_file;
+pub use progress::{ProgressCallback, ScanProgress};
+pub use stream::{scan_entries, ScannedEntry, ScannedEntryKind};
 
 /// Configuration options for disk usage analysis
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnalysisConfig {
     /// Maximum depth to traverse (None = unlimited)
     pub max_depth: Option<usize>,
@@ -21,23 +92,142 @@ pub struct AnalysisConfig {
     pub exclude_patterns: Vec<String>,
     /// Whether to follow symbolic links
     pub follow_links: bool,
-    /// Number of threads for parallel processing (None = auto-detect)
+    /// Number of threads for parallel processing (None = picked for the
+    /// storage holding the path, see [`mounts::StorageKind`])
     pub num_threads: Option<usize>,
+    /// Whether to build the full directory tree with cumulative sizes
+    pub build_tree: bool,
+    /// Whether tree nodes also list the individual files they contain
+    pub tree_files: bool,
+    /// Whether to stay on the filesystem of the root path (like `du -x`)
+    pub one_file_system: bool,
+    /// Whether to skip entries ignored by `.gitignore`, `.ignore` and `.git/info/exclude`
+    pub respect_gitignore: bool,
+    /// Only count files last modified before this time
+    pub modified_before: Option<SystemTime>,
+    /// Only count files last modified after this time
+    pub modified_after: Option<SystemTime>,
+    /// Skip files whose apparent size is below this many bytes
+    pub min_file_size: Option<u64>,
+    /// Add the size of NTFS alternate data streams to each file (Windows only)
+    pub alternate_streams: bool,
+    /// Detect APFS clones sharing their blocks with other files (macOS only)
+    pub detect_clones: bool,
+    /// Show the contents of tar and zip archives as virtual subtrees of the
+    /// directory tree, and accept an archive as the root path
+    pub expand_archives: bool,
+    /// Whether to record broken symbolic links, symlink loops and links
+    /// leading outside the root path
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub check_links: bool,
+    /// Whether to count symbolic links and special files (sockets, FIFOs,
+    /// devices) as files, adding their disk usage, instead of only tallying them
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub count_all_types: bool,
+    /// Whether to track hard links with a fixed-size Bloom filter instead of
+    /// an exact set, bounding memory on huge filesystems; a linked file may
+    /// then rarely be skipped as an extra link to another one
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fast_hardlinks: bool,
+    /// Whether to remember the inode of every file rather than only of those
+    /// with more than one link, for filesystems reporting unreliable link counts
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub track_all_inodes: bool,
+    /// How directories are read and entries stat'ed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub backend: ScanBackend,
+    /// File reusing the totals of directories unchanged since the previous
+    /// scan with the same cache (see [`cache::cache_file`]); ignored by scans
+    /// following links, filtering by age or by `.gitignore`, listing files,
+    /// expanding archives, checking links, detecting clones or tracking all
+    /// inodes
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cache: Option<PathBuf>,
+    /// File the totals of the completed directories are written to every
+    /// minute (see [`checkpoint::checkpoint_file`]); a scan finding a
+    /// checkpoint of the same root there resumes from it, and the file is
+    /// removed once the scan completes. Ignored by scans with a visitor or
+    /// building a tree
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub checkpoint: Option<PathBuf>,
+    /// Whether to measure the speed of the walk, the work the cache saved,
+    /// the time each worker spent in filesystem calls and the peak memory use
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub collect_stats: bool,
+    /// What to do with network filesystems (NFS, SMB, SSHFS, ...) mounted
+    /// below the root path
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub remote_fs: RemoteFs,
+    /// Give up on reading a directory or the metadata of an entry after this
+    /// long, skipping it with a [`ScanErrorKind::TimedOut`] error instead of
+    /// hanging on a dead network server or FUSE mount (None = wait forever)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub entry_timeout: Option<Duration>,
+    /// Whether to walk pseudo filesystems mounted below the root path (such as
+    /// `/proc`, `/sys`, `/dev` and `/run`), which are left out by default
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub include_virtual: bool,
+}
+
+/// System interface used to read directories and the metadata of their entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ScanBackend {
+    /// The portable `std::fs` directory iterator and metadata
+    #[default]
+    Walkdir,
+    /// Large `getdents64` batches and `statx` without forcing network
+    /// filesystems to sync (Linux only, elsewhere the portable backend is
+    /// used); scans with an [`EntryVisitor`] always use the portable backend,
+    /// since visitors receive `std::fs::Metadata`
+    Uring,
+    /// Read the Master File Table of NTFS volumes instead of directories
+    /// (Windows only, needs administrator rights); falls back to walking
+    /// directories when the MFT cannot be read or a visitor is given
+    Mft,
 }
 
-impl Default for AnalysisConfig {
-    fn default() -> Self {
-        Self {
-            max_depth: None,
-            exclude_patterns: Vec::new(),
-            follow_links: false,
-            num_threads: None,
+/// How network filesystems mounted below the root path are scanned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RemoteFs {
+    /// Walk them like local directories
+    Scan,
+    /// Walk them, logging a warning for each and using fewer threads unless
+    /// a thread count was set
+    #[default]
+    Warn,
+    /// Leave them out, without even reading the metadata of their mount point
+    Skip,
+}
+
+impl RemoteFs {
+    /// Stable identifier used in machine-readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RemoteFs::Scan => "scan",
+            RemoteFs::Warn => "warn",
+            RemoteFs::Skip => "skip",
+        }
+    }
+}
+
+impl ScanBackend {
+    /// Stable identifier used in machine-readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanBackend::Walkdir => "walkdir",
+            ScanBackend::Uring => "uring",
+            ScanBackend::Mft => "mft",
         }
     }
 }
 
 /// Represents a directory entry with its size information
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DirectoryEntry {
     /// Path to the directory
     pub path: PathBuf,
@@ -47,10 +237,49 @@ pub struct DirectoryEntry {
     pub file_count: usize,
     /// Number of subdirectories
     pub dir_count: usize,
+    /// Most recent modification time of the files below it, in seconds since
+    /// the Unix epoch (None when unknown or when it holds no files)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub modified: Option<u64>,
+}
+
+/// Entries that are neither regular files nor directories, by type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OtherTypes {
+    /// Symbolic links (not followed ones included)
+    pub symlinks: usize,
+    /// Total length of the targets stored in those symbolic links, in bytes
+    pub symlink_size: u64,
+    /// Unix domain sockets
+    pub sockets: usize,
+    /// Named pipes
+    pub fifos: usize,
+    /// Block device nodes
+    pub block_devices: usize,
+    /// Character device nodes
+    pub char_devices: usize,
+}
+
+impl OtherTypes {
+    /// Number of sockets, FIFOs and device nodes
+    pub fn special_files(&self) -> usize {
+        self.sockets + self.fifos + self.block_devices + self.char_devices
+    }
+
+    pub(crate) fn merge(&mut self, other: &OtherTypes) {
+        self.symlinks += other.symlinks;
+        self.symlink_size += other.symlink_size;
+        self.sockets += other.sockets;
+        self.fifos += other.fifos;
+        self.block_devices += other.block_devices;
+        self.char_devices += other.char_devices;
+    }
 }
 
 /// Results of disk usage analysis
-#[derive(Debug)]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnalysisResult {
     /// Path that was analyzed
     pub root_path: PathBuf,
@@ -62,6 +291,212 @@ pub struct AnalysisResult {
     pub total_dirs: usize,
     /// Top directories sorted by size
     pub top_directories: Vec<DirectoryEntry>,
+    /// Full directory tree rooted at `root_path` (only when `build_tree` is set)
+    pub tree: Option<DirectoryNode>,
+    /// Whether the analysis was cancelled, leaving the results partial
+    pub cancelled: bool,
+    /// Entries that could not be read and were skipped
+    pub errors: Vec<ScanError>,
+    /// Additional links to files that were already counted through another hard link
+    pub hard_links: usize,
+    /// Disk usage not counted twice thanks to hard link deduplication, in bytes
+    pub hard_link_savings: u64,
+    /// Symbolic links, sockets, FIFOs and device nodes found
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub other_types: OtherTypes,
+    /// Disk usage of files sharing their blocks with a file counted earlier
+    /// (APFS clones, only when `detect_clones` is set), included in `total_size`
+    pub cloned_size: u64,
+    /// Files whose contents are only stored in the cloud (OneDrive and other
+    /// cloud file placeholders on Windows, dataless iCloud files on macOS)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cloud_files: usize,
+    /// Logical size of those files in bytes; `total_size` only counts the
+    /// part stored locally
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cloud_size: u64,
+    /// Capacity of the filesystem holding `root_path`, when it could be queried
+    pub filesystem: Option<mounts::FsUsage>,
+    /// Most recent modification time of the files analyzed, in seconds since
+    /// the Unix epoch
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub modified: Option<u64>,
+    /// Problematic symbolic links found (only when `check_links` is set)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub link_issues: Vec<LinkIssue>,
+    /// Performance figures of the scan (only when `collect_stats` is set)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub stats: Option<stats::ScanStats>,
+}
+
+impl AnalysisResult {
+    /// Disk usage with blocks shared by clones counted once
+    pub fn unique_size(&self) -> u64 {
+        self.total_size - self.cloned_size
+    }
+
+    /// Share of the filesystem's capacity taken by the analyzed files, in percent
+    pub fn capacity_percentage(&self) -> Option<f64> {
+        self.filesystem
+            .filter(|fs| fs.total > 0)
+            .map(|fs| self.total_size as f64 / fs.total as f64 * 100.0)
+    }
+}
+
+/// An entry skipped during the analysis because it could not be read
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanError {
+    /// Path of the entry
+    pub path: PathBuf,
+    /// Category of the failure
+    pub kind: ScanErrorKind,
+    /// Description of the underlying error
+    pub message: String,
+}
+
+/// Category of a [`ScanError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ScanErrorKind {
+    /// The entry could not be read due to missing permissions
+    PermissionDenied,
+    /// The entry disappeared while scanning
+    NotFound,
+    /// Following a symbolic link led back to one of its ancestors
+    SymlinkLoop,
+    /// Reading the entry did not return within the entry timeout
+    TimedOut,
+    /// Any other I/O error
+    Io,
+}
+
+impl ScanErrorKind {
+    /// Stable identifier used in machine-readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanErrorKind::PermissionDenied => "permission_denied",
+            ScanErrorKind::NotFound => "not_found",
+            ScanErrorKind::SymlinkLoop => "symlink_loop",
+            ScanErrorKind::TimedOut => "timed_out",
+            ScanErrorKind::Io => "io",
+        }
+    }
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+impl From<std::io::ErrorKind> for ScanErrorKind {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+            std::io::ErrorKind::NotFound => ScanErrorKind::NotFound,
+            std::io::ErrorKind::TimedOut => ScanErrorKind::TimedOut,
+            _ => ScanErrorKind::Io,
+        }
+    }
+}
+
+/// A symbolic link that is broken, part of a loop or leads outside the root
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkIssue {
+    /// Path of the link
+    pub path: PathBuf,
+    /// Target the link points to, as stored in the link
+    pub target: PathBuf,
+    /// What is wrong with the link
+    pub kind: LinkIssueKind,
+}
+
+/// Category of a [`LinkIssue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LinkIssueKind {
+    /// The target does not exist
+    Broken,
+    /// Resolving the link leads back to itself or to one of its ancestors
+    Loop,
+    /// The target exists but lies outside the analyzed root
+    OutsideRoot,
+}
+
+impl LinkIssueKind {
+    /// Stable identifier used in machine-readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkIssueKind::Broken => "broken",
+            LinkIssueKind::Loop => "loop",
+            LinkIssueKind::OutsideRoot => "outside_root",
+        }
+    }
+}
+
+/// A directory in the full tree, with sizes accumulated over its whole subtree
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirectoryNode {
+    /// Path to the directory
+    pub path: PathBuf,
+    /// Total size in bytes of all files below this directory
+    pub size: u64,
+    /// Number of files below this directory
+    pub file_count: usize,
+    /// Number of directories below this directory (excluding itself)
+    pub dir_count: usize,
+    /// Immediate subdirectories, sorted by size
+    pub children: Vec<DirectoryNode>,
+    /// Files stored directly in this directory, sorted by size
+    /// (only when `tree_files` is set)
+    pub files: Vec<FileNode>,
+}
+
+/// A file listed in the directory tree
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileNode {
+    /// File name within its directory
+    pub name: std::ffi::OsString,
+    /// Disk usage in bytes
+    pub size: u64,
+    /// Apparent (logical) size in bytes
+    pub apparent_size: u64,
+}
+
+impl DirectoryNode {
+    /// Iterate over this node and all of its descendants in depth-first order
+    pub fn iter(&self) -> impl Iterator<Item = &DirectoryNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// Find the node for `path` within this subtree
+    pub fn find(&self, path: &Path) -> Option<&DirectoryNode> {
+        if path == self.path {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .find(|child| path.starts_with(&child.path))
+            .and_then(|child| child.find(path))
+    }
+
+    /// Size of the files stored directly in this directory
+    pub fn own_size(&self) -> u64 {
+        self.size - self.children.iter().map(|c| c.size).sum::<u64>()
+    }
 }
 
 /// Analyzes disk usage for the given path with specified configuration
@@ -77,82 +512,260 @@ pub fn analyze_disk_usage(
     path: &Path,
     config: &AnalysisConfig,
     top_n: usize,
+) -> Result<AnalysisResult> {
+    analyze_with_hooks(path, config, top_n, &ScanHooks::default())
+}
+
+/// Analyzes disk usage like [`analyze_disk_usage`], reporting progress to `progress`
+pub fn analyze_disk_usage_with_progress(
+    path: &Path,
+    config: &AnalysisConfig,
+    top_n: usize,
+    progress: &dyn ProgressCallback,
+) -> Result<AnalysisResult> {
+    let hooks = ScanHooks {
+        progress: Some(progress),
+        ..Default::default()
+    };
+    analyze_with_hooks(path, config, top_n, &hooks)
+}
+
+/// Analyzes disk usage like [`analyze_disk_usage`], stopping early once `token` is cancelled
+///
+/// A cancelled analysis still succeeds: it returns the partial results gathered
+/// so far with [`AnalysisResult::cancelled`] set.
+pub fn analyze_disk_usage_with_cancel(
+    path: &Path,
+    config: &AnalysisConfig,
+    top_n: usize,
+    token: &CancellationToken,
+) -> Result<AnalysisResult> {
+    let hooks = ScanHooks {
+        cancel: Some(token),
+        ..Default::default()
+    };
+    analyze_with_hooks(path, config, top_n, &hooks)
+}
+
+/// Merge the analyses of several roots into a single ranking with a grand total
+///
+/// The combined result is rooted at the deepest common ancestor of the roots.
+/// Its entries are the immediate children of every root, ranked together, and
+/// its tree (when every analysis has one) holds each root as a child. Files
+/// hard-linked across roots are counted once per root. No filesystem capacity
+/// is reported, since the roots may live on different filesystems.
+pub fn combine_results(results: Vec<AnalysisResult>, top_n: usize) -> AnalysisResult {
+    let root_path = results
+        .iter()
+        .map(|r| r.root_path.clone())
+        .reduce(|common, path| common_ancestor(&common, &path))
+        .unwrap_or_default();
+    let has_trees = results.iter().all(|r| r.tree.is_some());
+
+    let mut combined = AnalysisResult {
+        root_path,
+        ..Default::default()
+    };
+    let mut trees = Vec::new();
+    for result in results {
+        combined.total_size += result.total_size;
+        combined.total_files += result.total_files;
+        // Each root is a directory below the common ancestor
+        combined.total_dirs += result.total_dirs + 1;
+        combined.cancelled |= result.cancelled;
+        combined.hard_links += result.hard_links;
+        combined.hard_link_savings += result.hard_link_savings;
+        combined.other_types.merge(&result.other_types);
+        combined.cloned_size += result.cloned_size;
+        combined.cloud_files += result.cloud_files;
+        combined.cloud_size += result.cloud_size;
+        combined.modified = combined.modified.max(result.modified);
+        combined.errors.extend(result.errors);
+        if let Some(stats) = &result.stats {
+            combined
+                .stats
+                .get_or_insert_with(Default::default)
+                .merge(stats);
+        }
+        combined.top_directories.extend(result.top_directories);
+        trees.extend(result.tree);
+    }
+
+    combined
+        .top_directories
+        .sort_unstable_by_key(|d| std::cmp::Reverse(d.size));
+    combined.top_directories.truncate(top_n);
+
+    if has_trees {
+        trees.sort_unstable_by_key(|t| std::cmp::Reverse(t.size));
+        combined.tree = Some(DirectoryNode {
+            path: combined.root_path.clone(),
+            size: combined.total_size,
+            file_count: combined.total_files,
+            dir_count: combined.total_dirs,
+            children: trees,
+            files: Vec::new(),
+        });
+    }
+
+    combined
+}
+
+/// Immediate child of `root` holding `path`, when `path` is below `root`
+pub(crate) fn root_child(root: &Path, path: &Path) -> Option<PathBuf> {
+    match path.strip_prefix(root).ok()?.components().next()? {
+        std::path::Component::Normal(name) => Some(root.join(name)),
+        _ => None,
+    }
+}
+
+/// Longest path that both `a` and `b` start with
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Observers attached to a single analysis
+#[derive(Default)]
+pub(crate) struct ScanHooks<'a> {
+    pub progress: Option<&'a dyn ProgressCallback>,
+    pub cancel: Option<&'a CancellationToken>,
+    pub visitor: Option<&'a dyn EntryVisitor>,
+}
+
+/// Receives the entries reached by the walker, from any worker thread
+pub(crate) trait EntryVisitor: Sync {
+    /// Called once per counted file with its disk usage in bytes
+    fn visit_file(&self, path: &Path, metadata: &std::fs::Metadata, size: u64);
+
+    /// Called when the walk enters a directory below the root, before its contents
+    fn visit_dir(&self, _path: &Path) {}
+
+    /// Called for every entry skipped because of an error
+    fn visit_error(&self, _error: &ScanError) {}
+
+    /// Called for a file skipped because its inode was already counted through
+    /// another hard link, with the disk usage it would have added
+    fn visit_hard_link(&self, _path: &Path, _metadata: &std::fs::Metadata, _size: u64) {}
+}
+
+fn analyze_with_hooks(
+    path: &Path,
+    config: &AnalysisConfig,
+    top_n: usize,
+    hooks: &ScanHooks,
 ) -> Result<AnalysisResult> {
     // Validate path exists and is accessible
     if !path.exists() {
         anyhow::bail!("Path does not exist: {}", path.display());
     }
 
+    if config.expand_archives && path.is_file() && archive::is_archive(path) {
+        return archive::analyze_archive(path, top_n);
+    }
+
     if !path.is_dir() {
         anyhow::bail!("Path is not a directory: {}", path.display());
     }
 
-    // Configure rayon thread pool if specified
-    if let Some(threads) = config.num_threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .context("Failed to configure thread pool")?;
-    }
+    let remote = remote_mounts(path, config);
+    let config: &AnalysisConfig = &with_storage_threads(config, path, &remote);
+    let pool = build_thread_pool(config)?;
 
     log::info!("Starting disk usage analysis for: {}", path.display());
 
     // Build exclusion matcher
-    let exclusions = build_exclusion_matcher(&config.exclude_patterns)?;
-
-    // Collect immediate subdirectories and their entries
-    let mut dir_sizes: HashMap<PathBuf, DirectoryStats> = HashMap::new();
-    let mut total_files = 0;
-    let mut total_dirs = 0;
-    let mut seen_inodes: HashSet<(u64, u64)> = HashSet::new(); // (dev, ino) pairs
-    let mut error_count = 0;
-
-    // Walk the directory tree
-    let walker = WalkDir::new(path)
-        .follow_links(config.follow_links)
-        .max_depth(config.max_depth.unwrap_or(usize::MAX));
-
-    for entry in walker.into_iter().filter_entry(|e| !is_excluded(e, &exclusions)) {
-        match entry {
-            Ok(entry) => {
-                if let Err(e) = process_entry(&entry, path, &mut dir_sizes, &mut total_files, &mut total_dirs, &mut seen_inodes) {
-                    log::debug!("Error processing {}: {}", entry.path().display(), e);
-                    error_count += 1;
-                }
-            }
-            Err(e) => {
-                // Only log non-transient errors in debug mode
-                let err_str = e.to_string();
-                if !err_str.contains("Interrupted system call") {
-                    log::debug!("Error accessing path: {}", e);
-                }
-                error_count += 1;
-            }
+    let exclusions = exclude::Exclusions::new(&config.exclude_patterns)?;
+
+    #[cfg(windows)]
+    if config.backend == ScanBackend::Mft && hooks.visitor.is_none() {
+        match mft::analyze(path, config, &exclusions, top_n, hooks) {
+            Ok(result) => return Ok(result),
+            Err(e) => log::warn!("Cannot scan the MFT, walking directories instead: {:#}", e),
         }
     }
 
-    if error_count > 0 {
-        log::info!("Skipped {} items due to errors (use --debug to see details)", error_count);
+    // Walk the directory tree in parallel
+    let mut walker = walk::Walker::new(path, config, &exclusions, hooks);
+    walker = walker.with_skipped_mounts(skipped_mounts(path, config, &remote));
+    let cache_path = config
+        .cache
+        .as_deref()
+        .filter(|_| cache::supports(config) && hooks.visitor.is_none());
+    if let Some(cache_path) = cache_path {
+        walker = walker.with_cache(cache::ScanCache::load(cache_path), cache_path);
+    }
+    if let (Some(checkpoint), None, false) = (&config.checkpoint, hooks.visitor, config.build_tree)
+    {
+        walker = walker.with_checkpoint(checkpoint::Checkpointer::new(checkpoint, path));
+    }
+    let scan = match &pool {
+        Some(pool) => pool.install(|| walker.scan_root(top_n)),
+        None => walker.scan_root(top_n),
+    };
+    let walk::RootScan {
+        stats,
+        top,
+        mut nodes,
+        mut files,
+    } = scan;
+    walker.finish();
+    let scan_stats = walker.stats(stats.file_count, stats.dir_count);
+
+    let cancelled = walker.is_cancelled();
+    if cancelled {
+        log::warn!(
+            "Analysis of {} was cancelled; results are partial",
+            path.display()
+        );
     }
 
-    // Calculate total size
-    let total_size: u64 = dir_sizes.values().map(|s| s.size).sum();
+    let errors = walker.take_errors();
+    if !errors.is_empty() {
+        log::info!(
+            "Skipped {} items due to errors (use --debug to see details)",
+            errors.len()
+        );
+    }
+    let mut link_issues = walker.take_link_issues();
+    link_issues.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.path.cmp(&b.path)));
+    let (hard_links, hard_link_savings) = walker.hard_link_totals();
+    let cloned_size = walker.cloned_size();
+    let (cloud_files, cloud_size) = walker.cloud_only();
+    // The totals of the directories completed before cancelling are still valid
+    walker.save_cache(cancelled);
+    if cancelled {
+        walker.save_checkpoint();
+    } else {
+        walker.remove_checkpoint();
+    }
+    let filesystem = mounts::filesystem_usage(path)
+        .map_err(|e| log::debug!("Cannot query filesystem of {}: {}", path.display(), e))
+        .ok();
 
-    // Convert to DirectoryEntry and sort by size
-    let mut directories: Vec<DirectoryEntry> = dir_sizes
-        .into_par_iter()
-        .map(|(path, stats)| DirectoryEntry {
-            path,
-            size: stats.size,
-            file_count: stats.file_count,
-            dir_count: stats.dir_count,
-        })
-        .collect();
+    let total_size = stats.size;
+    let total_files = stats.file_count;
+    let total_dirs = stats.dir_count;
+    let modified = stats.modified;
 
-    directories.sort_by(|a, b| b.size.cmp(&a.size));
+    let tree = config.build_tree.then(|| {
+        nodes.sort_unstable_by_key(|c| std::cmp::Reverse(c.size));
+        files.sort_unstable_by_key(|f| std::cmp::Reverse(f.size));
+        DirectoryNode {
+            path: path.to_path_buf(),
+            size: total_size,
+            file_count: total_files,
+            dir_count: total_dirs,
+            children: nodes,
+            files,
+        }
+    });
 
-    // Take top N
-    let top_directories = directories.into_iter().take(top_n).collect();
+    // The largest entries, largest first
+    let top_directories = top.into_sorted_vec();
 
     log::info!(
         "Analysis complete: {} bytes, {} files, {} directories",
@@ -167,73 +780,144 @@ pub fn analyze_disk_usage(
         total_files,
         total_dirs,
         top_directories,
+        tree,
+        cancelled,
+        errors,
+        hard_links,
+        hard_link_savings,
+        other_types: stats.other_types,
+        cloned_size,
+        cloud_files,
+        cloud_size,
+        filesystem,
+        modified,
+        link_issues,
+        stats: scan_stats,
     })
 }
 
-/// Statistics for a directory
-#[derive(Debug, Default, Clone)]
-struct DirectoryStats {
-    size: u64,
-    file_count: usize,
-    dir_count: usize,
-}
-
-/// Process a single directory entry
-fn process_entry(
-    entry: &DirEntry,
-    root_path: &Path,
-    dir_sizes: &mut HashMap<PathBuf, DirectoryStats>,
-    total_files: &mut usize,
-    total_dirs: &mut usize,
-    seen_inodes: &mut HashSet<(u64, u64)>,
-) -> Result<()> {
-    let path = entry.path();
-
-    if entry.file_type().is_file() {
-        let metadata = entry.metadata()
-            .context("Failed to read file metadata")?;
-
-        // Get inode information to track hard links
-        let inode_key = get_inode_key(&metadata);
-
-        // Skip if we've already counted this inode (hard link)
-        if !seen_inodes.insert(inode_key) {
-            log::trace!("Skipping hard link: {}", path.display());
-            return Ok(());
+/// Network filesystems mounted below `path` that are not simply scanned,
+/// announced as `config.remote_fs` asks
+pub(crate) fn remote_mounts(path: &Path, config: &AnalysisConfig) -> Vec<mounts::Mount> {
+    if config.remote_fs == RemoteFs::Scan {
+        return Vec::new();
+    }
+    let remote = mounts::remote_mounts_below(path);
+    for mount in &remote {
+        if config.remote_fs == RemoteFs::Skip {
+            log::info!(
+                "Skipping {} filesystem {} mounted from {}",
+                mount.fs_type,
+                mount.mount_point.display(),
+                mount.source
+            );
+        } else {
+            log::warn!(
+                "{} is a {} filesystem mounted from {}, scanning it over the network \
+                 (--remote-fs skip leaves it out)",
+                mount.mount_point.display(),
+                mount.fs_type,
+                mount.source
+            );
         }
+    }
+    remote
+}
 
-        // Use actual disk usage (blocks) instead of apparent size
-        let size = get_disk_usage(&metadata);
-        *total_files += 1;
-
-        // Find the immediate subdirectory under root (or file directly in root)
-        let subdir = find_immediate_subdir(path, root_path);
+/// Mount points left out of a scan of `path` with `config`, relative to it:
+/// the `remote` filesystems when skipping them, and pseudo filesystems unless
+/// they are included
+pub(crate) fn skipped_mounts(
+    path: &Path,
+    config: &AnalysisConfig,
+    remote: &[mounts::Mount],
+) -> Vec<PathBuf> {
+    let mut skipped = Vec::new();
+    if config.remote_fs == RemoteFs::Skip {
+        skipped.extend_from_slice(remote);
+    }
+    if !config.include_virtual {
+        for mount in mounts::virtual_mounts_below(path) {
+            log::debug!(
+                "Skipping {} filesystem {}",
+                mount.fs_type,
+                mount.mount_point.display()
+            );
+            skipped.push(mount);
+        }
+    }
+    relative_mount_points(path, &skipped)
+}
 
-        let stats = dir_sizes.entry(subdir).or_default();
-        stats.size += size;
-        stats.file_count += 1;
-    } else if entry.file_type().is_dir() && path != root_path {
-        *total_dirs += 1;
+/// Mount points of `mounts` relative to `path`, which they are below
+fn relative_mount_points(path: &Path, mounts: &[mounts::Mount]) -> Vec<PathBuf> {
+    let root = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    mounts
+        .iter()
+        .filter_map(|mount| mount.mount_point.strip_prefix(&root).ok())
+        .map(Path::to_path_buf)
+        .collect()
+}
 
-        // Track this as a subdirectory
-        let subdir = find_immediate_subdir(path, root_path);
-        let stats = dir_sizes.entry(subdir).or_default();
-        stats.dir_count += 1;
+/// `config` with the thread count suited to the storage holding `path`, or
+/// to the `remote` filesystems below it that are walked too, unless one was set
+pub(crate) fn with_storage_threads<'a>(
+    config: &'a AnalysisConfig,
+    path: &Path,
+    remote: &[mounts::Mount],
+) -> Cow<'a, AnalysisConfig> {
+    if config.num_threads.is_some() {
+        return Cow::Borrowed(config);
+    }
+    let mut storage = mounts::storage_kind(path);
+    if config.remote_fs == RemoteFs::Warn
+        && !remote.is_empty()
+        && storage.default_threads().is_none()
+    {
+        storage = mounts::StorageKind::Network;
     }
+    match storage.default_threads() {
+        Some(threads) => {
+            log::info!(
+                "{} is on {:?} storage, scanning with {} threads",
+                path.display(),
+                storage,
+                threads
+            );
+            Cow::Owned(AnalysisConfig {
+                num_threads: Some(threads),
+                ..config.clone()
+            })
+        }
+        None => Cow::Borrowed(config),
+    }
+}
 
-    Ok(())
+/// Use a dedicated thread pool if a thread count is specified, so analyses
+/// with different settings can run in the same process
+pub(crate) fn build_thread_pool(config: &AnalysisConfig) -> Result<Option<rayon::ThreadPool>> {
+    config
+        .num_threads
+        .map(|threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|i| format!("dutop-worker-{}", i))
+                .build()
+                .context("Failed to configure thread pool")
+        })
+        .transpose()
 }
 
 /// Get a unique key for an inode (handles hard links correctly)
 #[cfg(unix)]
-fn get_inode_key(metadata: &std::fs::Metadata) -> (u64, u64) {
+pub(crate) fn get_inode_key(metadata: &std::fs::Metadata) -> (u64, u64) {
     use std::os::unix::fs::MetadataExt;
     (metadata.dev(), metadata.ino())
 }
 
 /// Get a unique key for a file on Windows
 #[cfg(windows)]
-fn get_inode_key(_metadata: &std::fs::Metadata) -> (u64, u64) {
+pub(crate) fn get_inode_key(_metadata: &std::fs::Metadata) -> (u64, u64) {
     // On Windows, proper hard link detection requires unstable APIs (volume_serial_number/file_index)
     // For stable builds, we use a counter approach. Hard links are rare on Windows, so this is acceptable.
     // Each file gets a unique counter value, which may slightly overcount if hard links exist.
@@ -244,17 +928,44 @@ fn get_inode_key(_metadata: &std::fs::Metadata) -> (u64, u64) {
 
 /// Fallback for other platforms
 #[cfg(not(any(unix, windows)))]
-fn get_inode_key(_metadata: &std::fs::Metadata) -> (u64, u64) {
+pub(crate) fn get_inode_key(_metadata: &std::fs::Metadata) -> (u64, u64) {
     // On unsupported platforms, return a dummy key (will count hard links separately)
     use std::sync::atomic::{AtomicU64, Ordering};
     static COUNTER: AtomicU64 = AtomicU64::new(0);
     (0, COUNTER.fetch_add(1, Ordering::SeqCst))
 }
 
+/// Get the number of hard links to a file
+#[cfg(unix)]
+pub(crate) fn get_link_count(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+/// Hard links are not detected on other platforms, so every file counts as a single link
+#[cfg(not(unix))]
+pub(crate) fn get_link_count(_metadata: &std::fs::Metadata) -> u64 {
+    1
+}
+
+/// Get the identifier of the device holding a file
+#[cfg(unix)]
+pub(crate) fn get_device_id(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+/// Device identifiers are not available on stable Rust for other platforms,
+/// so mount points cannot be detected there
+#[cfg(not(unix))]
+pub(crate) fn get_device_id(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
 /// Get actual disk usage in bytes (matching du behavior)
 /// This uses blocks allocated on disk, not apparent file size
 #[cfg(unix)]
-fn get_disk_usage(metadata: &std::fs::Metadata) -> u64 {
+pub(crate) fn get_disk_usage(_path: &Path, metadata: &std::fs::Metadata) -> u64 {
     use std::os::unix::fs::MetadataExt;
     // blocks() returns the number of 512-byte blocks allocated
     metadata.blocks() * 512
@@ -262,59 +973,19 @@ fn get_disk_usage(metadata: &std::fs::Metadata) -> u64 {
 
 /// Get disk usage on Windows
 #[cfg(windows)]
-fn get_disk_usage(metadata: &std::fs::Metadata) -> u64 {
-    use std::os::windows::fs::MetadataExt;
-    // On Windows, use file_size which is the actual size on disk
-    // This is an approximation; Windows uses cluster sizes
-    metadata.file_size()
+pub(crate) fn get_disk_usage(path: &Path, metadata: &std::fs::Metadata) -> u64 {
+    // Allocated size of compressed and sparse files, rounded up to whole clusters
+    // like Explorer's "size on disk"; falls back to the logical size on error
+    windows::allocated_size(path).unwrap_or(metadata.len())
 }
 
 /// Fallback for other platforms
 #[cfg(not(any(unix, windows)))]
-fn get_disk_usage(metadata: &std::fs::Metadata) -> u64 {
+pub(crate) fn get_disk_usage(_path: &Path, metadata: &std::fs::Metadata) -> u64 {
     // Fall back to apparent size
     metadata.len()
 }
 
-/// Find the immediate subdirectory under root for a given path
-fn find_immediate_subdir(path: &Path, root: &Path) -> PathBuf {
-    // Strip the root prefix and get the first component
-    match path.strip_prefix(root) {
-        Ok(relative) => {
-            if let Some(first) = relative.components().next() {
-                root.join(first)
-            } else {
-                // Path is the root itself
-                root.to_path_buf()
-            }
-        }
-        Err(_) => {
-            // Path is not under root (shouldn't happen)
-            path.to_path_buf()
-        }
-    }
-}
-
-/// Build exclusion matcher from patterns
-fn build_exclusion_matcher(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
-    patterns
-        .iter()
-        .map(|p| glob::Pattern::new(p).context(format!("Invalid glob pattern: {}", p)))
-        .collect()
-}
-
-/// Check if a directory entry should be excluded
-fn is_excluded(entry: &DirEntry, exclusions: &[glob::Pattern]) -> bool {
-    if exclusions.is_empty() {
-        return false;
-    }
-
-    let path = entry.path();
-    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-
-    exclusions.iter().any(|pattern| pattern.matches(file_name))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -372,11 +1043,677 @@ mod tests {
     }
 
     #[test]
-    fn test_find_immediate_subdir() {
-        let root = Path::new("/home/user");
-        let path = Path::new("/home/user/projects/rust/src/main.rs");
+    fn test_exclusion_relative_paths() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("build/cache")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("app/build/cache")).unwrap();
+        fs::write(temp_dir.path().join("build/cache/blob"), "x").unwrap();
+        fs::write(temp_dir.path().join("build/out"), "x").unwrap();
+        fs::write(temp_dir.path().join("app/build/cache/blob"), "x").unwrap();
+
+        let config = AnalysisConfig {
+            exclude_patterns: vec!["build/cache".to_string()],
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.total_files, 2);
+
+        let config = AnalysisConfig {
+            exclude_patterns: vec!["**/build/cache".to_string()],
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.total_files, 1);
+    }
+
+    #[test]
+    fn test_nested_directories_aggregate() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("a/b/c")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("d")).unwrap();
+        fs::write(temp_dir.path().join("a/one.txt"), "1").unwrap();
+        fs::write(temp_dir.path().join("a/b/two.txt"), "2").unwrap();
+        fs::write(temp_dir.path().join("a/b/c/three.txt"), "3").unwrap();
+        fs::write(temp_dir.path().join("root.txt"), "r").unwrap();
+
+        let config = AnalysisConfig::default();
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        assert_eq!(result.total_files, 4);
+        assert_eq!(result.total_dirs, 4);
+        assert_eq!(result.top_directories.len(), 3);
+
+        let a = result
+            .top_directories
+            .iter()
+            .find(|d| d.path.ends_with("a"))
+            .unwrap();
+        assert_eq!(a.file_count, 3);
+        assert_eq!(a.dir_count, 3);
+
+        // Without entries to keep, the totals are the same
+        let totals = analyze_disk_usage(temp_dir.path(), &config, 0).unwrap();
+        assert!(totals.top_directories.is_empty());
+        assert_eq!(totals.total_size, result.total_size);
+        assert_eq!(totals.total_files, 4);
+        assert_eq!(totals.total_dirs, 4);
+    }
+
+    #[test]
+    fn test_max_depth_limits_traversal() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/one.txt"), "1").unwrap();
+        fs::write(temp_dir.path().join("a/b/two.txt"), "2").unwrap();
+
+        let config = AnalysisConfig {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        // a/b is visited but its contents are beyond the depth limit
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.total_dirs, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_counted_once() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("a")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("b")).unwrap();
+        fs::write(temp_dir.path().join("a/data.bin"), vec![0u8; 8192]).unwrap();
+        fs::hard_link(
+            temp_dir.path().join("a/data.bin"),
+            temp_dir.path().join("b/data.bin"),
+        )
+        .unwrap();
+
+        let config = AnalysisConfig::default();
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
 
-        let result = find_immediate_subdir(path, root);
-        assert_eq!(result, Path::new("/home/user/projects"));
+        assert_eq!(result.total_files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fast_hardlinks() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::write(temp_dir.path().join("data.bin"), vec![1u8; 8192]).unwrap();
+        fs::write(temp_dir.path().join("single.bin"), vec![1u8; 4096]).unwrap();
+        fs::hard_link(
+            temp_dir.path().join("data.bin"),
+            temp_dir.path().join("link.bin"),
+        )
+        .unwrap();
+
+        let config = AnalysisConfig {
+            fast_hardlinks: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        assert_eq!(result.total_files, 2);
+        assert_eq!(result.hard_links, 1);
+
+        // Tracking every inode finds the same links
+        let config = AnalysisConfig {
+            track_all_inodes: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.total_files, 2);
+        assert_eq!(result.hard_links, 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_statx_backend_matches_walkdir() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/b/data.bin"), vec![1u8; 20_000]).unwrap();
+        fs::write(temp_dir.path().join("a/small"), vec![1u8; 100]).unwrap();
+        fs::hard_link(
+            temp_dir.path().join("a/b/data.bin"),
+            temp_dir.path().join("link.bin"),
+        )
+        .unwrap();
+
+        let scan = |backend| {
+            let config = AnalysisConfig {
+                backend,
+                build_tree: true,
+                ..Default::default()
+            };
+            analyze_disk_usage(temp_dir.path(), &config, 10).unwrap()
+        };
+        let walkdir = scan(ScanBackend::Walkdir);
+        let uring = scan(ScanBackend::Uring);
+
+        assert_eq!(uring.total_size, walkdir.total_size);
+        assert_eq!((uring.total_files, uring.total_dirs), (2, 2));
+        assert_eq!(uring.hard_links, 1);
+        let tree = uring.tree.unwrap();
+        assert_eq!(tree.size, walkdir.total_size);
+        let b = tree.find(&temp_dir.path().join("a/b")).unwrap();
+        assert_eq!(b.dir_count, 0);
+    }
+
+    #[test]
+    fn test_modification_time_window() {
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        fs::create_dir_all(temp_dir.path().join("a")).unwrap();
+        for (name, age_days) in [("a/old", 400), ("a/recent", 3), ("month", 30)] {
+            let file = fs::File::create(temp_dir.path().join(name)).unwrap();
+            file.set_modified(now - day * age_days).unwrap();
+        }
+
+        let config = AnalysisConfig {
+            modified_before: Some(now - day * 365),
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.top_directories[0].path, temp_dir.path().join("a"));
+        assert_eq!(result.top_directories[0].file_count, 1);
+
+        let config = AnalysisConfig {
+            modified_after: Some(now - day * 7),
+            ..Default::default()
+        };
+        assert_eq!(
+            analyze_disk_usage(temp_dir.path(), &config, 10)
+                .unwrap()
+                .total_files,
+            1
+        );
+
+        let config = AnalysisConfig {
+            modified_before: Some(now - day * 7),
+            modified_after: Some(now - day * 365),
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.total_files, 1);
+        assert!(result
+            .top_directories
+            .iter()
+            .any(|d| d.path == temp_dir.path().join("month")));
+    }
+
+    #[test]
+    fn test_newest_modification_time() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::create_dir(temp_dir.path().join("empty")).unwrap();
+        for (name, secs) in [
+            ("a/old", 1_000_000),
+            ("a/b/new", 2_000_000),
+            ("top", 1_500_000),
+        ] {
+            let file = fs::File::create(temp_dir.path().join(name)).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        }
+
+        let result = analyze_disk_usage(temp_dir.path(), &AnalysisConfig::default(), 10).unwrap();
+        let modified = |name: &str| {
+            let path = temp_dir.path().join(name);
+            result
+                .top_directories
+                .iter()
+                .find(|d| d.path == path)
+                .unwrap()
+                .modified
+        };
+        assert_eq!(modified("a"), Some(2_000_000));
+        assert_eq!(modified("top"), Some(1_500_000));
+        assert_eq!(modified("empty"), None);
+        assert_eq!(result.modified, Some(2_000_000));
+    }
+
+    #[test]
+    fn test_min_file_size() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("assets")).unwrap();
+        fs::write(temp_dir.path().join("assets/big.bin"), vec![0u8; 64 * 1024]).unwrap();
+        fs::write(temp_dir.path().join("assets/small.txt"), "tiny").unwrap();
+        fs::write(temp_dir.path().join("note.txt"), "tiny").unwrap();
+
+        let config = AnalysisConfig {
+            min_file_size: Some(1024),
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.top_directories.len(), 1);
+        assert_eq!(
+            result.top_directories[0].path,
+            temp_dir.path().join("assets")
+        );
+    }
+
+    #[test]
+    fn test_combine_results() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("var/log")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("home/alice")).unwrap();
+        fs::write(temp_dir.path().join("var/log/syslog"), vec![0u8; 4096]).unwrap();
+        fs::write(temp_dir.path().join("home/alice/big"), vec![0u8; 16384]).unwrap();
+        fs::write(temp_dir.path().join("home/readme"), "hi").unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            ..Default::default()
+        };
+        let results = ["var", "home"]
+            .iter()
+            .map(|root| analyze_disk_usage(&temp_dir.path().join(root), &config, 10).unwrap())
+            .collect::<Vec<_>>();
+        let total_size: u64 = results.iter().map(|r| r.total_size).sum();
+
+        let combined = combine_results(results, 2);
+        assert_eq!(combined.root_path, temp_dir.path());
+        assert_eq!(combined.total_size, total_size);
+        assert_eq!(combined.total_files, 3);
+        // var, var/log, home and home/alice
+        assert_eq!(combined.total_dirs, 4);
+        assert_eq!(combined.top_directories.len(), 2);
+        assert_eq!(
+            combined.top_directories[0].path,
+            temp_dir.path().join("home/alice")
+        );
+
+        let tree = combined.tree.unwrap();
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.children[0].path, temp_dir.path().join("home"));
+        assert_eq!(tree.size, total_size);
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("a/b/c")).unwrap();
+        fs::write(temp_dir.path().join("a/one.txt"), "1").unwrap();
+        fs::write(temp_dir.path().join("a/b/two.txt"), "2").unwrap();
+        fs::write(temp_dir.path().join("a/b/c/three.txt"), "3").unwrap();
+        fs::write(temp_dir.path().join("root.txt"), "r").unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        let tree = result.tree.unwrap();
+
+        assert_eq!(tree.size, result.total_size);
+        assert_eq!(tree.file_count, 4);
+        assert_eq!(tree.dir_count, 3);
+        assert_eq!(tree.iter().count(), 4);
+
+        let b = tree.find(&temp_dir.path().join("a/b")).unwrap();
+        assert_eq!(b.file_count, 2);
+        assert_eq!(b.dir_count, 1);
+        assert_eq!(b.children.len(), 1);
+        assert_eq!(b.own_size() + b.children[0].size, b.size);
+    }
+
+    #[test]
+    fn test_tree_not_built_by_default() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a")).unwrap();
+
+        let result = analyze_disk_usage(temp_dir.path(), &AnalysisConfig::default(), 10).unwrap();
+        assert!(result.tree.is_none());
+    }
+
+    #[test]
+    fn test_one_file_system_keeps_same_device_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/b/file.txt"), "data").unwrap();
+
+        let config = AnalysisConfig {
+            one_file_system: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.total_dirs, 2);
+    }
+
+    #[test]
+    fn test_respect_gitignore() {
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::create_dir_all(temp_dir.path().join("target/debug")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("src/generated")).unwrap();
+        fs::write(temp_dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(
+            temp_dir.path().join("src/.gitignore"),
+            "generated/\n!keep.log\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("target/debug/app"), "binary").unwrap();
+        fs::write(temp_dir.path().join("src/generated/out.rs"), "gen").unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(temp_dir.path().join("src/debug.log"), "log").unwrap();
+        fs::write(temp_dir.path().join("src/keep.log"), "log").unwrap();
+
+        let config = AnalysisConfig {
+            respect_gitignore: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        // .gitignore, src/.gitignore, src/main.rs and the whitelisted src/keep.log
+        assert_eq!(result.total_files, 4);
+        assert!(result
+            .top_directories
+            .iter()
+            .all(|d| !d.path.ends_with("target")));
+
+        let unfiltered =
+            analyze_disk_usage(temp_dir.path(), &AnalysisConfig::default(), 10).unwrap();
+        assert_eq!(unfiltered.total_files, 7);
+    }
+
+    #[test]
+    fn test_progress_reports_final_counts() {
+        use std::sync::Mutex;
+
+        struct LastProgress(Mutex<Option<ScanProgress>>);
+        impl ProgressCallback for LastProgress {
+            fn on_progress(&self, progress: &ScanProgress) {
+                *self.0.lock().unwrap() = Some(progress.clone());
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/one.txt"), "1").unwrap();
+        fs::write(temp_dir.path().join("two.txt"), "2").unwrap();
+
+        let progress = LastProgress(Mutex::new(None));
+        let result = analyze_disk_usage_with_progress(
+            temp_dir.path(),
+            &AnalysisConfig::default(),
+            10,
+            &progress,
+        )
+        .unwrap();
+
+        let last = progress.0.lock().unwrap().clone().unwrap();
+        assert_eq!(last.files_scanned, 2);
+        assert_eq!(last.dirs_scanned, 2);
+        assert_eq!(last.bytes, result.total_size);
+    }
+
+    #[test]
+    fn test_cancelled_analysis_returns_partial_result() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/b/file.txt"), "data").unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result =
+            analyze_disk_usage_with_cancel(temp_dir.path(), &AnalysisConfig::default(), 10, &token)
+                .unwrap();
+
+        assert!(result.cancelled);
+        assert_eq!(result.total_files, 0);
+
+        let complete = analyze_disk_usage_with_cancel(
+            temp_dir.path(),
+            &AnalysisConfig::default(),
+            10,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert!(!complete.cancelled);
+        assert_eq!(complete.total_files, 1);
+    }
+
+    #[test]
+    fn test_analyses_with_different_thread_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/file.txt"), "data").unwrap();
+
+        for threads in [1, 2, 3] {
+            let config = AnalysisConfig {
+                num_threads: Some(threads),
+                ..Default::default()
+            };
+            let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+            assert_eq!(result.total_files, 1);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop_is_reported_as_error() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path(), temp_dir.path().join("a/loop")).unwrap();
+
+        let config = AnalysisConfig {
+            follow_links: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].path, temp_dir.path().join("a/loop"));
+        assert_eq!(result.errors[0].kind, ScanErrorKind::SymlinkLoop);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_links() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("a")).unwrap();
+        fs::write(root.join("a/file.txt"), "data").unwrap();
+        symlink("file.txt", root.join("a/ok")).unwrap();
+        symlink("missing.txt", root.join("a/broken")).unwrap();
+        symlink("ping", root.join("a/pong")).unwrap();
+        symlink("pong", root.join("a/ping")).unwrap();
+        symlink(outside.path(), root.join("a/elsewhere")).unwrap();
+        symlink(root, root.join("a/up")).unwrap();
+
+        let config = AnalysisConfig {
+            check_links: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(root, &config, 10).unwrap();
+        let issues: Vec<(LinkIssueKind, &Path)> = result
+            .link_issues
+            .iter()
+            .map(|issue| (issue.kind, issue.path.strip_prefix(root).unwrap()))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (LinkIssueKind::Broken, Path::new("a/broken")),
+                (LinkIssueKind::Loop, Path::new("a/ping")),
+                (LinkIssueKind::Loop, Path::new("a/pong")),
+                (LinkIssueKind::OutsideRoot, Path::new("a/elsewhere")),
+            ]
+        );
+        assert_eq!(result.link_issues[0].target, PathBuf::from("missing.txt"));
+
+        // Following links also turns up directories that lead back to an ancestor
+        let config = AnalysisConfig {
+            follow_links: true,
+            ..config
+        };
+        let result = analyze_disk_usage(root, &config, 10).unwrap();
+        assert!(result
+            .link_issues
+            .iter()
+            .any(|issue| issue.kind == LinkIssueKind::Loop && issue.path == root.join("a/up")));
+
+        let result = analyze_disk_usage(root, &AnalysisConfig::default(), 10).unwrap();
+        assert!(result.link_issues.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_and_special_files() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/file.txt"), "data").unwrap();
+        std::os::unix::fs::symlink("file.txt", temp_dir.path().join("a/link")).unwrap();
+        let fifo =
+            std::ffi::CString::new(temp_dir.path().join("a/fifo").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+        let _socket = std::os::unix::net::UnixListener::bind(temp_dir.path().join("sock")).unwrap();
+
+        let result = analyze_disk_usage(temp_dir.path(), &AnalysisConfig::default(), 10).unwrap();
+        assert_eq!(result.total_files, 1);
+        assert_eq!(
+            result.other_types,
+            OtherTypes {
+                symlinks: 1,
+                symlink_size: "file.txt".len() as u64,
+                sockets: 1,
+                fifos: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(result.other_types.special_files(), 2);
+
+        let config = AnalysisConfig {
+            count_all_types: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.total_files, 4);
+        assert_eq!(result.other_types.symlinks, 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_result_serde_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/file.txt"), "data").unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            tree_files: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        let json = serde_json::to_string(&result).unwrap();
+        let restored: AnalysisResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.root_path, result.root_path);
+        assert_eq!(restored.total_size, result.total_size);
+        assert_eq!(restored.top_directories[0].path, temp_dir.path().join("a"));
+        assert_eq!(restored.tree.unwrap().children[0].files[0].name, "file.txt");
+
+        let json = serde_json::to_string(&config).unwrap();
+        let config: AnalysisConfig = serde_json::from_str(&json).unwrap();
+        assert!(config.tree_files);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_detect_clones() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("original"), vec![1u8; 64 * 1024]).unwrap();
+        let status = std::process::Command::new("cp")
+            .arg("-c")
+            .arg(temp_dir.path().join("original"))
+            .arg(temp_dir.path().join("clone"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let config = AnalysisConfig {
+            detect_clones: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        assert_eq!(result.cloned_size * 2, result.total_size);
+        assert_eq!(result.unique_size(), result.cloned_size);
+    }
+
+    #[test]
+    fn test_filesystem_capacity() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("file.txt"), vec![0u8; 8192]).unwrap();
+
+        let result = analyze_disk_usage(temp_dir.path(), &AnalysisConfig::default(), 10).unwrap();
+        let fs = result.filesystem.unwrap();
+        assert!(fs.total >= result.total_size);
+
+        let percent = result.capacity_percentage().unwrap();
+        assert!(percent > 0.0 && percent <= 100.0);
+    }
+
+    #[test]
+    fn test_skipped_remote_mounts() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("mnt/nas")).unwrap();
+        fs::write(root.join("mnt/local.txt"), "data").unwrap();
+        fs::write(root.join("mnt/nas/remote.txt"), "data").unwrap();
+
+        // Pretend a network filesystem is mounted on mnt/nas
+        let nas = mounts::Mount {
+            source: "server:/export".to_string(),
+            mount_point: fs::canonicalize(root).unwrap().join("mnt/nas"),
+            fs_type: "nfs4".to_string(),
+            usage: None,
+        };
+        let config = AnalysisConfig {
+            remote_fs: RemoteFs::Skip,
+            ..Default::default()
+        };
+        let skipped = skipped_mounts(root, &config, std::slice::from_ref(&nas));
+        assert_eq!(skipped, vec![PathBuf::from("mnt/nas")]);
+        let warn = AnalysisConfig::default();
+        assert!(skipped_mounts(root, &warn, &[nas]).is_empty());
+
+        let exclusions = exclude::Exclusions::new(&[]).unwrap();
+        let walker = walk::Walker::new(root, &config, &exclusions, &ScanHooks::default())
+            .with_skipped_mounts(skipped);
+        let scan = walker.scan_root(10);
+        assert_eq!(scan.stats.file_count, 1);
+        assert_eq!(scan.stats.dir_count, 1);
+
+        // Scanning them looks nothing up
+        let config = AnalysisConfig {
+            remote_fs: RemoteFs::Scan,
+            ..config
+        };
+        assert!(remote_mounts(root, &config).is_empty());
     }
 }
@@ -0,0 +1,59 @@
+//! macOS-specific filesystem queries
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// File flag of dataless files, whose contents are only stored in the cloud
+/// (iCloud Drive and File Provider placeholders); missing from the libc crate
+pub(crate) const SF_DATALESS: u32 = 0x4000_0000;
+
+/// Recognizes APFS clones, which share their blocks with the file they were copied from
+///
+/// A clone starts out pointing at the same extents as its source, so files
+/// whose first byte lives at the same physical location on the same device
+/// are treated as copies of one another. A clone that was partly rewritten
+/// afterwards still counts as fully shared.
+#[derive(Default)]
+pub(crate) struct CloneTracker {
+    /// (device, physical offset of the first byte) of every file seen so far
+    seen: Mutex<HashSet<(u64, u64)>>,
+}
+
+impl CloneTracker {
+    /// Whether the file at `path` shares its blocks with a file seen before
+    pub(crate) fn is_clone(&self, path: &Path, device: u64) -> bool {
+        let Some(offset) = physical_offset(path) else {
+            return false;
+        };
+        !self
+            .seen
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((device, offset))
+    }
+}
+
+/// Physical location on its device of the first byte of the file at `path`
+fn physical_offset(path: &Path) -> Option<u64> {
+    let file = File::open(path).ok()?;
+    let mut extent = libc::log2phys {
+        l2p_flags: 0,
+        // Query a single byte at file offset zero
+        l2p_contigbytes: 1,
+        l2p_devoffset: 0,
+    };
+    // SAFETY: the descriptor is open and `extent` is valid for F_LOG2PHYS_EXT
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_LOG2PHYS_EXT, &mut extent) };
+    if ret == -1 {
+        log::trace!(
+            "Cannot locate blocks of {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+    Some(extent.l2p_devoffset as u64)
+}
@@ -3,10 +3,33 @@
 //! A fast, parallel disk usage analyzer built in Rust to replace legacy shell scripts.
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use dutop::{analyze_disk_usage, output, AnalysisConfig};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use dutop::clean::ScriptFormat;
+use dutop::config::{self, ConfigFile, Settings};
+use dutop::daemon::DaemonConfig;
+use dutop::delete::Removal;
+use dutop::format::{
+    format_duration, format_size_auto, locale_thousands_separator, parse_duration, parse_percent,
+    parse_size, parse_size_cutoff, SizeCutoff, SizeFormat,
+};
+use dutop::hyperlink::HyperlinkChoice;
+use dutop::icons::IconStyle;
+use dutop::metrics::Metrics;
+use dutop::output::OutputSink;
+use dutop::theme::{ColorBy, ColorChoice, Theme};
+use dutop::threshold::{ThresholdExceeded, Thresholds, Violation};
+use dutop::{
+    analyze_disk_usage, analyze_disk_usage_with_progress, output, read_exclude_file, render,
+    snapshot, tui, AnalysisConfig, AnalysisResult, CancellationToken, DirectoryEntry,
+    ProgressCallback, RemoteFs, ScanBackend, ScanProgress,
+};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 /// High-performance disk usage analysis tool
 #[derive(Parser, Debug)]
@@ -15,121 +38,2532 @@ use std::process;
 #[command(version)]
 #[command(about = "Analyze disk usage and display top directories", long_about = None)]
 struct Args {
-    /// Directory to analyze (default: current directory)
-    #[arg(default_value = ".")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Don't show a progress spinner while scanning
+    #[arg(long = "no-progress", global = true)]
+    no_progress: bool,
+
+    /// Reuse the totals of directories unchanged since the previous scan with
+    /// --cache, kept in ~/.cache/dutop (files rewritten in place keep their old
+    /// size until their directory changes)
+    #[arg(long = "cache", global = true)]
+    cache: bool,
+
+    /// Discard the cached totals of the scanned path and record them afresh;
+    /// implies --cache
+    #[arg(long = "rebuild-cache", global = true)]
+    rebuild_cache: bool,
+
+    /// Write the totals of the directories completed so far every minute, kept
+    /// in ~/.cache/dutop, so an interrupted scan can be continued with --resume
+    /// (not for outputs showing a tree)
+    #[arg(long = "checkpoint", global = true)]
+    checkpoint: bool,
+
+    /// Continue an interrupted --checkpoint scan of the same paths with the
+    /// same options from its last checkpoint instead of starting over; implies
+    /// --checkpoint
+    #[arg(long = "resume", global = true)]
+    resume: bool,
+
+    /// Apply the named profile from the config file
+    #[arg(long = "profile", value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Read settings from FILE instead of ~/.config/dutop/config.toml
+    #[arg(long = "config", value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// Aggregate sizes by file owner and group instead of by directory
+    #[cfg(unix)]
+    #[arg(long = "by-owner")]
+    by_owner: bool,
+
+    /// List files reached through several hard links and the space deduplication saved
+    #[arg(long = "hardlinks")]
+    hardlinks: bool,
+
+    /// Show how many files and bytes fall into each size range (<4K, 4K-64K, ...,
+    /// >1G), overall and per top directory
+    #[arg(long = "histogram")]
+    histogram: bool,
+
+    /// Rank directories by their size or by the number of files and
+    /// subdirectories directly in them, with the average file size
+    #[arg(long = "rank-by", value_name = "KEY", default_value = "size")]
+    rank_by: RankBy,
+
+    /// Estimate usage from a sample of the directories at each level instead of
+    /// walking all of them, with 95% confidence intervals
+    #[arg(long = "estimate")]
+    estimate: bool,
+
+    /// With --estimate, list N percent of the directories at each level
+    #[arg(
+        long = "sample-percent",
+        value_name = "N",
+        default_value = "1",
+        requires = "estimate",
+        value_parser = parse_percent
+    )]
+    sample_percent: f64,
+
+    /// List git repositories with their usage split into repository data (objects,
+    /// LFS), build artifacts and working-tree files
+    #[arg(long = "git-aware")]
+    git_aware: bool,
+
+    /// List directories without any files below them, for pruning dead trees
+    #[arg(long = "find-empty")]
+    find_empty: bool,
+
+    /// With --find-empty, also list directories smaller than SIZE (e.g. 4K)
+    #[arg(long = "tiny", value_name = "SIZE", requires = "find_empty", value_parser = parse_size)]
+    tiny: Option<u64>,
+
+    /// When to color the output: auto (on a terminal, unless NO_COLOR is set or
+    /// CLICOLOR_FORCE forces colors), always (e.g. for `less -R`) or never
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        default_value = "auto",
+        global = true
+    )]
+    color: ColorWhen,
+
+    /// Color theme: default, solarized, colorblind, mono or one defined in the
+    /// config file
+    #[arg(long = "theme", value_name = "NAME", global = true)]
+    theme: Option<String>,
+
+    /// Color the bars of the table and the tree by share of the largest entry
+    /// (size, the default), newest modification time (age) or kind of content
+    /// told by the extension (type, for files listed in the tree with -a)
+    #[arg(long = "color-by", value_name = "MODE", global = true)]
+    color_by: Option<ColorMode>,
+
+    /// Draw tables, bars and trees with plain ASCII instead of box-drawing
+    /// and block characters, for serial consoles, legacy terminals and email
+    #[arg(long = "ascii", global = true)]
+    ascii: bool,
+
+    /// When to make the names in the table and the tree hyperlinks opening
+    /// their entry in the file manager: auto (on terminals known to support
+    /// them), always or never
+    #[arg(
+        long = "hyperlinks",
+        value_name = "WHEN",
+        default_value = "auto",
+        global = true
+    )]
+    hyperlinks: HyperlinkWhen,
+
+    /// Put icons telling git repositories, projects, caches and media apart
+    /// before the names in the table and the tree: nerd (Nerd Font glyphs, the
+    /// default) or emoji, as in --icons=emoji (not for snapshots, ssh or s3)
+    #[arg(
+        long = "icons",
+        value_name = "STYLE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "nerd",
+        conflicts_with = "ascii",
+        global = true
+    )]
+    icons: Option<IconSet>,
+
+    /// Theme resolved from --theme and the config file
+    #[arg(skip)]
+    theme_colors: Theme,
+
+    /// Coloring mode resolved from --color-by and the config file
+    #[arg(skip)]
+    coloring: ColorBy,
+
+    /// Browse the results interactively (same as `dutop tui`)
+    #[arg(short = 'i', long = "interactive")]
+    interactive: bool,
+
+    /// With --interactive, move deleted entries to the trash instead of removing them
+    #[arg(long = "trash", requires = "interactive")]
+    trash: bool,
+
+    /// Enable verbose logging
+    #[arg(short = 'v', long = "verbose", global = true)]
+    verbose: bool,
+
+    /// Enable debug logging
+    #[arg(long = "debug", global = true)]
+    debug: bool,
+}
+
+impl Args {
+    /// Output settings with the chosen theme, colored as chosen with --color
+    /// unless `no_color` is set, for output going to a terminal when
+    /// `is_terminal` is set
+    fn output_config(&self, no_color: bool, is_terminal: bool) -> output::OutputConfig {
+        let choice = match self.color {
+            _ if no_color => ColorChoice::Never,
+            ColorWhen::Auto => ColorChoice::Auto,
+            ColorWhen::Always => ColorChoice::Always,
+            ColorWhen::Never => ColorChoice::Never,
+        };
+        output::OutputConfig {
+            use_colors: choice.enabled(is_terminal),
+            theme: self.theme_colors.clone(),
+            color_by: self.coloring,
+            ascii: self.ascii,
+            hyperlinks: match self.hyperlinks {
+                HyperlinkWhen::Auto => HyperlinkChoice::Auto,
+                HyperlinkWhen::Always => HyperlinkChoice::Always,
+                HyperlinkWhen::Never => HyperlinkChoice::Never,
+            }
+            .enabled(is_terminal),
+            icons: self.icons.map(|icons| match icons {
+                IconSet::Nerd => IconStyle::NerdFont,
+                IconSet::Emoji => IconStyle::Emoji,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Merge the config file settings into the options not given on the command line
+    fn apply_config(&mut self) -> Result<()> {
+        let profile = self.profile.as_deref();
+        let settings = match (&self.config, config::default_path()) {
+            (Some(path), _) => ConfigFile::load(path)?.settings(profile)?,
+            (None, Some(path)) => config::load_settings(&path, profile)?,
+            (None, None) if profile.is_some() => {
+                anyhow::bail!("No config file found for --profile")
+            }
+            (None, None) => Settings::default(),
+        };
+        self.theme_colors = settings.theme(self.theme.as_deref())?;
+        self.coloring = match self.color_by {
+            Some(ColorMode::Size) => ColorBy::Size,
+            Some(ColorMode::Age) => ColorBy::Age,
+            Some(ColorMode::Type) => ColorBy::Type,
+            None => settings.color_by()?,
+        };
+        let no_color = settings.color == Some(false);
+        self.ascii |= settings.ascii == Some(true);
+
+        match &mut self.command {
+            None => {
+                self.scan.apply(&settings);
+                self.output.apply(&settings)?;
+            }
+            Some(Command::Tui(tui)) => tui.scan.apply(&settings),
+            Some(Command::Report(report)) => report.scan.apply(&settings),
+            Some(Command::Scan(cmd)) => {
+                cmd.scan.apply(&settings);
+                cmd.output.apply(&settings)?;
+            }
+            Some(Command::Load(load)) => load.output.apply(&settings)?,
+            Some(Command::Diff(diff)) => diff.no_color |= no_color,
+            Some(Command::Watch(watch)) => {
+                watch.scan.apply(&settings);
+                watch.no_color |= no_color;
+            }
+            Some(Command::Dupes(dupes)) => {
+                dupes.scan.apply(&settings);
+                dupes.no_color |= no_color;
+            }
+            Some(Command::Mounts(mounts)) => mounts.threads = mounts.threads.or(settings.threads),
+            Some(Command::History(history)) => {
+                if let HistoryAction::Record(record) = &mut history.action {
+                    record.scan.apply(&settings);
+                }
+            }
+            Some(Command::Clean(clean)) => {
+                clean.scan_args.apply(&settings);
+                clean.no_color |= no_color;
+            }
+            Some(Command::Age(age)) => age.scan.apply(&settings),
+            Some(Command::Types(types)) => {
+                types.scan.apply(&settings);
+                types.no_color |= no_color;
+            }
+            Some(Command::Projects(projects)) => {
+                projects.scan.apply(&settings);
+                projects.no_color |= no_color;
+            }
+            Some(Command::Depth(depth)) => depth.scan.apply(&settings),
+            #[cfg(unix)]
+            Some(Command::Audit(audit)) => {
+                audit.scan.apply(&settings);
+                audit.no_color |= no_color;
+            }
+            Some(Command::Docker(docker)) => docker.output.apply(&settings)?,
+            Some(Command::Ssh(ssh)) => ssh.output.apply(&settings)?,
+            #[cfg(feature = "object-store")]
+            Some(Command::S3(s3)) => s3.output.apply(&settings)?,
+            Some(Command::Serve(serve)) => serve.scan.apply(&settings),
+            #[cfg(feature = "webhook")]
+            Some(Command::Alert(alert)) => alert.scan.apply(&settings),
+            Some(Command::Daemon(_)) => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Browse disk usage interactively, drilling into directories
+    Tui(TuiArgs),
+
+    /// Write a self-contained HTML report with a sortable table and treemap
+    Report(ReportArgs),
+
+    /// Analyze a directory (the default command), optionally saving a snapshot
+    Scan(ScanCommandArgs),
+
+    /// Display a snapshot saved with `dutop scan --save`
+    Load(LoadArgs),
+
+    /// Show which directories grew or shrank between two snapshots
+    Diff(DiffArgs),
+
+    /// Scan a directory and keep the results updated as files change
+    Watch(WatchArgs),
+
+    /// Find duplicate files and the space they waste
+    Dupes(DupesArgs),
+
+    /// List mounted filesystems with their capacity, optionally analyzing each
+    Mounts(MountsArgs),
+
+    /// Record scans in a local database and show how directories grow over time
+    History(HistoryArgs),
+
+    /// Find build output, dependency and cache directories that can be reclaimed
+    Clean(CleanArgs),
+
+    /// Break down disk usage by file age, per top directory and overall, to see how
+    /// much data is cold
+    Age(AgeArgs),
+
+    /// Break down disk usage by file extension and kind of content (video, images,
+    /// archives, code, logs, ...)
+    Types(TypesArgs),
+
+    /// Group disk usage by project (Cargo, npm, Maven, git, ...), split into sources,
+    /// build artifacts and repository data
+    Projects(ProjectsArgs),
+
+    /// Report tree depth, the longest paths and directories with huge numbers of
+    /// direct entries, which break backup and sync tools
+    Depth(DepthArgs),
+
+    /// Analyze a directory and flag setuid and setgid files, and large files that are
+    /// world-writable or owned by unexpected users
+    #[cfg(unix)]
+    Audit(AuditArgs),
+
+    /// Break down Docker disk usage by image, container, volume and build cache
+    Docker(DockerArgs),
+
+    /// Analyze a directory on another machine by running dutop there over ssh
+    Ssh(SshArgs),
+
+    /// Analyze the objects in an S3 bucket, treating key prefixes as directories
+    #[cfg(feature = "object-store")]
+    S3(S3Args),
+
+    /// Expose directory sizes as Prometheus metrics or through a JSON API
+    Serve(ServeArgs),
+
+    /// Post to a webhook (JSON or Slack) when a directory is over a size threshold
+    #[cfg(feature = "webhook")]
+    Alert(AlertArgs),
+
+    /// Scan the roots of a daemon configuration file periodically, keeping snapshots
+    /// and history and serving metrics and alerts
+    Daemon(DaemonArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct DaemonArgs {
+    /// Daemon configuration file listing the roots and what to do with each scan
+    #[arg(long = "daemon-config", value_name = "FILE")]
+    daemon_config: PathBuf,
+
+    /// Time between scans, overriding the configuration file (default: 6h)
+    #[arg(long = "interval", value_name = "DURATION", value_parser = parse_duration)]
+    interval: Option<Duration>,
+
+    /// Print a systemd service unit running this daemon instead of running it
+    #[arg(long = "systemd-unit")]
+    systemd_unit: bool,
+}
+
+#[cfg(feature = "webhook")]
+#[derive(clap::Args, Debug)]
+#[command(group = clap::ArgGroup::new("limit").required(true).multiple(true))]
+struct AlertArgs {
+    /// URL receiving the alert with a POST request
+    #[arg(long = "webhook", value_name = "URL")]
+    webhook: String,
+
+    /// Alert when a path or one of its top directories is over SIZE (e.g. 100G)
+    #[arg(long = "threshold", value_name = "SIZE", group = "limit", value_parser = parse_size)]
+    threshold: Option<u64>,
+
+    /// Alert when a path or one of its top directories takes more than N percent
+    /// of the filesystem
+    #[arg(long = "percent-threshold", value_name = "N", group = "limit")]
+    percent_threshold: Option<f64>,
+
+    /// Body of the request: json or slack
+    #[arg(long = "webhook-format", value_name = "FORMAT", default_value = "json")]
+    webhook_format: dutop::alert::AlertFormat,
+
+    /// Keep rescanning at this interval (e.g. 6h), alerting when a path crosses the
+    /// threshold; without it, scan once and exit
+    #[arg(long = "interval", value_name = "DURATION", value_parser = parse_duration)]
+    interval: Option<Duration>,
+
+    /// Number of largest directories included in the alert
+    #[arg(short = 'n', long = "top", default_value = "5")]
+    top: usize,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group = clap::ArgGroup::new("server").required(true).multiple(true))]
+struct ServeArgs {
+    /// Serve Prometheus metrics at /metrics on ADDR (e.g. :9184 or 127.0.0.1:9184),
+    /// rescanning the paths periodically
+    #[arg(long = "prometheus", value_name = "ADDR", group = "server")]
+    prometheus: Option<String>,
+
+    /// Serve a JSON API on ADDR (e.g. :8080) running scans of paths inside the
+    /// given ones on demand: GET /scan?path=&depth=&top=, GET /results/{id}, GET /jobs
+    #[arg(long = "http", value_name = "ADDR", group = "server")]
+    http: Option<String>,
+
+    /// How long results of the JSON API are reused for identical scans
+    #[arg(long = "cache-ttl", value_name = "DURATION", default_value = "5m", value_parser = parse_duration)]
+    cache_ttl: Duration,
+
+    /// Time between rescans for Prometheus metrics (e.g. 30s, 5m, 1h)
+    #[arg(long = "interval", value_name = "DURATION", default_value = "5m", value_parser = parse_duration)]
+    interval: Duration,
+
+    /// Number of largest entries exported for each path
+    #[arg(short = 'n', long = "top", default_value = "20")]
+    top: usize,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct TuiArgs {
+    /// Move deleted entries to the trash instead of removing them permanently
+    #[arg(long = "trash")]
+    trash: bool,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ScanCommandArgs {
+    /// Save the full analysis to FILE for later use with `dutop load`
+    #[arg(long = "save", value_name = "FILE")]
+    save: Option<PathBuf>,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct LoadArgs {
+    /// Snapshot file to load
+    file: PathBuf,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    /// HTML file to write
+    #[arg(long = "html", value_name = "FILE")]
+    html: PathBuf,
+
+    /// Number of top directories listed in the table
+    #[arg(short = 'n', long = "top", default_value = "50")]
+    top: usize,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Older snapshot
+    old: PathBuf,
+
+    /// Newer snapshot
+    #[arg(required_unless_present = "compare")]
+    new: Option<PathBuf>,
+
+    /// Compare the old snapshot against a fresh scan of PATH instead
+    #[arg(long = "compare", value_name = "PATH", conflicts_with = "new")]
+    compare: Option<PathBuf>,
+
+    /// Number of changed directories to display
+    #[arg(short = 'n', long = "top", default_value = "20")]
+    top: usize,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct WatchArgs {
+    /// Number of top directories to display
+    #[arg(short = 'n', long = "top", default_value = "10")]
+    top: usize,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DupesArgs {
+    /// Number of duplicate groups to display
+    #[arg(short = 'n', long = "top", default_value = "10")]
+    top: usize,
+
+    /// Ignore files smaller than SIZE (e.g. 1M)
+    #[arg(long = "min-size", value_name = "SIZE", default_value = "1", value_parser = parse_size)]
+    min_size: u64,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct MountsArgs {
+    /// Include pseudo filesystems without any capacity (proc, sysfs, ...)
+    #[arg(short = 'a', long = "all")]
+    all: bool,
+
+    /// Analyze every mount in parallel and show the size found by scanning it
+    #[arg(long = "analyze")]
+    analyze: bool,
+
+    /// Number of threads to use for the analyses (default: one per CPU, fewer on
+    /// spinning disks and network filesystems)
+    #[arg(short = 'j', long = "threads")]
+    threads: Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryArgs {
+    /// History database (default: ~/.local/share/dutop/history.db)
+    #[arg(long = "db", value_name = "FILE", global = true)]
+    db: Option<PathBuf>,
+
+    #[command(subcommand)]
+    action: HistoryAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryAction {
+    /// Scan a directory and store the sizes of its subdirectories
+    Record(HistoryRecordArgs),
+
+    /// Print the recorded sizes of a directory with its growth rate
+    Show(HistoryShowArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryRecordArgs {
+    /// Store directories up to N levels below PATH
+    #[arg(long = "record-depth", value_name = "N", default_value = "3")]
+    record_depth: usize,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryShowArgs {
+    /// Directory to show (a recorded root or one of its subdirectories)
     path: PathBuf,
 
-    /// Number of top directories to display
-    #[arg(short = 'n', long = "top", default_value = "10")]
-    top: usize,
+    /// Number of most recent scans to display
+    #[arg(short = 'n', long = "last", default_value = "20")]
+    last: usize,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group(clap::ArgGroup::new("action").required(true)))]
+struct CleanArgs {
+    /// Report reclaimable directories grouped by category
+    #[arg(long = "scan", group = "action")]
+    scan: bool,
+
+    /// Print a reviewable script deleting the candidates, largest first, instead
+    #[arg(long = "emit-script", value_name = "SHELL", group = "action")]
+    emit_script: Option<ScriptShell>,
+
+    /// Only consider candidates of this category (can be given multiple times):
+    /// build-output, dependencies, virtualenv, tool-cache, package-cache, docker, cache
+    #[arg(long = "category", value_name = "NAME")]
+    categories: Vec<dutop::clean::Category>,
+
+    /// Ignore candidates smaller than SIZE (e.g. 10M)
+    #[arg(long = "min-size", value_name = "SIZE", default_value = "0", value_parser = parse_size)]
+    min_size: u64,
+
+    /// Number of candidate directories to display
+    #[arg(short = 'n', long = "top", default_value = "20")]
+    top: usize,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    #[command(flatten)]
+    scan_args: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct AgeArgs {
+    /// Ages splitting the buckets, comma-separated (default: 7d,30d,1y)
+    #[arg(
+        long = "buckets",
+        value_name = "AGES",
+        value_delimiter = ',',
+        value_parser = parse_duration
+    )]
+    buckets: Vec<Duration>,
+
+    /// Number of top directories to display
+    #[arg(short = 'n', long = "top", default_value = "10")]
+    top: usize,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct TypesArgs {
+    /// Number of extensions to display
+    #[arg(short = 'n', long = "top", default_value = "20")]
+    top: usize,
+
+    /// Output format: human or json
+    #[arg(short = 'f', long = "format", default_value = "human")]
+    format: ReportFormat,
+
+    /// Classify files by reading their first bytes: those without a known
+    /// extension, and those whose contents contradict their extension
+    #[arg(long = "deep-types")]
+    deep_types: bool,
+
+    /// Files of each extension to read with --deep-types; the rest are
+    /// estimated from them
+    #[arg(
+        long = "sample",
+        value_name = "N",
+        default_value_t = dutop::types::DEFAULT_SAMPLE as u64,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "deep_types"
+    )]
+    sample: u64,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ProjectsArgs {
+    /// Number of projects to display
+    #[arg(short = 'n', long = "top", default_value = "20")]
+    top: usize,
+
+    /// Output format: human or json
+    #[arg(short = 'f', long = "format", default_value = "human")]
+    format: ReportFormat,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DepthArgs {
+    /// Number of deepest paths, longest paths and widest directories to display
+    #[arg(short = 'n', long = "top", default_value = "10")]
+    top: usize,
+
+    /// Output format: human or json
+    #[arg(short = 'f', long = "format", default_value = "human")]
+    format: ReportFormat,
+
+    /// Report directories with at least N direct entries
+    #[arg(long = "fan-out", value_name = "N", default_value_t = dutop::depth::DEFAULT_FAN_OUT)]
+    fan_out: u64,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args, Debug)]
+struct AuditArgs {
+    /// Number of top directories and flagged files to display
+    #[arg(short = 'n', long = "top", default_value = "20")]
+    top: usize,
+
+    /// Output format: human or json
+    #[arg(short = 'f', long = "format", default_value = "human")]
+    format: ReportFormat,
+
+    /// Flag world-writable files and unexpected owners from SIZE up (e.g. 100M);
+    /// setuid and setgid files are flagged whatever their size
+    #[arg(long = "min-size", value_name = "SIZE", default_value = "10M", value_parser = parse_size)]
+    min_size: u64,
+
+    /// Users expected to own files, as names or IDs (default: root and the owner
+    /// of the scanned directory)
+    #[arg(long = "owners", value_name = "USERS", value_delimiter = ',')]
+    owners: Vec<String>,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DockerArgs {
+    /// Analyze the contents of volume NAME instead (usually needs root)
+    #[arg(long = "volume", value_name = "NAME")]
+    volume: Option<String>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct SshArgs {
+    /// Remote directory, as [user@]host:/path
+    target: dutop::remote::RemoteTarget,
+
+    /// Command that runs dutop on the remote host
+    #[arg(long = "remote-command", value_name = "CMD", default_value = "dutop")]
+    remote_command: String,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Extra options for the remote dutop, after `--` (e.g. `-- --exclude '*.gz'`)
+    #[arg(last = true, value_name = "ARGS")]
+    remote_args: Vec<String>,
+}
+
+#[cfg(feature = "object-store")]
+#[derive(clap::Args, Debug)]
+struct S3Args {
+    /// Bucket and key prefix, as s3://bucket/prefix
+    location: dutop::s3::S3Location,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+/// Options controlling how results are displayed
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    /// Number of top directories to display (default: 10)
+    #[arg(short = 'n', long = "top")]
+    top: Option<usize>,
+
+    /// Output format: human (default), tree, json, json-compact, csv, tsv, ndjson
+    /// (streamed while scanning), du, paths, ncdu, svg, sqlite or parquet (require
+    /// --output)
+    #[arg(short = 'f', long = "format")]
+    format: Option<OutputFormat>,
+
+    /// Write the output to FILE instead of stdout, replacing it only once complete
+    /// (compressed with gzip when FILE ends in .gz)
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "FILE",
+        required_if_eq_any([("format", "sqlite"), ("format", "parquet")])
+    )]
+    output: Option<PathBuf>,
+
+    /// Print only the total of each path, like `du -s` (human and du formats);
+    /// with --combine, only the grand total
+    #[arg(short = 's', long = "summarize", conflicts_with = "all")]
+    summarize: bool,
+
+    /// List every file and directory in the tree, du and paths formats, not only
+    /// the top entries (like `du -a`); --threshold leaves out the small ones
+    #[arg(short = 'a', long = "all", conflicts_with = "time")]
+    all: bool,
+
+    /// Include a row for every file in SQLite and Parquet exports
+    #[arg(long = "with-files")]
+    with_files: bool,
+
+    /// Append a row with the totals to CSV and TSV output
+    #[arg(long = "total-row")]
+    total_row: bool,
+
+    /// Leave out entries smaller than SIZE (e.g. 1G) or than a share of the total
+    /// (e.g. 5%)
+    #[arg(long = "threshold", value_name = "SIZE", value_parser = parse_size_cutoff)]
+    threshold: Option<SizeCutoff>,
+
+    /// Show only the largest entries that together account for N percent of the
+    /// total (e.g. 90), instead of the 10 largest
+    #[arg(long = "coverage", value_name = "N", value_parser = parse_percent)]
+    coverage: Option<f64>,
+
+    /// Add a row summing up the entries left out by --top, --threshold and
+    /// --coverage, so that the rows add up to the total (human and tree formats)
+    #[arg(long = "show-others")]
+    show_others: bool,
+
+    /// Levels below the root shown by --format tree (--top limits the entries
+    /// shown in each directory)
+    #[arg(long = "display-depth", value_name = "N", default_value = "3")]
+    display_depth: usize,
+
+    /// Print sizes in du output as blocks of SIZE bytes, rounded up (e.g. 1 or 1M;
+    /// default 1K)
+    #[arg(
+        short = 'B',
+        long = "block-size",
+        value_name = "SIZE",
+        group = "du_units",
+        value_parser = parse_size
+    )]
+    block_size: Option<u64>,
+
+    /// Like --block-size 1K
+    #[arg(short = 'k', group = "du_units")]
+    kibibytes: bool,
+
+    /// Like --block-size 1M
+    #[arg(short = 'm', group = "du_units")]
+    mebibytes: bool,
+
+    /// Print sizes in du output like 4.0K or 1.5G (du -h)
+    #[arg(long = "human-readable", group = "du_units")]
+    human_readable: bool,
+
+    /// Print exact byte counts instead of rounded sizes in the table and the tree
+    /// (same as -B 1 for --format du)
+    #[arg(long = "bytes", group = "du_units")]
+    bytes: bool,
+
+    /// With --bytes, group digits by thousands with the separator of the locale
+    /// (LC_NUMERIC), like 1,234,567
+    #[arg(long = "group-digits", requires = "bytes")]
+    group_digits: bool,
+
+    /// End du and paths records (and --find-empty paths) with a NUL byte instead
+    /// of a newline, for `xargs -0`; implies --format paths when none is given
+    #[arg(short = '0', long = "print0")]
+    print0: bool,
+
+    /// Show the newest modification time of the files below each entry, to tell
+    /// active directories from abandoned ones (human and du formats)
+    #[arg(long = "time")]
+    time: bool,
+
+    /// Columns of the table and their order, from bar, size, percent, files,
+    /// dirs, mtime and path (default: bar,size,percent,path, with mtime before
+    /// path when --time is given)
+    #[arg(long = "columns", value_name = "COLUMNS", value_delimiter = ',')]
+    columns: Vec<TableColumn>,
+
+    /// Show the number of files and directories below each entry, as columns
+    /// of the table and after the names of the tree
+    #[arg(long = "show-counts", conflicts_with = "columns")]
+    show_counts: bool,
+
+    /// Name the entries of the table and the tree by their full path
+    #[arg(long = "full-paths", conflicts_with = "relative_paths")]
+    full_paths: bool,
+
+    /// Name the entries of the table and the tree by their path below the
+    /// analyzed one, like projects/rust/target, instead of the tree showing
+    /// file names and a wide terminal showing full paths
+    #[arg(long = "relative-paths")]
+    relative_paths: bool,
+
+    /// Disable colored output
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    /// Print human and tree output directly instead of through $PAGER (less by
+    /// default) when it is longer than the terminal
+    #[arg(long = "no-pager")]
+    no_pager: bool,
+
+    /// Exit with code 6 when the total or a top directory is larger than SIZE (e.g. 100G)
+    #[arg(long = "fail-if-over", value_name = "SIZE", value_parser = parse_size)]
+    fail_if_over: Option<u64>,
+
+    /// Exit with code 6 when the total or a top directory takes more than N percent
+    /// of the filesystem
+    #[arg(long = "fail-if-percent-over", value_name = "N")]
+    fail_if_percent_over: Option<f64>,
+}
+
+impl OutputArgs {
+    fn top(&self) -> usize {
+        // A coverage cutoff makes the default count pointless
+        let default = if self.coverage.is_some() {
+            usize::MAX
+        } else {
+            10
+        };
+        self.top.unwrap_or(default)
+    }
+
+    fn format(&self) -> OutputFormat {
+        let default = if self.print0 {
+            OutputFormat::Paths
+        } else {
+            OutputFormat::Human
+        };
+        self.format.unwrap_or(default)
+    }
+
+    fn size_format(&self) -> SizeFormat {
+        if !self.bytes {
+            SizeFormat::Human
+        } else if self.group_digits {
+            SizeFormat::Bytes(Some(locale_thousands_separator()))
+        } else {
+            SizeFormat::Bytes(None)
+        }
+    }
+
+    /// Columns of the table, the default ones unless others were chosen
+    fn columns(&self) -> Vec<output::Column> {
+        if self.columns.is_empty() {
+            let mut columns = vec![
+                output::Column::Bar,
+                output::Column::Size,
+                output::Column::Percent,
+            ];
+            if self.show_counts {
+                columns.extend([output::Column::Files, output::Column::Dirs]);
+            }
+            if self.time {
+                columns.push(output::Column::Mtime);
+            }
+            columns.push(output::Column::Path);
+            return columns;
+        }
+        self.columns
+            .iter()
+            .map(|column| match column {
+                TableColumn::Bar => output::Column::Bar,
+                TableColumn::Size => output::Column::Size,
+                TableColumn::Percent => output::Column::Percent,
+                TableColumn::Files => output::Column::Files,
+                TableColumn::Dirs => output::Column::Dirs,
+                TableColumn::Mtime => output::Column::Mtime,
+                TableColumn::Path => output::Column::Path,
+            })
+            .collect()
+    }
+
+    fn path_style(&self) -> output::PathStyle {
+        if self.full_paths {
+            output::PathStyle::Full
+        } else if self.relative_paths {
+            output::PathStyle::Relative
+        } else {
+            output::PathStyle::Auto
+        }
+    }
+
+    /// Whether every file is listed, which needs the tree with its files
+    fn lists_all(&self) -> bool {
+        self.all
+            && matches!(
+                self.format(),
+                OutputFormat::Tree | OutputFormat::Du | OutputFormat::Paths
+            )
+    }
+
+    /// Byte ending each record of the du and paths formats
+    fn terminator(&self) -> u8 {
+        if self.print0 {
+            b'\0'
+        } else {
+            b'\n'
+        }
+    }
+
+    fn du_units(&self) -> output::DuUnits {
+        if self.human_readable {
+            output::DuUnits::Human
+        } else if self.bytes {
+            output::DuUnits::Blocks(1)
+        } else if self.mebibytes {
+            output::DuUnits::Blocks(1 << 20)
+        } else {
+            output::DuUnits::Blocks(self.block_size.unwrap_or(1024))
+        }
+    }
+
+    fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            max_size: self.fail_if_over,
+            max_percent: self.fail_if_percent_over,
+        }
+    }
+
+    /// Where to print the results (export formats write their file themselves)
+    fn sink(&self) -> Result<OutputSink> {
+        let path = self
+            .output
+            .as_deref()
+            .filter(|_| !self.format().is_export());
+        let human = matches!(self.format(), OutputFormat::Human | OutputFormat::Tree);
+        if path.is_none() && human && !self.no_pager {
+            return Ok(OutputSink::paged());
+        }
+        OutputSink::open(path)
+    }
+
+    /// Fill in options not given on the command line from the config file
+    fn apply(&mut self, settings: &Settings) -> Result<()> {
+        self.top = self.top.or(settings.top);
+        if self.format.is_none() && !self.print0 {
+            self.format = settings
+                .format
+                .as_deref()
+                .map(|name| {
+                    OutputFormat::from_str(name, true)
+                        .map_err(|_| anyhow::anyhow!("Invalid format in config file: {}", name))
+                })
+                .transpose()?;
+        }
+        self.no_color |= settings.color == Some(false);
+        Ok(())
+    }
+}
+
+/// Options controlling what is scanned, shared by all commands
+#[derive(clap::Args, Debug)]
+struct ScanArgs {
+    /// Directories to analyze (default: current directory)
+    #[arg(value_name = "PATH", default_value = ".")]
+    paths: Vec<PathBuf>,
+
+    /// With several paths, rank their entries together and print a grand total
+    #[arg(short = 'c', long = "combine")]
+    combine: bool,
+
+    /// Maximum depth to traverse (default: unlimited)
+    #[arg(short = 'd', long = "depth")]
+    depth: Option<usize>,
+
+    /// Exclude patterns (glob syntax, can be specified multiple times); patterns
+    /// containing a slash match paths relative to PATH, others match names
+    #[arg(short = 'x', long = "exclude")]
+    exclude: Vec<String>,
+
+    /// Read exclude patterns from FILE, one per line (# starts a comment)
+    #[arg(long = "exclude-from", value_name = "FILE")]
+    exclude_from: Vec<PathBuf>,
+
+    /// Follow symbolic links
+    #[arg(short = 'L', long = "follow-links")]
+    follow_links: bool,
+
+    /// Count symlinks and special files (sockets, FIFOs, devices) as files,
+    /// including their disk usage
+    #[arg(long = "all-types")]
+    all_types: bool,
+
+    /// Report broken symlinks, symlink loops and symlinks pointing outside PATH
+    #[arg(long = "check-links")]
+    check_links: bool,
+
+    /// Track hard links in bounded memory, at the risk of rarely skipping a
+    /// linked file (for filesystems with hundreds of millions of files)
+    #[arg(long = "fast-hardlinks")]
+    fast_hardlinks: bool,
+
+    /// Check every file for hard links, not only those with a link count above
+    /// one (for filesystems that do not report link counts reliably)
+    #[arg(long = "track-all-inodes")]
+    track_all_inodes: bool,
+
+    /// Skip files ignored by .gitignore, .ignore and .git/info/exclude
+    #[arg(long = "ignore-vcs")]
+    ignore_vcs: bool,
+
+    /// Stay on the filesystem of PATH, skipping mount points (like `du -x`)
+    #[arg(long = "one-file-system")]
+    one_file_system: bool,
+
+    /// Include NTFS alternate data streams (such as Zone.Identifier) in file sizes
+    #[cfg(windows)]
+    #[arg(long = "streams")]
+    streams: bool,
+
+    /// Detect APFS clones and report the unique on-disk usage separately
+    #[cfg(target_os = "macos")]
+    #[arg(long = "clones")]
+    clones: bool,
+
+    /// Show the contents of .tar, .tar.gz and .zip files as virtual directories
+    #[arg(long = "archives")]
+    archives: bool,
+
+    /// Number of threads to use (default: one per CPU, fewer on spinning disks
+    /// and network filesystems)
+    #[arg(short = 'j', long = "threads")]
+    threads: Option<usize>,
+
+    /// How directories are read: walkdir (portable), uring (Linux: batched
+    /// getdents64 and statx, much fewer system calls on huge trees) or mft
+    /// (Windows: reads the NTFS Master File Table, needs administrator rights)
+    #[arg(long = "backend", default_value = "walkdir")]
+    backend: Backend,
+
+    /// What to do with network filesystems (NFS, SMB, SSHFS, ...) mounted below
+    /// the path: scan them, warn and scan them with fewer threads, or skip them
+    #[arg(long = "remote-fs", value_name = "MODE", default_value = "warn")]
+    remote_fs: RemoteFsMode,
+
+    /// Also walk pseudo filesystems mounted below the path (/proc, /sys, /dev,
+    /// /run and other tmpfs, ...), which are skipped by their type by default
+    #[arg(long = "include-virtual")]
+    include_virtual: bool,
+
+    /// Skip entries whose directory listing or metadata takes longer than
+    /// DURATION (e.g. 30s) to read, such as on a dead NFS server, reporting them
+    /// as errors instead of hanging
+    #[arg(long = "timeout", value_name = "DURATION", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Report wall time, files and directories per second, system calls saved
+    /// by the cache, per-thread utilization and peak memory after the scan (on
+    /// stderr, or as "stats" in JSON)
+    #[arg(long = "stats")]
+    stats: bool,
+
+    /// Only count files not modified within AGE (e.g. 90d, 12h, 1y)
+    #[arg(long = "older-than", value_name = "AGE", value_parser = parse_duration)]
+    older_than: Option<Duration>,
+
+    /// Only count files modified within AGE (e.g. 7d, 2w)
+    #[arg(long = "newer-than", value_name = "AGE", value_parser = parse_duration)]
+    newer_than: Option<Duration>,
+
+    /// Skip files smaller than SIZE (e.g. 10M, 512K)
+    #[arg(long = "min-file-size", value_name = "SIZE", value_parser = parse_size)]
+    min_file_size: Option<u64>,
+}
+
+impl ScanArgs {
+    /// Add the config file settings to the options given on the command line
+    fn apply(&mut self, settings: &Settings) {
+        self.exclude.extend(settings.exclude.iter().cloned());
+        self.threads = self.threads.or(settings.threads);
+    }
+
+    /// Resolve the path to analyze, for commands that take a single one
+    fn canonical_path(&self) -> Result<PathBuf> {
+        match self.canonical_paths()?.as_slice() {
+            [path] => Ok(path.clone()),
+            _ => anyhow::bail!("This command accepts a single path"),
+        }
+    }
+
+    /// Resolve all paths to analyze
+    fn canonical_paths(&self) -> Result<Vec<PathBuf>> {
+        self.paths
+            .iter()
+            .map(|path| {
+                path.canonicalize()
+                    .context(format!("Failed to access path: {}", path.display()))
+            })
+            .collect()
+    }
+
+    /// Build the analysis configuration from the scan options
+    fn config(&self) -> Result<AnalysisConfig> {
+        let mut exclude_patterns = self.exclude.clone();
+        for file in &self.exclude_from {
+            exclude_patterns.extend(read_exclude_file(file)?);
+        }
+
+        let now = SystemTime::now();
+        Ok(AnalysisConfig {
+            max_depth: self.depth,
+            exclude_patterns,
+            follow_links: self.follow_links,
+            num_threads: self.threads,
+            one_file_system: self.one_file_system,
+            respect_gitignore: self.ignore_vcs,
+            modified_before: self.older_than.and_then(|age| now.checked_sub(age)),
+            modified_after: self.newer_than.and_then(|age| now.checked_sub(age)),
+            min_file_size: self.min_file_size,
+            expand_archives: self.archives,
+            check_links: self.check_links,
+            count_all_types: self.all_types,
+            fast_hardlinks: self.fast_hardlinks,
+            track_all_inodes: self.track_all_inodes,
+            collect_stats: self.stats,
+            backend: match self.backend {
+                Backend::Walkdir => ScanBackend::Walkdir,
+                #[cfg(target_os = "linux")]
+                Backend::Uring => ScanBackend::Uring,
+                #[cfg(windows)]
+                Backend::Mft => ScanBackend::Mft,
+            },
+            entry_timeout: self.timeout,
+            include_virtual: self.include_virtual,
+            remote_fs: match self.remote_fs {
+                RemoteFsMode::Scan => RemoteFs::Scan,
+                RemoteFsMode::Warn => RemoteFs::Warn,
+                RemoteFsMode::Skip => RemoteFs::Skip,
+            },
+            #[cfg(windows)]
+            alternate_streams: self.streams,
+            #[cfg(target_os = "macos")]
+            detect_clones: self.clones,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ScriptShell {
+    /// POSIX shell script using `rm -rf`
+    Sh,
+    /// PowerShell script using `Remove-Item`
+    Ps1,
+}
+
+/// Backends reading directories and metadata
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Backend {
+    Walkdir,
+    #[cfg(target_os = "linux")]
+    Uring,
+    #[cfg(windows)]
+    Mft,
+}
+
+/// Handling of network filesystems below the scanned path
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum RemoteFsMode {
+    Scan,
+    Warn,
+    Skip,
+}
+
+/// What directories are ranked by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum RankBy {
+    Size,
+    Files,
+}
+
+/// When the output is colored
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+/// When names are written as hyperlinks
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum HyperlinkWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Icons put before the names
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum IconSet {
+    Nerd,
+    Emoji,
+}
+
+/// Columns of the table
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum TableColumn {
+    Bar,
+    Size,
+    Percent,
+    Files,
+    Dirs,
+    Mtime,
+    Path,
+}
+
+/// What the table and the tree are colored by
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ColorMode {
+    Size,
+    Age,
+    Type,
+}
+
+/// Output formats of the reports that are not directory listings
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ReportFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+    JsonCompact,
+    Csv,
+    Tsv,
+    Ndjson,
+    Du,
+    Paths,
+    Ncdu,
+    Svg,
+    Tree,
+    Sqlite,
+    #[cfg(feature = "arrow")]
+    Parquet,
+}
+
+impl OutputFormat {
+    /// Formats exporting every directory of the tree to a file
+    fn is_export(self) -> bool {
+        match self {
+            OutputFormat::Sqlite => true,
+            #[cfg(feature = "arrow")]
+            OutputFormat::Parquet => true,
+            _ => false,
+        }
+    }
+}
+
+fn main() {
+    let exit_code = match run() {
+        Ok(()) => 0,
+        // The reader of the output went away, like `head` once it has its lines
+        Err(e) if is_broken_pipe(&e) => 0,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+
+            // Return appropriate exit code based on error type
+            if e.is::<ThresholdExceeded>() {
+                6 // Over --fail-if-over or --fail-if-percent-over
+            } else if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
+                match io_err.kind() {
+                    std::io::ErrorKind::NotFound => 4,         // Path not found
+                    std::io::ErrorKind::PermissionDenied => 3, // Permission denied
+                    _ => 5,                                    // Disk I/O error
+                }
+            } else {
+                1 // General error
+            }
+        }
+    };
+
+    process::exit(exit_code);
+}
+
+/// Whether `error` comes from writing to a pipe whose reader has exited
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Parse the command line `args`, rejecting options of the default scan given
+/// along with a subcommand, where they would be ignored
+///
+/// Global options, like --config, are accepted before and after subcommands.
+fn parse_args<I, T>(args: I) -> Result<Args, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    use clap::error::ErrorKind;
+    use clap::parser::ValueSource;
+    use clap::{CommandFactory, FromArgMatches};
+
+    let mut command = Args::command();
+    let matches = command.try_get_matches_from_mut(args)?;
+    if let Some((name, _)) = matches.subcommand() {
+        let given = command.get_arguments().find(|arg| {
+            let source = matches.value_source(arg.get_id().as_str());
+            !arg.is_global_set() && source == Some(ValueSource::CommandLine)
+        });
+        if let Some(arg) = given {
+            let arg = match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => arg.get_id().to_string(),
+            };
+            let message = format!("'{}' cannot be used with the '{}' subcommand", arg, name);
+            return Err(command.error(ErrorKind::ArgumentConflict, message));
+        }
+    }
+    Args::from_arg_matches(&matches).map_err(|e| e.format(&mut command))
+}
+
+fn run() -> Result<()> {
+    let mut args = parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit());
+
+    // Initialize logging
+    init_logging(&args)?;
+
+    args.apply_config()?;
+
+    log::debug!("Starting DuTop with args: {:?}", args);
+
+    match &args.command {
+        Some(Command::Tui(tui)) => run_tui(&tui.scan, tui.trash),
+        Some(Command::Report(report)) => run_report(report, &args),
+        Some(Command::Scan(cmd)) => run_scan(&cmd.scan, &cmd.output, cmd.save.as_deref(), &args),
+        Some(Command::Load(load)) => run_load(load, &args),
+        Some(Command::Diff(diff)) => run_diff(diff, &args),
+        Some(Command::Watch(watch)) => run_watch(watch, &args),
+        Some(Command::Dupes(dupes)) => run_dupes(dupes, &args),
+        Some(Command::Mounts(mounts)) => run_mounts(mounts),
+        Some(Command::History(history)) => run_history(history, &args),
+        Some(Command::Clean(clean)) => run_clean(clean, &args),
+        Some(Command::Age(age)) => run_age(age),
+        Some(Command::Types(types)) => run_types(types, &args),
+        Some(Command::Projects(projects)) => run_projects(projects, &args),
+        Some(Command::Depth(depth)) => run_depth(depth),
+        #[cfg(unix)]
+        Some(Command::Audit(audit)) => run_audit(audit, &args),
+        Some(Command::Docker(docker)) => run_docker(docker, &args),
+        Some(Command::Ssh(ssh)) => run_ssh(ssh, &args),
+        #[cfg(feature = "object-store")]
+        Some(Command::S3(s3)) => run_s3(s3, &args),
+        Some(Command::Serve(serve)) => run_serve(serve),
+        #[cfg(feature = "webhook")]
+        Some(Command::Alert(alert)) => run_alert(alert),
+        Some(Command::Daemon(daemon)) => run_daemon(daemon),
+        None if args.interactive => run_tui(&args.scan, args.trash),
+        #[cfg(unix)]
+        None if args.by_owner => run_by_owner(&args),
+        None if args.hardlinks => run_hard_links(&args),
+        None if args.histogram => run_histogram(&args),
+        None if args.rank_by == RankBy::Files => run_counts(&args),
+        None if args.estimate => run_estimate(&args),
+        None if args.git_aware => run_git_repos(&args),
+        None if args.find_empty => run_find_empty(&args),
+        None => run_scan(&args.scan, &args.output, None, &args),
+    }
+}
+
+fn run_scan(scan: &ScanArgs, output: &OutputArgs, save: Option<&Path>, args: &Args) -> Result<()> {
+    // Validate paths
+    let paths = scan.canonical_paths()?;
+    if paths.len() > 1 && !scan.combine && save.is_some() {
+        anyhow::bail!("Saving a snapshot of several paths requires --combine");
+    }
+
+    // Build configuration
+    let mut config = scan.config()?;
+    if matches!(output.format(), OutputFormat::Ncdu) || save.is_some() {
+        config.build_tree = true;
+        config.tree_files = true;
+    }
+    if matches!(output.format(), OutputFormat::Svg | OutputFormat::Tree) {
+        config.build_tree = true;
+    }
+    if output.lists_all() {
+        config.build_tree = true;
+        config.tree_files = true;
+    }
+    // Archives are expanded into the tree, which their counts come from
+    if config.expand_archives {
+        config.build_tree = true;
+    }
+    if output.format().is_export() {
+        if paths.len() > 1 && !scan.combine {
+            anyhow::bail!("Exporting several paths requires --combine");
+        }
+        config.build_tree = true;
+        config.tree_files |= output.with_files;
+    }
+
+    // Only the totals are printed, so the tree is not needed either
+    if output.summarize && save.is_none() {
+        config.build_tree = false;
+        config.tree_files = false;
+    }
+
+    // Snapshots, combined rankings, the others row and coverage need every
+    // top-level entry
+    let every_entry =
+        save.is_some() || scan.combine || output.show_others || output.coverage.is_some();
+    let top = if output.summarize && save.is_none() {
+        // Only the totals are printed, so no entry needs to be kept
+        0
+    } else if every_entry {
+        usize::MAX
+    } else {
+        output.top()
+    };
+    let stream_ndjson = matches!(output.format(), OutputFormat::Ndjson);
+    let mut sink = Mutex::new(output.sink()?);
+
+    // Perform analysis, reporting each root separately unless combining them
+    let started_at = SystemTime::now();
+    let mut violations = Vec::new();
+    let mut results = Vec::with_capacity(paths.len());
+    for path in &paths {
+        log::info!("Analyzing path: {}", path.display());
+        let context = output::JsonContext {
+            config: Some(&config),
+            started_at: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        let mut result = analyze(path, &config, top, args, stream_ndjson.then_some(&sink))?;
+        if scan.combine || paths.len() == 1 {
+            results.push(result);
+        } else {
+            let sink = sink.get_mut().unwrap_or_else(PoisonError::into_inner);
+            print_output(
+                &mut result,
+                output,
+                &context,
+                stream_ndjson,
+                true,
+                sink,
+                args,
+            )?;
+            violations.extend(output.thresholds().check(&result));
+        }
+    }
+    let mut sink = sink.into_inner().unwrap_or_else(PoisonError::into_inner);
+    if results.is_empty() {
+        sink.finish()?;
+        return fail_on_violations(violations);
+    }
+    let mut result = if results.len() == 1 {
+        results.remove(0)
+    } else {
+        dutop::combine_results(results, top)
+    };
+
+    if let Some(save) = save {
+        snapshot::save_snapshot(&result, save)?;
+        eprintln!("Snapshot written to {}", save.display());
+    }
+    let context = output::JsonContext {
+        config: Some(&config),
+        started_at: Some(started_at),
+        ..Default::default()
+    };
+    print_output(
+        &mut result,
+        output,
+        &context,
+        stream_ndjson,
+        true,
+        &mut sink,
+        args,
+    )?;
+    sink.finish()?;
+
+    log::info!("Analysis complete");
+
+    check_thresholds(&result, output)
+}
+
+fn run_load(load: &LoadArgs, args: &Args) -> Result<()> {
+    let snapshot = snapshot::load_snapshot(&load.file)?;
+    let mut result = snapshot.result;
+    log::info!(
+        "Loaded snapshot of {} taken {}s ago",
+        result.root_path.display(),
+        snapshot.created.elapsed().map(|d| d.as_secs()).unwrap_or(0)
+    );
+
+    let context = output::JsonContext {
+        started_at: Some(snapshot.created),
+        ..Default::default()
+    };
+    let mut sink = load.output.sink()?;
+    print_output(
+        &mut result,
+        &load.output,
+        &context,
+        false,
+        false,
+        &mut sink,
+        args,
+    )?;
+    sink.finish()?;
+    check_thresholds(&result, &load.output)
+}
+
+fn run_diff(diff: &DiffArgs, args: &Args) -> Result<()> {
+    let old = snapshot::load_snapshot(&diff.old)?.result;
+    let new = match (&diff.new, &diff.compare) {
+        (Some(new), _) => snapshot::load_snapshot(new)?.result,
+        (None, Some(path)) => {
+            let path = path
+                .canonicalize()
+                .context(format!("Failed to access path: {}", path.display()))?;
+            let config = AnalysisConfig {
+                build_tree: true,
+                ..Default::default()
+            };
+            analyze(&path, &config, usize::MAX, args, None)?
+        }
+        (None, None) => anyhow::bail!("Nothing to compare against"),
+    };
+
+    let deltas = dutop::diff::diff_results(&old, &new);
+    let output_config = args.output_config(diff.no_color, atty::is(atty::Stream::Stdout));
+    output::print_diff(&old, &new, &deltas, diff.top, &output_config);
+
+    Ok(())
+}
+
+fn run_watch(watch: &WatchArgs, args: &Args) -> Result<()> {
+    let path = watch.scan.canonical_path()?;
+    let is_terminal = atty::is(atty::Stream::Stdout);
+    let output_config = args.output_config(watch.no_color, is_terminal);
+
+    // Runs until interrupted
+    let cancel = CancellationToken::new();
+    let config = watch.scan.config()?;
+    dutop::watch::watch(&path, &config, watch.top, &cancel, |result| {
+        if is_terminal {
+            // Clear the screen so the table is redrawn in place
+            print!("\x1b[2J\x1b[H");
+        }
+        output::print_results(result, &output_config);
+        println!("\nWatching for changes (Ctrl-C to stop)...");
+    })
+}
+
+fn run_dupes(dupes: &DupesArgs, args: &Args) -> Result<()> {
+    let path = dupes.scan.canonical_path()?;
+    let groups = dutop::dedupe::find_duplicates(&path, &dupes.scan.config()?, dupes.min_size)?;
+
+    let output_config = args.output_config(dupes.no_color, atty::is(atty::Stream::Stdout));
+    output::print_duplicates(&path, &groups, dupes.top, &output_config);
+
+    Ok(())
+}
+
+fn run_mounts(args: &MountsArgs) -> Result<()> {
+    let mounts = dutop::mounts::list_mounts(args.all)?;
+
+    if !args.analyze {
+        output::print_mounts(&mounts, None);
+        return Ok(());
+    }
 
-    /// Maximum depth to traverse (default: unlimited)
-    #[arg(short = 'd', long = "depth")]
-    depth: Option<usize>,
+    let config = AnalysisConfig {
+        num_threads: args.threads,
+        ..Default::default()
+    };
+    let scanned: Vec<Option<u64>> = dutop::mounts::analyze_mounts(&mounts, &config, 0)
+        .into_iter()
+        .zip(&mounts)
+        .map(|(result, mount)| match result {
+            Ok(result) => Some(result.total_size),
+            Err(e) => {
+                log::warn!("Failed to analyze {}: {}", mount.mount_point.display(), e);
+                None
+            }
+        })
+        .collect();
+    output::print_mounts(&mounts, Some(&scanned));
+    Ok(())
+}
 
-    /// Exclude patterns (glob syntax, can be specified multiple times)
-    #[arg(short = 'x', long = "exclude")]
-    exclude: Vec<String>,
+fn run_history(history: &HistoryArgs, args: &Args) -> Result<()> {
+    let db = match &history.db {
+        Some(db) => db.clone(),
+        None => dutop::history::default_path()
+            .context("Cannot locate a data directory for the history database, use --db")?,
+    };
+    let mut database = dutop::history::History::open(&db)?;
 
-    /// Follow symbolic links
-    #[arg(short = 'L', long = "follow-links")]
-    follow_links: bool,
+    match &history.action {
+        HistoryAction::Record(record) => {
+            let path = record.scan.canonical_path()?;
+            let config = AnalysisConfig {
+                build_tree: true,
+                ..record.scan.config()?
+            };
+            let result = analyze(&path, &config, 0, args, None)?;
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let stored = database.record(&result, timestamp, record.record_depth)?;
+            eprintln!(
+                "Recorded {} ({}, {} directories) in {}",
+                path.display(),
+                format_size_auto(result.total_size),
+                stored,
+                db.display()
+            );
+        }
+        HistoryAction::Show(show) => {
+            // Recorded paths are canonical, but the directory may be gone by now
+            let path = show
+                .path
+                .canonicalize()
+                .unwrap_or_else(|_| show.path.clone());
+            let points = database.timeline(&path, show.last)?;
+            let output_config = args.output_config(false, atty::is(atty::Stream::Stdout));
+            output::print_history(&path, &points, &output_config);
+        }
+    }
+    Ok(())
+}
 
-    /// Number of threads to use (default: auto-detect)
-    #[arg(short = 'j', long = "threads")]
-    threads: Option<usize>,
+fn run_clean(clean: &CleanArgs, args: &Args) -> Result<()> {
+    let path = clean.scan_args.canonical_path()?;
+    let mut report = dutop::clean::find_reclaimable(&path, &clean.scan_args.config()?)?;
+    report.retain(&clean.categories, clean.min_size);
 
-    /// Output format: human (default), json
-    #[arg(short = 'f', long = "format", default_value = "human")]
-    format: OutputFormat,
+    if let Some(shell) = clean.emit_script {
+        let format = match shell {
+            ScriptShell::Sh => ScriptFormat::Sh,
+            ScriptShell::Ps1 => ScriptFormat::PowerShell,
+        };
+        output::write_clean_script(&mut io::stdout().lock(), &report, format)?;
+        return Ok(());
+    }
 
-    /// Disable colored output
-    #[arg(long = "no-color")]
-    no_color: bool,
+    let output_config = args.output_config(clean.no_color, atty::is(atty::Stream::Stdout));
+    output::print_clean(&report, clean.top, &output_config);
+    Ok(())
+}
 
-    /// Enable verbose logging
-    #[arg(short = 'v', long = "verbose")]
-    verbose: bool,
+fn run_age(age: &AgeArgs) -> Result<()> {
+    let path = age.scan.canonical_path()?;
+    let boundaries = if age.buckets.is_empty() {
+        dutop::age::DEFAULT_BOUNDARIES.to_vec()
+    } else {
+        age.buckets.clone()
+    };
+    let report = dutop::age::analyze_by_age(&path, &age.scan.config()?, &boundaries)?;
+    output::print_age(&report, age.top);
+    Ok(())
+}
 
-    /// Enable debug logging
-    #[arg(long = "debug")]
-    debug: bool,
+fn run_types(types: &TypesArgs, args: &Args) -> Result<()> {
+    let path = types.scan.canonical_path()?;
+    let report = dutop::types::analyze_by_type(
+        &path,
+        &types.scan.config()?,
+        types.deep_types.then_some(types.sample as usize),
+    )?;
+
+    match types.format {
+        ReportFormat::Human => {
+            let output_config = args.output_config(types.no_color, atty::is(atty::Stream::Stdout));
+            output::print_types(&report, types.top, &output_config);
+        }
+        ReportFormat::Json => {
+            let mut out = io::stdout().lock();
+            output::write_types_json(&mut out, &report, types.top)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone, clap::ValueEnum)]
-enum OutputFormat {
-    Human,
-    Json,
+fn run_projects(projects: &ProjectsArgs, args: &Args) -> Result<()> {
+    let path = projects.scan.canonical_path()?;
+    let report = dutop::projects::find_projects(&path, &projects.scan.config()?)?;
+
+    match projects.format {
+        ReportFormat::Human => {
+            let output_config =
+                args.output_config(projects.no_color, atty::is(atty::Stream::Stdout));
+            output::print_projects(&report, projects.top, &output_config);
+        }
+        ReportFormat::Json => {
+            let mut out = io::stdout().lock();
+            output::write_projects_json(&mut out, &report, projects.top)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
 }
 
-fn main() {
-    let exit_code = match run() {
-        Ok(()) => 0,
-        Err(e) => {
-            eprintln!("Error: {:#}", e);
+fn run_depth(depth: &DepthArgs) -> Result<()> {
+    let path = depth.scan.canonical_path()?;
+    let report =
+        dutop::depth::analyze_depth(&path, &depth.scan.config()?, depth.top, depth.fan_out)?;
 
-            // Return appropriate exit code based on error type
-            if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
-                match io_err.kind() {
-                    std::io::ErrorKind::NotFound => 4,        // Path not found
-                    std::io::ErrorKind::PermissionDenied => 3, // Permission denied
-                    _ => 5,                                    // Disk I/O error
-                }
-            } else {
-                1 // General error
-            }
+    match depth.format {
+        ReportFormat::Human => output::print_depth(&report),
+        ReportFormat::Json => {
+            let mut out = io::stdout().lock();
+            output::write_depth_json(&mut out, &report)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_audit(audit: &AuditArgs, args: &Args) -> Result<()> {
+    let path = audit.scan.canonical_path()?;
+    let owners = audit
+        .owners
+        .iter()
+        .map(|name| dutop::audit::resolve_user(name))
+        .collect::<Result<Vec<_>>>()?;
+    let report = dutop::audit::audit(
+        &path,
+        &audit.scan.config()?,
+        audit.top,
+        audit.min_size,
+        &owners,
+    )?;
+
+    match audit.format {
+        ReportFormat::Human => {
+            let output_config = args.output_config(audit.no_color, atty::is(atty::Stream::Stdout));
+            output::print_results(&report.result, &output_config);
+            output::print_audit(&report, audit.top, &output_config);
         }
+        ReportFormat::Json => {
+            let mut out = io::stdout().lock();
+            output::write_audit_json(&mut out, &report, audit.top)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn run_docker(docker: &DockerArgs, args: &Args) -> Result<()> {
+    let Some(volume) = &docker.volume else {
+        let usage = dutop::docker::disk_usage()?;
+        output::print_docker(&usage, docker.output.top());
+        return Ok(());
     };
 
-    process::exit(exit_code);
+    let mountpoint = dutop::docker::volume_mountpoint(volume)?;
+    let path = mountpoint.canonicalize().context(format!(
+        "Failed to access volume {} at {}",
+        volume,
+        mountpoint.display()
+    ))?;
+    let config = AnalysisConfig::default();
+    let context = output::JsonContext {
+        config: Some(&config),
+        started_at: Some(SystemTime::now()),
+        ..Default::default()
+    };
+    let mut sink = docker.output.sink()?;
+    let mut result = analyze(&path, &config, docker.output.top(), args, None)?;
+    print_output(
+        &mut result,
+        &docker.output,
+        &context,
+        false,
+        true,
+        &mut sink,
+        args,
+    )?;
+    sink.finish()?;
+    check_thresholds(&result, &docker.output)
 }
 
-fn run() -> Result<()> {
-    let args = Args::parse();
+fn run_ssh(ssh: &SshArgs, args: &Args) -> Result<()> {
+    let context = output::JsonContext {
+        started_at: Some(SystemTime::now()),
+        ..Default::default()
+    };
+    let mut result = dutop::remote::analyze_remote(
+        &ssh.target,
+        &ssh.remote_command,
+        &ssh.remote_args,
+        ssh.output.top(),
+    )?;
+    let mut sink = ssh.output.sink()?;
+    print_output(
+        &mut result,
+        &ssh.output,
+        &context,
+        false,
+        false,
+        &mut sink,
+        args,
+    )?;
+    sink.finish()?;
+    check_thresholds(&result, &ssh.output)
+}
 
-    // Initialize logging
-    init_logging(&args)?;
+#[cfg(feature = "object-store")]
+fn run_s3(s3: &S3Args, args: &Args) -> Result<()> {
+    let context = output::JsonContext {
+        started_at: Some(SystemTime::now()),
+        ..Default::default()
+    };
+    let mut result = dutop::s3::analyze_s3(&s3.location, s3.output.top())?;
+    let mut sink = s3.output.sink()?;
+    print_output(
+        &mut result,
+        &s3.output,
+        &context,
+        false,
+        false,
+        &mut sink,
+        args,
+    )?;
+    sink.finish()?;
+    check_thresholds(&result, &s3.output)
+}
 
-    log::debug!("Starting DuTop with args: {:?}", args);
+fn run_serve(serve: &ServeArgs) -> Result<()> {
+    let roots = serve.scan.canonical_paths()?;
+    let config = serve.scan.config()?;
 
-    // Validate path
-    let path = args.path.canonicalize().context(format!(
-        "Failed to access path: {}",
-        args.path.display()
-    ))?;
+    // Bind everything first so a taken port fails before anything runs
+    let prometheus = match &serve.prometheus {
+        Some(addr) => {
+            let addr = dutop::http::parse_listen_addr(addr)?;
+            Some((addr, dutop::http::bind(addr)?))
+        }
+        None => None,
+    };
+    let api = match &serve.http {
+        Some(addr) => {
+            let addr = dutop::http::parse_listen_addr(addr)?;
+            Some((addr, dutop::http::bind(addr)?))
+        }
+        None => None,
+    };
 
-    log::info!("Analyzing path: {}", path.display());
+    let mut servers = Vec::new();
+    if let Some((addr, listener)) = prometheus {
+        let metrics = Arc::new(Metrics::new());
+        let scanned = Arc::clone(&metrics);
+        let (roots, config) = (roots.clone(), config.clone());
+        let (top, interval) = (serve.top, serve.interval);
+        thread::spawn(move || {
+            let cancel = CancellationToken::new();
+            dutop::metrics::scan_loop(&scanned, &roots, &config, top, interval, &cancel);
+        });
 
-    // Build configuration
-    let config = AnalysisConfig {
-        max_depth: args.depth,
-        exclude_patterns: args.exclude,
-        follow_links: args.follow_links,
-        num_threads: args.threads,
+        eprintln!("Serving metrics on http://{}/metrics", addr);
+        servers.push(thread::spawn(move || {
+            dutop::http::serve(listener, move |request| {
+                dutop::metrics::handle(&metrics, request)
+            })
+        }));
+    }
+    if let Some((addr, listener)) = api {
+        let api = dutop::api::Api::new(roots, config, serve.cache_ttl);
+        eprintln!("Serving the JSON API on http://{}/", addr);
+        servers.push(thread::spawn(move || {
+            dutop::http::serve(listener, move |request| api.handle(request))
+        }));
+    }
+
+    for server in servers {
+        server
+            .join()
+            .map_err(|_| anyhow::anyhow!("Server thread panicked"))??;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "webhook")]
+fn run_alert(args: &AlertArgs) -> Result<()> {
+    let roots = args.scan.canonical_paths()?;
+    let config = args.scan.config()?;
+    let thresholds = Thresholds {
+        max_size: args.threshold,
+        max_percent: args.percent_threshold,
+    };
+    let webhook = dutop::alert::Webhook {
+        url: args.webhook.clone(),
+        format: args.webhook_format,
     };
 
-    // Perform analysis
-    let result = analyze_disk_usage(&path, &config, args.top)?;
+    if let Some(interval) = args.interval {
+        let mut alerter = dutop::alert::Alerter::new(webhook, thresholds);
+        let cancel = CancellationToken::new();
+        dutop::alert::alert_loop(&roots, &config, args.top, &mut alerter, interval, &cancel);
+        return Ok(());
+    }
+    for root in &roots {
+        let result = analyze_disk_usage(root, &config, args.top)?;
+        let violations = thresholds.check(&result);
+        if violations.is_empty() {
+            continue;
+        }
+        for violation in &violations {
+            eprintln!("Over threshold: {}", violation);
+        }
+        webhook.post(&result, &violations, &thresholds)?;
+        eprintln!("Alert sent for {}", root.display());
+    }
+    Ok(())
+}
+
+fn run_daemon(args: &DaemonArgs) -> Result<()> {
+    let config = DaemonConfig::load(&args.daemon_config)?;
+    let interval = args
+        .interval
+        .or(config.interval)
+        .unwrap_or(dutop::daemon::DEFAULT_INTERVAL);
 
-    // Output results
-    match args.format {
-        OutputFormat::Human => {
-            let output_config = output::OutputConfig {
-                use_colors: !args.no_color && atty::is(atty::Stream::Stdout),
-                ..Default::default()
-            };
-            output::print_results(&result, &output_config);
+    if args.systemd_unit {
+        let exe = std::env::current_exe().context("Cannot determine the path of dutop")?;
+        let config_path = args
+            .daemon_config
+            .canonicalize()
+            .with_context(|| format!("Failed to access path: {}", args.daemon_config.display()))?;
+        let mut unit_args = vec![
+            "daemon".to_string(),
+            "--daemon-config".to_string(),
+            config_path.to_string_lossy().into_owned(),
+        ];
+        if let Some(interval) = args.interval {
+            unit_args.extend(["--interval".to_string(), format_duration(interval)]);
+        }
+        print!("{}", dutop::daemon::systemd_unit(&exe, &unit_args));
+        return Ok(());
+    }
+
+    eprintln!(
+        "Scanning {} roots every {}",
+        config.roots.len(),
+        format_duration(interval)
+    );
+    dutop::daemon::run(config, interval, &CancellationToken::new())
+}
+
+fn run_hard_links(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let report = dutop::hardlinks::find_hard_links(&path, &args.scan.config()?)?;
+
+    let output_config = args.output_config(args.output.no_color, atty::is(atty::Stream::Stdout));
+    output::print_hard_links(&report, args.output.top(), &output_config);
+    Ok(())
+}
+
+fn run_histogram(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let report = dutop::histogram::analyze_histogram(&path, &args.scan.config()?)?;
+
+    let output_config = args.output_config(args.output.no_color, atty::is(atty::Stream::Stdout));
+    output::print_histogram(&report, args.output.top(), &output_config);
+    Ok(())
+}
+
+fn run_counts(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let compact = match args.output.format() {
+        OutputFormat::Human => None,
+        OutputFormat::Json => Some(false),
+        OutputFormat::JsonCompact => Some(true),
+        _ => anyhow::bail!("--rank-by files only applies to the human and json formats"),
+    };
+    let report = dutop::counts::count_entries(&path, &args.scan.config()?)?;
+
+    match compact {
+        None => {
+            let output_config =
+                args.output_config(args.output.no_color, atty::is(atty::Stream::Stdout));
+            output::print_counts(&report, args.output.top(), &output_config);
+        }
+        Some(compact) => {
+            let mut out = io::stdout().lock();
+            output::write_counts_json(&mut out, &report, args.output.top(), compact)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn run_estimate(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let fraction = args.sample_percent / 100.0;
+    let report = dutop::estimate::estimate_usage(&path, &args.scan.config()?, fraction)?;
+
+    let output_config = args.output_config(args.output.no_color, atty::is(atty::Stream::Stdout));
+    output::print_estimate(&report, args.output.top(), &output_config);
+    Ok(())
+}
+
+fn run_git_repos(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let compact = match args.output.format() {
+        OutputFormat::Human => None,
+        OutputFormat::Json => Some(false),
+        OutputFormat::JsonCompact => Some(true),
+        _ => anyhow::bail!("--git-aware only applies to the human and json formats"),
+    };
+    let report = dutop::git::analyze_git_repos(&path, &args.scan.config()?)?;
+    match compact {
+        None => {
+            let output_config =
+                args.output_config(args.output.no_color, atty::is(atty::Stream::Stdout));
+            output::print_git_repos(&report, args.output.top(), &output_config);
+        }
+        Some(compact) => {
+            let mut out = io::stdout().lock();
+            output::write_git_repos_json(&mut out, &report, args.output.top(), compact)?;
+            out.flush()?;
         }
+    }
+    Ok(())
+}
+
+fn run_find_empty(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let report = dutop::empty::find_empty(&path, &args.scan.config()?, args.tiny)?;
+    if args.output.print0 {
+        let mut out = std::io::stdout().lock();
+        output::write_empty_paths(&mut out, &report, args.output.terminator())?;
+        out.flush()?;
+    } else {
+        output::print_empty(&report);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_by_owner(args: &Args) -> Result<()> {
+    let path = args.scan.canonical_path()?;
+    let report = dutop::owners::analyze_by_owner(&path, &args.scan.config()?)?;
+    output::print_owners(&report, args.output.top());
+    Ok(())
+}
+
+/// Print `result` in the chosen format, keeping only the `--top` entries
+///
+/// `context` is echoed in JSON output. `streamed` is set when NDJSON entries
+/// were already written to `out` during the scan. `local` is set when the
+/// paths of `result` are on this machine, as they are looked at for icons.
+fn print_output(
+    result: &mut AnalysisResult,
+    output: &OutputArgs,
+    context: &output::JsonContext,
+    streamed: bool,
+    local: bool,
+    out: &mut OutputSink,
+    args: &Args,
+) -> Result<()> {
+    if output.print0 && !matches!(output.format(), OutputFormat::Du | OutputFormat::Paths) {
+        anyhow::bail!("--print0 only applies to --format du and paths");
+    }
+    if output.summarize {
+        result.top_directories.clear();
+        return match output.format() {
+            OutputFormat::Human => {
+                let size = output.size_format().format(result.total_size);
+                Ok(output::write_summary(out, result, &size)?)
+            }
+            OutputFormat::Du => {
+                let (units, terminator) = (output.du_units(), output.terminator());
+                Ok(output::write_du(
+                    out,
+                    result,
+                    units,
+                    output.time,
+                    terminator,
+                )?)
+            }
+            _ => anyhow::bail!("--summarize only applies to the human and du formats"),
+        };
+    }
+    if output.all && !output.lists_all() {
+        anyhow::bail!("--all only applies to --format tree, du and paths");
+    }
+    let full_tree = match &result.tree {
+        Some(tree) if output.lists_all() => Some(tree),
+        None if output.lists_all() => anyhow::bail!("--all requires the full tree with files"),
+        _ => None,
+    };
+
+    let selection = output::Selection {
+        top: output.top(),
+        min_size: output
+            .threshold
+            .map_or(0, |cutoff| cutoff.bytes(result.total_size)),
+        coverage: output.coverage,
+    };
+    let others = output::Others::split(&mut result.top_directories, &selection, result.total_size);
+    let result = &*result;
+
+    // Shared by the table and the tree, wide enough for the largest size
+    let size_format = output.size_format();
+    let table_config = output::OutputConfig {
+        size_width: 8.max(size_format.format(result.total_size).chars().count()),
+        size_format,
+        count_width: 5.max(result.total_files.max(result.total_dirs).to_string().len()),
+        columns: output.columns(),
+        show_counts: output.show_counts,
+        paths: output.path_style(),
+        others: output.show_others.then_some(others),
+        ..args.output_config(output.no_color, out.is_terminal())
+    };
+    // Icons look inside the directories, which only works for paths on this machine
+    let table_config = output::OutputConfig {
+        icons: table_config.icons.filter(|_| local),
+        ..table_config
+    };
+
+    match output.format() {
+        OutputFormat::Human => match terminal_width(out.is_terminal()) {
+            Some(width) => {
+                let config = table_config.clone().fit_to_width(width, result);
+                output::write_results(out, result, &config)?;
+            }
+            None => output::write_results(out, result, &table_config)?,
+        },
         OutputFormat::Json => {
-            output::print_json(&result)?;
+            output::write_json(out, result, context)?;
+        }
+        OutputFormat::JsonCompact => {
+            let context = output::JsonContext {
+                compact: true,
+                ..*context
+            };
+            output::write_json(out, result, &context)?;
+        }
+        OutputFormat::Csv => {
+            output::write_csv(out, result, output.total_row)?;
+        }
+        OutputFormat::Tsv => {
+            output::write_tsv(out, result, output.total_row)?;
+        }
+        OutputFormat::Ndjson => {
+            if !streamed {
+                for entry in &result.top_directories {
+                    writeln!(out, "{}", output::ndjson_entry(entry)?)?;
+                }
+            }
+            writeln!(out, "{}", output::ndjson_summary(result)?)?;
+        }
+        OutputFormat::Du => {
+            let (units, terminator) = (output.du_units(), output.terminator());
+            match full_tree {
+                Some(tree) => {
+                    output::write_du_all(out, tree, units, selection.min_size, terminator)?
+                }
+                None => output::write_du(out, result, units, output.time, terminator)?,
+            }
+        }
+        OutputFormat::Paths => match full_tree {
+            Some(tree) => {
+                output::write_paths_all(out, tree, selection.min_size, output.terminator())?
+            }
+            None => output::write_paths(out, result, output.terminator())?,
+        },
+        OutputFormat::Ncdu => {
+            output::write_ncdu(out, result)?;
+        }
+        OutputFormat::Svg => {
+            render::write_svg(out, result)?;
         }
+        OutputFormat::Tree => {
+            output::write_tree(
+                out,
+                result,
+                &table_config,
+                output.display_depth,
+                &selection,
+                output.all,
+            )?;
+        }
+        format @ OutputFormat::Sqlite => export(result, output, format)?,
+        #[cfg(feature = "arrow")]
+        format @ OutputFormat::Parquet => export(result, output, format)?,
     }
 
-    log::info!("Analysis complete");
+    // JSON documents carry the statistics themselves
+    if let Some(stats) = &result.stats {
+        if !matches!(
+            output.format(),
+            OutputFormat::Json | OutputFormat::JsonCompact
+        ) {
+            output::write_stats(&mut io::stderr().lock(), stats)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fail with [`ThresholdExceeded`] when `result` is over the limits set in `output`
+fn check_thresholds(result: &AnalysisResult, output: &OutputArgs) -> Result<()> {
+    fail_on_violations(output.thresholds().check(result))
+}
+
+/// Print the paths over the limits and fail if there are any
+fn fail_on_violations(violations: Vec<Violation>) -> Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    for violation in &violations {
+        eprintln!("Over threshold: {}", violation);
+    }
+    Err(ThresholdExceeded { violations }.into())
+}
+
+/// Write `result` to the `--output` file in one of the export formats
+fn export(result: &AnalysisResult, output: &OutputArgs, format: OutputFormat) -> Result<()> {
+    let path = output
+        .output
+        .as_deref()
+        .context("Exporting to SQLite or Parquet requires --output FILE")?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        anyhow::bail!("SQLite and Parquet exports cannot be compressed with gzip");
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match format {
+        #[cfg(feature = "arrow")]
+        OutputFormat::Parquet => dutop::export::export_parquet(result, path, timestamp)?,
+        _ => dutop::export::export_sqlite(result, path, timestamp)?,
+    }
+    eprintln!("Scan written to {}", path.display());
+    Ok(())
+}
+
+/// Progress reporting for the CLI: a spinner on stderr and/or streamed NDJSON entries
+struct CliProgress<'a> {
+    spinner: Option<ProgressBar>,
+    ndjson: Option<&'a Mutex<OutputSink>>,
+}
+
+impl CliProgress<'_> {
+    fn is_active(&self) -> bool {
+        self.spinner.is_some() || self.ndjson.is_some()
+    }
+
+    fn finish(&self) {
+        if let Some(spinner) = &self.spinner {
+            spinner.finish_and_clear();
+        }
+    }
+}
+
+impl ProgressCallback for CliProgress<'_> {
+    fn on_progress(&self, progress: &ScanProgress) {
+        if let Some(spinner) = &self.spinner {
+            spinner.set_message(format!(
+                "{} files, {}  {}",
+                progress.files_scanned,
+                format_size_auto(progress.bytes),
+                progress.current_path.display()
+            ));
+        }
+    }
+
+    fn on_entry_complete(&self, entry: &DirectoryEntry) {
+        let Some(sink) = self.ndjson else {
+            return;
+        };
+
+        let line = match output::ndjson_entry(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("Failed to serialize {}: {}", entry.path.display(), e);
+                return;
+            }
+        };
+
+        // A closed pipe must not abort the scan; the summary write reports it
+        let write = || {
+            let mut sink = sink.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = writeln!(sink, "{}", line);
+        };
+        match &self.spinner {
+            Some(spinner) => spinner.suspend(write),
+            None => write(),
+        }
+    }
+}
+
+/// Width the table is fitted to: `COLUMNS` when set, or the width of the
+/// terminal output goes to, if it goes to one
+fn terminal_width(is_terminal: bool) -> Option<usize> {
+    let columns = std::env::var("COLUMNS").ok();
+    match columns.and_then(|columns| columns.trim().parse().ok()) {
+        Some(width) if width > 0 => Some(width),
+        _ if is_terminal => crossterm::terminal::size()
+            .ok()
+            .map(|(width, _)| usize::from(width))
+            .filter(|&width| width > 0),
+        _ => None,
+    }
+}
+
+/// Create the progress spinner drawn on stderr while scanning, spinning
+/// through plain ASCII frames when `ascii` is set
+fn spinner(ascii: bool) -> ProgressBar {
+    let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+    let style = ProgressStyle::with_template("{spinner} Scanning {wide_msg}")
+        .unwrap_or_else(|_| ProgressStyle::default_spinner());
+    bar.set_style(if ascii {
+        style.tick_chars("|/-\\ ")
+    } else {
+        style
+    });
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+fn run_tui(scan: &ScanArgs, trash: bool) -> Result<()> {
+    let path = scan.canonical_path()?;
+    let removal = if trash {
+        Removal::Trash
+    } else {
+        Removal::Delete
+    };
+    tui::run(&path, &scan.config()?, removal)
+}
+
+fn run_report(report: &ReportArgs, args: &Args) -> Result<()> {
+    let path = report.scan.canonical_path()?;
+    let config = AnalysisConfig {
+        build_tree: true,
+        ..report.scan.config()?
+    };
+
+    let result = analyze(&path, &config, report.top, args, None)?;
+    dutop::report::save_html_report(&result, &report.html)?;
+    eprintln!("Report written to {}", report.html.display());
 
     Ok(())
 }
 
+/// Run an analysis, showing the progress spinner unless disabled
+///
+/// With `ndjson`, every completed top-level entry is written to it as an NDJSON record.
+fn analyze(
+    path: &Path,
+    config: &AnalysisConfig,
+    top: usize,
+    args: &Args,
+    ndjson: Option<&Mutex<OutputSink>>,
+) -> Result<AnalysisResult> {
+    let progress = CliProgress {
+        spinner: (!args.no_progress && atty::is(atty::Stream::Stderr)).then(|| spinner(args.ascii)),
+        ndjson,
+    };
+
+    // Reuse the totals of unchanged directories from the previous scan
+    let cache_dir = dutop::cache::default_dir();
+    let cache = cache_dir
+        .as_deref()
+        .filter(|_| (args.cache || args.rebuild_cache) && config.cache.is_none())
+        .map(|dir| dutop::cache::cache_file(dir, path, config));
+    if let (Some(cache), true) = (&cache, args.rebuild_cache) {
+        remove_if_exists(cache)?;
+    }
+
+    // Checkpoint long scans, continuing the last one with --resume
+    let checkpointing = args.checkpoint || args.resume;
+    if checkpointing && config.build_tree {
+        log::warn!("Checkpoints only hold totals, so outputs showing a tree are not checkpointed");
+    }
+    let checkpoint = cache_dir
+        .as_deref()
+        .filter(|_| checkpointing && !config.build_tree && config.checkpoint.is_none())
+        .map(|dir| dutop::checkpoint::checkpoint_file(dir, path, config));
+    if let Some(checkpoint) = &checkpoint {
+        if !args.resume {
+            remove_if_exists(checkpoint)?;
+        } else if !checkpoint.exists() {
+            log::warn!(
+                "No interrupted scan of {} to resume, starting over",
+                path.display()
+            );
+        }
+    }
+
+    let config = &AnalysisConfig {
+        cache: cache.or_else(|| config.cache.clone()),
+        checkpoint: checkpoint.or_else(|| config.checkpoint.clone()),
+        ..config.clone()
+    };
+
+    if progress.is_active() {
+        let result = analyze_disk_usage_with_progress(path, config, top, &progress);
+        progress.finish();
+        result
+    } else {
+        analyze_disk_usage(path, config, top)
+    }
+}
+
+/// Remove the file at `path`, if there is one
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
 fn init_logging(args: &Args) -> Result<()> {
     let log_level = if args.debug {
         "debug"
@@ -146,3 +2580,42 @@ fn init_logging(args: &Args) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_parse_args() {
+        Args::command().debug_assert();
+
+        let args = parse_args(["dutop", "--profile", "p", "scan", "/srv"]).unwrap();
+        assert_eq!(args.profile.as_deref(), Some("p"));
+        let Some(Command::Scan(scan)) = &args.command else {
+            panic!("expected the scan subcommand");
+        };
+        assert_eq!(scan.scan.paths, [PathBuf::from("/srv")]);
+
+        // Global options go after the subcommand too, next to its own options
+        let args = parse_args([
+            "dutop",
+            "daemon",
+            "--daemon-config",
+            "daemon.toml",
+            "--config",
+            "settings.toml",
+        ])
+        .unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("settings.toml")));
+        let Some(Command::Daemon(daemon)) = &args.command else {
+            panic!("expected the daemon subcommand");
+        };
+        assert_eq!(daemon.daemon_config, PathBuf::from("daemon.toml"));
+
+        // Options of the default scan would be ignored by a subcommand
+        assert!(parse_args(["dutop", "-n", "3", "scan", "/srv"]).is_err());
+        let args = parse_args(["dutop", "-n", "3", "/srv"]).unwrap();
+        assert!(args.command.is_none());
+    }
+}
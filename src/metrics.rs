@@ -0,0 +1,281 @@
+//! Prometheus metrics for `dutop serve --prometheus`
+//!
+//! A background loop rescans the configured roots at a fixed interval and
+//! keeps the latest results; the `/metrics` endpoint renders them in the
+//! Prometheus text exposition format. Only each root and its largest entries
+//! are exported, which keeps the number of series bounded.
+
+use crate::http::{Request, Response};
+use crate::{
+    analyze_disk_usage, AnalysisConfig, AnalysisResult, CancellationToken, DirectoryEntry,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the cancellation token is checked between scans
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Latest state of one scanned root
+#[derive(Debug, Clone, Default)]
+struct RootMetrics {
+    /// The root itself, then its largest entries
+    directories: Vec<DirectoryEntry>,
+    scans: u64,
+    failures: u64,
+    errors: u64,
+    duration: Duration,
+    timestamp: u64,
+}
+
+/// Metrics of every scanned root, shared between the scan loop and the server
+#[derive(Debug, Default)]
+pub struct Metrics {
+    roots: Mutex<BTreeMap<PathBuf, RootMetrics>>,
+}
+
+impl Metrics {
+    /// Create an empty set of metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan `root` and record the outcome, keeping its `top_n` largest entries
+    pub fn scan(&self, root: &Path, config: &AnalysisConfig, top_n: usize) {
+        let started = Instant::now();
+        let result = analyze_disk_usage(root, config, top_n);
+        self.record(root, result.as_ref(), started.elapsed());
+    }
+
+    /// Record the outcome of a scan of `root` that took `duration`
+    pub fn record(
+        &self,
+        root: &Path,
+        result: Result<&AnalysisResult, &anyhow::Error>,
+        duration: Duration,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut roots = self.roots.lock().unwrap_or_else(PoisonError::into_inner);
+        let metrics = roots.entry(root.to_path_buf()).or_default();
+        metrics.scans += 1;
+        metrics.duration = duration;
+        metrics.timestamp = timestamp;
+        match result {
+            Ok(result) => {
+                metrics.errors += result.errors.len() as u64;
+                metrics.directories = std::iter::once(DirectoryEntry {
+                    path: result.root_path.clone(),
+                    size: result.total_size,
+                    file_count: result.total_files,
+                    dir_count: result.total_dirs,
+                    modified: None,
+                })
+                .chain(result.top_directories.iter().cloned())
+                .collect();
+            }
+            Err(e) => {
+                // Keep the previous sizes so graphs do not drop to zero
+                log::warn!("Failed to scan {}: {:#}", root.display(), e);
+                metrics.failures += 1;
+            }
+        }
+    }
+
+    /// Render the metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let roots = self.roots.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::new();
+
+        // Overlapping roots would otherwise repeat a series, which scrapers reject
+        let mut seen = std::collections::HashSet::new();
+        let directories: Vec<&DirectoryEntry> = roots
+            .values()
+            .flat_map(|root| &root.directories)
+            .filter(|d| seen.insert(&d.path))
+            .collect();
+        family(
+            &mut out,
+            "dutop_directory_bytes",
+            "gauge",
+            "Disk usage of a scanned root or one of its largest entries in bytes",
+            directories
+                .iter()
+                .map(|d| (path_label(&d.path), d.size as f64)),
+        );
+        family(
+            &mut out,
+            "dutop_directory_files",
+            "gauge",
+            "Number of files below a scanned root or one of its largest entries",
+            directories
+                .iter()
+                .map(|d| (path_label(&d.path), d.file_count as f64)),
+        );
+
+        let root_family = |out: &mut String, name, kind, help, value: fn(&RootMetrics) -> f64| {
+            family(
+                out,
+                name,
+                kind,
+                help,
+                roots
+                    .iter()
+                    .map(|(root, metrics)| (root_label(root), value(metrics))),
+            );
+        };
+        root_family(
+            &mut out,
+            "dutop_scan_duration_seconds",
+            "gauge",
+            "Duration of the latest scan of a root",
+            |m| m.duration.as_secs_f64(),
+        );
+        root_family(
+            &mut out,
+            "dutop_scan_timestamp_seconds",
+            "gauge",
+            "Unix time at which the latest scan of a root finished",
+            |m| m.timestamp as f64,
+        );
+        root_family(
+            &mut out,
+            "dutop_scans_total",
+            "counter",
+            "Number of scans of a root",
+            |m| m.scans as f64,
+        );
+        root_family(
+            &mut out,
+            "dutop_scan_failures_total",
+            "counter",
+            "Number of scans of a root that failed entirely",
+            |m| m.failures as f64,
+        );
+        root_family(
+            &mut out,
+            "dutop_scan_errors_total",
+            "counter",
+            "Number of entries that could not be read while scanning a root",
+            |m| m.errors as f64,
+        );
+        out
+    }
+}
+
+/// Answer a request to the metrics server
+pub fn handle(metrics: &Metrics, request: &Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => {
+            Response::ok("text/plain; version=0.0.4; charset=utf-8", metrics.render())
+        }
+        ("GET", "/") => Response::ok(
+            "text/html; charset=utf-8",
+            "<html><body><a href=\"/metrics\">Metrics</a></body></html>\n",
+        ),
+        (_, "/metrics") => Response::text(405, "Method not allowed\n"),
+        _ => Response::not_found(),
+    }
+}
+
+/// Scan every root in `roots` once per `interval` until `cancel` is triggered
+pub fn scan_loop(
+    metrics: &Metrics,
+    roots: &[PathBuf],
+    config: &AnalysisConfig,
+    top_n: usize,
+    interval: Duration,
+    cancel: &CancellationToken,
+) {
+    while !cancel.is_cancelled() {
+        let started = Instant::now();
+        for root in roots {
+            metrics.scan(root, config, top_n);
+        }
+        log::info!("Scanned {} roots in {:?}", roots.len(), started.elapsed());
+
+        let next = started + interval;
+        while !cancel.is_cancelled() && Instant::now() < next {
+            thread::sleep(POLL_INTERVAL.min(next.saturating_duration_since(Instant::now())));
+        }
+    }
+}
+
+/// Write one metric family with its help and type lines
+fn family(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl Iterator<Item = (String, f64)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+fn path_label(path: &Path) -> String {
+    format!("path=\"{}\"", escape_label(&path.to_string_lossy()))
+}
+
+fn root_label(path: &Path) -> String {
+    format!("root=\"{}\"", escape_label(&path.to_string_lossy()))
+}
+
+/// Escape a label value as required by the text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_metrics() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("data")).unwrap();
+        fs::write(root.join("data/file"), vec![0u8; 8192]).unwrap();
+
+        let metrics = Metrics::new();
+        metrics.scan(&root, &AnalysisConfig::default(), 10);
+        metrics.scan(&root.join("missing"), &AnalysisConfig::default(), 10);
+        let text = metrics.render();
+
+        let root_label = escape_label(&root.to_string_lossy());
+        let data_label = escape_label(&root.join("data").to_string_lossy());
+        assert!(text.contains("# TYPE dutop_directory_bytes gauge\n"));
+        assert!(text.contains(&format!(
+            "dutop_directory_files{{path=\"{}\"}} 1\n",
+            root_label
+        )));
+        assert!(text.contains(&format!(
+            "dutop_directory_files{{path=\"{}\"}} 1\n",
+            data_label
+        )));
+        assert!(text.contains(&format!("dutop_scans_total{{root=\"{}\"}} 1\n", root_label)));
+        assert!(text.contains(&format!(
+            "dutop_scan_failures_total{{root=\"{}/missing\"}} 1\n",
+            root_label
+        )));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("C:\\a \"b\"\n"), "C:\\\\a \\\"b\\\"\\n");
+    }
+}
@@ -0,0 +1,858 @@
+//! Scanning NTFS volumes from the Master File Table
+//!
+//! Every file on an NTFS volume has a record in the MFT holding its names, the
+//! directories they are in and its sizes, so reading the records one after the
+//! other yields the whole tree without opening a single directory. The MFT is
+//! read straight from the volume in large chunks, following the clusters its
+//! own record lists. Reading it needs administrator rights; when it is
+//! unavailable (other filesystems, missing privileges) the analysis walks
+//! directories instead.
+
+use crate::archive::VirtualDir;
+use crate::exclude::Exclusions;
+use crate::{AnalysisConfig, DirectoryNode};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Records below this number hold NTFS metadata (`$MFT`, `$Bitmap`, ...)
+const FIRST_USER_RECORD: u64 = 24;
+/// File references keep the record number in their low 48 bits
+const RECORD_MASK: u64 = 0xFFFF_FFFF_FFFF;
+/// Records are protected by an update sequence at the end of every sector
+const SECTOR: usize = 512;
+
+const STANDARD_INFORMATION: u32 = 0x10;
+const FILE_NAME: u32 = 0x30;
+const DATA: u32 = 0x80;
+const END_OF_ATTRIBUTES: u32 = 0xFFFF_FFFF;
+/// Length of the header shared by resident and non-resident attributes
+const ATTRIBUTE_HEADER: usize = 24;
+
+/// Namespace of the short 8.3 aliases, which duplicate a long name
+const DOS_NAMESPACE: u8 = 2;
+/// Seconds between 1601-01-01, the FILETIME epoch, and the Unix epoch
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+/// What the walk needs from an MFT record
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct FileRecord {
+    /// Record this one extends, when the attributes of a file span several
+    pub base: Option<u64>,
+    pub is_dir: bool,
+    /// Parent directory and name of every hard link of the file
+    pub names: Vec<(u64, OsString)>,
+    /// Disk usage in bytes, when the record holds the main data stream
+    pub size: Option<u64>,
+    pub apparent_size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileRecord {
+    /// Fold an extension record into the base record of the file
+    fn merge(&mut self, extension: FileRecord) {
+        self.names.extend(extension.names);
+        if extension.size.is_some() {
+            self.size = extension.size;
+            self.apparent_size = extension.apparent_size;
+        }
+    }
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        buf.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        buf.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(windows)]
+fn name_from_utf16(units: &[u16]) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+    OsString::from_wide(units)
+}
+
+#[cfg(not(windows))]
+fn name_from_utf16(units: &[u16]) -> OsString {
+    OsString::from(String::from_utf16_lossy(units))
+}
+
+/// Attributes of a record, as their type and bytes
+struct Attributes<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Attributes<'a> {
+    /// Attributes of the record in `buf`, whose fixups were applied
+    fn new(buf: &'a [u8]) -> Option<Self> {
+        let offset = usize::from(u16_at(buf, 20)?);
+        Some(Self { buf, offset })
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = u32_at(self.buf, self.offset)?;
+        let length = u32_at(self.buf, self.offset + 4)? as usize;
+        // A damaged record may claim attributes too short for their header
+        if kind == END_OF_ATTRIBUTES || length < ATTRIBUTE_HEADER {
+            return None;
+        }
+        let attribute = self.buf.get(self.offset..self.offset + length)?;
+        self.offset += length;
+        Some((kind, attribute))
+    }
+}
+
+/// Little-endian integer of 1 to 8 `bytes`, sign-extended when `signed`
+fn int_from_le(bytes: &[u8], signed: bool) -> Option<i64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    let negative = signed && bytes[bytes.len() - 1] & 0x80 != 0;
+    let mut value = [if negative { 0xff } else { 0 }; 8];
+    value[..bytes.len()].copy_from_slice(bytes);
+    Some(i64::from_le_bytes(value))
+}
+
+/// Clusters holding the data of the non-resident `attribute`, as the first
+/// cluster and number of clusters of each run, in order
+///
+/// Returns `None` for damaged mapping pairs and for sparse runs, which have no
+/// clusters on disk.
+fn data_runs(attribute: &[u8]) -> Option<Vec<(u64, u64)>> {
+    let mut offset = usize::from(u16_at(attribute, 32)?);
+    let mut runs = Vec::new();
+    let mut cluster = 0i64;
+    loop {
+        let header = *attribute.get(offset)?;
+        if header == 0 {
+            return Some(runs);
+        }
+        let length_size = usize::from(header & 0x0f);
+        let offset_size = usize::from(header >> 4);
+        let fields = attribute.get(offset + 1..offset + 1 + length_size + offset_size)?;
+        let length = int_from_le(&fields[..length_size], false)?;
+        // Each run starts relative to the one before
+        cluster = cluster.checked_add(int_from_le(&fields[length_size..], true)?)?;
+        runs.push((u64::try_from(cluster).ok()?, u64::try_from(length).ok()?));
+        offset += 1 + length_size + offset_size;
+    }
+}
+
+/// Clusters holding the MFT, from the raw record of `$MFT` itself
+pub(crate) fn mft_runs(buf: &mut [u8]) -> Option<Vec<(u64, u64)>> {
+    if buf.get(0..4)? != b"FILE" {
+        return None;
+    }
+    apply_fixups(buf)?;
+    Attributes::new(buf)?
+        .find(|&(kind, attribute)| {
+            // The unnamed, non-resident stream, from its first cluster on
+            kind == DATA
+                && attribute[8] != 0
+                && attribute[9] == 0
+                && u64_at(attribute, 16) == Some(0)
+        })
+        .and_then(|(_, attribute)| data_runs(attribute))
+}
+
+/// Restore the last two bytes of every sector, which the update sequence
+/// replaced on disk; `None` when a sector was torn during a write
+fn apply_fixups(buf: &mut [u8]) -> Option<()> {
+    let offset = usize::from(u16_at(buf, 4)?);
+    let count = usize::from(u16_at(buf, 6)?);
+    let check = buf.get(offset..offset + 2)?.to_vec();
+    for i in 1..count {
+        let end = i * SECTOR;
+        if end > buf.len() {
+            break;
+        }
+        if buf[end - 2..end] != check[..] {
+            return None;
+        }
+        let original = buf.get(offset + 2 * i..offset + 2 * i + 2)?.to_vec();
+        buf[end - 2..end].copy_from_slice(&original);
+    }
+    Some(())
+}
+
+/// Decode a raw MFT record, counting resident data as `cluster` bytes per
+/// started cluster like the directory walk does
+///
+/// Returns `None` for records that are unused or damaged.
+pub(crate) fn parse_record(buf: &mut [u8], cluster: u64) -> Option<FileRecord> {
+    if buf.get(0..4)? != b"FILE" {
+        return None;
+    }
+    apply_fixups(buf)?;
+    let flags = u16_at(buf, 22)?;
+    if flags & 0x1 == 0 {
+        return None;
+    }
+
+    let base = u64_at(buf, 32)? & RECORD_MASK;
+    let mut record = FileRecord {
+        base: (base != 0).then_some(base),
+        is_dir: flags & 0x2 != 0,
+        ..Default::default()
+    };
+
+    for (kind, attribute) in Attributes::new(buf)? {
+        // Attributes are at least as long as their header
+        let resident = attribute[8] == 0;
+        let unnamed = attribute[9] == 0;
+        let content = usize::from(u16_at(attribute, 20)?);
+
+        match kind {
+            STANDARD_INFORMATION if resident => {
+                let filetime = u64_at(attribute, content + 8)?;
+                record.modified = (filetime / 10_000_000)
+                    .checked_sub(FILETIME_UNIX_OFFSET)
+                    .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            }
+            FILE_NAME if resident => {
+                let parent = u64_at(attribute, content)? & RECORD_MASK;
+                let length = usize::from(*attribute.get(content + 64)?);
+                let namespace = *attribute.get(content + 65)?;
+                if namespace != DOS_NAMESPACE {
+                    let bytes = attribute.get(content + 66..content + 66 + 2 * length)?;
+                    let units: Vec<u16> = bytes
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                        .collect();
+                    record.names.push((parent, name_from_utf16(&units)));
+                }
+            }
+            DATA if unnamed && resident => {
+                let length = u64::from(u32_at(attribute, 16)?);
+                record.apparent_size = length;
+                record.size = Some(length.div_ceil(cluster) * cluster);
+            }
+            // Only the first extent of a fragmented stream carries its sizes
+            DATA if unnamed && u64_at(attribute, 16)? == 0 => {
+                let compressed_or_sparse = u16_at(attribute, 12)? & 0x8001 != 0;
+                record.apparent_size = u64_at(attribute, 48)?;
+                record.size = Some(if compressed_or_sparse {
+                    u64_at(attribute, 64)?
+                } else {
+                    u64_at(attribute, 40)?
+                });
+            }
+            _ => {}
+        }
+    }
+    Some(record)
+}
+
+/// Collects the records of a volume as they are read
+#[derive(Default)]
+pub(crate) struct RecordTable {
+    records: HashMap<u64, FileRecord>,
+    extensions: Vec<(u64, FileRecord)>,
+}
+
+impl RecordTable {
+    pub(crate) fn insert(&mut self, number: u64, record: FileRecord) {
+        match record.base {
+            Some(base) => self.extensions.push((base, record)),
+            None => {
+                self.records.insert(number, record);
+            }
+        }
+    }
+
+    /// Tree of the directory with record number `root`, found at `root_path`
+    ///
+    /// Returns the tree along with the number of extra hard links to files
+    /// already counted, and the disk usage they would have added.
+    pub(crate) fn into_tree(
+        mut self,
+        root: u64,
+        root_path: &Path,
+        config: &AnalysisConfig,
+        exclusions: &Exclusions,
+    ) -> (DirectoryNode, usize, u64) {
+        for (base, extension) in std::mem::take(&mut self.extensions) {
+            if let Some(record) = self.records.get_mut(&base) {
+                record.merge(extension);
+            }
+        }
+
+        let mut resolver = Resolver {
+            records: &self.records,
+            max_depth: config.max_depth.unwrap_or(usize::MAX),
+            exclusions,
+            paths: HashMap::from([(root, Some(PathBuf::new()))]),
+        };
+        let mut tree = VirtualDir::default();
+        let (mut hard_links, mut hard_link_savings) = (0, 0);
+        for (&number, record) in &self.records {
+            if number < FIRST_USER_RECORD || number == root {
+                continue;
+            }
+            let mut paths = record
+                .names
+                .iter()
+                .filter_map(|(parent, name)| resolver.child(*parent, name));
+            let Some(path) = paths.next() else {
+                continue;
+            };
+            if record.is_dir {
+                tree.insert_dir(&path);
+                continue;
+            }
+
+            let size = record.size.unwrap_or(0);
+            if record.apparent_size < config.min_file_size.unwrap_or(0)
+                || !in_time_window(record.modified, config)
+            {
+                continue;
+            }
+            let links = paths.count();
+            hard_links += links;
+            hard_link_savings += links as u64 * size;
+            tree.insert(&path, size, record.apparent_size);
+        }
+
+        (
+            tree.into_node(root_path.to_path_buf()),
+            hard_links,
+            hard_link_savings,
+        )
+    }
+}
+
+fn in_time_window(modified: Option<SystemTime>, config: &AnalysisConfig) -> bool {
+    let Some(modified) = modified else {
+        return true;
+    };
+    config
+        .modified_before
+        .map_or(true, |before| modified < before)
+        && config.modified_after.map_or(true, |after| modified > after)
+}
+
+/// Paths of directories relative to the root, memoized
+struct Resolver<'a> {
+    records: &'a HashMap<u64, FileRecord>,
+    max_depth: usize,
+    exclusions: &'a Exclusions,
+    /// `None` for directories outside the root, excluded or too deep
+    paths: HashMap<u64, Option<PathBuf>>,
+}
+
+impl Resolver<'_> {
+    /// Path of `name` in the directory `parent`, unless it is left out of the scan
+    fn child(&mut self, parent: u64, name: &OsString) -> Option<PathBuf> {
+        let path = self.dir(parent)?.join(name);
+        let depth = path.components().count();
+        (depth <= self.max_depth && !self.exclusions.is_excluded(&path)).then_some(path)
+    }
+
+    fn dir(&mut self, number: u64) -> Option<PathBuf> {
+        // Climb to the nearest directory already resolved, then come back down
+        let mut chain = Vec::new();
+        let mut current = number;
+        while !self.paths.contains_key(&current) {
+            if current < FIRST_USER_RECORD || chain.contains(&current) {
+                break;
+            }
+            chain.push(current);
+            current = match self.records.get(&current) {
+                Some(record) if record.is_dir => match record.names.first() {
+                    Some(&(parent, _)) => parent,
+                    None => break,
+                },
+                _ => break,
+            };
+        }
+
+        let mut path = self.paths.get(&current).cloned().flatten();
+        for &number in chain.iter().rev() {
+            path = match path {
+                Some(parent) => self.records[&number]
+                    .names
+                    .first()
+                    .map(|(_, name)| parent.join(name))
+                    .filter(|path| {
+                        path.components().count() <= self.max_depth
+                            && !self.exclusions.is_excluded(path)
+                    }),
+                None => None,
+            };
+            self.paths.insert(number, path.clone());
+        }
+        path
+    }
+}
+
+#[cfg(windows)]
+pub(crate) use volume::analyze;
+
+#[cfg(windows)]
+mod volume {
+    use super::{mft_runs, parse_record, RecordTable, RECORD_MASK};
+    use crate::archive::tree_result;
+    use crate::exclude::Exclusions;
+    use crate::progress::ProgressTracker;
+    use crate::stats::StatsCollector;
+    use crate::windows::{volume_fs_type, volume_root, wide};
+    use crate::{mounts, AnalysisConfig, AnalysisResult, ScanHooks};
+    use anyhow::{bail, Context, Result};
+    use std::ffi::OsStr;
+    use std::io;
+    use std::path::Path;
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_READ, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, GetFileInformationByHandle, ReadFile, SetFilePointerEx,
+        BY_HANDLE_FILE_INFORMATION, FILE_BEGIN, FILE_FLAG_BACKUP_SEMANTICS, FILE_SHARE_READ,
+        FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::{
+        FSCTL_GET_NTFS_FILE_RECORD, FSCTL_GET_NTFS_VOLUME_DATA, NTFS_FILE_RECORD_INPUT_BUFFER,
+        NTFS_VOLUME_DATA_BUFFER,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    /// Offset of the record in the output of `FSCTL_GET_NTFS_FILE_RECORD`,
+    /// after its reference number and length
+    const RECORD_OFFSET: usize = 12;
+    /// Bytes of the MFT read at once, a multiple of every record and cluster size
+    const CHUNK_SIZE: u64 = 4 << 20;
+
+    /// Handle closed when dropped
+    struct Handle(HANDLE);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: the handle was opened by `open` and is closed once
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    fn open(path: &OsStr, flags: u32) -> io::Result<Handle> {
+        let name = wide(path);
+        // SAFETY: `name` is NUL-terminated and the other arguments are optional
+        let handle = unsafe {
+            CreateFileW(
+                name.as_ptr(),
+                GENERIC_READ,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null(),
+                OPEN_EXISTING,
+                flags,
+                std::ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Handle(handle))
+    }
+
+    /// Issue `code` on `handle`, returning the number of bytes written to `output`
+    fn ioctl<T>(
+        handle: &Handle,
+        code: u32,
+        input: Option<&T>,
+        output: &mut [u8],
+    ) -> io::Result<usize> {
+        let (input, input_len) = match input {
+            Some(input) => ((input as *const T).cast(), std::mem::size_of::<T>() as u32),
+            None => (std::ptr::null(), 0),
+        };
+        let mut returned = 0u32;
+        // SAFETY: the buffers are valid for the lengths given
+        let ok = unsafe {
+            DeviceIoControl(
+                handle.0,
+                code,
+                input,
+                input_len,
+                output.as_mut_ptr().cast(),
+                output.len() as u32,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(returned as usize)
+    }
+
+    /// Fill `buf` with the bytes of the volume `handle` from `offset` on
+    fn read_at(handle: &Handle, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let offset = i64::try_from(offset).map_err(io::Error::other)?;
+        // SAFETY: `handle` is open and the new position is not asked for
+        if unsafe { SetFilePointerEx(handle.0, offset, std::ptr::null_mut(), FILE_BEGIN) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut read = 0u32;
+        // SAFETY: `buf` is valid for its length and the read is synchronous
+        let ok = unsafe {
+            ReadFile(
+                handle.0,
+                buf.as_mut_ptr(),
+                buf.len() as u32,
+                &mut read,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if (read as usize) < buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The MFT ends early",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Record number of the file or directory at `path`
+    fn record_number(path: &Path) -> io::Result<u64> {
+        let handle = open(path.as_os_str(), FILE_FLAG_BACKUP_SEMANTICS)?;
+        // SAFETY: the structure is plain data, valid when zeroed
+        let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+        // SAFETY: `handle` is open and `info` is a valid out pointer
+        if unsafe { GetFileInformationByHandle(handle.0, &mut info) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(((u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow)) & RECORD_MASK)
+    }
+
+    /// Analyze `path` from the MFT of the NTFS volume holding it, reporting
+    /// progress and stopping early through `hooks`
+    pub(crate) fn analyze(
+        path: &Path,
+        config: &AnalysisConfig,
+        exclusions: &Exclusions,
+        top_n: usize,
+        hooks: &ScanHooks,
+    ) -> Result<AnalysisResult> {
+        let root = volume_root(path).context("Cannot find the volume")?;
+        if volume_fs_type(&root).as_deref() != Some("NTFS") {
+            bail!("{} is not an NTFS volume", root);
+        }
+        let device = format!(r"\\.\{}", root.trim_end_matches('\\'));
+        let volume = open(OsStr::new(&device), 0)
+            .with_context(|| format!("Cannot open {} (administrator rights are needed)", device))?;
+        // The MFT is read from a single thread, outside the worker pool
+        let stats = config.collect_stats.then(|| StatsCollector::new(0));
+        let progress = ProgressTracker::new(hooks.progress);
+
+        let mut data = vec![0u8; std::mem::size_of::<NTFS_VOLUME_DATA_BUFFER>()];
+        ioctl::<()>(&volume, FSCTL_GET_NTFS_VOLUME_DATA, None, &mut data)
+            .context("Cannot query the NTFS volume")?;
+        // SAFETY: the buffer is the size of the structure, which is plain data
+        let data: NTFS_VOLUME_DATA_BUFFER =
+            unsafe { std::ptr::read_unaligned(data.as_ptr().cast()) };
+        let record_size = data.BytesPerFileRecordSegment as usize;
+        let cluster = u64::from(data.BytesPerCluster).max(1);
+        if record_size == 0 || CHUNK_SIZE % record_size as u64 != 0 {
+            bail!("Unsupported MFT record size: {}", record_size);
+        }
+
+        // Record 0 describes the MFT itself, with the clusters holding it
+        let mut output = vec![0u8; RECORD_OFFSET + record_size];
+        let input = NTFS_FILE_RECORD_INPUT_BUFFER {
+            FileReferenceNumber: 0,
+        };
+        let written = ioctl(
+            &volume,
+            FSCTL_GET_NTFS_FILE_RECORD,
+            Some(&input),
+            &mut output,
+        )
+        .context("Cannot read the MFT record of $MFT")?;
+        let end = written.min(output.len());
+        let runs = mft_runs(&mut output[RECORD_OFFSET..end])
+            .context("Cannot find the clusters of the MFT")?;
+
+        let mut remaining = data.MftValidDataLength as u64;
+        log::info!(
+            "Reading {} MFT records of {}",
+            remaining / record_size as u64,
+            root
+        );
+        let mut table = RecordTable::default();
+        let mut chunk = vec![0u8; CHUNK_SIZE as usize];
+        let mut number = 0u64;
+        let mut cancelled = false;
+        'runs: for (first, clusters) in runs {
+            let mut position = first * cluster;
+            let mut left = (clusters * cluster).min(remaining);
+            while left > 0 {
+                if hooks.cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+                    cancelled = true;
+                    break 'runs;
+                }
+                let buf = &mut chunk[..left.min(CHUNK_SIZE) as usize];
+                let mut read = || read_at(&volume, position, buf);
+                let read = match &stats {
+                    Some(stats) => stats.time(read),
+                    None => read(),
+                };
+                read.with_context(|| format!("Cannot read the MFT of {}", root))?;
+                for raw in buf.chunks_exact_mut(record_size) {
+                    if let Some(record) = parse_record(raw, cluster) {
+                        if record.is_dir {
+                            progress.enter_dir(path);
+                        } else {
+                            progress.add_file(record.size.unwrap_or(0));
+                        }
+                        table.insert(number, record);
+                    }
+                    number += 1;
+                }
+                position += buf.len() as u64;
+                left -= buf.len() as u64;
+                remaining -= buf.len() as u64;
+            }
+        }
+        if cancelled {
+            log::warn!(
+                "Analysis of {} was cancelled; results are partial",
+                path.display()
+            );
+        }
+
+        let root_record =
+            record_number(path).with_context(|| format!("Cannot identify {}", path.display()))?;
+        let (tree, hard_links, hard_link_savings) =
+            table.into_tree(root_record, path, config, exclusions);
+
+        let mut result = tree_result(tree, top_n);
+        result.cancelled = cancelled;
+        result.hard_links = hard_links;
+        result.hard_link_savings = hard_link_savings;
+        result.filesystem = mounts::filesystem_usage(path).ok();
+        result.stats = stats.map(|stats| stats.finish(result.total_files, result.total_dirs));
+        progress.finish(path);
+        if !config.build_tree {
+            result.tree = None;
+        } else if !config.tree_files {
+            if let Some(tree) = &mut result.tree {
+                strip_files(tree);
+            }
+        }
+        Ok(result)
+    }
+
+    fn strip_files(node: &mut crate::DirectoryNode) {
+        node.files = Vec::new();
+        node.children.iter_mut().for_each(strip_files);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD_SIZE: usize = 1024;
+    /// Record number of the root directory of a volume
+    const ROOT_RECORD: u64 = 5;
+
+    /// Raw record with the given attributes, protected by an update sequence
+    fn raw_record(flags: u16, base: u64, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut buf = vec![0u8; RECORD_SIZE];
+        buf[0..4].copy_from_slice(b"FILE");
+        buf[4..6].copy_from_slice(&48u16.to_le_bytes());
+        buf[6..8].copy_from_slice(&3u16.to_le_bytes());
+        buf[20..22].copy_from_slice(&56u16.to_le_bytes());
+        buf[22..24].copy_from_slice(&flags.to_le_bytes());
+        buf[32..40].copy_from_slice(&base.to_le_bytes());
+        let mut offset = 56;
+        for attribute in attributes {
+            buf[offset..offset + attribute.len()].copy_from_slice(attribute);
+            offset += attribute.len();
+        }
+        buf[offset..offset + 4].copy_from_slice(&END_OF_ATTRIBUTES.to_le_bytes());
+
+        // Move the last two bytes of each sector to the update sequence array
+        let check = [0x2a, 0x00];
+        buf[48..50].copy_from_slice(&check);
+        for i in 1..3 {
+            let end = i * SECTOR;
+            let original = [buf[end - 2], buf[end - 1]];
+            buf[48 + 2 * i..50 + 2 * i].copy_from_slice(&original);
+            buf[end - 2..end].copy_from_slice(&check);
+        }
+        buf
+    }
+
+    fn resident(kind: u32, content: &[u8]) -> Vec<u8> {
+        let length = (24 + content.len()).next_multiple_of(8);
+        let mut attribute = vec![0u8; length];
+        attribute[0..4].copy_from_slice(&kind.to_le_bytes());
+        attribute[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attribute[16..20].copy_from_slice(&(content.len() as u32).to_le_bytes());
+        attribute[20..22].copy_from_slice(&24u16.to_le_bytes());
+        attribute[24..24 + content.len()].copy_from_slice(content);
+        attribute
+    }
+
+    fn file_name(parent: u64, name: &str, namespace: u8) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let mut content = vec![0u8; 66 + 2 * units.len()];
+        content[0..8].copy_from_slice(&(parent | 1 << 48).to_le_bytes());
+        content[64] = units.len() as u8;
+        content[65] = namespace;
+        for (i, unit) in units.iter().enumerate() {
+            content[66 + 2 * i..68 + 2 * i].copy_from_slice(&unit.to_le_bytes());
+        }
+        resident(FILE_NAME, &content)
+    }
+
+    fn non_resident_data(allocated: u64, real: u64) -> Vec<u8> {
+        let mut attribute = vec![0u8; 72];
+        attribute[0..4].copy_from_slice(&DATA.to_le_bytes());
+        attribute[4..8].copy_from_slice(&72u32.to_le_bytes());
+        attribute[8] = 1;
+        attribute[40..48].copy_from_slice(&allocated.to_le_bytes());
+        attribute[48..56].copy_from_slice(&real.to_le_bytes());
+        attribute
+    }
+
+    #[test]
+    fn test_parse_record() {
+        // 2024-01-01T00:00:00Z as a FILETIME
+        let filetime = (1_704_067_200 + FILETIME_UNIX_OFFSET) * 10_000_000;
+        let mut standard = vec![0u8; 48];
+        standard[8..16].copy_from_slice(&filetime.to_le_bytes());
+        let mut raw = raw_record(
+            0x1,
+            0,
+            &[
+                resident(STANDARD_INFORMATION, &standard),
+                file_name(40, "REPORT~1.PDF", DOS_NAMESPACE),
+                file_name(40, "report.pdf", 1),
+                non_resident_data(12_288, 10_000),
+            ],
+        );
+        let record = parse_record(&mut raw, 4096).unwrap();
+        assert_eq!(record.names, vec![(40, OsString::from("report.pdf"))]);
+        assert_eq!((record.size, record.apparent_size), (Some(12_288), 10_000));
+        assert_eq!(
+            record.modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+        );
+        assert!(!record.is_dir && record.base.is_none());
+
+        let mut small = raw_record(0x1, 0, &[resident(DATA, b"hello")]);
+        let record = parse_record(&mut small, 4096).unwrap();
+        assert_eq!((record.size, record.apparent_size), (Some(4096), 5));
+
+        let mut unused = raw_record(0x0, 0, &[]);
+        assert!(parse_record(&mut unused, 4096).is_none());
+        let mut torn = raw_record(0x1, 0, &[]);
+        torn[SECTOR - 1] ^= 0xff;
+        assert!(parse_record(&mut torn, 4096).is_none());
+    }
+
+    #[test]
+    fn test_damaged_attribute() {
+        // An attribute claiming to be shorter than its own header
+        let mut short = vec![0u8; 8];
+        short[0..4].copy_from_slice(&DATA.to_le_bytes());
+        short[4..8].copy_from_slice(&8u32.to_le_bytes());
+        let mut raw = raw_record(0x1, 0, &[file_name(40, "a.txt", 1), short]);
+        let record = parse_record(&mut raw, 4096).unwrap();
+        assert_eq!(record.names.len(), 1);
+        assert_eq!(record.size, None);
+    }
+
+    #[test]
+    fn test_mft_runs() {
+        let with_runs = |pairs: &[u8]| {
+            let mut data = non_resident_data(0x30 << 12, 0x30 << 12);
+            data[32..34].copy_from_slice(&72u16.to_le_bytes());
+            data.extend_from_slice(pairs);
+            data.resize(data.len().next_multiple_of(8), 0);
+            let length = data.len() as u32;
+            data[4..8].copy_from_slice(&length.to_le_bytes());
+            raw_record(0x1, 0, &[file_name(ROOT_RECORD, "$MFT", 1), data])
+        };
+
+        // 0x20 clusters at 0x1000, then 0x10 clusters 0x100 clusters before it
+        let mut raw = with_runs(&[0x21, 0x20, 0x00, 0x10, 0x21, 0x10, 0x00, 0xff, 0x00]);
+        assert_eq!(
+            mft_runs(&mut raw),
+            Some(vec![(0x1000, 0x20), (0x0f00, 0x10)])
+        );
+        // A sparse run has no clusters to read
+        let mut sparse = with_runs(&[0x01, 0x20, 0x00]);
+        assert_eq!(mft_runs(&mut sparse), None);
+    }
+
+    #[test]
+    fn test_record_table_tree() {
+        let dir = |parent, name: &str| FileRecord {
+            is_dir: true,
+            names: vec![(parent, OsString::from(name))],
+            ..Default::default()
+        };
+        let file = |names: &[(u64, &str)], size| FileRecord {
+            names: names
+                .iter()
+                .map(|&(parent, name)| (parent, OsString::from(name)))
+                .collect(),
+            size: Some(size),
+            apparent_size: size,
+            ..Default::default()
+        };
+
+        let mut table = RecordTable::default();
+        table.insert(ROOT_RECORD, dir(ROOT_RECORD, "."));
+        table.insert(2, file(&[(ROOT_RECORD, "$LogFile")], 1 << 20));
+        table.insert(30, dir(ROOT_RECORD, "Users"));
+        table.insert(31, dir(30, "alice"));
+        table.insert(32, dir(31, "empty"));
+        table.insert(33, file(&[(31, "video.mp4"), (30, "shared.mp4")], 8192));
+        table.insert(34, dir(31, "node_modules"));
+        table.insert(35, file(&[(34, "lib.js")], 4096));
+        table.insert(36, dir(ROOT_RECORD, "Windows"));
+        table.insert(37, file(&[(36, "kernel.dll")], 2048));
+        // An extension record carrying the data of a fragmented file
+        table.insert(38, file(&[(31, "notes.txt")], 0));
+        table.insert(
+            39,
+            FileRecord {
+                base: Some(38),
+                size: Some(1024),
+                apparent_size: 1000,
+                ..Default::default()
+            },
+        );
+
+        let exclusions = Exclusions::new(&["node_modules".to_string()]).unwrap();
+        let root = Path::new("C:\\Users");
+        let (tree, hard_links, savings) =
+            table.into_tree(30, root, &AnalysisConfig::default(), &exclusions);
+
+        assert_eq!(tree.path, root);
+        assert_eq!(tree.size, 8192 + 1024);
+        assert_eq!((tree.file_count, tree.dir_count), (2, 2));
+        assert_eq!((hard_links, savings), (1, 8192));
+        let alice = tree.find(&root.join("alice")).unwrap();
+        assert_eq!(alice.children[0].path, root.join("alice").join("empty"));
+    }
+}
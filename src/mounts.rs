@@ -0,0 +1,461 @@
+//! Mounted filesystems and their capacity
+//!
+//! Mounts are read from `/proc/mounts` on Linux, `getmntinfo` on macOS and
+//! the drive letters reported by `GetLogicalDrives` on Windows. Capacity comes
+//! from `statvfs` on Unix and `GetDiskFreeSpaceExW` on Windows.
+//!
+//! The kind of storage behind a path decides how many threads walk it by
+//! default: network filesystems are recognized by their type (or as remote
+//! drives on Windows), and spinning disks by the `rotational` flag in sysfs on
+//! Linux or the seek penalty the device reports on Windows.
+
+use crate::{analyze_disk_usage, AnalysisConfig, AnalysisResult};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Capacity of a filesystem in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FsUsage {
+    /// Total size of the filesystem
+    pub total: u64,
+    /// Free space, including blocks reserved for the superuser
+    pub free: u64,
+    /// Free space available to unprivileged users
+    pub available: u64,
+}
+
+impl FsUsage {
+    /// Space in use
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+}
+
+/// A mounted filesystem
+#[derive(Debug, Clone)]
+pub struct Mount {
+    /// Device or source of the filesystem (e.g. `/dev/sda1`)
+    pub source: String,
+    /// Directory the filesystem is mounted on
+    pub mount_point: PathBuf,
+    /// Filesystem type (e.g. `ext4`, `apfs`, `NTFS`)
+    pub fs_type: String,
+    /// Capacity, when it could be queried
+    pub usage: Option<FsUsage>,
+}
+
+/// Filesystem types served over the network
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afpfs",
+    "webdav",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "sshfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.davfs2",
+    "fuse.glusterfs",
+];
+
+/// Filesystem types without files on any storage: kernel interfaces, device
+/// nodes, automounters and memory-backed filesystems (such as `/run`)
+const VIRTUAL_FS_TYPES: &[&str] = &[
+    "proc",
+    "sysfs",
+    "devtmpfs",
+    "devpts",
+    "devfs",
+    "fdesc",
+    "tmpfs",
+    "ramfs",
+    "securityfs",
+    "selinuxfs",
+    "cgroup",
+    "cgroup2",
+    "pstore",
+    "bpf",
+    "tracefs",
+    "debugfs",
+    "configfs",
+    "fusectl",
+    "mqueue",
+    "hugetlbfs",
+    "autofs",
+    "binfmt_misc",
+    "efivarfs",
+    "rpc_pipefs",
+    "nsfs",
+];
+
+/// Kind of storage a filesystem lives on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    /// Spinning disk, where parallel requests mostly add seeks
+    Rotational,
+    /// SSD or NVMe drive
+    SolidState,
+    /// Filesystem served over the network (NFS, SMB, SSHFS, ...)
+    Network,
+    /// Storage that could not be identified
+    Unknown,
+}
+
+impl StorageKind {
+    /// Threads to walk this storage with when none were asked for, or `None`
+    /// for one per CPU
+    pub fn default_threads(self) -> Option<usize> {
+        match self {
+            StorageKind::Rotational => Some(2),
+            StorageKind::Network => Some(4),
+            StorageKind::SolidState | StorageKind::Unknown => None,
+        }
+    }
+}
+
+/// Whether filesystems of type `fs_type` are served over the network
+pub fn is_network_fs(fs_type: &str) -> bool {
+    NETWORK_FS_TYPES
+        .iter()
+        .any(|network| fs_type.eq_ignore_ascii_case(network))
+}
+
+/// Whether filesystems of type `fs_type` are pseudo filesystems
+pub fn is_virtual_fs(fs_type: &str) -> bool {
+    VIRTUAL_FS_TYPES.contains(&fs_type)
+}
+
+/// The mount holding `path`, if the mounts can be listed
+pub fn mount_of(path: &Path) -> Option<Mount> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    // A later mount on the same point hides the earlier ones
+    platform_mounts()
+        .ok()?
+        .into_iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+/// Network filesystems mounted below `root`, not counting `root` itself
+pub fn remote_mounts_below(root: &Path) -> Vec<Mount> {
+    mounts_below(root, is_network_fs)
+}
+
+/// Pseudo filesystems mounted below `root`, not counting `root` itself
+pub fn virtual_mounts_below(root: &Path) -> Vec<Mount> {
+    mounts_below(root, is_virtual_fs)
+}
+
+fn mounts_below(root: &Path, is_type: fn(&str) -> bool) -> Vec<Mount> {
+    let root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    platform_mounts()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|mount| is_type(&mount.fs_type))
+        .filter(|mount| mount.mount_point != root && mount.mount_point.starts_with(&root))
+        .collect()
+}
+
+/// Kind of storage holding `path`
+#[cfg(windows)]
+pub fn storage_kind(path: &Path) -> StorageKind {
+    crate::windows::storage_kind(path)
+}
+
+/// Kind of storage holding `path`
+#[cfg(not(windows))]
+pub fn storage_kind(path: &Path) -> StorageKind {
+    let mount = mount_of(path);
+    if mount
+        .as_ref()
+        .is_some_and(|mount| is_network_fs(&mount.fs_type))
+    {
+        return StorageKind::Network;
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    match is_rotational(path, mount.as_ref()) {
+        Some(true) => return StorageKind::Rotational,
+        Some(false) => return StorageKind::SolidState,
+        None => {}
+    }
+    StorageKind::Unknown
+}
+
+/// Whether the block device holding `path` is a spinning disk, from sysfs
+///
+/// Filesystems without a block device number of their own (btrfs, ...) are
+/// looked up by the device they are mounted from.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_rotational(path: &Path, mount: Option<&Mount>) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let by_number = PathBuf::from(format!(
+        "/sys/dev/block/{}:{}",
+        libc::major(dev),
+        libc::minor(dev)
+    ));
+    let by_source = mount
+        .filter(|mount| mount.source.starts_with("/dev/"))
+        .and_then(|mount| std::fs::canonicalize(&mount.source).ok())
+        .and_then(|device| Some(Path::new("/sys/class/block").join(device.file_name()?)));
+    let device = [Some(by_number), by_source]
+        .into_iter()
+        .flatten()
+        .find_map(|device| std::fs::canonicalize(device).ok())?;
+    // Partitions keep the queue settings on their disk
+    let flag = [
+        device.join("queue/rotational"),
+        device.parent()?.join("queue/rotational"),
+    ]
+    .iter()
+    .find_map(|flag| std::fs::read_to_string(flag).ok())?;
+    Some(flag.trim() == "1")
+}
+
+/// Capacity of the filesystem holding `path`
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // statvfs field types differ between platforms
+pub fn filesystem_usage(path: &Path) -> io::Result<FsUsage> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs is plain data, valid when zeroed
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is valid for writes
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let block = stat.f_frsize as u64;
+    Ok(FsUsage {
+        total: stat.f_blocks as u64 * block,
+        free: stat.f_bfree as u64 * block,
+        available: stat.f_bavail as u64 * block,
+    })
+}
+
+/// Capacity of the filesystem holding `path`
+#[cfg(windows)]
+pub fn filesystem_usage(path: &Path) -> io::Result<FsUsage> {
+    crate::windows::filesystem_usage(path)
+}
+
+/// Capacity of the filesystem holding `path`
+#[cfg(not(any(unix, windows)))]
+pub fn filesystem_usage(_path: &Path) -> io::Result<FsUsage> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// List the mounted filesystems
+///
+/// Unless `all` is set, pseudo filesystems without any capacity (such as
+/// `proc` or `sysfs`) are left out.
+pub fn list_mounts(all: bool) -> Result<Vec<Mount>> {
+    let mut mounts = platform_mounts()?;
+    for mount in &mut mounts {
+        if mount.usage.is_none() {
+            mount.usage = filesystem_usage(&mount.mount_point)
+                .map_err(|e| log::debug!("Cannot query {}: {}", mount.mount_point.display(), e))
+                .ok();
+        }
+    }
+    if !all {
+        mounts.retain(|m| m.usage.is_some_and(|usage| usage.total > 0));
+    }
+    Ok(mounts)
+}
+
+/// Analyze every mount in parallel, each without crossing into other filesystems
+///
+/// Results are returned in the order of `mounts`.
+pub fn analyze_mounts(
+    mounts: &[Mount],
+    config: &AnalysisConfig,
+    top_n: usize,
+) -> Vec<Result<AnalysisResult>> {
+    let config = AnalysisConfig {
+        one_file_system: true,
+        ..config.clone()
+    };
+    mounts
+        .par_iter()
+        .map(|mount| analyze_disk_usage(&mount.mount_point, &config, top_n))
+        .collect()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn platform_mounts() -> Result<Vec<Mount>> {
+    use anyhow::Context;
+    let contents =
+        std::fs::read_to_string("/proc/mounts").context("Failed to read /proc/mounts")?;
+    Ok(parse_mount_table(&contents))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_mounts() -> Result<Vec<Mount>> {
+    use std::ffi::CStr;
+
+    let mut entries: *mut libc::statfs = std::ptr::null_mut();
+    // SAFETY: getmntinfo stores a pointer to a buffer it owns in `entries`
+    let count = unsafe { libc::getmntinfo(&mut entries, libc::MNT_NOWAIT) };
+    if count <= 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // SAFETY: getmntinfo returned `count` initialized entries
+    let entries = unsafe { std::slice::from_raw_parts(entries, count as usize) };
+
+    let text = |chars: &[libc::c_char]| {
+        // SAFETY: the names are NUL-terminated within their fixed-size buffers
+        unsafe { CStr::from_ptr(chars.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let block = u64::from(entry.f_bsize);
+            Mount {
+                source: text(&entry.f_mntfromname),
+                mount_point: PathBuf::from(text(&entry.f_mntonname)),
+                fs_type: text(&entry.f_fstypename),
+                usage: Some(FsUsage {
+                    total: entry.f_blocks * block,
+                    free: entry.f_bfree * block,
+                    available: entry.f_bavail * block,
+                }),
+            }
+        })
+        .collect())
+}
+
+#[cfg(windows)]
+fn platform_mounts() -> Result<Vec<Mount>> {
+    Ok(crate::windows::logical_drives())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    windows
+)))]
+fn platform_mounts() -> Result<Vec<Mount>> {
+    anyhow::bail!("Listing mounts is not supported on this platform")
+}
+
+/// Parse a mount table in the `/proc/mounts` format
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn parse_mount_table(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = unescape(fields.next()?);
+            let mount_point = PathBuf::from(unescape(fields.next()?));
+            let fs_type = fields.next()?.to_string();
+            Some(Mount {
+                source,
+                mount_point,
+                fs_type,
+                usage: None,
+            })
+        })
+        .collect()
+}
+
+/// Decode the octal escapes (`\040` for a space, ...) used in mount tables
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 4;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_parse_mount_table() {
+        let contents = "/dev/sda1 / ext4 rw,relatime 0 0\n\
+                        proc /proc proc rw,nosuid 0 0\n\
+                        /dev/sdb1 /mnt/my\\040disk vfat rw 0 0\n";
+        let mounts = parse_mount_table(contents);
+
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(mounts[0].source, "/dev/sda1");
+        assert_eq!(mounts[0].mount_point, PathBuf::from("/"));
+        assert_eq!(mounts[0].fs_type, "ext4");
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/mnt/my disk"));
+    }
+
+    #[test]
+    fn test_storage_kind() {
+        assert!(is_network_fs("nfs4"));
+        assert!(is_network_fs("fuse.sshfs"));
+        assert!(!is_network_fs("ext4"));
+        assert_eq!(StorageKind::Rotational.default_threads(), Some(2));
+        assert_eq!(StorageKind::SolidState.default_threads(), None);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert_ne!(storage_kind(temp_dir.path()), StorageKind::Network);
+    }
+
+    #[test]
+    fn test_virtual_mounts_below() {
+        assert!(is_virtual_fs("proc"));
+        assert!(is_virtual_fs("tmpfs"));
+        assert!(!is_virtual_fs("ext4"));
+
+        #[cfg(target_os = "linux")]
+        if Path::new("/proc/self").exists() {
+            let mounts = virtual_mounts_below(Path::new("/"));
+            assert!(mounts.iter().any(|m| m.mount_point == Path::new("/proc")));
+            assert!(virtual_mounts_below(Path::new("/proc"))
+                .iter()
+                .all(|m| m.mount_point != Path::new("/proc")));
+        }
+    }
+
+    #[test]
+    fn test_filesystem_usage() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let usage = filesystem_usage(temp_dir.path()).unwrap();
+
+        assert!(usage.total > 0);
+        assert!(usage.available <= usage.free);
+        assert_eq!(usage.used(), usage.total - usage.free);
+    }
+}
@@ -1,8 +1,29 @@
 //! Output formatting for disk usage results
 
-use crate::format::{format_percentage, format_size_auto};
-use crate::AnalysisResult;
+use crate::clean::{CleanReport, ScriptFormat};
+use crate::dedupe::DuplicateGroup;
+use crate::diff::{ChangeKind, DirectoryDelta};
+use crate::docker::DockerUsage;
+use crate::empty::EmptyReport;
+use crate::format::{
+    format_percentage, format_rfc3339, format_size_auto, format_size_delta, format_size_du,
+    format_timestamp, sparkline_with, SizeFormat,
+};
+use crate::hardlinks::HardLinkReport;
+use crate::history::{growth_per_day, HistoryPoint};
+use crate::icons::{EntryKind, IconStyle};
+use crate::mounts::Mount;
+use crate::stats::ScanStats;
+use crate::theme::{ColorBy, ColorChoice, ColorSupport, Theme};
+use crate::types::Category;
+use crate::{AnalysisConfig, AnalysisResult, DirectoryEntry, DirectoryNode};
+use std::borrow::Cow;
 use std::cmp;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /// Configuration for output display
 #[derive(Debug, Clone)]
@@ -13,104 +34,655 @@ pub struct OutputConfig {
     pub bar_width: usize,
     /// Width of the size column
     pub size_width: usize,
+    /// How sizes are printed in the table and the tree
+    pub size_format: SizeFormat,
     /// Width of the percentage column
     pub percent_width: usize,
     /// Width of the name column
     pub name_width: usize,
+    /// Width of the file and directory count columns
+    pub count_width: usize,
+    /// Columns of the table, in order
+    pub columns: Vec<Column>,
+    /// Whether the directories of the tree are followed by the number of
+    /// files and directories below them
+    pub show_counts: bool,
+    /// Entries left out of the table, summed up in a last row when set; the
+    /// tree output then also sums up what it leaves out of each directory
+    pub others: Option<Others>,
+    /// Colors used when `use_colors` is set
+    pub theme: Theme,
+    /// What the bars of the table and the tree are colored by
+    pub color_by: ColorBy,
+    /// Whether tables, bars and trees are drawn with plain ASCII instead of
+    /// box-drawing and block characters
+    pub ascii: bool,
+    /// How the table and the tree name their entries
+    pub paths: PathStyle,
+    /// Whether the names in the table and the tree are OSC 8 hyperlinks to
+    /// their entries
+    pub hyperlinks: bool,
+    /// Icons put before the names in the table and the tree, telling the kinds
+    /// of entries apart
+    pub icons: Option<IconStyle>,
+    /// Whether each entry of the table takes two lines, its name above its
+    /// bar, size and share, for narrow terminals
+    pub stacked: bool,
 }
 
 impl Default for OutputConfig {
     fn default() -> Self {
         Self {
-            use_colors: atty::is(atty::Stream::Stdout),
+            use_colors: ColorChoice::Auto.enabled(atty::is(atty::Stream::Stdout)),
             bar_width: 30,
             size_width: 8,
+            size_format: SizeFormat::Human,
             percent_width: 5,
             name_width: 30,
+            count_width: 5,
+            columns: Column::DEFAULT.to_vec(),
+            show_counts: false,
+            others: None,
+            theme: Theme::default(),
+            color_by: ColorBy::Size,
+            ascii: false,
+            paths: PathStyle::Auto,
+            hyperlinks: false,
+            icons: None,
+            stacked: false,
         }
     }
 }
 
-/// ANSI color codes
+/// How the rows of the table and the tree name their entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// Path below the analyzed one in the table, or the full path when a table
+    /// fitted to the terminal has room for it; file name in the tree
+    #[default]
+    Auto,
+    /// Path below the analyzed one, like `projects/rust/target`
+    Relative,
+    /// Full path
+    Full,
+}
+
+/// Column of the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// Bar scaled to the largest entry
+    Bar,
+    Size,
+    /// Share of the total
+    Percent,
+    /// Number of files below the entry
+    Files,
+    /// Number of directories below the entry
+    Dirs,
+    /// Newest modification time of the files below the entry
+    Mtime,
+    /// Name of the entry
+    Path,
+}
+
+impl Column {
+    /// Columns of the table unless others are chosen
+    pub const DEFAULT: [Column; 4] = [Column::Bar, Column::Size, Column::Percent, Column::Path];
+
+    /// Title of the column in the header row
+    fn title(self) -> &'static str {
+        match self {
+            Column::Bar => "Usage",
+            Column::Size => "Size",
+            Column::Percent => "Share",
+            Column::Files => "Files",
+            Column::Dirs => "Dirs",
+            Column::Mtime => "Modified",
+            Column::Path => "Path",
+        }
+    }
+
+    /// Whether values are aligned to the right of the column
+    fn is_numeric(self) -> bool {
+        matches!(
+            self,
+            Column::Size | Column::Percent | Column::Files | Column::Dirs
+        )
+    }
+}
+
+/// Narrowest bar of a table fitted to the terminal
+const MIN_BAR_WIDTH: usize = 10;
+/// Widest bar of a table fitted to the terminal
+const MAX_BAR_WIDTH: usize = 60;
+/// Narrowest name column before the rows of a table are stacked
+const MIN_NAME_WIDTH: usize = 20;
+
+impl OutputConfig {
+    /// Size the bar and name columns of the table of `result` to fill a
+    /// terminal `width` columns wide
+    ///
+    /// Unless another [`PathStyle`] is chosen, entries are named by their full
+    /// path when those fit next to a bar of the configured width. Below the
+    /// width of the narrowest bar and name columns, the rows are stacked
+    /// instead.
+    pub fn fit_to_width(mut self, width: usize, result: &AnalysisResult) -> Self {
+        let has_bar = self.columns.contains(&Column::Bar);
+        let has_path = self.columns.contains(&Column::Path);
+        // "│ " column " " for each column, then "│"
+        let fixed: usize = self
+            .columns
+            .iter()
+            .map(|&column| match column {
+                Column::Bar | Column::Path => 3,
+                column => self.column_width(column) + 3,
+            })
+            .sum();
+        let available = width.saturating_sub(fixed + 1);
+        let bar = if has_bar { MIN_BAR_WIDTH } else { 0 };
+        let name = if has_path { MIN_NAME_WIDTH } else { 0 };
+        if available < bar + name {
+            // "  " bar then " " column for each other column
+            let fixed: usize = self
+                .columns
+                .iter()
+                .filter(|&&column| column != Column::Bar && column != Column::Path)
+                .map(|&column| self.column_width(column) + 1)
+                .sum();
+            self.stacked = true;
+            self.bar_width = width.saturating_sub(fixed + 2).clamp(5, MAX_BAR_WIDTH);
+            self.name_width = width.max(MIN_BAR_WIDTH);
+            return self;
+        }
+
+        let widest = |paths: PathStyle| {
+            let names = result
+                .top_directories
+                .iter()
+                .map(|dir| display_width(&row_name(&dir.path, &result.root_path, paths)));
+            let others = self.others.filter(|others| !others.is_empty());
+            let others = others.map(|others| display_width(&others.label()));
+            let icon = if self.icons.is_some() { 3 } else { 0 };
+            (names.chain(others).max().unwrap_or(0) + icon).max(MIN_NAME_WIDTH)
+        };
+        let bar = if has_bar { self.bar_width } else { 0 };
+        let full = widest(PathStyle::Full);
+        if has_path && self.paths == PathStyle::Auto && full + bar <= available {
+            self.paths = PathStyle::Full;
+        }
+        let name = if has_path { widest(self.paths) } else { 0 };
+        if has_bar {
+            // Long names leave the bar at least a third of the room
+            let bar = available.saturating_sub(name).max(available / 3);
+            self.bar_width = bar.clamp(MIN_BAR_WIDTH, MAX_BAR_WIDTH);
+        }
+        let bar = if has_bar { self.bar_width } else { 0 };
+        self.name_width = name.min(available.saturating_sub(bar));
+        self
+    }
+
+    /// Width of the values of `column`
+    fn column_width(&self, column: Column) -> usize {
+        match column {
+            Column::Bar => self.bar_width,
+            Column::Size => self.size_width,
+            Column::Percent => self.percent_width,
+            Column::Files | Column::Dirs => self.count_width,
+            Column::Mtime => TIME_WIDTH,
+            Column::Path => self.name_width,
+        }
+    }
+}
+
+/// Characters tables, bars and trees are drawn with
+struct Glyphs {
+    vertical: &'static str,
+    horizontal: &'static str,
+    /// Left, middle and right corners of the top border
+    top: [&'static str; 3],
+    /// Left, middle and right corners of the line below the header row
+    middle: [&'static str; 3],
+    /// Left, middle and right corners of the bottom border
+    bottom: [&'static str; 3],
+    filled: &'static str,
+    empty: &'static str,
+    /// Tree branch to an entry followed by others
+    branch: &'static str,
+    /// Tree branch to the last entry of a directory
+    last_branch: &'static str,
+    /// Indent below an entry followed by others
+    continued: &'static str,
+    /// Sparkline levels, lowest first
+    sparks: [char; 8],
+}
+
+impl Glyphs {
+    const UNICODE: Glyphs = Glyphs {
+        vertical: "│",
+        horizontal: "─",
+        top: ["┌", "┬", "┐"],
+        middle: ["├", "┼", "┤"],
+        bottom: ["└", "┴", "┘"],
+        filled: "█",
+        empty: "░",
+        branch: "├── ",
+        last_branch: "└── ",
+        continued: "│   ",
+        sparks: ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'],
+    };
+
+    /// Glyphs that survive serial consoles, legacy terminals and email
+    const ASCII: Glyphs = Glyphs {
+        vertical: "|",
+        horizontal: "-",
+        top: ["+", "+", "+"],
+        middle: ["+", "+", "+"],
+        bottom: ["+", "+", "+"],
+        filled: "#",
+        empty: ".",
+        branch: "|-- ",
+        last_branch: "`-- ",
+        continued: "|   ",
+        sparks: ['_', '.', ':', '-', '=', '+', '*', '#'],
+    };
+
+    fn new(config: &OutputConfig) -> &'static Glyphs {
+        if config.ascii {
+            &Self::ASCII
+        } else {
+            &Self::UNICODE
+        }
+    }
+}
+
+/// Which of the entries of a listing, sorted by size, are shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Selection {
+    /// Largest number of entries shown
+    pub top: usize,
+    /// Smallest size of an entry shown, in bytes
+    pub min_size: u64,
+    /// Percentage of the total after which no more entries are shown
+    pub coverage: Option<f64>,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            top: usize::MAX,
+            min_size: 0,
+            coverage: None,
+        }
+    }
+}
+
+impl Selection {
+    /// Number of leading entries with `sizes`, largest first, shown in a
+    /// listing adding up to `total`
+    pub fn count(&self, sizes: impl IntoIterator<Item = u64>, total: u64) -> usize {
+        let target = self.coverage.map_or(u64::MAX, |percent| {
+            (total as f64 * percent / 100.0).ceil() as u64
+        });
+        let mut covered = 0u64;
+        sizes
+            .into_iter()
+            .take(self.top)
+            .take_while(|&size| {
+                let shown = size >= self.min_size && covered < target;
+                covered = covered.saturating_add(size);
+                shown
+            })
+            .count()
+    }
+}
+
+/// Entries left out of a listing, summed up in one row so that the rows add
+/// up to the total
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Others {
+    /// Number of entries left out, 0 when they are not known individually
+    pub count: usize,
+    /// Their combined disk usage in bytes
+    pub size: u64,
+}
+
+impl Others {
+    /// Keep the `selection` of `entries`, sorted by size, and sum up the
+    /// rest, where `total_size` also covers entries that were never listed
+    pub fn split(
+        entries: &mut Vec<DirectoryEntry>,
+        selection: &Selection,
+        total_size: u64,
+    ) -> Self {
+        let keep = selection.count(entries.iter().map(|entry| entry.size), total_size);
+        let count = entries.len() - keep;
+        entries.truncate(keep);
+        let shown: u64 = entries.iter().map(|entry| entry.size).sum();
+        Self {
+            count,
+            size: total_size.saturating_sub(shown),
+        }
+    }
+
+    /// Whether nothing was left out
+    pub fn is_empty(&self) -> bool {
+        self.count == 0 && self.size == 0
+    }
+
+    fn label(&self) -> String {
+        match self.count {
+            0 => "(other entries)".to_string(),
+            1 => "(1 other entry)".to_string(),
+            count => format!("(other {} entries)", count),
+        }
+    }
+}
+
+/// Escape sequences of the theme colors, all empty without colors
 struct Colors {
-    green: &'static str,
-    yellow: &'static str,
-    red: &'static str,
+    low: String,
+    medium: String,
+    high: String,
+    types: Vec<(Category, String)>,
     reset: &'static str,
 }
 
 impl Colors {
-    fn enabled() -> Self {
+    /// Colors of `config`, as far as the terminal supports them
+    fn new(config: &OutputConfig) -> Self {
+        if config.use_colors {
+            Self::from_theme(&config.theme, ColorSupport::detect())
+        } else {
+            Self::disabled()
+        }
+    }
+
+    fn from_theme(theme: &Theme, support: ColorSupport) -> Self {
+        if support == ColorSupport::None {
+            return Self::disabled();
+        }
         Self {
-            green: "\x1b[38;5;34m",
-            yellow: "\x1b[38;5;220m",
-            red: "\x1b[38;5;160m",
+            low: theme.low.escape(support),
+            medium: theme.medium.escape(support),
+            high: theme.high.escape(support),
+            types: theme
+                .types
+                .iter()
+                .map(|(&category, color)| (category, color.escape(support)))
+                .collect(),
             reset: "\x1b[0m",
         }
     }
 
     fn disabled() -> Self {
         Self {
-            green: "",
-            yellow: "",
-            red: "",
+            low: String::new(),
+            medium: String::new(),
+            high: String::new(),
+            types: Vec::new(),
             reset: "",
         }
     }
+
+    /// Color of an entry whose newest file was modified at `modified`: within
+    /// 30 days, within a year, or earlier
+    fn for_age(&self, modified: Option<u64>) -> &str {
+        let Some(modified) = modified else {
+            return "";
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let days = now.saturating_sub(modified) / 86_400;
+        if days < 30 {
+            &self.low
+        } else if days < 365 {
+            &self.medium
+        } else {
+            &self.high
+        }
+    }
+
+    /// Color of the kind of content the extension of `name` tells
+    fn for_type(&self, name: &Path) -> &str {
+        let category = crate::types::extension(name).map_or(Category::Other, |extension| {
+            Category::from_extension(&extension)
+        });
+        self.types
+            .iter()
+            .find(|(c, _)| *c == category)
+            .map_or("", |(_, color)| color)
+    }
 }
 
 /// Print analysis results in a formatted table with bar chart
 pub fn print_results(result: &AnalysisResult, config: &OutputConfig) {
-    let colors = if config.use_colors {
-        Colors::enabled()
-    } else {
-        Colors::disabled()
-    };
+    // A closed pipe only ends the output early
+    let _ = write_results(&mut io::stdout().lock(), result, config);
+}
 
-    println!("\nAnalyzing: {}", result.root_path.display());
-    println!();
+/// Write analysis results as a formatted table with bar chart to `out`
+pub fn write_results(
+    out: &mut impl Write,
+    result: &AnalysisResult,
+    config: &OutputConfig,
+) -> io::Result<()> {
+    let colors = Colors::new(config);
+
+    writeln!(out, "\nAnalyzing: {}", result.root_path.display())?;
+    writeln!(out)?;
 
     if result.top_directories.is_empty() {
-        println!("┌────────────────────┐");
-        println!("│ No files found     │");
-        println!("└────────────────────┘");
-        return;
+        let glyphs = Glyphs::new(config);
+        let border = glyphs.horizontal.repeat(20);
+        let [left, _, right] = glyphs.top;
+        writeln!(out, "{}{}{}", left, border, right)?;
+        let v = glyphs.vertical;
+        writeln!(out, "{} No files found     {}", v, v)?;
+        let [left, _, right] = glyphs.bottom;
+        writeln!(out, "{}{}{}", left, border, right)?;
+        return Ok(());
     }
 
     // Calculate maximum size for bar scaling
-    let max_size = result
-        .top_directories
-        .first()
-        .map(|d| d.size)
-        .unwrap_or(1);
+    let max_size = result.top_directories.first().map(|d| d.size).unwrap_or(1);
 
-    // Print table header
-    print_table_border(&config, true);
+    // Table header, with the titles of the columns when counts make them
+    // ambiguous
+    let glyphs = Glyphs::new(config);
+    if !config.stacked {
+        write_table_border(out, config, glyphs.top)?;
+        let counts = [Column::Files, Column::Dirs];
+        if config.columns.iter().any(|column| counts.contains(column)) {
+            write_table_header(out, config)?;
+        }
+    }
 
-    // Print each directory
+    // One row per directory
     for dir in &result.top_directories {
-        print_directory_row(dir, max_size, result.total_size, &colors, config);
+        write_directory_row(
+            out,
+            dir,
+            &row_name(&dir.path, &result.root_path, config.paths),
+            max_size,
+            result.total_size,
+            &colors,
+            config,
+        )?;
+    }
+
+    if let Some(others) = config.others.filter(|others| !others.is_empty()) {
+        // Without a path, the row is not linked
+        let row = DirectoryEntry {
+            path: PathBuf::new(),
+            size: others.size,
+            file_count: 0,
+            dir_count: 0,
+            modified: None,
+        };
+        write_directory_row(
+            out,
+            &row,
+            &others.label(),
+            max_size,
+            result.total_size,
+            &colors,
+            config,
+        )?;
     }
 
-    // Print table footer
-    print_table_border(&config, false);
+    // Table footer
+    if !config.stacked {
+        write_table_border(out, config, glyphs.bottom)?;
+    }
 
-    // Print total
-    println!("\nTotal: {}", format_size_auto(result.total_size));
-    println!(
+    // Totals
+    writeln!(
+        out,
+        "\nTotal: {}",
+        config.size_format.format(result.total_size)
+    )?;
+    if result.cloned_size > 0 {
+        writeln!(
+            out,
+            "Unique on disk: {} ({} shared by clones)",
+            format_size_auto(result.unique_size()),
+            format_size_auto(result.cloned_size)
+        )?;
+    }
+    if result.cloud_files > 0 {
+        writeln!(
+            out,
+            "Cloud-only: {} in {} files (not stored on this disk)",
+            format_size_auto(result.cloud_size),
+            result.cloud_files
+        )?;
+    }
+    if let (Some(fs), Some(percent)) = (result.filesystem, result.capacity_percentage()) {
+        let percent = if percent > 0.0 && percent < 0.1 {
+            "<0.1%".to_string()
+        } else {
+            format!("{:.1}%", percent)
+        };
+        writeln!(
+            out,
+            "Filesystem: {} of {} free (this scan is {} of the volume)",
+            format_size_auto(fs.available),
+            format_size_auto(fs.total),
+            percent
+        )?;
+    }
+    write_counts(out, result)?;
+    write_link_issues(out, result)
+}
+
+/// Write the number of files and directories of `result`, followed by those
+/// of the symbolic links and special files found
+fn write_counts(out: &mut impl Write, result: &AnalysisResult) -> io::Result<()> {
+    write!(
+        out,
         "Files: {}  Directories: {}",
         result.total_files, result.total_dirs
-    );
+    )?;
+    let other = &result.other_types;
+    for (label, count) in [
+        ("Symlinks", other.symlinks),
+        ("Sockets", other.sockets),
+        ("FIFOs", other.fifos),
+        ("Block devices", other.block_devices),
+        ("Char devices", other.char_devices),
+    ] {
+        if count > 0 {
+            write!(out, "  {}: {}", label, count)?;
+        }
+    }
+    writeln!(out)
+}
+
+/// Write the performance figures of a scan
+pub fn write_stats(out: &mut impl Write, stats: &ScanStats) -> io::Result<()> {
+    let percent = |share: f64| format!("{:.0}%", share * 100.0);
+    writeln!(out, "\nScan statistics:")?;
+    writeln!(
+        out,
+        "  Wall time:         {:.2}s",
+        stats.wall_time.as_secs_f64()
+    )?;
+    writeln!(out, "  Files/s:           {:.0}", stats.files_per_second())?;
+    writeln!(out, "  Directories/s:     {:.0}", stats.dirs_per_second())?;
+    writeln!(
+        out,
+        "  Syscalls avoided:  {} ({} directories and {} files from the cache)",
+        stats.syscalls_avoided(),
+        stats.cached_dirs,
+        stats.cached_files
+    )?;
+    let utilization = stats.utilization();
+    if !utilization.is_empty() {
+        let average = utilization.iter().sum::<f64>() / utilization.len() as f64;
+        let threads: Vec<String> = utilization.iter().copied().map(percent).collect();
+        writeln!(
+            out,
+            "  Thread busy time:  {} average over {} threads ({})",
+            percent(average),
+            utilization.len(),
+            threads.join(" ")
+        )?;
+    }
+    if let Some(rss) = stats.peak_rss {
+        writeln!(out, "  Peak memory:       {}", format_size_auto(rss))?;
+    }
+    Ok(())
 }
 
-/// Print a directory row in the table
-fn print_directory_row(
+/// Write the section listing problematic symbolic links, if any were found
+fn write_link_issues(out: &mut impl Write, result: &AnalysisResult) -> io::Result<()> {
+    if result.link_issues.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "\nSymbolic link problems: {}",
+        result.link_issues.len()
+    )?;
+    for issue in &result.link_issues {
+        let path = issue
+            .path
+            .strip_prefix(&result.root_path)
+            .unwrap_or(&issue.path);
+        writeln!(
+            out,
+            "  {:<12} {} -> {}",
+            issue.kind.as_str(),
+            path.display(),
+            issue.target.display()
+        )?;
+    }
+    Ok(())
+}
+
+/// Name of the table row of `path`: its full path with [`PathStyle::Full`],
+/// its path below `root` otherwise
+fn row_name<'a>(path: &'a Path, root: &Path, paths: PathStyle) -> Cow<'a, str> {
+    if paths == PathStyle::Full {
+        return path.to_string_lossy();
+    }
+    match path.strip_prefix(root) {
+        Ok(name) if !name.as_os_str().is_empty() => name.to_string_lossy(),
+        _ => Cow::Borrowed("."),
+    }
+}
+
+/// Write a directory row of the table, naming it `name`
+fn write_directory_row(
+    out: &mut impl Write,
     dir: &crate::DirectoryEntry,
+    name: &str,
     max_size: u64,
     total_size: u64,
     colors: &Colors,
     config: &OutputConfig,
-) {
+) -> io::Result<()> {
     // Calculate bar length
     let bar_length = if max_size > 0 {
         ((dir.size as f64 / max_size as f64) * config.bar_width as f64) as usize
@@ -119,161 +691,3584 @@ fn print_directory_row(
     };
     let bar_length = cmp::min(bar_length, config.bar_width);
 
-    // Select color based on size percentage
-    let color = select_color(bar_length, config.bar_width, colors);
+    // Directories have no type, so they are colored by size instead
+    let color = match config.color_by {
+        ColorBy::Size | ColorBy::Type => select_color(bar_length, config.bar_width, colors),
+        ColorBy::Age => colors.for_age(dir.modified),
+    };
 
     // Create bar
-    let filled = "█".repeat(bar_length);
-    let empty = "░".repeat(config.bar_width - bar_length);
+    let glyphs = Glyphs::new(config);
+    let filled = glyphs.filled.repeat(bar_length);
+    let empty = glyphs.empty.repeat(config.bar_width - bar_length);
     let bar = format!("{}{}{}", color, filled, empty);
 
-    // Format size and percentage
-    let size_str = format_size_auto(dir.size);
-    let percent_str = format_percentage(dir.size, total_size);
+    let time = dir
+        .modified
+        .map_or_else(|| "-".to_string(), format_timestamp);
+    let icon = icon_prefix(&dir.path, EntryKind::of_path, config);
+    let name = truncate(name, config.name_width.saturating_sub(display_width(&icon)));
+    let padding = config
+        .name_width
+        .saturating_sub(display_width(&icon) + display_width(&name));
+    let name = format!("{}{}", icon, linked(&name, &dir.path, config));
+    // The counts of the entries left out are not known
+    let count = |count: usize| {
+        if dir.path.as_os_str().is_empty() {
+            "-".to_string()
+        } else {
+            count.to_string()
+        }
+    };
+
+    let cells: Vec<(Column, String)> = config
+        .columns
+        .iter()
+        .map(|&column| {
+            let width = config.column_width(column);
+            let cell = match column {
+                Column::Bar => format!("{}{}", bar, colors.reset),
+                Column::Size => format!("{:>width$}", config.size_format.format(dir.size)),
+                Column::Percent => format!("{:>width$}", format_percentage(dir.size, total_size)),
+                Column::Files => format!("{:>width$}", count(dir.file_count)),
+                Column::Dirs => format!("{:>width$}", count(dir.dir_count)),
+                Column::Mtime => format!("{:<width$}", time),
+                Column::Path => format!("{}{}", name, " ".repeat(padding)),
+            };
+            (column, cell)
+        })
+        .collect();
+
+    if config.stacked {
+        if config.columns.contains(&Column::Path) {
+            writeln!(out, "{}", name)?;
+        }
+        let values: Vec<&str> = cells
+            .iter()
+            .filter(|(column, _)| *column != Column::Path)
+            .map(|(_, cell)| cell.as_str())
+            .collect();
+        return writeln!(out, "  {}", values.join(" ").trim_end());
+    }
+
+    // Write the row
+    let v = glyphs.vertical;
+    let cells: Vec<&str> = cells.iter().map(|(_, cell)| cell.as_str()).collect();
+    writeln!(out, "{v} {} {v}", cells.join(&format!(" {v} ")))
+}
 
-    // Get directory name (relative to analyzed path)
-    let name = dir
-        .path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(".");
+/// Icon of the entry at `path` of the kind told by `kind`, followed by a
+/// space, when the config has icons and `path` is not empty
+fn icon_prefix(path: &Path, kind: fn(&Path) -> EntryKind, config: &OutputConfig) -> String {
+    match config.icons {
+        Some(style) if !path.as_os_str().is_empty() => format!("{} ", kind(path).icon(style)),
+        _ => String::new(),
+    }
+}
 
-    // Truncate name if needed to fit in column
-    let display_name = if name.len() > config.name_width {
-        format!("{}...", &name[..config.name_width - 3])
+/// `text` as a hyperlink to `path` when the config has hyperlinks and `path`
+/// is not empty
+///
+/// Links take no room on the screen, so widths are measured before linking.
+fn linked<'a>(text: &'a str, path: &Path, config: &OutputConfig) -> Cow<'a, str> {
+    if config.hyperlinks && !path.as_os_str().is_empty() {
+        Cow::Owned(crate::hyperlink::link(text, path))
     } else {
-        name.to_string()
+        Cow::Borrowed(text)
+    }
+}
+
+/// Width of `text` in terminal columns, wide characters like CJK counting twice
+fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Cut `text` to at most `width` terminal columns, ending in "..." when cut
+///
+/// Whole grapheme clusters are kept, so accented letters and emoji sequences
+/// are never split.
+fn truncate(text: &str, width: usize) -> Cow<'_, str> {
+    if display_width(text) <= width {
+        return Cow::Borrowed(text);
+    }
+    let ellipsis = &"..."[..width.min(3)];
+    let available = width - ellipsis.len();
+    let mut used = 0;
+    let mut end = 0;
+    for (start, grapheme) in text.grapheme_indices(true) {
+        used += display_width(grapheme);
+        if used > available {
+            break;
+        }
+        end = start + grapheme.len();
+    }
+    Cow::Owned(format!("{}{}", &text[..end], ellipsis))
+}
+
+/// `text` followed by spaces up to `width` terminal columns, like `{:<width$}`
+/// but counting columns instead of characters
+fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+/// `text` cut and padded to exactly `width` terminal columns
+fn fit(text: &str, width: usize) -> String {
+    pad(&truncate(text, width), width)
+}
+
+/// Width of a modification time like "2024-03-09 14:05"
+const TIME_WIDTH: usize = 16;
+
+/// Write the full tree of `result` as nested branches with sizes and bars,
+/// down to `max_depth` levels below the root and with the `selection` of the
+/// subdirectories of each directory
+///
+/// With `files`, the files of each directory are listed and selected along
+/// with its subdirectories; the tree must have been built with `tree_files`.
+pub fn write_tree(
+    out: &mut impl Write,
+    result: &AnalysisResult,
+    config: &OutputConfig,
+    max_depth: usize,
+    selection: &Selection,
+    files: bool,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let tree = result
+        .tree
+        .as_ref()
+        .context("Tree output requires the full directory tree")?;
+    let colors = Colors::new(config);
+    let rows = TreeRows {
+        root: &tree.path,
+        total_size: tree.size,
+        max_depth,
+        selection,
+        files,
+        colors: &colors,
+        glyphs: Glyphs::new(config),
+        config,
     };
+    rows.write(out, tree, &tree.path.display().to_string(), "", "", 0)?;
+    writeln!(out)?;
+    write_counts(out, result)?;
+    Ok(())
+}
 
-    // Print the row
-    println!(
-        "│ {}{} │ {:>size_w$} │ {:>pct_w$} │ {:<name_w$} │",
-        bar,
-        colors.reset,
-        size_str,
-        percent_str,
-        display_name,
-        size_w = config.size_width,
-        pct_w = config.percent_width,
-        name_w = config.name_width
-    );
+/// Settings shared by every row of [`write_tree`]
+struct TreeRows<'a> {
+    root: &'a Path,
+    total_size: u64,
+    max_depth: usize,
+    selection: &'a Selection,
+    /// List files alongside subdirectories
+    files: bool,
+    colors: &'a Colors,
+    glyphs: &'static Glyphs,
+    config: &'a OutputConfig,
+}
+
+/// A row below a directory in [`write_tree`]
+enum TreeItem<'a> {
+    Dir(&'a DirectoryNode),
+    File(&'a crate::FileNode),
+}
+
+impl TreeItem<'_> {
+    fn size(&self) -> u64 {
+        match self {
+            TreeItem::Dir(node) => node.size,
+            TreeItem::File(file) => file.size,
+        }
+    }
+}
+
+impl TreeRows<'_> {
+    /// Write the row of `node` after `branch`, then its children below it,
+    /// each indented with `indent`
+    fn write(
+        &self,
+        out: &mut impl Write,
+        node: &DirectoryNode,
+        name: &str,
+        indent: &str,
+        branch: &str,
+        depth: usize,
+    ) -> io::Result<()> {
+        let icon = icon_prefix(&node.path, EntryKind::of_dir, self.config);
+        let name = linked(name, &node.path, self.config);
+        let mut text = format!("{}{}{}{}", indent, branch, icon, name);
+        if self.config.show_counts {
+            let files = plural(node.file_count, "file", "files");
+            let dirs = plural(node.dir_count, "directory", "directories");
+            text.push_str(&format!(" ({}, {})", files, dirs));
+        }
+        self.write_row(out, node.size, None, &text)?;
+        if depth >= self.max_depth {
+            return Ok(());
+        }
+        // The root has no branch, so its children are not indented further
+        let child_indent = match branch {
+            "" => String::new(),
+            _ if branch == self.glyphs.last_branch => format!("{}    ", indent),
+            _ => format!("{}{}", indent, self.glyphs.continued),
+        };
+        let mut items: Vec<TreeItem> = node.children.iter().map(TreeItem::Dir).collect();
+        if self.files {
+            items.extend(node.files.iter().map(TreeItem::File));
+            items.sort_by_key(|item| cmp::Reverse(item.size()));
+        }
+        let shown = self
+            .selection
+            .count(items.iter().map(TreeItem::size), node.size);
+        let shown = &items[..shown];
+        // Unless listed, files directly in the directory are not among its
+        // rows, so they are always summed up with the rows left out
+        let others_size = node
+            .size
+            .saturating_sub(shown.iter().map(TreeItem::size).sum());
+        let others = (self.config.others.is_some() && others_size > 0).then(|| {
+            let shown_dirs = shown
+                .iter()
+                .filter(|item| matches!(item, TreeItem::Dir(_)))
+                .count();
+            let files = node
+                .file_count
+                .saturating_sub(node.children.iter().map(|child| child.file_count).sum())
+                .saturating_sub(shown.len() - shown_dirs);
+            let dirs = node.children.len() - shown_dirs;
+            (others_size, tree_others_label(dirs, files))
+        });
+        for (i, item) in shown.iter().enumerate() {
+            let last = i + 1 == shown.len() && others.is_none();
+            let branch = if last {
+                self.glyphs.last_branch
+            } else {
+                self.glyphs.branch
+            };
+            match item {
+                TreeItem::Dir(child) => {
+                    let name = self.name(&child.path);
+                    self.write(out, child, &name, &child_indent, branch, depth + 1)?;
+                }
+                TreeItem::File(file) => {
+                    let path = node.path.join(&file.name);
+                    let name = self.name(&path);
+                    let icon = icon_prefix(&path, EntryKind::of_file, self.config);
+                    let name = linked(&name, &path, self.config);
+                    let text = format!("{}{}{}{}", child_indent, branch, icon, name);
+                    self.write_row(out, file.size, Some(Path::new(&file.name)), &text)?;
+                }
+            }
+        }
+        if let Some((size, label)) = others {
+            let text = format!("{}{}{}", child_indent, self.glyphs.last_branch, label);
+            self.write_row(out, size, None, &text)?;
+        }
+        Ok(())
+    }
+
+    /// Name of the row of `path`, its file name unless another [`PathStyle`]
+    /// is chosen
+    fn name<'p>(&self, path: &'p Path) -> Cow<'p, str> {
+        match (self.config.paths, path.file_name()) {
+            (PathStyle::Auto, Some(name)) => name.to_string_lossy(),
+            (PathStyle::Auto, None) => path.to_string_lossy(),
+            (paths, _) => row_name(path, self.root, paths),
+        }
+    }
+
+    /// Write a row with the size, share and bar of `size` followed by `text`,
+    /// naming `file` when the row is a file's
+    fn write_row(
+        &self,
+        out: &mut impl Write,
+        size: u64,
+        file: Option<&Path>,
+        text: &str,
+    ) -> io::Result<()> {
+        let width = self.config.bar_width;
+        let bar_length = if self.total_size > 0 {
+            ((size as f64 / self.total_size as f64) * width as f64) as usize
+        } else {
+            0
+        };
+        let bar_length = cmp::min(bar_length, width);
+        // The tree has no modification times, and only files have a type, so
+        // other rows are colored by size instead
+        let color = match (self.config.color_by, file) {
+            (ColorBy::Type, Some(file)) => self.colors.for_type(file),
+            _ => select_color(bar_length, width, self.colors),
+        };
+        writeln!(
+            out,
+            "{:>size_width$} {:>percent_width$} {}{}{}{} {}",
+            self.config.size_format.format(size),
+            format_percentage(size, self.total_size),
+            color,
+            self.glyphs.filled.repeat(bar_length),
+            self.glyphs.empty.repeat(width - bar_length),
+            self.colors.reset,
+            text,
+            size_width = self.config.size_width,
+            percent_width = self.config.percent_width,
+        )
+    }
+}
+
+/// Label of the tree row summing up `dirs` subdirectories and `files` files
+/// left out of a directory
+fn tree_others_label(dirs: usize, files: usize) -> String {
+    match (dirs, files) {
+        (0, 0) => "(other entries)".to_string(),
+        (dirs, 0) => format!("(other {})", plural(dirs, "directory", "directories")),
+        (0, files) => format!("(other {})", plural(files, "file", "files")),
+        (dirs, files) => format!(
+            "(other {}, {})",
+            plural(dirs, "directory", "directories"),
+            plural(files, "file", "files")
+        ),
+    }
+}
+
+/// `count` followed by `one` or `many`, like "1 file" or "3 files"
+fn plural(count: usize, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
 }
 
 /// Select color based on bar fill percentage
-fn select_color<'a>(bar_length: usize, bar_width: usize, colors: &'a Colors) -> &'a str {
+fn select_color(bar_length: usize, bar_width: usize, colors: &Colors) -> &str {
     let threshold_yellow = bar_width * 33 / 100;
     let threshold_red = bar_width * 50 / 100;
 
     if bar_length >= threshold_red {
-        colors.red
+        &colors.high
     } else if bar_length >= threshold_yellow {
-        colors.yellow
+        &colors.medium
     } else {
-        colors.green
+        &colors.low
     }
 }
 
-/// Print table border
-fn print_table_border(config: &OutputConfig, is_top: bool) {
-    let bar_border = "─".repeat(config.bar_width + 2);
-    let size_border = "─".repeat(config.size_width + 2);
-    let percent_border = "─".repeat(config.percent_width + 2);
-    let name_border = "─".repeat(config.name_width + 2);
+/// Write a table border with the left, middle and right `corners`
+fn write_table_border(
+    out: &mut impl Write,
+    config: &OutputConfig,
+    corners: [&str; 3],
+) -> io::Result<()> {
+    let horizontal = Glyphs::new(config).horizontal;
+    let [left, mid, right] = corners;
+    let borders: Vec<String> = config
+        .columns
+        .iter()
+        .map(|&column| horizontal.repeat(config.column_width(column) + 2))
+        .collect();
+    writeln!(out, "{}{}{}", left, borders.join(mid), right)
+}
 
-    let (left, mid, right) = if is_top {
-        ("┌", "┬", "┐")
-    } else {
-        ("└", "┴", "┘")
-    };
+/// Write the row of column titles and the line below it
+fn write_table_header(out: &mut impl Write, config: &OutputConfig) -> io::Result<()> {
+    let glyphs = Glyphs::new(config);
+    let v = glyphs.vertical;
+    let titles: Vec<String> = config
+        .columns
+        .iter()
+        .map(|&column| {
+            let width = config.column_width(column);
+            let title = truncate(column.title(), width);
+            if column.is_numeric() {
+                format!("{:>1$}", title, width)
+            } else {
+                pad(&title, width)
+            }
+        })
+        .collect();
+    writeln!(out, "{v} {} {v}", titles.join(&format!(" {v} ")))?;
+    write_table_border(out, config, glyphs.middle)
+}
+
+/// Print the changes between two analyses, largest absolute growth first
+pub fn print_diff(
+    old: &AnalysisResult,
+    new: &AnalysisResult,
+    deltas: &[DirectoryDelta],
+    top_n: usize,
+    config: &OutputConfig,
+) {
+    let colors = Colors::new(config);
+
+    println!(
+        "\nComparing: {} -> {}",
+        old.root_path.display(),
+        new.root_path.display()
+    );
+    println!();
+
+    if deltas.is_empty() {
+        println!("No changes");
+    }
+
+    for delta in deltas.iter().take(top_n) {
+        let color = if delta.delta() > 0 {
+            &colors.high
+        } else {
+            &colors.low
+        };
+        let marker = match delta.kind {
+            ChangeKind::Added => " [new]",
+            ChangeKind::Removed => " [removed]",
+            ChangeKind::Changed => "",
+        };
+        println!(
+            "{}{:>10}{}  {:>8} -> {:<8}  {}{}",
+            color,
+            format_size_delta(delta.delta()),
+            colors.reset,
+            format_size_auto(delta.old_size),
+            format_size_auto(delta.new_size),
+            delta.path.display(),
+            marker
+        );
+    }
+    if deltas.len() > top_n {
+        println!("... and {} more", deltas.len() - top_n);
+    }
 
+    let total_delta = new.total_size as i64 - old.total_size as i64;
+    println!(
+        "\nTotal: {} -> {} ({})",
+        format_size_auto(old.total_size),
+        format_size_auto(new.total_size),
+        format_size_delta(total_delta)
+    );
     println!(
-        "{}{}{}{}{}{}{}{}{}",
-        left, bar_border, mid, size_border, mid, percent_border, mid, name_border, right
+        "Files: {} -> {}  Directories: {} -> {}",
+        old.total_files, new.total_files, old.total_dirs, new.total_dirs
     );
 }
 
-/// Output results in JSON format
-pub fn print_json(result: &AnalysisResult) -> anyhow::Result<()> {
-    use serde::Serialize;
+/// Print the duplicate groups wasting the most space, with the total savings
+pub fn print_duplicates(
+    root: &Path,
+    groups: &[DuplicateGroup],
+    top_n: usize,
+    config: &OutputConfig,
+) {
+    let colors = Colors::new(config);
 
-    #[derive(Serialize)]
-    struct JsonOutput<'a> {
-        path: String,
-        total_size: u64,
-        total_size_human: String,
-        file_count: usize,
-        directory_count: usize,
-        top_directories: Vec<JsonDirectory<'a>>,
+    println!("\nDuplicates in: {}", root.display());
+    println!();
+
+    if groups.is_empty() {
+        println!("No duplicate files found");
+        return;
     }
 
-    #[derive(Serialize)]
-    struct JsonDirectory<'a> {
-        path: String,
-        size: u64,
-        size_human: String,
-        percentage: f64,
-        file_count: usize,
-        dir_count: usize,
-        #[serde(skip)]
-        _marker: std::marker::PhantomData<&'a ()>,
+    for group in groups.iter().take(top_n) {
+        println!(
+            "{}{} wasted{}  {} copies of {}",
+            colors.high,
+            format_size_auto(group.wasted()),
+            colors.reset,
+            group.paths.len(),
+            format_size_auto(group.size)
+        );
+        for path in &group.paths {
+            println!("    {}", path.display());
+        }
+    }
+    if groups.len() > top_n {
+        println!("... and {} more groups", groups.len() - top_n);
     }
 
-    let total = result.total_size as f64;
-    let output = JsonOutput {
-        path: result.root_path.display().to_string(),
-        total_size: result.total_size,
-        total_size_human: format_size_auto(result.total_size),
-        file_count: result.total_files,
-        directory_count: result.total_dirs,
-        top_directories: result
-            .top_directories
-            .iter()
-            .map(|d| JsonDirectory {
-                path: d.path.display().to_string(),
-                size: d.size,
-                size_human: format_size_auto(d.size),
-                percentage: if total > 0.0 {
-                    (d.size as f64 / total) * 100.0
-                } else {
-                    0.0
-                },
-                file_count: d.file_count,
-                dir_count: d.dir_count,
-                _marker: std::marker::PhantomData,
-            })
-            .collect(),
-    };
+    let wasted: u64 = groups.iter().map(DuplicateGroup::wasted).sum();
+    let files: usize = groups.iter().map(|g| g.paths.len()).sum();
+    println!(
+        "\nPotential savings: {} ({} files in {} groups)",
+        format_size_auto(wasted),
+        files,
+        groups.len()
+    );
+}
 
-    let json = serde_json::to_string_pretty(&output)?;
-    println!("{}", json);
+/// Print the hard-linked files saving the most space, with the totals
+pub fn print_hard_links(report: &HardLinkReport, top_n: usize, config: &OutputConfig) {
+    let colors = Colors::new(config);
 
-    Ok(())
-}
+    println!("\nHard links in: {}", report.root_path.display());
+    println!();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    if report.groups.is_empty() {
+        println!("No hard-linked files found");
+        return;
+    }
 
-    #[test]
-    fn test_select_color() {
-        let colors = Colors::enabled();
+    for group in report.groups.iter().take(top_n) {
+        println!(
+            "{}{} saved{}  {} of {} links to {}",
+            colors.low,
+            format_size_auto(group.saved()),
+            colors.reset,
+            group.paths.len(),
+            group.link_count,
+            format_size_auto(group.size)
+        );
+        for path in &group.paths {
+            println!("    {}", path.display());
+        }
+    }
+    if report.groups.len() > top_n {
+        println!("... and {} more groups", report.groups.len() - top_n);
+    }
 
-        // Low usage - green
-        assert_eq!(select_color(5, 30, &colors), colors.green);
+    println!(
+        "\nSaved by counting each file once: {} ({} extra links in {} groups)",
+        format_size_auto(report.hard_link_savings),
+        report.hard_links,
+        report.groups.len()
+    );
+    println!("Total: {}", format_size_auto(report.total_size));
+}
 
-        // Medium usage - yellow
-        assert_eq!(select_color(12, 30, &colors), colors.yellow);
+/// Print reclaimable space per category and the largest candidate directories
+pub fn print_clean(report: &CleanReport, top_n: usize, config: &OutputConfig) {
+    let colors = Colors::new(config);
 
-        // High usage - red
-        assert_eq!(select_color(20, 30, &colors), colors.red);
+    println!("\nReclaimable space in: {}", report.root_path.display());
+    println!();
+
+    if report.candidates.is_empty() {
+        println!("No build output, dependency or cache directories found");
+        return;
     }
 
-    #[test]
+    println!("{:<24} {:>10} {:>6}", "CATEGORY", "SIZE", "DIRS");
+    for (category, size, count) in report.by_category() {
+        println!(
+            "{:<24} {:>10} {:>6}",
+            category.label(),
+            format_size_auto(size),
+            count
+        );
+    }
+
+    println!("\nLargest candidates:");
+    for candidate in report.candidates.iter().take(top_n) {
+        println!(
+            "{}{:>10}{}  {:<16} {}",
+            colors.low,
+            format_size_auto(candidate.size),
+            colors.reset,
+            candidate.kind,
+            candidate.path.display()
+        );
+    }
+    if report.candidates.len() > top_n {
+        println!("... and {} more", report.candidates.len() - top_n);
+    }
+
+    println!(
+        "\nReclaimable: {} of {} ({})",
+        format_size_auto(report.reclaimable()),
+        format_size_auto(report.total_size),
+        format_percentage(report.reclaimable(), report.total_size).trim_start()
+    );
+}
+
+/// Write a script deleting every candidate of `report`, largest first
+///
+/// Each command is preceded by a comment with the size and kind of the
+/// directory so the script can be reviewed and edited before it is run.
+/// Paths that are not valid UTF-8 or hold control characters, such as line
+/// breaks, are left out with a comment showing them escaped.
+pub fn write_clean_script(
+    out: &mut impl Write,
+    report: &CleanReport,
+    format: ScriptFormat,
+) -> io::Result<()> {
+    if format == ScriptFormat::Sh {
+        writeln!(out, "#!/bin/sh")?;
+    }
+    writeln!(
+        out,
+        "# Reclaimable directories in {:?}, generated by dutop",
+        report.root_path
+    )?;
+    writeln!(
+        out,
+        "# Total: {} in {} directories. Review before running: every command deletes a whole tree.",
+        format_size_auto(report.reclaimable()),
+        report.candidates.len()
+    )?;
+    match format {
+        ScriptFormat::Sh => writeln!(out, "set -eu")?,
+        ScriptFormat::PowerShell => writeln!(out, "$ErrorActionPreference = 'Stop'")?,
+    }
+
+    for candidate in &report.candidates {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "# {}  {}",
+            format_size_auto(candidate.size),
+            candidate.kind
+        )?;
+        let Some(path) = candidate.path.to_str() else {
+            writeln!(
+                out,
+                "# skipped, path is not valid UTF-8: {:?}",
+                candidate.path
+            )?;
+            continue;
+        };
+        if path.chars().any(char::is_control) {
+            writeln!(out, "# skipped, path holds control characters: {:?}", path)?;
+            continue;
+        }
+        match format {
+            ScriptFormat::Sh => writeln!(out, "rm -rf -- {}", shell_quote(path))?,
+            ScriptFormat::PowerShell => writeln!(
+                out,
+                "Remove-Item -LiteralPath '{}' -Recurse -Force",
+                powershell_quote(path)
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// Quote `word` for a POSIX shell
+pub(crate) fn shell_quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:@,+".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Escape `word` for a single-quoted PowerShell string, which the curly single
+/// quotes end as well as `'`
+fn powershell_quote(word: &str) -> String {
+    let mut quoted = String::with_capacity(word.len());
+    for c in word.chars() {
+        if matches!(c, '\'' | '\u{2018}'..='\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+/// Print the recorded sizes of `path` with their changes, a sparkline and the growth rate
+pub fn print_history(path: &Path, points: &[HistoryPoint], config: &OutputConfig) {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        println!(
+            "No recorded scans of {} (run `dutop history record` first)",
+            path.display()
+        );
+        return;
+    };
+
+    println!(
+        "\nHistory of {}: {} scans from {} to {}",
+        path.display(),
+        points.len(),
+        format_timestamp(first.timestamp),
+        format_timestamp(last.timestamp)
+    );
+    println!();
+    println!(
+        "{:<16} {:>10} {:>10} {:>10}",
+        "DATE (UTC)", "SIZE", "CHANGE", "FILES"
+    );
+    let mut previous: Option<u64> = None;
+    for point in points {
+        let change = previous
+            .map(|size| format_size_delta(point.size as i64 - size as i64))
+            .unwrap_or_default();
+        println!(
+            "{:<16} {:>10} {:>10} {:>10}",
+            format_timestamp(point.timestamp),
+            format_size_auto(point.size),
+            change,
+            point.file_count
+        );
+        previous = Some(point.size);
+    }
+
+    let sizes: Vec<u64> = points.iter().map(|p| p.size).collect();
+    let trend = sparkline_with(&sizes, &Glyphs::new(config).sparks);
+    println!("\nTrend:  {}", trend);
+    if let Some(rate) = growth_per_day(points) {
+        println!(
+            "Growth: {}/day ({} per 30 days)",
+            format_size_delta(rate as i64),
+            format_size_delta((rate * 30.0) as i64)
+        );
+    }
+}
+
+/// Print empty and tiny directories, one per line so the list can be fed to cleanup scripts
+pub fn print_empty(report: &EmptyReport) {
+    println!("\nEmpty directories in: {}", report.root_path.display());
+    println!();
+    for path in &report.empty {
+        println!("{}", path.display());
+    }
+
+    if let Some(threshold) = report.tiny_threshold {
+        println!("\nDirectories under {}:", format_size_auto(threshold));
+        println!();
+        for entry in &report.tiny {
+            println!(
+                "{:>10}  {}",
+                format_size_auto(entry.size),
+                entry.path.display()
+            );
+        }
+    }
+
+    println!(
+        "\nEmpty directories: {} ({} including nested ones)",
+        report.empty.len(),
+        report.empty_count
+    );
+    if let Some(threshold) = report.tiny_threshold {
+        println!(
+            "Directories under {}: {} ({} in total)",
+            format_size_auto(threshold),
+            report.tiny.len(),
+            format_size_auto(report.tiny_size())
+        );
+    }
+}
+
+/// Print the tree depth, the deepest and longest paths and the widest directories
+pub fn print_depth(report: &crate::depth::DepthReport) {
+    println!("\nDepth and path lengths: {}", report.root_path.display());
+    println!();
+    println!(
+        "{} files and {} directories, maximum depth {}, average file depth {:.1}",
+        report.file_count, report.dir_count, report.max_depth, report.average_depth
+    );
+
+    for (title, stats) in [
+        ("Deepest paths", &report.deepest),
+        ("Longest paths", &report.longest),
+    ] {
+        if stats.is_empty() {
+            continue;
+        }
+        println!("\n{}:", title);
+        println!();
+        println!("{:>6} {:>7}  PATH", "DEPTH", "LENGTH");
+        for stat in stats {
+            println!(
+                "{:>6} {:>7}  {}",
+                stat.depth,
+                stat.length,
+                stat.path.display()
+            );
+        }
+    }
+
+    println!(
+        "\nDirectories with at least {} entries:",
+        report.fan_out_threshold
+    );
+    println!();
+    if report.fan_out.is_empty() {
+        println!("None (at most {} in one directory)", report.max_fan_out);
+    }
+    for (path, count) in &report.fan_out {
+        println!("{:>10}  {}", count, path.display());
+    }
+}
+
+/// Write the depth statistics and their top offenders as a JSON document
+pub fn write_depth_json(
+    out: &mut impl Write,
+    report: &crate::depth::DepthReport,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonDepth {
+        path: String,
+        file_count: u64,
+        dir_count: u64,
+        max_depth: usize,
+        average_depth: f64,
+        max_fan_out: u64,
+        fan_out_threshold: u64,
+        deepest: Vec<JsonPath>,
+        longest: Vec<JsonPath>,
+        fan_out: Vec<JsonFanOut>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonPath {
+        path: String,
+        depth: usize,
+        length: usize,
+    }
+
+    #[derive(Serialize)]
+    struct JsonFanOut {
+        path: String,
+        entries: u64,
+    }
+
+    let paths = |stats: &[crate::depth::PathStat]| {
+        stats
+            .iter()
+            .map(|stat| JsonPath {
+                path: stat.path.display().to_string(),
+                depth: stat.depth,
+                length: stat.length,
+            })
+            .collect()
+    };
+    let output = JsonDepth {
+        path: report.root_path.display().to_string(),
+        file_count: report.file_count,
+        dir_count: report.dir_count,
+        max_depth: report.max_depth,
+        average_depth: report.average_depth,
+        max_fan_out: report.max_fan_out,
+        fan_out_threshold: report.fan_out_threshold,
+        deepest: paths(&report.deepest),
+        longest: paths(&report.longest),
+        fan_out: report
+            .fan_out
+            .iter()
+            .map(|(path, entries)| JsonFanOut {
+                path: path.display().to_string(),
+                entries: *entries,
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &output)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Print the files flagged by an audit, largest first
+#[cfg(unix)]
+pub fn print_audit(report: &crate::audit::AuditReport, top_n: usize, config: &OutputConfig) {
+    use crate::audit::Issue;
+
+    let colors = Colors::new(config);
+
+    println!("\nSecurity audit: {}", report.result.root_path.display());
+    println!();
+    if report.findings.is_empty() {
+        println!("Nothing flagged");
+        return;
+    }
+    println!(
+        "{:>10} {:>6} {:<12} {:<32} PATH",
+        "SIZE", "MODE", "OWNER", "ISSUES"
+    );
+    for finding in report.findings.iter().take(top_n) {
+        let issues: Vec<&str> = finding.issues.iter().map(|issue| issue.as_str()).collect();
+        println!(
+            "{:>10} {:>6} {} {}{:<32}{} {}",
+            format_size_auto(finding.size),
+            format!("{:04o}", finding.mode),
+            pad(&finding.owner(), 12),
+            colors.high,
+            issues.join(","),
+            colors.reset,
+            finding.path.display()
+        );
+    }
+    if report.findings.len() > top_n {
+        println!("... and {} more", report.findings.len() - top_n);
+    }
+    println!();
+    println!(
+        "Flagged: {} world-writable and {} with unexpected owners from {} up, {} setuid, {} setgid",
+        report.count(Issue::WorldWritable),
+        report.count(Issue::UnexpectedOwner),
+        format_size_auto(report.min_size),
+        report.count(Issue::Setuid),
+        report.count(Issue::Setgid)
+    );
+}
+
+/// Write the usage summary and the files flagged by an audit as a JSON document
+#[cfg(unix)]
+pub fn write_audit_json(
+    out: &mut impl Write,
+    report: &crate::audit::AuditReport,
+    top_n: usize,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonAudit {
+        path: String,
+        total_size: u64,
+        total_files: usize,
+        total_dirs: usize,
+        min_size: u64,
+        expected_owners: Vec<u32>,
+        flagged_files: usize,
+        entries: Vec<JsonEntry>,
+        findings: Vec<JsonFinding>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonEntry {
+        path: String,
+        size: u64,
+    }
+
+    #[derive(Serialize)]
+    struct JsonFinding {
+        path: String,
+        size: u64,
+        mode: String,
+        uid: u32,
+        owner: String,
+        issues: Vec<&'static str>,
+    }
+
+    let result = &report.result;
+    let output = JsonAudit {
+        path: result.root_path.display().to_string(),
+        total_size: result.total_size,
+        total_files: result.total_files,
+        total_dirs: result.total_dirs,
+        min_size: report.min_size,
+        expected_owners: report.owners.clone(),
+        flagged_files: report.findings.len(),
+        entries: result
+            .top_directories
+            .iter()
+            .take(top_n)
+            .map(|entry| JsonEntry {
+                path: entry.path.display().to_string(),
+                size: entry.size,
+            })
+            .collect(),
+        findings: report
+            .findings
+            .iter()
+            .take(top_n)
+            .map(|finding| JsonFinding {
+                path: finding.path.display().to_string(),
+                size: finding.size,
+                mode: format!("{:04o}", finding.mode),
+                uid: finding.uid,
+                owner: finding.owner(),
+                issues: finding.issues.iter().map(|issue| issue.as_str()).collect(),
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &output)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Print usage per user and per group
+#[cfg(unix)]
+pub fn print_owners(report: &crate::owners::OwnerReport, top_n: usize) {
+    println!("\nUsage by owner: {}", report.root_path.display());
+
+    for (title, usages) in [("USER", &report.users), ("GROUP", &report.groups)] {
+        println!();
+        println!("{:<20} {:>10} {:>5} {:>10}", title, "SIZE", "%", "FILES");
+        for usage in usages.iter().take(top_n) {
+            println!(
+                "{} {:>10} {:>5} {:>10}",
+                pad(&usage.display_name(), 20),
+                format_size_auto(usage.size),
+                format_percentage(usage.size, report.total_size),
+                usage.file_count
+            );
+        }
+        if usages.len() > top_n {
+            println!("... and {} more", usages.len() - top_n);
+        }
+    }
+
+    println!("\nTotal: {}", format_size_auto(report.total_size));
+}
+
+/// Print usage split by file age, one column per age bucket
+pub fn print_age(report: &crate::age::AgeReport, top_n: usize) {
+    let root = &report.total.path;
+    println!("\nUsage by age: {}", root.display());
+    println!();
+
+    let row = |name: &str, cells: Vec<String>| {
+        print!("{}", pad(name, 30));
+        for cell in cells {
+            print!(" {:>10}", cell);
+        }
+        println!();
+    };
+    let sizes = |usage: &crate::age::AgeUsage| {
+        usage
+            .buckets
+            .iter()
+            .chain([&usage.size])
+            .map(|&size| format_size_auto(size))
+            .collect()
+    };
+
+    let mut header = report.labels();
+    header.push("TOTAL".to_string());
+    row("", header);
+    for entry in report.entries.iter().take(top_n) {
+        let name = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+        row(&name.display().to_string(), sizes(entry));
+    }
+    if report.entries.len() > top_n {
+        println!("... and {} more", report.entries.len() - top_n);
+    }
+    row("Total", sizes(&report.total));
+    let shares = report
+        .total
+        .buckets
+        .iter()
+        .chain([&report.total.size])
+        .map(|&size| {
+            format_percentage(size, report.total.size)
+                .trim()
+                .to_string()
+        })
+        .collect();
+    row("", shares);
+
+    if let (Some(label), Some(&cold)) = (report.labels().last(), report.total.buckets.last()) {
+        println!(
+            "\nOlder than {}: {} of {} ({})",
+            label.trim_start_matches('>'),
+            format_size_auto(cold),
+            format_size_auto(report.total.size),
+            format_percentage(cold, report.total.size).trim()
+        );
+    }
+}
+
+/// Print the file size distribution: the files and bytes of each bucket overall
+/// with a bar for the bytes, then per top directory
+pub fn print_histogram(
+    report: &crate::histogram::HistogramReport,
+    top_n: usize,
+    config: &OutputConfig,
+) {
+    use crate::histogram::Histogram;
+
+    let colors = Colors::new(config);
+    let labels = crate::histogram::bucket_labels();
+    let total = &report.total;
+    let (file_count, size) = (total.file_count(), total.size());
+    let largest = total.bytes.iter().copied().max().unwrap_or(0);
+
+    println!("\nFile sizes: {}", report.root_path.display());
+    println!();
+    println!(
+        "{:<10} {:>10} {:>5} {:>10} {:>5}",
+        "SIZE", "FILES", "%", "BYTES", "%"
+    );
+    for ((label, &count), &bytes) in labels.iter().zip(&total.counts).zip(&total.bytes) {
+        let bar_length = if largest > 0 {
+            (bytes as f64 / largest as f64 * config.bar_width as f64) as usize
+        } else {
+            0
+        };
+        println!(
+            "{:<10} {:>10} {:>5} {:>10} {:>5} {}{}{}",
+            label,
+            count,
+            format_percentage(count as u64, file_count as u64),
+            format_size_auto(bytes),
+            format_percentage(bytes, size),
+            select_color(bar_length, config.bar_width, &colors),
+            Glyphs::new(config).filled.repeat(bar_length),
+            colors.reset
+        );
+    }
+    println!(
+        "{:<10} {:>10} {:>5} {:>10}",
+        "Total",
+        file_count,
+        "",
+        format_size_auto(size)
+    );
+
+    let entries = &report.entries[..report.entries.len().min(top_n)];
+    if entries.is_empty() {
+        return;
+    }
+    let name = |path: &Path| {
+        let name = path.strip_prefix(&report.root_path).unwrap_or(path);
+        fit(&name.display().to_string(), config.name_width)
+    };
+    let counts = |h: &Histogram| h.counts.iter().map(|count| count.to_string()).collect();
+    let bytes = |h: &Histogram| {
+        h.bytes
+            .iter()
+            .map(|&bytes| format_size_auto(bytes))
+            .collect()
+    };
+    for (title, cells) in [
+        ("FILES", counts as fn(&Histogram) -> Vec<String>),
+        ("BYTES", bytes),
+    ] {
+        println!();
+        print!("{:<width$}", title, width = config.name_width);
+        for label in &labels {
+            print!(" {:>9}", label);
+        }
+        println!();
+        for (path, histogram) in entries {
+            print!("{}", name(path));
+            for cell in cells(histogram) {
+                print!(" {:>9}", cell);
+            }
+            println!();
+        }
+    }
+    if report.entries.len() > top_n {
+        println!("... and {} more", report.entries.len() - top_n);
+    }
+}
+
+/// Print estimated usage per top directory with the margin of each figure
+pub fn print_estimate(
+    report: &crate::estimate::EstimateReport,
+    top_n: usize,
+    config: &OutputConfig,
+) {
+    use crate::estimate::Estimate;
+
+    let colors = Colors::new(config);
+    let size = |estimate: &Estimate| format_size_auto(estimate.value.round() as u64);
+    let margin =
+        |estimate: &Estimate| format!("±{}", format_size_auto(estimate.margin.round() as u64));
+    let count = |estimate: &Estimate| format!("{:.0}", estimate.value);
+    let total = report.size.value.round() as u64;
+
+    println!(
+        "\nEstimated usage: {} ({} directories sampled in {:.1}s)",
+        report.root_path.display(),
+        report.sampled_dirs,
+        report.elapsed.as_secs_f64()
+    );
+    println!();
+    println!(
+        "{:>10} {:>10} {:>5} {:>10} {:>8}  PATH",
+        "SIZE", "±95%", "%", "FILES", "DIRS"
+    );
+    for entry in report.entries.iter().take(top_n) {
+        let share = entry.size.value.round() as u64;
+        let bar_length = if total > 0 {
+            (share as f64 / total as f64 * config.bar_width as f64) as usize
+        } else {
+            0
+        };
+        let name = entry
+            .path
+            .strip_prefix(&report.root_path)
+            .unwrap_or(&entry.path);
+        println!(
+            "{:>10} {:>10} {:>5} {:>10} {:>8}  {}{}{} {}",
+            size(&entry.size),
+            margin(&entry.size),
+            format_percentage(share, total),
+            count(&entry.file_count),
+            count(&entry.dir_count),
+            select_color(bar_length, config.bar_width, &colors),
+            Glyphs::new(config).filled.repeat(bar_length),
+            colors.reset,
+            name.display()
+        );
+    }
+    if report.entries.len() > top_n {
+        println!("... and {} more", report.entries.len() - top_n);
+    }
+    println!();
+    println!(
+        "Total: {} {} ({} to {}), {} files, {} directories",
+        size(&report.size),
+        margin(&report.size),
+        format_size_auto(report.size.low().round() as u64),
+        format_size_auto(report.size.high().round() as u64),
+        count(&report.file_count),
+        count(&report.dir_count)
+    );
+}
+
+/// Print the directories with the most direct entries and the average size of their files
+pub fn print_counts(report: &crate::counts::CountsReport, top_n: usize, config: &OutputConfig) {
+    let colors = Colors::new(config);
+    let total = report.total_files + report.total_dirs;
+
+    println!("\nEntries per directory: {}", report.root_path.display());
+    println!();
+    println!(
+        "{:>10} {:>5} {:>10} {:>8} {:>10} {:>10}  PATH",
+        "ENTRIES", "%", "FILES", "DIRS", "SIZE", "AVG FILE"
+    );
+    for (path, count) in report.entries.iter().take(top_n) {
+        let bar_length = if total > 0 {
+            (count.entries() as f64 / total as f64 * config.bar_width as f64) as usize
+        } else {
+            0
+        };
+        let name = match path.strip_prefix(&report.root_path) {
+            Ok(name) if name.as_os_str().is_empty() => Path::new("."),
+            Ok(name) => name,
+            Err(_) => path,
+        };
+        println!(
+            "{:>10} {:>5} {:>10} {:>8} {:>10} {:>10}  {}{}{} {}",
+            count.entries(),
+            format_percentage(count.entries(), total),
+            count.files,
+            count.dirs,
+            format_size_auto(count.size),
+            format_size_auto(count.average_file_size()),
+            select_color(bar_length, config.bar_width, &colors),
+            Glyphs::new(config).filled.repeat(bar_length),
+            colors.reset,
+            name.display()
+        );
+    }
+    if report.entries.len() > top_n {
+        println!("... and {} more", report.entries.len() - top_n);
+    }
+    println!();
+    println!(
+        "Total: {} files and {} directories",
+        report.total_files, report.total_dirs
+    );
+}
+
+/// Write the directories with the most direct entries as a JSON document, on
+/// a single line when `compact`
+pub fn write_counts_json(
+    out: &mut impl Write,
+    report: &crate::counts::CountsReport,
+    top_n: usize,
+    compact: bool,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonCounts {
+        path: String,
+        file_count: u64,
+        directory_count: u64,
+        directories: Vec<JsonDirCount>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonDirCount {
+        path: String,
+        entries: u64,
+        file_count: u64,
+        dir_count: u64,
+        size: u64,
+        size_human: String,
+        average_file_size: u64,
+    }
+
+    let output = JsonCounts {
+        path: report.root_path.display().to_string(),
+        file_count: report.total_files,
+        directory_count: report.total_dirs,
+        directories: report
+            .entries
+            .iter()
+            .take(top_n)
+            .map(|(path, count)| JsonDirCount {
+                path: path.display().to_string(),
+                entries: count.entries(),
+                file_count: count.files,
+                dir_count: count.dirs,
+                size: count.size,
+                size_human: format_size_auto(count.size),
+                average_file_size: count.average_file_size(),
+            })
+            .collect(),
+    };
+    if compact {
+        serde_json::to_writer(&mut *out, &output)?;
+    } else {
+        serde_json::to_writer_pretty(&mut *out, &output)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Print usage per category, then per extension with a bar for each
+pub fn print_types(report: &crate::types::TypeReport, top_n: usize, config: &OutputConfig) {
+    let colors = Colors::new(config);
+    let row = |name: &str, category: &str, usage: &crate::types::TypeUsage, largest: u64| {
+        let bar_length = if largest > 0 {
+            (usage.size as f64 / largest as f64 * config.bar_width as f64) as usize
+        } else {
+            0
+        };
+        println!(
+            "{} {:<10} {:>10} {:>5} {:>10} {}{}{}",
+            pad(name, 12),
+            category,
+            format_size_auto(usage.size),
+            format_percentage(usage.size, report.total_size),
+            usage.file_count,
+            select_color(bar_length, config.bar_width, &colors),
+            Glyphs::new(config).filled.repeat(bar_length),
+            colors.reset
+        );
+    };
+
+    println!("\nUsage by file type: {}", report.root_path.display());
+    println!();
+    println!(
+        "{:<23} {:>10} {:>5} {:>10}",
+        "CATEGORY", "SIZE", "%", "FILES"
+    );
+    let largest = report.categories.first().map_or(0, |usage| usage.size);
+    for usage in &report.categories {
+        row(usage.category.as_str(), "", usage, largest);
+    }
+
+    println!();
+    println!(
+        "{:<12} {:<10} {:>10} {:>5} {:>10}",
+        "EXTENSION", "CATEGORY", "SIZE", "%", "FILES"
+    );
+    let largest = report.extensions.first().map_or(0, |usage| usage.size);
+    for usage in report.extensions.iter().take(top_n) {
+        let mut name = usage
+            .name
+            .as_ref()
+            .map_or_else(|| "(none)".to_string(), |name| format!(".{}", name));
+        if usage.detected {
+            name.push('*');
+        }
+        row(&name, usage.category.as_str(), usage, largest);
+    }
+    if report.extensions.len() > top_n {
+        println!("... and {} more", report.extensions.len() - top_n);
+    }
+    if report.extensions.iter().any(|usage| usage.detected) {
+        println!(
+            "* detected from file contents{}",
+            if report.estimated {
+                " (estimated from a sample of the files)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    println!(
+        "\nTotal: {} in {} files",
+        format_size_auto(report.total_size),
+        report.total_files
+    );
+}
+
+/// Print the git repositories found and how their usage splits up
+pub fn print_git_repos(report: &crate::git::GitReport, top_n: usize, config: &OutputConfig) {
+    println!("\nGit repositories: {}", report.root_path.display());
+    if report.repos.is_empty() {
+        println!("\nNo git repositories found");
+        return;
+    }
+    println!();
+    println!(
+        "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+        "REPOSITORY",
+        "SIZE",
+        "GIT",
+        "LFS",
+        "ARTIFACTS",
+        "WORKTREE",
+        "LOOSE",
+        width = config.name_width
+    );
+    for repo in report.repos.iter().take(top_n) {
+        let name = repo
+            .path
+            .strip_prefix(&report.root_path)
+            .unwrap_or(&repo.path);
+        let name = match name.display().to_string() {
+            name if name.is_empty() => ".".to_string(),
+            name => name,
+        };
+        println!(
+            "{} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+            fit(&name, config.name_width),
+            format_size_auto(repo.size()),
+            format_size_auto(repo.git),
+            format_size_auto(repo.lfs),
+            format_size_auto(repo.artifacts),
+            format_size_auto(repo.working_tree),
+            repo.loose_objects
+        );
+    }
+    if report.repos.len() > top_n {
+        println!("... and {} more", report.repos.len() - top_n);
+    }
+
+    let sum =
+        |field: fn(&crate::git::RepoUsage) -> u64| report.repos.iter().map(field).sum::<u64>();
+    println!(
+        "\nTotal: {} in {} repositories ({} git data, {} artifacts)",
+        format_size_auto(sum(crate::git::RepoUsage::size)),
+        report.repos.len(),
+        format_size_auto(sum(|repo| repo.git)),
+        format_size_auto(sum(|repo| repo.artifacts))
+    );
+    println!(
+        "Repositories with many loose objects shrink with `git gc`, LFS data with `git lfs prune`"
+    );
+}
+
+/// Write the git repositories and how their usage splits up as a JSON
+/// document, on a single line when `compact`
+pub fn write_git_repos_json(
+    out: &mut impl Write,
+    report: &crate::git::GitReport,
+    top_n: usize,
+    compact: bool,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonRepos {
+        path: String,
+        total_size: u64,
+        repositories: Vec<JsonRepo>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonRepo {
+        path: String,
+        size: u64,
+        size_human: String,
+        git_size: u64,
+        lfs_size: u64,
+        artifacts_size: u64,
+        working_tree_size: u64,
+        loose_objects: usize,
+    }
+
+    let output = JsonRepos {
+        path: report.root_path.display().to_string(),
+        total_size: report.total_size,
+        repositories: report
+            .repos
+            .iter()
+            .take(top_n)
+            .map(|repo| JsonRepo {
+                path: repo.path.display().to_string(),
+                size: repo.size(),
+                size_human: format_size_auto(repo.size()),
+                git_size: repo.git,
+                lfs_size: repo.lfs,
+                artifacts_size: repo.artifacts,
+                working_tree_size: repo.working_tree,
+                loose_objects: repo.loose_objects,
+            })
+            .collect(),
+    };
+    if compact {
+        serde_json::to_writer(&mut *out, &output)?;
+    } else {
+        serde_json::to_writer_pretty(&mut *out, &output)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Print the projects found and how their usage splits up
+pub fn print_projects(
+    report: &crate::projects::ProjectReport,
+    top_n: usize,
+    config: &OutputConfig,
+) {
+    let colors = Colors::new(config);
+    let largest = report.projects.first().map_or(0, |project| project.size);
+
+    println!("\nProjects: {}", report.root_path.display());
+    if report.projects.is_empty() {
+        println!("\nNo projects found");
+        return;
+    }
+    println!();
+    println!(
+        "{:<24} {:<12} {:>10} {:>10} {:>10} {:>10}",
+        "PROJECT", "KIND", "SIZE", "SOURCES", "ARTIFACTS", "GIT"
+    );
+    for project in report.projects.iter().take(top_n) {
+        let bar_length = if largest > 0 {
+            (project.size as f64 / largest as f64 * config.bar_width as f64) as usize
+        } else {
+            0
+        };
+        println!(
+            "{} {:<12} {:>10} {:>10} {:>10} {:>10} {}{}{}",
+            fit(&project.name, 24),
+            project.kinds.join(","),
+            format_size_auto(project.size),
+            format_size_auto(project.sources()),
+            format_size_auto(project.artifacts),
+            format_size_auto(project.git),
+            select_color(bar_length, config.bar_width, &colors),
+            Glyphs::new(config).filled.repeat(bar_length),
+            colors.reset
+        );
+        let path = project
+            .path
+            .strip_prefix(&report.root_path)
+            .unwrap_or(&project.path);
+        if !path.as_os_str().is_empty() && path != Path::new(&project.name) {
+            println!("  {}", path.display());
+        }
+    }
+    if report.projects.len() > top_n {
+        println!("... and {} more", report.projects.len() - top_n);
+    }
+
+    let artifacts: u64 = report
+        .projects
+        .iter()
+        .map(|project| project.artifacts)
+        .sum();
+    println!(
+        "\nTotal: {} in {} projects ({} artifacts), {} outside of projects",
+        format_size_auto(report.total_size - report.unassigned()),
+        report.projects.len(),
+        format_size_auto(artifacts),
+        format_size_auto(report.unassigned())
+    );
+}
+
+/// Write the projects and their usage as a JSON document
+pub fn write_projects_json(
+    out: &mut impl Write,
+    report: &crate::projects::ProjectReport,
+    top_n: usize,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonProjects {
+        path: String,
+        total_size: u64,
+        unassigned_size: u64,
+        projects: Vec<JsonProject>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonProject {
+        name: String,
+        path: String,
+        kinds: Vec<&'static str>,
+        size: u64,
+        size_human: String,
+        file_count: usize,
+        sources_size: u64,
+        artifacts_size: u64,
+        git_size: u64,
+    }
+
+    let output = JsonProjects {
+        path: report.root_path.display().to_string(),
+        total_size: report.total_size,
+        unassigned_size: report.unassigned(),
+        projects: report
+            .projects
+            .iter()
+            .take(top_n)
+            .map(|project| JsonProject {
+                name: project.name.clone(),
+                path: project.path.display().to_string(),
+                kinds: project.kinds.clone(),
+                size: project.size,
+                size_human: format_size_auto(project.size),
+                file_count: project.file_count,
+                sources_size: project.sources(),
+                artifacts_size: project.artifacts,
+                git_size: project.git,
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &output)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write the usage per category and per extension as a JSON document
+pub fn write_types_json(
+    out: &mut impl Write,
+    report: &crate::types::TypeReport,
+    top_n: usize,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonTypes {
+        path: String,
+        total_size: u64,
+        total_size_human: String,
+        file_count: usize,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        estimated: bool,
+        categories: Vec<JsonType>,
+        extensions: Vec<JsonType>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonType {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        extension: Option<Option<String>>,
+        category: &'static str,
+        size: u64,
+        size_human: String,
+        percentage: f64,
+        file_count: usize,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        detected: bool,
+    }
+
+    let json_type = |usage: &crate::types::TypeUsage, extension| JsonType {
+        extension,
+        category: usage.category.as_str(),
+        size: usage.size,
+        size_human: format_size_auto(usage.size),
+        percentage: if report.total_size > 0 {
+            usage.size as f64 / report.total_size as f64 * 100.0
+        } else {
+            0.0
+        },
+        file_count: usage.file_count,
+        detected: usage.detected,
+    };
+    let output = JsonTypes {
+        path: report.root_path.display().to_string(),
+        total_size: report.total_size,
+        total_size_human: format_size_auto(report.total_size),
+        file_count: report.total_files,
+        estimated: report.estimated,
+        categories: report
+            .categories
+            .iter()
+            .map(|usage| json_type(usage, None))
+            .collect(),
+        extensions: report
+            .extensions
+            .iter()
+            .take(top_n)
+            .map(|usage| json_type(usage, Some(usage.name.clone())))
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &output)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Print mounted filesystems like `df`
+///
+/// `scanned` holds the analyzed size of each mount, when the mounts were analyzed.
+pub fn print_mounts(mounts: &[Mount], scanned: Option<&[Option<u64>]>) {
+    let size = |value: Option<u64>| value.map_or_else(|| "-".to_string(), format_size_auto);
+
+    print!(
+        "{:<24} {:<8} {:>10} {:>10} {:>10} {:>5}",
+        "FILESYSTEM", "TYPE", "SIZE", "USED", "AVAIL", "USE%"
+    );
+    if scanned.is_some() {
+        print!(" {:>10}", "SCANNED");
+    }
+    println!("  MOUNTED ON");
+
+    for (i, mount) in mounts.iter().enumerate() {
+        let usage = mount.usage;
+        print!(
+            "{} {:<8} {:>10} {:>10} {:>10} {:>5}",
+            pad(&mount.source, 24),
+            mount.fs_type,
+            size(usage.map(|u| u.total)),
+            size(usage.map(|u| u.used())),
+            size(usage.map(|u| u.available)),
+            usage.map_or_else(|| "-".to_string(), |u| format_percentage(u.used(), u.total)),
+        );
+        if let Some(scanned) = scanned {
+            print!(" {:>10}", size(scanned.get(i).copied().flatten()));
+        }
+        println!("  {}", mount.mount_point.display());
+    }
+}
+
+/// Print the disk usage of Docker images, containers, volumes and build cache
+pub fn print_docker(usage: &DockerUsage, top_n: usize) {
+    fn more(shown: usize, total: usize) {
+        if total > shown {
+            println!("... and {} more", total - shown);
+        }
+    }
+
+    println!("\nDocker disk usage");
+
+    println!();
+    println!(
+        "{:<40} {:>10} {:>10} {:>10}",
+        "IMAGE", "SIZE", "UNIQUE", "CONTAINERS"
+    );
+    for image in usage.images.iter().take(top_n) {
+        println!(
+            "{:<40} {:>10} {:>10} {:>10}",
+            image.name(),
+            format_size_auto(image.size),
+            format_size_auto(image.unique_size()),
+            image.containers
+        );
+    }
+    more(top_n, usage.images.len());
+
+    println!();
+    println!(
+        "{:<24} {:<24} {:>10} {:>10}  STATE",
+        "CONTAINER", "IMAGE", "WRITABLE", "VIRTUAL"
+    );
+    for container in usage.containers.iter().take(top_n) {
+        println!(
+            "{:<24} {:<24} {:>10} {:>10}  {}",
+            container.name,
+            container.image,
+            format_size_auto(container.size_rw),
+            format_size_auto(container.size_root_fs),
+            container.state
+        );
+    }
+    more(top_n, usage.containers.len());
+
+    println!();
+    println!(
+        "{:<40} {:>10} {:>10}  MOUNTPOINT",
+        "VOLUME", "SIZE", "LINKS"
+    );
+    for volume in usage.volumes.iter().take(top_n) {
+        println!(
+            "{:<40} {:>10} {:>10}  {}",
+            volume.name,
+            volume
+                .size
+                .map_or_else(|| "-".to_string(), format_size_auto),
+            volume.ref_count,
+            volume.mountpoint.display()
+        );
+    }
+    more(top_n, usage.volumes.len());
+
+    let containers: u64 = usage.containers.iter().map(|c| c.size_rw).sum();
+    let volumes: u64 = usage.volumes.iter().filter_map(|v| v.size).sum();
+    println!(
+        "\nImages: {}  Containers: {}  Volumes: {}  Build cache: {}",
+        format_size_auto(usage.layers_size),
+        format_size_auto(containers),
+        format_size_auto(volumes),
+        format_size_auto(usage.build_cache_size)
+    );
+    println!("Total: {}", format_size_auto(usage.total_size()));
+}
+
+/// Version of the JSON document written by [`print_json`]
+///
+/// The minor version grows when fields are added, the major version when
+/// fields are removed or change meaning. See `docs/json-schema.md`.
+pub const JSON_SCHEMA_VERSION: &str = "1.0";
+
+/// Details about the run echoed in JSON output next to the results
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonContext<'a> {
+    /// Options the analysis ran with, when it ran locally
+    pub config: Option<&'a AnalysisConfig>,
+    /// When the analysis started (or when the snapshot was taken)
+    pub started_at: Option<SystemTime>,
+    /// Write the document on a single line instead of indenting it
+    pub compact: bool,
+}
+
+/// Output results in JSON format
+pub fn print_json(result: &AnalysisResult, context: &JsonContext) -> anyhow::Result<()> {
+    let mut stdout = io::stdout().lock();
+    write_json(&mut stdout, result, context)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Write results as a JSON document to `out`
+pub fn write_json(
+    out: &mut impl Write,
+    result: &AnalysisResult,
+    context: &JsonContext,
+) -> anyhow::Result<()> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct JsonOutput {
+        schema_version: &'static str,
+        dutop_version: &'static str,
+        hostname: Option<String>,
+        started_at: Option<String>,
+        finished_at: String,
+        config: Option<JsonConfig>,
+        path: String,
+        total_size: u64,
+        total_size_human: String,
+        file_count: usize,
+        directory_count: usize,
+        cancelled: bool,
+        top_directories: Vec<JsonDirectory>,
+        errors: Vec<JsonError>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        link_issues: Vec<JsonLinkIssue>,
+        hard_links: usize,
+        hard_link_savings: u64,
+        symlink_count: usize,
+        symlink_size: u64,
+        special_file_count: usize,
+        socket_count: usize,
+        fifo_count: usize,
+        block_device_count: usize,
+        char_device_count: usize,
+        unique_size: u64,
+        cloud_only_files: usize,
+        cloud_only_size: u64,
+        filesystem: Option<JsonFilesystem>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stats: Option<JsonStats>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonStats {
+        wall_time_secs: f64,
+        files_per_second: f64,
+        dirs_per_second: f64,
+        cached_dirs: usize,
+        cached_files: usize,
+        syscalls_avoided: usize,
+        thread_utilization: Vec<f64>,
+        peak_rss: Option<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonConfig {
+        max_depth: Option<usize>,
+        exclude_patterns: Vec<String>,
+        follow_links: bool,
+        one_file_system: bool,
+        respect_gitignore: bool,
+        modified_before: Option<String>,
+        modified_after: Option<String>,
+        min_file_size: Option<u64>,
+        alternate_streams: bool,
+        detect_clones: bool,
+        expand_archives: bool,
+        check_links: bool,
+        count_all_types: bool,
+        fast_hardlinks: bool,
+        track_all_inodes: bool,
+        backend: &'static str,
+        remote_fs: &'static str,
+        include_virtual: bool,
+    }
+
+    #[derive(Serialize)]
+    struct JsonFilesystem {
+        total: u64,
+        used: u64,
+        free: u64,
+        available: u64,
+        scan_percentage: f64,
+    }
+
+    #[derive(Serialize)]
+    struct JsonError {
+        path: String,
+        kind: &'static str,
+        message: String,
+    }
+
+    #[derive(Serialize)]
+    struct JsonLinkIssue {
+        path: String,
+        target: String,
+        kind: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct JsonDirectory {
+        path: String,
+        size: u64,
+        size_human: String,
+        percentage: f64,
+        file_count: usize,
+        dir_count: usize,
+    }
+
+    let total = result.total_size as f64;
+    let output = JsonOutput {
+        schema_version: JSON_SCHEMA_VERSION,
+        dutop_version: env!("CARGO_PKG_VERSION"),
+        hostname: hostname(),
+        started_at: context.started_at.map(rfc3339),
+        finished_at: rfc3339(SystemTime::now()),
+        config: context.config.map(|config| JsonConfig {
+            max_depth: config.max_depth,
+            exclude_patterns: config.exclude_patterns.clone(),
+            follow_links: config.follow_links,
+            one_file_system: config.one_file_system,
+            respect_gitignore: config.respect_gitignore,
+            modified_before: config.modified_before.map(rfc3339),
+            modified_after: config.modified_after.map(rfc3339),
+            min_file_size: config.min_file_size,
+            alternate_streams: config.alternate_streams,
+            detect_clones: config.detect_clones,
+            expand_archives: config.expand_archives,
+            check_links: config.check_links,
+            count_all_types: config.count_all_types,
+            fast_hardlinks: config.fast_hardlinks,
+            track_all_inodes: config.track_all_inodes,
+            backend: config.backend.as_str(),
+            remote_fs: config.remote_fs.as_str(),
+            include_virtual: config.include_virtual,
+        }),
+        path: result.root_path.display().to_string(),
+        total_size: result.total_size,
+        total_size_human: format_size_auto(result.total_size),
+        file_count: result.total_files,
+        directory_count: result.total_dirs,
+        cancelled: result.cancelled,
+        top_directories: result
+            .top_directories
+            .iter()
+            .map(|d| JsonDirectory {
+                path: d.path.display().to_string(),
+                size: d.size,
+                size_human: format_size_auto(d.size),
+                percentage: if total > 0.0 {
+                    (d.size as f64 / total) * 100.0
+                } else {
+                    0.0
+                },
+                file_count: d.file_count,
+                dir_count: d.dir_count,
+            })
+            .collect(),
+        errors: result
+            .errors
+            .iter()
+            .map(|e| JsonError {
+                path: e.path.display().to_string(),
+                kind: e.kind.as_str(),
+                message: e.message.clone(),
+            })
+            .collect(),
+        link_issues: result
+            .link_issues
+            .iter()
+            .map(|issue| JsonLinkIssue {
+                path: issue.path.display().to_string(),
+                target: issue.target.display().to_string(),
+                kind: issue.kind.as_str(),
+            })
+            .collect(),
+        hard_links: result.hard_links,
+        hard_link_savings: result.hard_link_savings,
+        symlink_count: result.other_types.symlinks,
+        symlink_size: result.other_types.symlink_size,
+        special_file_count: result.other_types.special_files(),
+        socket_count: result.other_types.sockets,
+        fifo_count: result.other_types.fifos,
+        block_device_count: result.other_types.block_devices,
+        char_device_count: result.other_types.char_devices,
+        unique_size: result.unique_size(),
+        cloud_only_files: result.cloud_files,
+        cloud_only_size: result.cloud_size,
+        filesystem: result.filesystem.map(|fs| JsonFilesystem {
+            total: fs.total,
+            used: fs.used(),
+            free: fs.free,
+            available: fs.available,
+            scan_percentage: result.capacity_percentage().unwrap_or(0.0),
+        }),
+        stats: result.stats.as_ref().map(|stats| JsonStats {
+            wall_time_secs: stats.wall_time.as_secs_f64(),
+            files_per_second: stats.files_per_second(),
+            dirs_per_second: stats.dirs_per_second(),
+            cached_dirs: stats.cached_dirs,
+            cached_files: stats.cached_files,
+            syscalls_avoided: stats.syscalls_avoided(),
+            thread_utilization: stats.utilization(),
+            peak_rss: stats.peak_rss,
+        }),
+    };
+
+    if context.compact {
+        serde_json::to_writer(&mut *out, &output)?;
+    } else {
+        serde_json::to_writer_pretty(&mut *out, &output)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    format_rfc3339(
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    )
+}
+
+/// Name of this machine, if it can be determined
+pub(crate) fn hostname() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // SAFETY: the buffer is valid for its whole length, and one byte is
+        // kept back so the name is always NUL-terminated
+        let ret = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len() - 1) };
+        if ret != 0 {
+            return None;
+        }
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        Some(String::from_utf8_lossy(&buf[..len]).into_owned()).filter(|name| !name.is_empty())
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// Format a single entry as an NDJSON record
+///
+/// Records are emitted while scanning, so they carry no percentage.
+pub fn ndjson_entry(entry: &DirectoryEntry) -> anyhow::Result<String> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct EntryRecord {
+        r#type: &'static str,
+        path: String,
+        size: u64,
+        size_human: String,
+        file_count: usize,
+        dir_count: usize,
+    }
+
+    let record = EntryRecord {
+        r#type: "entry",
+        path: entry.path.display().to_string(),
+        size: entry.size,
+        size_human: format_size_auto(entry.size),
+        file_count: entry.file_count,
+        dir_count: entry.dir_count,
+    };
+
+    Ok(serde_json::to_string(&record)?)
+}
+
+/// Format the final NDJSON record with the totals of the analysis
+pub fn ndjson_summary(result: &AnalysisResult) -> anyhow::Result<String> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct SummaryRecord {
+        r#type: &'static str,
+        path: String,
+        total_size: u64,
+        total_size_human: String,
+        file_count: usize,
+        directory_count: usize,
+        cancelled: bool,
+    }
+
+    let record = SummaryRecord {
+        r#type: "summary",
+        path: result.root_path.display().to_string(),
+        total_size: result.total_size,
+        total_size_human: format_size_auto(result.total_size),
+        file_count: result.total_files,
+        directory_count: result.total_dirs,
+        cancelled: result.cancelled,
+    };
+
+    Ok(serde_json::to_string(&record)?)
+}
+
+/// Output the full tree in the ncdu JSON export format
+///
+/// The result must have been produced with `build_tree` and `tree_files` set,
+/// since ncdu reconstructs directory sizes from the individual files.
+pub fn print_ncdu(result: &AnalysisResult) -> anyhow::Result<()> {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    write_ncdu(&mut out, result)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Write the ncdu JSON export of the full tree to `out`
+pub fn write_ncdu(out: &mut impl Write, result: &AnalysisResult) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let tree = result
+        .tree
+        .as_ref()
+        .context("ncdu export requires the full directory tree")?;
+    if tree.file_count > 0 && tree.iter().all(|node| node.files.is_empty()) {
+        anyhow::bail!("ncdu export requires the files of each directory in the tree");
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    write!(
+        out,
+        "[1,2,{{\"progname\":\"dutop\",\"progver\":{},\"timestamp\":{}}},",
+        serde_json::to_string(env!("CARGO_PKG_VERSION"))?,
+        timestamp
+    )?;
+    write_ncdu_dir(out, tree, &tree.path.display().to_string())?;
+    writeln!(out, "]")?;
+
+    Ok(())
+}
+
+/// Write a directory as an ncdu array: its info object followed by its entries
+fn write_ncdu_dir(out: &mut impl Write, node: &DirectoryNode, name: &str) -> anyhow::Result<()> {
+    write!(out, "[{{\"name\":{}}}", serde_json::to_string(name)?)?;
+
+    for file in &node.files {
+        write!(
+            out,
+            ",{{\"name\":{},\"asize\":{},\"dsize\":{}}}",
+            serde_json::to_string(&file.name.to_string_lossy())?,
+            file.apparent_size,
+            file.size
+        )?;
+    }
+
+    for child in &node.children {
+        let child_name = child
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        write!(out, ",")?;
+        write_ncdu_dir(out, child, &child_name)?;
+    }
+
+    write!(out, "]")?;
+    Ok(())
+}
+
+/// Output results as CSV, one row per top directory
+///
+/// Columns: path, size_bytes, size_human, percent, file_count, dir_count.
+/// With `total_row`, a final row for the analyzed path holds the totals.
+pub fn print_csv(result: &AnalysisResult, total_row: bool) -> anyhow::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write_csv(&mut out, result, total_row)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Write CSV output to `out`
+pub fn write_csv(out: &mut impl Write, result: &AnalysisResult, total_row: bool) -> io::Result<()> {
+    writeln!(
+        out,
+        "path,size_bytes,size_human,percent,file_count,dir_count"
+    )?;
+
+    let total = result.total_size as f64;
+    for dir in &result.top_directories {
+        let percent = if total > 0.0 {
+            (dir.size as f64 / total) * 100.0
+        } else {
+            0.0
+        };
+        writeln!(
+            out,
+            "{},{},{},{:.2},{},{}",
+            csv_field(&dir.path.display().to_string()),
+            dir.size,
+            csv_field(&format_size_auto(dir.size)),
+            percent,
+            dir.file_count,
+            dir.dir_count
+        )?;
+    }
+
+    if total_row {
+        writeln!(
+            out,
+            "{},{},{},{:.2},{},{}",
+            csv_field(&result.root_path.display().to_string()),
+            result.total_size,
+            csv_field(&format_size_auto(result.total_size)),
+            100.0,
+            result.total_files,
+            result.total_dirs
+        )?;
+    }
+
+    Ok(())
+}
+
+/// How `du`-style output prints sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuUnits {
+    /// Number of blocks of this many bytes, rounded up like `du -B`
+    Blocks(u64),
+    /// Sizes like "4.0K" or "1.5G", like `du -h`
+    Human,
+}
+
+impl Default for DuUnits {
+    fn default() -> Self {
+        Self::Blocks(1024)
+    }
+}
+
+impl DuUnits {
+    /// Text of `bytes` in these units
+    pub fn format(self, bytes: u64) -> String {
+        match self {
+            Self::Blocks(size) => bytes.div_ceil(size.max(1)).to_string(),
+            Self::Human => format_size_du(bytes),
+        }
+    }
+}
+
+/// Write output like `du -d 1`: a `SIZE<TAB>PATH` record for each top
+/// directory, then one for the analyzed path, each ended by `terminator`
+/// (a newline, or NUL for `xargs -0`)
+///
+/// With `time`, a column with the newest modification time of the entry
+/// comes before the path, like `du --time`.
+pub fn write_du(
+    out: &mut impl Write,
+    result: &AnalysisResult,
+    units: DuUnits,
+    time: bool,
+    terminator: u8,
+) -> io::Result<()> {
+    let entries = result
+        .top_directories
+        .iter()
+        .map(|d| (&d.path, d.size, d.modified));
+    let root = (&result.root_path, result.total_size, result.modified);
+    for (path, size, modified) in entries.chain([root]) {
+        write!(out, "{}\t", units.format(size))?;
+        if time {
+            let time = modified.map_or_else(|| "-".to_string(), format_timestamp);
+            write!(out, "{}\t", time)?;
+        }
+        write_raw_path(out, path)?;
+        out.write_all(&[terminator])?;
+    }
+    Ok(())
+}
+
+/// Write the total of `result` as `size` followed by a tab and the path, like
+/// `du -s`
+pub fn write_summary(out: &mut impl Write, result: &AnalysisResult, size: &str) -> io::Result<()> {
+    write!(out, "{}\t", size)?;
+    write_raw_path(out, &result.root_path)?;
+    writeln!(out)
+}
+
+/// Write every directory and file of `tree` of at least `min_size` bytes in the
+/// du format, each directory after its contents like `du -a`
+pub fn write_du_all(
+    out: &mut impl Write,
+    tree: &DirectoryNode,
+    units: DuUnits,
+    min_size: u64,
+    terminator: u8,
+) -> io::Result<()> {
+    for_each_entry(tree, min_size, &mut |path, size| {
+        write!(out, "{}\t", units.format(size))?;
+        write_raw_path(out, path)?;
+        out.write_all(&[terminator])
+    })
+}
+
+/// Write the path of every directory and file of `tree` of at least
+/// `min_size` bytes, in the order of [`write_du_all`]
+pub fn write_paths_all(
+    out: &mut impl Write,
+    tree: &DirectoryNode,
+    min_size: u64,
+    terminator: u8,
+) -> io::Result<()> {
+    for_each_entry(tree, min_size, &mut |path, _| {
+        write_raw_path(out, path)?;
+        out.write_all(&[terminator])
+    })
+}
+
+/// Call `f` with the path and size of every directory and file below `node`
+/// of at least `min_size` bytes, the files of a directory and then the
+/// directory itself after its subdirectories
+fn for_each_entry(
+    node: &DirectoryNode,
+    min_size: u64,
+    f: &mut impl FnMut(&Path, u64) -> io::Result<()>,
+) -> io::Result<()> {
+    for child in &node.children {
+        for_each_entry(child, min_size, f)?;
+    }
+    for file in node.files.iter().filter(|file| file.size >= min_size) {
+        f(&node.path.join(&file.name), file.size)?;
+    }
+    if node.size >= min_size {
+        f(&node.path, node.size)?;
+    }
+    Ok(())
+}
+
+/// Write the path of each top directory, largest first, each ended by
+/// `terminator`
+pub fn write_paths(
+    out: &mut impl Write,
+    result: &AnalysisResult,
+    terminator: u8,
+) -> io::Result<()> {
+    for dir in &result.top_directories {
+        write_raw_path(out, &dir.path)?;
+        out.write_all(&[terminator])?;
+    }
+    Ok(())
+}
+
+/// Write the empty directories of `report`, then the tiny ones, each ended by
+/// `terminator`, for piping into `rm -r`
+pub fn write_empty_paths(
+    out: &mut impl Write,
+    report: &EmptyReport,
+    terminator: u8,
+) -> io::Result<()> {
+    let tiny = report.tiny.iter().map(|entry| &entry.path);
+    for path in report.empty.iter().chain(tiny) {
+        write_raw_path(out, path)?;
+        out.write_all(&[terminator])?;
+    }
+    Ok(())
+}
+
+/// Write `path` byte for byte, so that names which are not valid UTF-8 reach
+/// other tools intact on Unix
+fn write_raw_path(out: &mut impl Write, path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        out.write_all(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    write!(out, "{}", path.display())
+}
+
+/// Write tab-separated columns with a header row, one row per top directory
+///
+/// Columns: size_bytes, size_human, percent, file_count, dir_count, path. The
+/// path comes last so that `cut -f6-` and awk can take it whole; tabs, line
+/// breaks and backslashes in it are escaped as `\t`, `\n`, `\r` and `\\`.
+/// With `total_row`, a final row for the analyzed path holds the totals.
+pub fn write_tsv(out: &mut impl Write, result: &AnalysisResult, total_row: bool) -> io::Result<()> {
+    writeln!(
+        out,
+        "size_bytes\tsize_human\tpercent\tfile_count\tdir_count\tpath"
+    )?;
+
+    let total = result.total_size;
+    let rows = result
+        .top_directories
+        .iter()
+        .map(|d| (&d.path, d.size, d.file_count, d.dir_count));
+    let totals = (
+        &result.root_path,
+        result.total_size,
+        result.total_files,
+        result.total_dirs,
+    );
+    for (path, size, files, dirs) in rows.chain(total_row.then_some(totals)) {
+        let percent = if total > 0 {
+            size as f64 / total as f64 * 100.0
+        } else {
+            0.0
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{:.2}\t{}\t{}\t{}",
+            size,
+            format_size_auto(size),
+            percent,
+            files,
+            dirs,
+            tsv_field(&path.display().to_string())
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Escape the characters that would break a TSV row
+fn tsv_field(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quote a CSV field if it contains separators, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Destination of command output: stdout, or the file given with `--output`
+pub enum OutputSink {
+    /// Standard output
+    Stdout(io::Stdout),
+    /// Standard output of a terminal, held back until complete to go through
+    /// a pager when longer than the screen
+    Paged(Vec<u8>),
+    /// A file replaced once the output is complete
+    File(OutputFile),
+}
+
+impl OutputSink {
+    /// Write to `path` when given, otherwise to stdout
+    pub fn open(path: Option<&Path>) -> anyhow::Result<Self> {
+        Ok(match path {
+            Some(path) => OutputSink::File(OutputFile::create(path)?),
+            None => OutputSink::Stdout(io::stdout()),
+        })
+    }
+
+    /// Write to stdout, through a pager when it is a terminal and the output
+    /// turns out longer than the screen
+    pub fn paged() -> Self {
+        if atty::is(atty::Stream::Stdout) {
+            OutputSink::Paged(Vec::new())
+        } else {
+            OutputSink::Stdout(io::stdout())
+        }
+    }
+
+    /// Whether output goes to a terminal, which allows colors
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            OutputSink::Stdout(_) => atty::is(atty::Stream::Stdout),
+            OutputSink::Paged(_) => true,
+            OutputSink::File(_) => false,
+        }
+    }
+
+    /// Flush stdout, show the output held back, or move the complete file
+    /// into place
+    pub fn finish(self) -> anyhow::Result<()> {
+        match self {
+            OutputSink::Stdout(mut stdout) => Ok(stdout.flush()?),
+            OutputSink::Paged(text) => page(&text),
+            OutputSink::File(file) => file.commit(),
+        }
+    }
+}
+
+/// Show `text` through the pager, like git does, when it has at least as many
+/// lines as the terminal, and print it directly otherwise
+fn page(text: &[u8]) -> anyhow::Result<()> {
+    let rows = crossterm::terminal::size().map_or(usize::MAX, |(_, rows)| usize::from(rows));
+    let lines = text.iter().filter(|&&byte| byte == b'\n').count();
+    let pager = std::env::var("PAGER").ok();
+    if let Some((program, args)) = pager_command(pager.as_deref(), lines, rows) {
+        let mut command = std::process::Command::new(program);
+        command.args(args).stdin(std::process::Stdio::piped());
+        // Keep colors, and quit right away when the text fits after all
+        if std::env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        // Without the pager, the text is printed directly
+        if let Ok(mut child) = command.spawn() {
+            if let Some(mut stdin) = child.stdin.take() {
+                // Quitting the pager before the end closes its input
+                let _ = stdin.write_all(text);
+            }
+            child.wait()?;
+            return Ok(());
+        }
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(text)?;
+    Ok(stdout.flush()?)
+}
+
+/// Program and arguments of the pager in `$PAGER` (less when unset) for
+/// `lines` of text on a terminal with `rows`, unless the text fits or paging
+/// is turned off with an empty `$PAGER` or `cat`
+fn pager_command(pager: Option<&str>, lines: usize, rows: usize) -> Option<(&str, Vec<&str>)> {
+    let mut words = pager.unwrap_or("less").split_whitespace();
+    let program = words.next().filter(|&program| program != "cat")?;
+    (lines >= rows).then(|| (program, words.collect()))
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputSink::Stdout(stdout) => stdout.write(buf),
+            OutputSink::Paged(text) => text.write(buf),
+            OutputSink::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputSink::Stdout(stdout) => stdout.flush(),
+            OutputSink::Paged(_) => Ok(()),
+            OutputSink::File(file) => file.flush(),
+        }
+    }
+}
+
+/// An output file written atomically
+///
+/// Output goes to a temporary file next to the destination, which replaces
+/// it on [`OutputFile::commit`], so readers never see a partial file. The
+/// temporary file is removed when the output is dropped without committing.
+/// Output is compressed with gzip when the file name ends in `.gz`.
+pub struct OutputFile {
+    path: std::path::PathBuf,
+    temp_path: std::path::PathBuf,
+    writer: Option<FileWriter>,
+}
+
+enum FileWriter {
+    Plain(io::BufWriter<std::fs::File>),
+    Gzip(Box<flate2::write::GzEncoder<io::BufWriter<std::fs::File>>>),
+}
+
+impl OutputFile {
+    /// Start writing the output for `path`
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let name = path
+            .file_name()
+            .with_context(|| format!("Invalid output file: {}", path.display()))?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let file = std::fs::File::create(&temp_path)
+            .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+        let file = io::BufWriter::new(file);
+        let writer = if path.extension().is_some_and(|ext| ext == "gz") {
+            FileWriter::Gzip(Box::new(flate2::write::GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            )))
+        } else {
+            FileWriter::Plain(file)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            temp_path,
+            writer: Some(writer),
+        })
+    }
+
+    /// Finish writing and replace the destination with the complete file
+    pub fn commit(mut self) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let file = match self.writer.take() {
+            Some(FileWriter::Plain(file)) => file,
+            Some(FileWriter::Gzip(encoder)) => (*encoder).finish()?,
+            None => unreachable!("output file committed twice"),
+        };
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&self.temp_path, &self.path)
+            .with_context(|| format!("Failed to write output file: {}", self.path.display()))
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self.writer.as_mut() {
+            Some(FileWriter::Plain(file)) => file,
+            Some(FileWriter::Gzip(encoder)) => encoder.as_mut(),
+            None => unreachable!("output file written after commit"),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_select_color() {
+        let colors = Colors::from_theme(&Theme::default(), ColorSupport::Ansi256);
+        assert_eq!(colors.low, "\x1b[38;5;34m");
+
+        // Low usage - green
+        assert_eq!(select_color(5, 30, &colors), colors.low);
+
+        // Medium usage - yellow
+        assert_eq!(select_color(12, 30, &colors), colors.medium);
+
+        // High usage - red
+        assert_eq!(select_color(20, 30, &colors), colors.high);
+    }
+
+    #[test]
+    fn test_color_by_age_and_type() {
+        let colors = Colors::from_theme(&Theme::default(), ColorSupport::Ansi256);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert_eq!(colors.for_age(Some(now - 86_400)), colors.low);
+        assert_eq!(colors.for_age(Some(now - 100 * 86_400)), colors.medium);
+        assert_eq!(colors.for_age(Some(now - 800 * 86_400)), colors.high);
+        assert_eq!(colors.for_age(None), "");
+
+        assert_eq!(colors.for_type(Path::new("movie.MKV")), "\x1b[38;5;135m");
+        assert_eq!(colors.for_type(Path::new("/srv/data")), "");
+        let colors = Colors::from_theme(&Theme::default(), ColorSupport::None);
+        assert_eq!(colors.low, "");
+    }
+
+    #[test]
     fn test_colors_disabled() {
         let colors = Colors::disabled();
-        assert_eq!(colors.green, "");
-        assert_eq!(colors.yellow, "");
-        assert_eq!(colors.red, "");
+        assert_eq!(colors.low, "");
+        assert_eq!(colors.medium, "");
+        assert_eq!(colors.high, "");
         assert_eq!(colors.reset, "");
     }
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_write_csv() {
+        use crate::DirectoryEntry;
+        use std::path::PathBuf;
+
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data"),
+            total_size: 4096,
+            total_files: 3,
+            total_dirs: 2,
+            top_directories: vec![
+                DirectoryEntry {
+                    path: PathBuf::from("/data/a,b"),
+                    size: 3072,
+                    file_count: 2,
+                    dir_count: 2,
+                    modified: None,
+                },
+                DirectoryEntry {
+                    path: PathBuf::from("/data/c"),
+                    size: 1024,
+                    file_count: 1,
+                    dir_count: 0,
+                    modified: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &result, true).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "path,size_bytes,size_human,percent,file_count,dir_count"
+        );
+        assert_eq!(lines[1], "\"/data/a,b\",3072,3.0 K,75.00,2,2");
+        assert_eq!(lines[2], "/data/c,1024,1.0 K,25.00,1,0");
+        assert_eq!(lines[3], "/data,4096,4.0 K,100.00,3,2");
+    }
+
+    #[test]
+    fn test_write_tsv() {
+        use crate::DirectoryEntry;
+        use std::path::PathBuf;
+
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data"),
+            total_size: 4096,
+            total_files: 3,
+            total_dirs: 2,
+            top_directories: vec![DirectoryEntry {
+                path: PathBuf::from("/data/a\tb\\c"),
+                size: 3072,
+                file_count: 2,
+                dir_count: 1,
+                modified: None,
+            }],
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_tsv(&mut out, &result, true).unwrap();
+        let tsv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = tsv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "size_bytes\tsize_human\tpercent\tfile_count\tdir_count\tpath"
+        );
+        assert_eq!(lines[1], "3072\t3.0 K\t75.00\t2\t1\t/data/a\\tb\\\\c");
+        assert_eq!(lines[2], "4096\t4.0 K\t100.00\t3\t2\t/data");
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_write_du() {
+        use crate::DirectoryEntry;
+        use std::path::PathBuf;
+
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data"),
+            total_size: 5 << 20,
+            top_directories: vec![DirectoryEntry {
+                path: PathBuf::from("/data/logs"),
+                size: 4097,
+                file_count: 1,
+                dir_count: 0,
+                modified: None,
+            }],
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_du(&mut out, &result, DuUnits::default(), false, b'\n').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "5\t/data/logs\n5120\t/data\n"
+        );
+
+        let mut out = Vec::new();
+        write_du(&mut out, &result, DuUnits::Human, false, b'\0').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "4.1K\t/data/logs\x005.0M\t/data\x00"
+        );
+
+        let mut out = Vec::new();
+        write_du(&mut out, &result, DuUnits::default(), true, b'\n').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "5\t-\t/data/logs\n5120\t-\t/data\n"
+        );
+
+        let mut out = Vec::new();
+        write_paths(&mut out, &result, b'\0').unwrap();
+        assert_eq!(out, b"/data/logs\0");
+        assert_eq!(DuUnits::Blocks(1).format(4097), "4097");
+        assert_eq!(DuUnits::Blocks(1 << 20).format(4097), "1");
+    }
+
+    #[test]
+    fn test_ndjson_records() {
+        use std::path::PathBuf;
+
+        let entry = DirectoryEntry {
+            path: PathBuf::from("/data/a"),
+            size: 2048,
+            file_count: 2,
+            dir_count: 1,
+            modified: None,
+        };
+        let line = ndjson_entry(&entry).unwrap();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["type"], "entry");
+        assert_eq!(value["path"], "/data/a");
+        assert_eq!(value["size"], 2048);
+
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data"),
+            total_size: 2048,
+            total_files: 2,
+            total_dirs: 1,
+            ..Default::default()
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&ndjson_summary(&result).unwrap()).unwrap();
+        assert_eq!(value["type"], "summary");
+        assert_eq!(value["file_count"], 2);
+    }
+
+    #[test]
+    fn test_write_ncdu() {
+        use crate::{analyze_disk_usage, AnalysisConfig};
+        use std::fs;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("sub")).unwrap();
+        fs::write(temp_dir.path().join("top.txt"), "top").unwrap();
+        fs::write(temp_dir.path().join("sub/inner.txt"), "inner").unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            tree_files: true,
+            ..Default::default()
+        };
+        let result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+
+        let mut out = Vec::new();
+        write_ncdu(&mut out, &result).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        assert_eq!(value[0], 1);
+        assert_eq!(value[2]["progname"], "dutop");
+        let root = &value[3];
+        assert_eq!(root[0]["name"], temp_dir.path().display().to_string());
+        assert_eq!(root[1]["name"], "top.txt");
+        assert_eq!(root[1]["asize"], 3);
+        assert_eq!(root[2][0]["name"], "sub");
+        assert_eq!(root[2][1]["name"], "inner.txt");
+    }
+
+    #[test]
+    fn test_write_ncdu_requires_tree() {
+        let result = AnalysisResult::default();
+        assert!(write_ncdu(&mut Vec::new(), &result).is_err());
+    }
+
+    #[test]
+    fn test_write_tree() {
+        let node = |path: &str, size, children| DirectoryNode {
+            path: PathBuf::from(path),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            children,
+            files: Vec::new(),
+        };
+        let tree = node(
+            "/srv",
+            100,
+            vec![
+                node(
+                    "/srv/data",
+                    60,
+                    vec![node(
+                        "/srv/data/a",
+                        40,
+                        vec![node("/srv/data/a/deep", 40, vec![])],
+                    )],
+                ),
+                node("/srv/logs", 30, vec![]),
+                node("/srv/tmp", 10, vec![]),
+            ],
+        );
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            tree: Some(tree),
+            other_types: crate::OtherTypes {
+                symlinks: 1,
+                fifos: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            bar_width: 10,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        let top_two = Selection {
+            top: 2,
+            ..Default::default()
+        };
+        write_tree(&mut out, &result, &config, 2, &top_two, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "   100 B  100% ██████████ /srv");
+        assert_eq!(lines[1], "    60 B   60% ██████░░░░ ├── data");
+        assert_eq!(lines[2], "    40 B   40% ████░░░░░░ │   └── a");
+        assert_eq!(lines[3], "    30 B   30% ███░░░░░░░ └── logs");
+        assert_eq!(lines[5], "Files: 0  Directories: 0  Symlinks: 1  FIFOs: 1");
+        assert_eq!(lines.len(), 6);
+
+        for (paths, name) in [
+            (PathStyle::Relative, "data/a"),
+            (PathStyle::Full, "/srv/data/a"),
+        ] {
+            let config = OutputConfig {
+                paths,
+                ..config.clone()
+            };
+            let mut out = Vec::new();
+            write_tree(&mut out, &result, &config, 2, &top_two, false).unwrap();
+            let text = String::from_utf8(out).unwrap();
+            let row = format!("    40 B   40% ████░░░░░░ │   └── {}", name);
+            assert_eq!(text.lines().nth(2), Some(row.as_str()));
+        }
+
+        let counted = OutputConfig {
+            show_counts: true,
+            ..config.clone()
+        };
+        let mut out = Vec::new();
+        write_tree(&mut out, &result, &counted, 2, &top_two, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let row = "    40 B   40% ████░░░░░░ │   └── a (1 file, 0 directories)";
+        assert_eq!(text.lines().nth(2), Some(row));
+
+        let empty = AnalysisResult::default();
+        assert!(write_tree(&mut Vec::new(), &empty, &config, 1, &top_two, false).is_err());
+
+        let config = OutputConfig {
+            others: Some(Others::default()),
+            ..config
+        };
+        let mut out = Vec::new();
+        let selection = Selection {
+            min_size: 20,
+            ..Default::default()
+        };
+        write_tree(&mut out, &result, &config, 1, &selection, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("    60 B   60% ██████░░░░ ├── data\n"));
+        assert!(text.contains("    30 B   30% ███░░░░░░░ ├── logs\n"));
+        assert!(text.contains("    10 B   10% █░░░░░░░░░ └── (other 1 directory)\n"));
+    }
+
+    #[test]
+    fn test_fit_to_width() {
+        let entry = |name: &str, size| DirectoryEntry {
+            path: PathBuf::from("/srv/data").join(name),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv/data"),
+            total_size: 100,
+            top_directories: vec![entry("backups/2024", 60), entry("logs", 40)],
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            ..Default::default()
+        };
+
+        // Full paths fit next to the default bar, which grows into the rest
+        let wide = config.clone().fit_to_width(120, &result);
+        assert!(wide.paths == PathStyle::Full && !wide.stacked);
+        assert_eq!((wide.bar_width, wide.name_width), (60, 22));
+
+        let medium = config.clone().fit_to_width(60, &result);
+        assert!(medium.paths == PathStyle::Auto && !medium.stacked);
+        assert_eq!((medium.bar_width, medium.name_width), (14, 20));
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &medium).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.lines().all(|line| display_width(line) <= 60));
+        assert!(text.contains("│ backups/2024         │"));
+
+        let narrow = config.fit_to_width(40, &result);
+        assert!(narrow.stacked);
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &narrow).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("\nbackups/2024\n  ███████████████████████     60 B   60%\n"));
+        assert!(!text.contains('┌'));
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let entry = |name: &str, size| DirectoryEntry {
+            path: PathBuf::from("/srv").join(name),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 100,
+            top_directories: vec![entry("data", 80)],
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            name_width: 8,
+            hyperlinks: true,
+            others: Some(Others { count: 1, size: 20 }),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        // Padded after the link, and the row summing up the others is not linked
+        assert!(text.contains("│ \x1b]8;;file:///srv/data\x1b\\data\x1b]8;;\x1b\\     │\n"));
+        assert!(text.contains("│ (1 ot... │\n"));
+
+        let tree = DirectoryNode {
+            path: PathBuf::from("/srv"),
+            size: 100,
+            file_count: 1,
+            dir_count: 0,
+            children: Vec::new(),
+            files: Vec::new(),
+        };
+        let result = AnalysisResult {
+            tree: Some(tree),
+            ..result
+        };
+        let mut out = Vec::new();
+        write_tree(&mut out, &result, &config, 1, &Selection::default(), false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains(" \x1b]8;;file:///srv\x1b\\/srv\x1b]8;;\x1b\\\n"));
+    }
+
+    #[test]
+    fn test_icons() {
+        let entry = |name: &str| DirectoryEntry {
+            path: PathBuf::from("/srv").join(name),
+            size: 50,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 100,
+            top_directories: vec![entry("Music"), entry("a-rather-long-name")],
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            name_width: 12,
+            icons: Some(IconStyle::Emoji),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        // The icon counts towards the width of the name column
+        assert!(text.contains("│ 🎬 Music     │\n"));
+        assert!(text.contains("│ 📁 a-rath... │\n"));
+    }
+
+    #[test]
+    fn test_table_columns() {
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 100,
+            top_directories: vec![DirectoryEntry {
+                path: PathBuf::from("/srv/data"),
+                size: 60,
+                file_count: 1234,
+                dir_count: 56,
+                modified: None,
+            }],
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            name_width: 6,
+            columns: vec![Column::Path, Column::Size, Column::Files, Column::Dirs],
+            others: Some(Others { count: 3, size: 40 }),
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("┌────────┬──────────┬───────┬───────┐\n"));
+        assert!(text.contains("│ Path   │     Size │ Files │  Dirs │\n├────────┼"));
+        assert!(text.contains("│ data   │     60 B │  1234 │    56 │\n"));
+        assert!(text.contains("│ (ot... │     40 B │     - │     - │\n"));
+
+        // Without counts, the columns need no titles
+        let config = OutputConfig {
+            columns: vec![Column::Size, Column::Bar],
+            bar_width: 10,
+            ..config
+        };
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("\n│     60 B │ ██████████ │\n"));
+        assert!(!text.contains("Size"));
+    }
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(pager_command(None, 100, 40), Some(("less", vec![])));
+        assert_eq!(
+            pager_command(Some("most -s"), 40, 40),
+            Some(("most", vec!["-s"]))
+        );
+        // Output fitting on the screen, and paging turned off
+        assert_eq!(pager_command(None, 10, 40), None);
+        assert_eq!(pager_command(Some(""), 100, 40), None);
+        assert_eq!(pager_command(Some("cat"), 100, 40), None);
+    }
+
+    #[test]
+    fn test_ascii_output() {
+        let node = |path: &str, size, children| DirectoryNode {
+            path: PathBuf::from(path),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            children,
+            files: Vec::new(),
+        };
+        let tree = node(
+            "/srv",
+            100,
+            vec![
+                node("/srv/data", 60, vec![node("/srv/data/a", 40, vec![])]),
+                node("/srv/logs", 40, vec![]),
+            ],
+        );
+        let entry = |name: &str, size| DirectoryEntry {
+            path: PathBuf::from("/srv").join(name),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 100,
+            top_directories: vec![entry("data", 60), entry("logs", 40)],
+            tree: Some(tree),
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            bar_width: 10,
+            name_width: 6,
+            ascii: true,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_tree(&mut out, &result, &config, 2, &Selection::default(), false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "    60 B   60% ######.... |-- data");
+        assert_eq!(lines[2], "    40 B   40% ####...... |   `-- a");
+        assert_eq!(lines[3], "    40 B   40% ####...... `-- logs");
+
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("+------------+----------+-------+--------+\n"));
+        assert!(text.contains("| ########## |     60 B |   60% | data   |\n"));
+        assert!(text.is_ascii());
+
+        let mut out = Vec::new();
+        write_results(&mut out, &AnalysisResult::default(), &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("| No files found     |"));
+    }
+
+    #[test]
+    fn test_all_files() {
+        let file = |name: &str, size| crate::FileNode {
+            name: name.into(),
+            size,
+            apparent_size: size,
+        };
+        let tree = DirectoryNode {
+            path: PathBuf::from("/srv"),
+            size: 100,
+            file_count: 3,
+            dir_count: 1,
+            children: vec![DirectoryNode {
+                path: PathBuf::from("/srv/data"),
+                size: 60,
+                file_count: 1,
+                dir_count: 0,
+                children: Vec::new(),
+                files: vec![file("big.bin", 60)],
+            }],
+            files: vec![file("notes.txt", 30), file("tiny", 10)],
+        };
+
+        let mut out = Vec::new();
+        write_du_all(&mut out, &tree, DuUnits::Blocks(1), 20, b'\n').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "60\t/srv/data/big.bin\n60\t/srv/data\n30\t/srv/notes.txt\n100\t/srv\n"
+        );
+
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            tree: Some(tree),
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            bar_width: 10,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_tree(&mut out, &result, &config, 2, &Selection::default(), true).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "    60 B   60% ██████░░░░ ├── data");
+        assert_eq!(lines[2], "    60 B   60% ██████░░░░ │   └── big.bin");
+        assert_eq!(lines[3], "    30 B   30% ███░░░░░░░ ├── notes.txt");
+        assert_eq!(lines[4], "    10 B   10% █░░░░░░░░░ └── tiny");
+    }
+
+    #[test]
+    fn test_selection_coverage() {
+        let sizes = [50, 30, 15, 5];
+        let coverage = |percent| Selection {
+            coverage: Some(percent),
+            ..Default::default()
+        };
+        assert_eq!(coverage(50.0).count(sizes, 100), 1);
+        assert_eq!(coverage(80.0).count(sizes, 100), 2);
+        assert_eq!(coverage(81.0).count(sizes, 100), 3);
+        assert_eq!(coverage(100.0).count(sizes, 100), 4);
+        assert_eq!(coverage(0.0).count(sizes, 100), 0);
+        // Files outside of the listed entries count toward the total
+        assert_eq!(coverage(90.0).count(sizes, 200), 4);
+        assert_eq!(Selection::default().count(sizes, 100), 4);
+    }
+
+    #[test]
+    fn test_others_row() {
+        let entry = |name: &str, size| DirectoryEntry {
+            path: PathBuf::from("/srv").join(name),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let mut result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 100,
+            top_directories: vec![
+                entry("a", 50),
+                entry("b", 30),
+                entry("c", 15),
+                entry("d", 5),
+            ],
+            ..Default::default()
+        };
+        let selection = Selection {
+            top: 3,
+            min_size: 20,
+            coverage: None,
+        };
+        let others = Others::split(&mut result.top_directories, &selection, result.total_size);
+        assert_eq!(others, Others { count: 2, size: 20 });
+        assert_eq!(result.top_directories.len(), 2);
+        let all = Selection::default();
+        assert!(Others::split(&mut result.top_directories, &all, 80).is_empty());
+
+        let config = OutputConfig {
+            use_colors: false,
+            others: Some(others),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("│     20 B │   20% │ (other 2 entries) "));
+    }
+
+    #[test]
+    fn test_truncate_to_display_width() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("a-long-name", 8), "a-lon...");
+        // Wide characters take two columns and are never cut in half
+        assert_eq!(truncate("日本語のファイル", 9), "日本語...");
+        assert_eq!(display_width(&truncate("日本語のファイル", 8)), 7);
+        // Combining accents and emoji sequences stay with their base
+        assert_eq!(truncate("cafe\u{301}-menu", 7), "cafe\u{301}...");
+        assert_eq!(truncate("👨‍👩‍👧 family photos", 5), "👨‍👩‍👧...");
+        assert_eq!(truncate("abcdef", 2), "..");
+
+        assert_eq!(pad("日本", 6), "日本  ");
+        assert_eq!(fit("日本語のファイル", 8), "日本... ");
+        assert_eq!(display_width(&fit("résumé", 10)), 10);
+    }
+
+    #[test]
+    fn test_table_aligns_wide_names() {
+        let entry = |name: &str| DirectoryEntry {
+            path: PathBuf::from("/srv").join(name),
+            size: 100,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 300,
+            top_directories: vec![
+                entry("写真とビデオのバックアップ二〇二四年"),
+                entry("🎵 music"),
+                entry("plain"),
+            ],
+            ..Default::default()
+        };
+        let config = OutputConfig {
+            use_colors: false,
+            name_width: 20,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_results(&mut out, &result, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let rows: Vec<&str> = text.lines().filter(|line| line.starts_with('│')).collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].contains("写真とビデオのバ...  │"));
+        let widths: Vec<usize> = rows.iter().map(|row| display_width(row)).collect();
+        assert_eq!(widths, [widths[0]; 3]);
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/var/log"), "/var/log");
+        assert_eq!(shell_quote("my dir"), "'my dir'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_clean_script() {
+        use crate::clean::{Candidate, Category};
+
+        let report = CleanReport {
+            root_path: PathBuf::from("/home/me"),
+            total_size: 10_000_000,
+            candidates: vec![
+                Candidate {
+                    path: PathBuf::from("/home/me/app/target"),
+                    category: Category::BuildOutput,
+                    kind: "cargo target",
+                    size: 3 * 1024 * 1024,
+                    file_count: 10,
+                },
+                Candidate {
+                    path: PathBuf::from("/home/me/bob's app/node_modules"),
+                    category: Category::Dependencies,
+                    kind: "node_modules",
+                    size: 1024,
+                    file_count: 3,
+                },
+            ],
+        };
+
+        let mut sh = Vec::new();
+        write_clean_script(&mut sh, &report, ScriptFormat::Sh).unwrap();
+        let sh = String::from_utf8(sh).unwrap();
+        assert!(sh.starts_with("#!/bin/sh\n"));
+        assert!(sh.contains("# 3.0 M  cargo target\nrm -rf -- /home/me/app/target\n"));
+        assert!(sh.contains("rm -rf -- '/home/me/bob'\\''s app/node_modules'\n"));
+
+        let mut ps1 = Vec::new();
+        write_clean_script(&mut ps1, &report, ScriptFormat::PowerShell).unwrap();
+        let ps1 = String::from_utf8(ps1).unwrap();
+        assert!(ps1.contains(
+            "Remove-Item -LiteralPath '/home/me/bob''s app/node_modules' -Recurse -Force"
+        ));
+    }
+
+    #[test]
+    fn test_clean_script_escapes_paths() {
+        use crate::clean::{Candidate, Category};
+
+        let evil = "/home/me/evil\ntouch /tmp/PWNED\n#/node_modules";
+        let report = CleanReport {
+            root_path: PathBuf::from("/home/me\nrm -rf ~"),
+            total_size: 10_000,
+            candidates: vec![
+                Candidate {
+                    path: PathBuf::from(evil),
+                    category: Category::Dependencies,
+                    kind: "node_modules",
+                    size: 2048,
+                    file_count: 1,
+                },
+                Candidate {
+                    path: PathBuf::from("/home/me/it\u{2019}s; ls/node_modules"),
+                    category: Category::Dependencies,
+                    kind: "node_modules",
+                    size: 1024,
+                    file_count: 1,
+                },
+            ],
+        };
+
+        for format in [ScriptFormat::Sh, ScriptFormat::PowerShell] {
+            let mut script = Vec::new();
+            write_clean_script(&mut script, &report, format).unwrap();
+            let script = String::from_utf8(script).unwrap();
+            for line in script.lines() {
+                assert!(
+                    line.is_empty()
+                        || line.starts_with('#')
+                        || line == "set -eu"
+                        || line == "$ErrorActionPreference = 'Stop'"
+                        || line.starts_with("rm -rf -- '") && line.ends_with('\'')
+                        || line.starts_with("Remove-Item -LiteralPath '")
+                            && line.ends_with("' -Recurse -Force"),
+                    "{:?}",
+                    line
+                );
+            }
+        }
+
+        let mut ps1 = Vec::new();
+        write_clean_script(&mut ps1, &report, ScriptFormat::PowerShell).unwrap();
+        let ps1 = String::from_utf8(ps1).unwrap();
+        assert!(ps1.contains("'/home/me/it\u{2019}\u{2019}s; ls/node_modules'"));
+    }
+
+    #[test]
+    fn test_write_json_schema() {
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data"),
+            total_size: 2048,
+            errors: vec![crate::ScanError {
+                path: PathBuf::from("/data/locked"),
+                kind: crate::ScanErrorKind::PermissionDenied,
+                message: "denied".to_string(),
+            }],
+            ..Default::default()
+        };
+        let config = AnalysisConfig {
+            max_depth: Some(2),
+            exclude_patterns: vec!["*.tmp".to_string()],
+            ..Default::default()
+        };
+        let context = JsonContext {
+            config: Some(&config),
+            started_at: Some(UNIX_EPOCH + std::time::Duration::from_secs(1_709_993_107)),
+            compact: true,
+        };
+
+        let mut out = Vec::new();
+        write_json(&mut out, &result, &context).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        assert_eq!(json.lines().count(), 1);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(value["started_at"], "2024-03-09T14:05:07Z");
+        assert_eq!(value["config"]["max_depth"], 2);
+        assert_eq!(value["config"]["exclude_patterns"][0], "*.tmp");
+        assert_eq!(value["errors"][0]["kind"], "permission_denied");
+        assert_eq!(value["cancelled"], false);
+
+        let mut out = Vec::new();
+        write_json(&mut out, &result, &JsonContext::default()).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert!(value["config"].is_null());
+        assert!(value["started_at"].is_null());
+    }
+
+    #[test]
+    fn test_output_file() {
+        use std::io::Read;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("out.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        // The destination is untouched until the output is committed
+        let mut file = OutputFile::create(&path).unwrap();
+        write!(file, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        file.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+
+        let mut file = OutputFile::create(&path).unwrap();
+        write!(file, "partial").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        let gz_path = temp_dir.path().join("out.json.gz");
+        let mut file = OutputFile::create(&gz_path).unwrap();
+        write!(file, "compressed").unwrap();
+        file.commit().unwrap();
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&gz_path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "compressed");
+    }
 }
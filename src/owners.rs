@@ -0,0 +1,133 @@
+//! Disk usage broken down by file owner and group (Unix only)
+
+use crate::{analyze_with_hooks, AnalysisConfig, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Usage attributed to a single user or group
+#[derive(Debug, Clone)]
+pub struct OwnerUsage {
+    /// Numeric user or group ID
+    pub id: u32,
+    /// Account or group name, when it can be resolved
+    pub name: Option<String>,
+    /// Total disk usage in bytes
+    pub size: u64,
+    /// Number of files owned
+    pub file_count: usize,
+}
+
+impl OwnerUsage {
+    /// Name of the owner, falling back to the numeric ID
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.id.to_string())
+    }
+}
+
+/// Usage per user and per group, each sorted by size
+#[derive(Debug, Default)]
+pub struct OwnerReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Total size of all files in bytes
+    pub total_size: u64,
+    /// Usage by owning user
+    pub users: Vec<OwnerUsage>,
+    /// Usage by owning group
+    pub groups: Vec<OwnerUsage>,
+}
+
+/// Running totals keyed by ID
+type Tally = Mutex<HashMap<u32, (u64, usize)>>;
+
+#[derive(Default)]
+struct OwnerTally {
+    users: Tally,
+    groups: Tally,
+}
+
+impl EntryVisitor for OwnerTally {
+    fn visit_file(&self, _path: &Path, metadata: &std::fs::Metadata, size: u64) {
+        for (tally, id) in [
+            (&self.users, metadata.uid()),
+            (&self.groups, metadata.gid()),
+        ] {
+            let mut tally = tally.lock().unwrap_or_else(|e| e.into_inner());
+            let entry = tally.entry(id).or_default();
+            entry.0 += size;
+            entry.1 += 1;
+        }
+    }
+}
+
+/// Analyze `path`, aggregating sizes by the UID and GID of each file
+pub fn analyze_by_owner(path: &Path, config: &AnalysisConfig) -> Result<OwnerReport> {
+    let tally = OwnerTally::default();
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+
+    let users = collect(tally.users, |uid| {
+        uzers::get_user_by_uid(uid).map(|user| user.name().to_string_lossy().into_owned())
+    });
+    let groups = collect(tally.groups, |gid| {
+        uzers::get_group_by_gid(gid).map(|group| group.name().to_string_lossy().into_owned())
+    });
+
+    Ok(OwnerReport {
+        root_path: result.root_path,
+        total_size: result.total_size,
+        users,
+        groups,
+    })
+}
+
+/// Turn a tally into usages sorted by size, resolving names with `lookup`
+fn collect(tally: Tally, lookup: impl Fn(u32) -> Option<String>) -> Vec<OwnerUsage> {
+    let mut usages: Vec<OwnerUsage> = tally
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|(id, (size, file_count))| OwnerUsage {
+            id,
+            name: lookup(id),
+            size,
+            file_count,
+        })
+        .collect();
+    usages.sort_by(|a, b| b.size.cmp(&a.size).then(a.id.cmp(&b.id)));
+    usages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_analyze_by_owner() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/one"), vec![0u8; 4096]).unwrap();
+        fs::write(temp_dir.path().join("two"), vec![0u8; 8192]).unwrap();
+
+        let report = analyze_by_owner(temp_dir.path(), &AnalysisConfig::default()).unwrap();
+        let uid = fs::metadata(temp_dir.path()).unwrap().uid();
+
+        assert_eq!(report.users.len(), 1);
+        assert_eq!(report.users[0].id, uid);
+        assert_eq!(report.users[0].file_count, 2);
+        assert_eq!(report.users[0].size, report.total_size);
+        assert_eq!(report.groups.iter().map(|g| g.file_count).sum::<usize>(), 2);
+    }
+}
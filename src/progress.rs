@@ -0,0 +1,146 @@
+//! Progress reporting for long-running scans
+
+use crate::DirectoryEntry;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two progress notifications
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Snapshot of a scan in progress
+#[derive(Debug, Clone, Default)]
+pub struct ScanProgress {
+    /// Number of files counted so far
+    pub files_scanned: usize,
+    /// Number of directories read so far
+    pub dirs_scanned: usize,
+    /// Disk usage accumulated so far in bytes
+    pub bytes: u64,
+    /// Directory most recently entered by the walker
+    pub current_path: PathBuf,
+}
+
+/// Receives progress notifications during an analysis
+///
+/// Notifications are throttled and may arrive from any worker thread.
+pub trait ProgressCallback: Send + Sync {
+    /// Called periodically while the scan is running, and once when it ends
+    fn on_progress(&self, progress: &ScanProgress);
+
+    /// Called as soon as an immediate child of the analyzed path is fully aggregated
+    ///
+    /// Entries arrive in completion order, not sorted by size.
+    fn on_entry_complete(&self, _entry: &DirectoryEntry) {}
+}
+
+/// Shared counters the walker updates while scanning
+pub(crate) struct ProgressTracker<'a> {
+    callback: Option<&'a dyn ProgressCallback>,
+    files: AtomicUsize,
+    dirs: AtomicUsize,
+    bytes: AtomicU64,
+    last_report: Mutex<Instant>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(callback: Option<&'a dyn ProgressCallback>) -> Self {
+        Self {
+            callback,
+            files: AtomicUsize::new(0),
+            dirs: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            last_report: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Record a counted file
+    pub(crate) fn add_file(&self, size: u64) {
+        self.add_files(1, size);
+    }
+
+    /// Record `count` files counted at once, totalling `size` bytes
+    pub(crate) fn add_files(&self, count: usize, size: u64) {
+        if self.callback.is_some() {
+            self.files.fetch_add(count, Ordering::Relaxed);
+            self.bytes.fetch_add(size, Ordering::Relaxed);
+        }
+    }
+
+    /// Record entering a directory, notifying the callback if enough time has passed
+    pub(crate) fn enter_dir(&self, path: &Path) {
+        let Some(callback) = self.callback else {
+            return;
+        };
+        self.dirs.fetch_add(1, Ordering::Relaxed);
+
+        // Skip the notification when another thread holds the lock or reported recently
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        if last_report.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        *last_report = Instant::now();
+        drop(last_report);
+
+        callback.on_progress(&self.snapshot(path));
+    }
+
+    /// Forward a finalized top-level entry to the callback
+    pub(crate) fn complete_entry(&self, entry: &DirectoryEntry) {
+        if let Some(callback) = self.callback {
+            callback.on_entry_complete(entry);
+        }
+    }
+
+    /// Send the final counts to the callback
+    pub(crate) fn finish(&self, root: &Path) {
+        if let Some(callback) = self.callback {
+            callback.on_progress(&self.snapshot(root));
+        }
+    }
+
+    fn snapshot(&self, path: &Path) -> ScanProgress {
+        ScanProgress {
+            files_scanned: self.files.load(Ordering::Relaxed),
+            dirs_scanned: self.dirs.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            current_path: path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder(Mutex<Vec<ScanProgress>>);
+
+    impl ProgressCallback for Recorder {
+        fn on_progress(&self, progress: &ScanProgress) {
+            self.0.lock().unwrap().push(progress.clone());
+        }
+    }
+
+    #[test]
+    fn test_tracker_throttles_and_finishes() {
+        let recorder = Recorder(Mutex::new(Vec::new()));
+        let tracker = ProgressTracker::new(Some(&recorder));
+
+        tracker.add_file(100);
+        tracker.add_file(50);
+        tracker.enter_dir(Path::new("/a"));
+        tracker.enter_dir(Path::new("/b"));
+        tracker.finish(Path::new("/"));
+
+        let reports = recorder.0.lock().unwrap();
+        // Both directories were entered within the first interval
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].files_scanned, 2);
+        assert_eq!(reports[0].dirs_scanned, 2);
+        assert_eq!(reports[0].bytes, 150);
+        assert_eq!(reports[0].current_path, Path::new("/"));
+    }
+}
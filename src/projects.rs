@@ -0,0 +1,202 @@
+//! Disk usage per software project rather than per directory
+//!
+//! A project root is a directory holding a manifest (`Cargo.toml`,
+//! `package.json`, ...) or a `.git` entry. Everything below the outermost root
+//! belongs to that project, so workspace members and vendored packages count
+//! towards the project around them. Usage is split into the reclaimable
+//! directories recognized by [`crate::clean`], repository data and sources.
+
+use crate::{analyze_disk_usage, clean, git, AnalysisConfig, DirectoryNode};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Files marking a project root, with the kind of project they stand for
+const MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "cargo"),
+    ("package.json", "npm"),
+    ("pom.xml", "maven"),
+    ("build.gradle", "gradle"),
+    ("build.gradle.kts", "gradle"),
+    ("pyproject.toml", "python"),
+    ("setup.py", "python"),
+    ("go.mod", "go"),
+    (".git", "git"),
+];
+
+/// Usage of a single project
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectUsage {
+    /// Path to the project root
+    pub path: PathBuf,
+    /// Name from the manifest, or of the directory
+    pub name: String,
+    /// Kinds of project detected (`cargo`, `npm`, `git`, ...)
+    pub kinds: Vec<&'static str>,
+    /// Total disk usage in bytes
+    pub size: u64,
+    /// Number of files in the project
+    pub file_count: usize,
+    /// Build output, dependencies and caches (`target/`, `node_modules/`, ...)
+    pub artifacts: u64,
+    /// Repository data below `.git`
+    pub git: u64,
+}
+
+impl ProjectUsage {
+    /// Everything that is neither an artifact nor repository data
+    pub fn sources(&self) -> u64 {
+        self.size - self.artifacts - self.git
+    }
+}
+
+/// Projects found below a root
+#[derive(Debug, Default)]
+pub struct ProjectReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Total size of the analyzed tree in bytes
+    pub total_size: u64,
+    /// Projects sorted by size, largest first
+    pub projects: Vec<ProjectUsage>,
+}
+
+impl ProjectReport {
+    /// Usage outside of any project in bytes
+    pub fn unassigned(&self) -> u64 {
+        self.total_size - self.projects.iter().map(|p| p.size).sum::<u64>()
+    }
+}
+
+/// Kinds of project whose markers are found in `path`
+pub fn detect(path: &Path) -> Vec<&'static str> {
+    let mut kinds: Vec<&'static str> = MARKERS
+        .iter()
+        .filter(|(marker, _)| path.join(marker).exists())
+        .map(|&(_, kind)| kind)
+        .collect();
+    kinds.dedup();
+    kinds
+}
+
+/// Name of the project at `path`, from its Cargo.toml or package.json when they
+/// give one
+fn project_name(path: &Path) -> String {
+    let from_cargo = || {
+        let manifest: toml::Table = std::fs::read_to_string(path.join("Cargo.toml"))
+            .ok()?
+            .parse()
+            .ok()?;
+        Some(manifest.get("package")?.get("name")?.as_str()?.to_string())
+    };
+    let from_npm = || {
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path.join("package.json")).ok()?).ok()?;
+        Some(manifest.get("name")?.as_str()?.to_string())
+    };
+    from_cargo()
+        .or_else(from_npm)
+        .or_else(|| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Find the projects below `path`, the root included
+pub fn find_projects(path: &Path, config: &AnalysisConfig) -> Result<ProjectReport> {
+    let config = AnalysisConfig {
+        build_tree: true,
+        ..config.clone()
+    };
+    let result = analyze_disk_usage(path, &config, 0)?;
+    let tree = result.tree.context("Analysis did not produce a tree")?;
+
+    let mut projects = Vec::new();
+    collect(&tree, &mut projects);
+    projects.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    Ok(ProjectReport {
+        root_path: result.root_path,
+        total_size: result.total_size,
+        projects,
+    })
+}
+
+fn collect(node: &DirectoryNode, projects: &mut Vec<ProjectUsage>) {
+    let kinds = detect(&node.path);
+    if kinds.is_empty() {
+        // Packages unpacked in caches (~/.cargo/registry, ...) are not projects
+        if clean::classify(&node.path).is_some() {
+            return;
+        }
+        for child in &node.children {
+            collect(child, projects);
+        }
+        return;
+    }
+
+    let mut artifacts = 0;
+    let mut pending: Vec<&DirectoryNode> = node.children.iter().collect();
+    while let Some(dir) = pending.pop() {
+        if dir.path.ends_with(".git") {
+            continue;
+        }
+        match clean::classify(&dir.path) {
+            Some(_) => artifacts += dir.size,
+            None => pending.extend(&dir.children),
+        }
+    }
+
+    projects.push(ProjectUsage {
+        path: node.path.clone(),
+        name: project_name(&node.path),
+        kinds,
+        size: node.size,
+        file_count: node.file_count,
+        artifacts,
+        git: git::child(node, ".git").map_or(0, |git| git.size),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_find_projects() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        write(
+            &root.join("tool/Cargo.toml"),
+            b"[package]\nname = \"mytool\"\nversion = \"0.1.0\"\n",
+        );
+        write(&root.join("tool/.git/HEAD"), b"ref: refs/heads/main\n");
+        write(&root.join("tool/src/main.rs"), &[1; 4_000]);
+        write(&root.join("tool/target/debug/mytool"), &[1; 80_000]);
+        // A workspace member belongs to the project around it
+        write(&root.join("tool/crates/core/Cargo.toml"), b"[package]\n");
+        write(&root.join("site/package.json"), b"{\"name\": \"website\"}");
+        write(&root.join("site/node_modules/react/index.js"), &[1; 20_000]);
+        write(&root.join("site/app/styles.css"), &[1; 12_000]);
+        write(&root.join("Downloads/movie.mp4"), &[1; 40_000]);
+
+        let report = find_projects(&root, &AnalysisConfig::default()).unwrap();
+        let names: Vec<&str> = report.projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["mytool", "website"]);
+
+        let tool = &report.projects[0];
+        assert_eq!(tool.kinds, vec!["cargo", "git"]);
+        assert!(tool.artifacts >= 80_000);
+        assert!(tool.git > 0);
+        assert!(tool.sources() >= 4_000 && tool.sources() < tool.artifacts);
+
+        let site = &report.projects[1];
+        assert_eq!(site.kinds, vec!["npm"]);
+        assert!(site.artifacts >= 20_000 && site.sources() >= 12_000);
+        assert!(report.unassigned() >= 40_000);
+    }
+}
@@ -0,0 +1,215 @@
+//! Analyzing directories on other machines over SSH
+//!
+//! The local `ssh` client runs `dutop --format ndjson` on the remote host and
+//! the streamed records are turned back into an [`AnalysisResult`], so remote
+//! results go through the same output formats as local ones. Authentication,
+//! host keys and connection options are left to ssh and its configuration.
+
+use crate::output::shell_quote;
+use crate::{AnalysisResult, DirectoryEntry};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Exit status of a remote shell that could not find the command
+const COMMAND_NOT_FOUND: i32 = 127;
+
+/// A directory on a remote host, written `[user@]host:/path`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteTarget {
+    /// SSH destination (`host` or `user@host`)
+    pub destination: String,
+    /// Directory to analyze on the remote host
+    pub path: String,
+}
+
+impl std::str::FromStr for RemoteTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(target: &str) -> Result<Self> {
+        let (destination, path) = target
+            .split_once(':')
+            .filter(|(destination, _)| !destination.is_empty())
+            .with_context(|| format!("Expected [user@]host:/path, got: {}", target))?;
+        Ok(Self {
+            destination: destination.to_string(),
+            path: if path.is_empty() { "." } else { path }.to_string(),
+        })
+    }
+}
+
+/// Records written by `dutop --format ndjson`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record {
+    Entry {
+        path: PathBuf,
+        size: u64,
+        file_count: usize,
+        dir_count: usize,
+    },
+    Summary {
+        path: PathBuf,
+        total_size: u64,
+        file_count: usize,
+        directory_count: usize,
+        #[serde(default)]
+        cancelled: bool,
+    },
+}
+
+/// Analyze `target` by running `remote_command` on the remote host through ssh
+///
+/// `remote_args` are passed on to the remote command (e.g. `--exclude`
+/// patterns), and `top_n` entries are kept in the result.
+pub fn analyze_remote(
+    target: &RemoteTarget,
+    remote_command: &str,
+    remote_args: &[String],
+    top_n: usize,
+) -> Result<AnalysisResult> {
+    // ssh hands the command line to the remote shell, so every word is quoted
+    let mut command_line = vec![
+        remote_command.to_string(),
+        "--format".to_string(),
+        "ndjson".to_string(),
+        "--no-progress".to_string(),
+    ];
+    command_line.extend(remote_args.iter().cloned());
+    command_line.push("--".to_string());
+    command_line.push(target.path.clone());
+    let command_line: Vec<String> = command_line.iter().map(|arg| shell_quote(arg)).collect();
+
+    log::info!(
+        "Running on {}: {}",
+        target.destination,
+        command_line.join(" ")
+    );
+    let mut child = Command::new("ssh")
+        .arg("--")
+        .arg(&target.destination)
+        .args(&command_line)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run ssh")?;
+
+    let stdout = child.stdout.take().context("Failed to read ssh output")?;
+    let result = read_ndjson(BufReader::new(stdout), top_n);
+    let status = child.wait().context("Failed to wait for ssh")?;
+
+    match status.code() {
+        Some(0) => result,
+        Some(COMMAND_NOT_FOUND) => anyhow::bail!(
+            "{} was not found on {} (use --remote-command to give its path)",
+            remote_command,
+            target.destination
+        ),
+        _ => anyhow::bail!(
+            "Remote analysis on {} failed ({})",
+            target.destination,
+            status
+        ),
+    }
+}
+
+/// Rebuild an analysis from NDJSON records, keeping the `top_n` largest entries
+pub fn read_ndjson(reader: impl BufRead, top_n: usize) -> Result<AnalysisResult> {
+    let mut directories = Vec::new();
+    let mut result = None;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid NDJSON record: {}", line))?;
+        match record {
+            Record::Entry {
+                path,
+                size,
+                file_count,
+                dir_count,
+            } => directories.push(DirectoryEntry {
+                path,
+                size,
+                file_count,
+                dir_count,
+                modified: None,
+            }),
+            Record::Summary {
+                path,
+                total_size,
+                file_count,
+                directory_count,
+                cancelled,
+            } => {
+                result = Some(AnalysisResult {
+                    root_path: path,
+                    total_size,
+                    total_files: file_count,
+                    total_dirs: directory_count,
+                    cancelled,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    let mut result = result.context("No summary record in the NDJSON output")?;
+    directories.sort_unstable_by_key(|d| std::cmp::Reverse(d.size));
+    directories.truncate(top_n);
+    result.top_directories = directories;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let target: RemoteTarget = "admin@web1:/var/log".parse().unwrap();
+        assert_eq!(target.destination, "admin@web1");
+        assert_eq!(target.path, "/var/log");
+
+        let target: RemoteTarget = "web1:".parse().unwrap();
+        assert_eq!(target.path, ".");
+
+        assert!("/var/log".parse::<RemoteTarget>().is_err());
+        assert!(":/var/log".parse::<RemoteTarget>().is_err());
+    }
+
+    #[test]
+    fn test_read_ndjson() {
+        let output = r#"{"type":"entry","path":"/srv/a","size":100,"size_human":"100 B","file_count":1,"dir_count":0}
+{"type":"entry","path":"/srv/b","size":300,"size_human":"300 B","file_count":2,"dir_count":1}
+{"type":"entry","path":"/srv/c","size":200,"size_human":"200 B","file_count":1,"dir_count":0}
+{"type":"summary","path":"/srv","total_size":600,"total_size_human":"600 B","file_count":4,"directory_count":1,"cancelled":false}
+"#;
+        let result = read_ndjson(output.as_bytes(), 2).unwrap();
+
+        assert_eq!(result.root_path, PathBuf::from("/srv"));
+        assert_eq!(result.total_size, 600);
+        assert_eq!(result.total_files, 4);
+        let paths: Vec<_> = result
+            .top_directories
+            .iter()
+            .map(|d| d.path.clone())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("/srv/b"), PathBuf::from("/srv/c")]
+        );
+
+        assert!(read_ndjson(
+            &b"{\"type\":\"entry\",\"path\":\"/a\",\"size\":1,\"file_count\":1,\"dir_count\":0}\n"
+                [..],
+            10
+        )
+        .is_err());
+    }
+}
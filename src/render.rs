@@ -0,0 +1,423 @@
+//! Treemap rendering
+//!
+//! Directories are laid out with the squarified treemap algorithm (Bruls,
+//! Huizing and van Wijk), which keeps cells close to square so sizes are easy
+//! to compare at a glance. Nested levels are drawn inside their parent cell
+//! below a small header carrying the parent's name.
+
+use crate::format::format_size_auto;
+use crate::{AnalysisResult, DirectoryNode};
+use anyhow::Context;
+use std::fmt::Write;
+use std::io;
+
+/// Default width of the rendered SVG in pixels
+pub const DEFAULT_WIDTH: f64 = 1200.0;
+/// Default height of the rendered SVG in pixels
+pub const DEFAULT_HEIGHT: f64 = 800.0;
+/// Default number of nested levels drawn
+pub const DEFAULT_DEPTH: usize = 3;
+
+/// Height of the header strip showing a directory's name above its children
+const HEADER_HEIGHT: f64 = 16.0;
+/// Cells smaller than this (in either dimension) are not subdivided or labeled
+const MIN_CELL: f64 = 4.0;
+
+const PALETTE: &[(u8, u8, u8)] = &[
+    (31, 119, 180),
+    (255, 127, 14),
+    (44, 160, 44),
+    (214, 39, 40),
+    (148, 103, 189),
+    (140, 86, 75),
+    (227, 119, 194),
+    (23, 190, 207),
+    (188, 189, 34),
+];
+
+/// Axis-aligned rectangle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    fn area(&self) -> f64 {
+        self.width * self.height
+    }
+}
+
+/// Lay out `sizes` inside `bounds` using squarified treemaps
+///
+/// `sizes` must be sorted in descending order. The returned rectangles are in
+/// the same order; zero sizes get empty rectangles.
+pub fn squarify(sizes: &[u64], bounds: Rect) -> Vec<Rect> {
+    let total: u64 = sizes.iter().sum();
+    let mut rects = vec![
+        Rect {
+            x: bounds.x,
+            y: bounds.y,
+            width: 0.0,
+            height: 0.0,
+        };
+        sizes.len()
+    ];
+    if total == 0 || bounds.area() <= 0.0 {
+        return rects;
+    }
+
+    let scale = bounds.area() / total as f64;
+    let areas: Vec<f64> = sizes.iter().map(|&s| s as f64 * scale).collect();
+
+    let mut remaining = bounds;
+    let mut row_start = 0;
+    let mut index = 0;
+    while index < areas.len() {
+        if areas[index] <= 0.0 {
+            index += 1;
+            continue;
+        }
+
+        let side = remaining.width.min(remaining.height);
+        let row = &areas[row_start..index];
+        let extended = &areas[row_start..=index];
+        if row.is_empty() || worst_ratio(extended, side) <= worst_ratio(row, side) {
+            index += 1;
+        } else {
+            remaining = place_row(&areas, row_start..index, remaining, &mut rects);
+            row_start = index;
+        }
+    }
+    if row_start < areas.len() {
+        place_row(&areas, row_start..areas.len(), remaining, &mut rects);
+    }
+
+    rects
+}
+
+/// Worst aspect ratio of a row of areas laid along `side`
+fn worst_ratio(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let (min, max) = row
+        .iter()
+        .filter(|&&a| a > 0.0)
+        .fold((f64::INFINITY, 0.0f64), |(min, max), &a| {
+            (min.min(a), max.max(a))
+        });
+    let side_sq = side * side;
+    let sum_sq = sum * sum;
+    (side_sq * max / sum_sq).max(sum_sq / (side_sq * min))
+}
+
+/// Place a row along the shorter side of `bounds`, returning the space left
+fn place_row(areas: &[f64], row: std::ops::Range<usize>, bounds: Rect, rects: &mut [Rect]) -> Rect {
+    let sum: f64 = areas[row.clone()].iter().sum();
+
+    if bounds.width >= bounds.height {
+        let width = sum / bounds.height;
+        let mut y = bounds.y;
+        for i in row {
+            let height = areas[i] / width;
+            rects[i] = Rect {
+                x: bounds.x,
+                y,
+                width,
+                height,
+            };
+            y += height;
+        }
+        Rect {
+            x: bounds.x + width,
+            width: bounds.width - width,
+            ..bounds
+        }
+    } else {
+        let height = sum / bounds.width;
+        let mut x = bounds.x;
+        for i in row {
+            let width = areas[i] / height;
+            rects[i] = Rect {
+                x,
+                y: bounds.y,
+                width,
+                height,
+            };
+            x += width;
+        }
+        Rect {
+            y: bounds.y + height,
+            height: bounds.height - height,
+            ..bounds
+        }
+    }
+}
+
+/// Render `tree` as an SVG treemap of the given size, nesting up to `depth` levels
+pub fn treemap_svg(tree: &DirectoryNode, width: f64, height: f64, depth: usize) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="Helvetica, Arial, sans-serif" font-size="11">"#,
+        w = width,
+        h = height
+    );
+    let _ = writeln!(
+        svg,
+        r#"<title>{} ({})</title>"#,
+        escape_xml(&tree.path.display().to_string()),
+        format_size_auto(tree.size)
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect x="0" y="0" width="{}" height="{}" fill="#f0f0f0"/>"##,
+        width, height
+    );
+
+    let bounds = Rect {
+        x: 0.0,
+        y: 0.0,
+        width,
+        height,
+    };
+    render_children(&mut svg, tree, bounds, depth, 1, None);
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Print an SVG treemap of the analyzed tree to stdout
+pub fn print_svg(result: &AnalysisResult) -> anyhow::Result<()> {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    write_svg(&mut out, result)?;
+    io::Write::flush(&mut out)?;
+    Ok(())
+}
+
+/// Write an SVG treemap of the analyzed tree to `out` using the default dimensions
+pub fn write_svg(out: &mut impl io::Write, result: &AnalysisResult) -> anyhow::Result<()> {
+    let tree = result
+        .tree
+        .as_ref()
+        .context("treemap rendering requires the full directory tree")?;
+    let svg = treemap_svg(tree, DEFAULT_WIDTH, DEFAULT_HEIGHT, DEFAULT_DEPTH);
+    out.write_all(svg.as_bytes())?;
+    Ok(())
+}
+
+/// Draw the children of `node` (plus a cell for its own files) inside `bounds`
+fn render_children(
+    svg: &mut String,
+    node: &DirectoryNode,
+    bounds: Rect,
+    max_depth: usize,
+    level: usize,
+    color: Option<(u8, u8, u8)>,
+) {
+    let own_size = node.own_size();
+    let mut sizes: Vec<u64> = node.children.iter().map(|c| c.size).collect();
+    sizes.push(own_size);
+    // Children are sorted by size; keep the combined list sorted for the layout
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+    let sorted: Vec<u64> = order.iter().map(|&i| sizes[i]).collect();
+
+    let rects = squarify(&sorted, bounds);
+    for (position, &index) in order.iter().enumerate() {
+        let rect = rects[position];
+        if rect.width < 1.0 || rect.height < 1.0 {
+            continue;
+        }
+
+        match node.children.get(index) {
+            Some(child) => {
+                let base = color.unwrap_or(PALETTE[index % PALETTE.len()]);
+                let fill = shade(base, level);
+                let name = child
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| child.path.display().to_string());
+                let tooltip = format!("{}\n{}", child.path.display(), format_size_auto(child.size));
+                draw_cell(svg, rect, &fill, &tooltip);
+
+                let nested = level < max_depth
+                    && !child.children.is_empty()
+                    && rect.height > HEADER_HEIGHT + MIN_CELL * 2.0
+                    && rect.width > MIN_CELL * 2.0;
+                if nested {
+                    draw_label(
+                        svg,
+                        rect,
+                        &format!("{} {}", name, format_size_auto(child.size)),
+                    );
+                    let inner = Rect {
+                        x: rect.x + 2.0,
+                        y: rect.y + HEADER_HEIGHT,
+                        width: rect.width - 4.0,
+                        height: rect.height - HEADER_HEIGHT - 2.0,
+                    };
+                    render_children(svg, child, inner, max_depth, level + 1, Some(base));
+                } else {
+                    draw_label(
+                        svg,
+                        rect,
+                        &format!("{} {}", name, format_size_auto(child.size)),
+                    );
+                }
+            }
+            None => {
+                let tooltip = format!(
+                    "{} (files)\n{}",
+                    node.path.display(),
+                    format_size_auto(own_size)
+                );
+                draw_cell(svg, rect, "#999999", &tooltip);
+                draw_label(svg, rect, "(files)");
+            }
+        }
+    }
+}
+
+fn draw_cell(svg: &mut String, rect: Rect, fill: &str, tooltip: &str) {
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="#ffffff"><title>{}</title></rect>"##,
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height,
+        fill,
+        escape_xml(tooltip)
+    );
+}
+
+/// Label a cell if there is room, truncating to the cell width
+fn draw_label(svg: &mut String, rect: Rect, text: &str) {
+    if rect.width < 30.0 || rect.height < 14.0 {
+        return;
+    }
+    // Roughly 6.5px per character at 11px
+    let max_chars = ((rect.width - 6.0) / 6.5) as usize;
+    let label: String = if text.chars().count() > max_chars {
+        text.chars()
+            .take(max_chars.saturating_sub(1))
+            .chain(['…'])
+            .collect()
+    } else {
+        text.to_string()
+    };
+    let _ = writeln!(
+        svg,
+        r##"<text x="{:.1}" y="{:.1}" fill="#ffffff" pointer-events="none">{}</text>"##,
+        rect.x + 3.0,
+        rect.y + 12.0,
+        escape_xml(&label)
+    );
+}
+
+/// Lighten a base color for deeper levels
+fn shade((r, g, b): (u8, u8, u8), level: usize) -> String {
+    let factor = (0.18 * level.saturating_sub(1) as f64).min(0.6);
+    let mix = |c: u8| (c as f64 + (255.0 - c as f64) * factor).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", mix(r), mix(g), mix(b))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_squarify_fills_bounds() {
+        let bounds = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 6.0,
+            height: 4.0,
+        };
+        let rects = squarify(&[6, 6, 4, 3, 2, 2, 1], bounds);
+
+        let area: f64 = rects.iter().map(Rect::area).sum();
+        assert!((area - 24.0).abs() < 1e-9);
+        // The classic example places the two largest items in the first column
+        assert_eq!(
+            rects[0],
+            Rect {
+                x: 0.0,
+                y: 0.0,
+                width: 3.0,
+                height: 2.0
+            }
+        );
+        assert_eq!(
+            rects[1],
+            Rect {
+                x: 0.0,
+                y: 2.0,
+                width: 3.0,
+                height: 2.0
+            }
+        );
+        for rect in &rects {
+            assert!(rect.x >= 0.0 && rect.x + rect.width <= 6.0 + 1e-9);
+            assert!(rect.y >= 0.0 && rect.y + rect.height <= 4.0 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_squarify_handles_zero_sizes() {
+        let bounds = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+        };
+        assert!(squarify(&[0, 0], bounds).iter().all(|r| r.area() == 0.0));
+
+        let rects = squarify(&[5, 0], bounds);
+        assert!((rects[0].area() - 100.0).abs() < 1e-9);
+        assert_eq!(rects[1].area(), 0.0);
+    }
+
+    #[test]
+    fn test_treemap_svg() {
+        let tree = DirectoryNode {
+            path: PathBuf::from("/data"),
+            size: 300,
+            file_count: 3,
+            dir_count: 1,
+            children: vec![DirectoryNode {
+                path: PathBuf::from("/data/a&b"),
+                size: 200,
+                file_count: 2,
+                dir_count: 0,
+                children: Vec::new(),
+                files: Vec::new(),
+            }],
+            files: Vec::new(),
+        };
+
+        let svg = treemap_svg(&tree, 400.0, 300.0, DEFAULT_DEPTH);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("a&amp;b"));
+        assert!(svg.contains("(files)"));
+    }
+
+    #[test]
+    fn test_write_svg_requires_tree() {
+        let mut out = Vec::new();
+        assert!(write_svg(&mut out, &AnalysisResult::default()).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+//! Self-contained HTML reports
+//!
+//! A report embeds the scan results as JSON in a single HTML page with a
+//! sortable table of the top directories and a zoomable treemap, so it can be
+//! attached to tickets and opened in any browser without network access.
+
+use crate::format::format_size_auto;
+use crate::{AnalysisResult, DirectoryNode};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Maximum number of tree nodes embedded in a report
+///
+/// The largest directories are kept so the page stays responsive on huge scans.
+pub const DEFAULT_MAX_NODES: usize = 2000;
+
+/// Tree node as embedded in the page
+#[derive(Serialize)]
+struct ReportNode {
+    /// Name
+    n: String,
+    /// Full path
+    p: String,
+    /// Size in bytes
+    s: u64,
+    /// File count
+    f: usize,
+    /// Directory count
+    d: usize,
+    /// Children
+    c: Vec<ReportNode>,
+}
+
+/// Row of the top directories table
+#[derive(Serialize)]
+struct ReportRow {
+    path: String,
+    size: u64,
+    files: usize,
+    dirs: usize,
+}
+
+#[derive(Serialize)]
+struct ReportData {
+    root: String,
+    total: u64,
+    files: usize,
+    dirs: usize,
+    rows: Vec<ReportRow>,
+    tree: Option<ReportNode>,
+}
+
+/// Write an HTML report for `result` to the file at `path`
+pub fn save_html_report(result: &AnalysisResult, path: &Path) -> Result<()> {
+    let mut html = Vec::new();
+    write_html_report(&mut html, result, DEFAULT_MAX_NODES)?;
+    fs::write(path, html).context(format!("Failed to write report: {}", path.display()))
+}
+
+/// Render an HTML report for `result`, embedding at most `max_nodes` tree nodes
+pub fn write_html_report(
+    out: &mut impl Write,
+    result: &AnalysisResult,
+    max_nodes: usize,
+) -> Result<()> {
+    let data = ReportData {
+        root: result.root_path.display().to_string(),
+        total: result.total_size,
+        files: result.total_files,
+        dirs: result.total_dirs,
+        rows: result
+            .top_directories
+            .iter()
+            .map(|d| ReportRow {
+                path: d.path.display().to_string(),
+                size: d.size,
+                files: d.file_count,
+                dirs: d.dir_count,
+            })
+            .collect(),
+        tree: result.tree.as_ref().map(|tree| prune_tree(tree, max_nodes)),
+    };
+
+    // Keep the embedded JSON from closing the surrounding <script> element
+    let json = serde_json::to_string(&data)?.replace("</", "<\\/");
+    let title = format!(
+        "DuTop report: {} ({})",
+        result.root_path.display(),
+        format_size_auto(result.total_size)
+    );
+
+    // Both placeholders are filled in one pass, so neither value is searched
+    // for the other's marker
+    let (head, rest) = TEMPLATE
+        .split_once("{{TITLE}}")
+        .expect("template has a title");
+    let (middle, tail) = rest.split_once("{{DATA}}").expect("template has data");
+    for part in [
+        head,
+        &escape_html(&title).replace('{', "&#123;"),
+        middle,
+        &json,
+        tail,
+    ] {
+        out.write_all(part.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Keep the `max_nodes` largest nodes of the tree, always including ancestors
+///
+/// Nodes are selected greedily by size: a node only becomes a candidate once
+/// its parent has been selected, so the result is a connected subtree.
+fn prune_tree(tree: &DirectoryNode, max_nodes: usize) -> ReportNode {
+    // Heap of (size, path to node as child indices)
+    let mut heap = BinaryHeap::new();
+    let mut selected: Vec<Vec<usize>> = Vec::new();
+    heap.push((tree.size, Vec::<usize>::new()));
+
+    while let Some((_, route)) = heap.pop() {
+        if selected.len() >= max_nodes.max(1) {
+            break;
+        }
+        let node = follow(tree, &route);
+        for (index, child) in node.children.iter().enumerate() {
+            let mut child_route = route.clone();
+            child_route.push(index);
+            heap.push((child.size, child_route));
+        }
+        selected.push(route);
+    }
+
+    selected.sort();
+    build_node(tree, &[], &selected)
+}
+
+fn follow<'a>(tree: &'a DirectoryNode, route: &[usize]) -> &'a DirectoryNode {
+    route
+        .iter()
+        .fold(tree, |node, &index| &node.children[index])
+}
+
+fn build_node(node: &DirectoryNode, route: &[usize], selected: &[Vec<usize>]) -> ReportNode {
+    let children = node
+        .children
+        .iter()
+        .enumerate()
+        .filter_map(|(index, child)| {
+            let mut child_route = route.to_vec();
+            child_route.push(index);
+            selected
+                .binary_search(&child_route)
+                .ok()
+                .map(|_| build_node(child, &child_route, selected))
+        })
+        .collect();
+
+    ReportNode {
+        n: node
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| node.path.display().to_string()),
+        p: node.path.display().to_string(),
+        s: node.size,
+        f: node.file_count,
+        d: node.dir_count,
+        c: children,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+  body { font: 14px/1.4 -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 24px; color: #222; }
+  h1 { font-size: 20px; margin: 0 0 4px; }
+  .summary { color: #555; margin-bottom: 20px; }
+  #crumbs { margin: 8px 0; }
+  #crumbs a { color: #0366d6; cursor: pointer; text-decoration: none; }
+  #treemap { position: relative; width: 100%; height: 480px; background: #eee; border: 1px solid #ccc; }
+  .cell { position: absolute; box-sizing: border-box; border: 1px solid #fff; overflow: hidden;
+          font-size: 12px; color: #fff; padding: 2px 4px; cursor: pointer; white-space: nowrap; }
+  .cell.files { background: #999 !important; cursor: default; }
+  table { border-collapse: collapse; margin-top: 24px; width: 100%; }
+  th, td { padding: 4px 8px; border-bottom: 1px solid #ddd; text-align: right; }
+  th { cursor: pointer; user-select: none; background: #f6f6f6; }
+  th:first-child, td:first-child { text-align: left; }
+</style>
+</head>
+<body>
+<h1>Disk usage report</h1>
+<div class="summary" id="summary"></div>
+<div id="crumbs"></div>
+<div id="treemap"></div>
+<table id="table">
+  <thead><tr>
+    <th data-key="path">Path</th><th data-key="size">Size</th><th data-key="size">%</th>
+    <th data-key="files">Files</th><th data-key="dirs">Dirs</th>
+  </tr></thead>
+  <tbody></tbody>
+</table>
+<script>
+const DATA = {{DATA}};
+
+function human(bytes) {
+  const units = ["B", "K", "M", "G", "T", "P"];
+  let size = bytes, unit = 0;
+  while (size >= 1024 && unit < units.length - 1) { size /= 1024; unit++; }
+  return unit === 0 ? bytes + " B" : size.toFixed(1) + " " + units[unit];
+}
+
+function text(tag, value) {
+  const el = document.createElement(tag);
+  el.textContent = value;
+  return el;
+}
+
+document.getElementById("summary").textContent =
+  DATA.root + " — " + human(DATA.total) + ", " + DATA.files + " files, " + DATA.dirs + " directories";
+
+// Sortable table of the top directories
+let sortKey = "size", ascending = false;
+function renderTable() {
+  const rows = DATA.rows.slice().sort((a, b) => {
+    const x = a[sortKey], y = b[sortKey];
+    const order = x < y ? -1 : x > y ? 1 : 0;
+    return ascending ? order : -order;
+  });
+  const body = document.querySelector("#table tbody");
+  body.innerHTML = "";
+  for (const row of rows) {
+    const tr = document.createElement("tr");
+    const pct = DATA.total ? (row.size / DATA.total * 100).toFixed(1) + "%" : "0%";
+    [row.path, human(row.size), pct, row.files, row.dirs].forEach(v => tr.appendChild(text("td", v)));
+    body.appendChild(tr);
+  }
+}
+document.querySelectorAll("#table th").forEach(th => th.addEventListener("click", () => {
+  const key = th.dataset.key;
+  ascending = key === sortKey ? !ascending : key === "path";
+  sortKey = key;
+  renderTable();
+}));
+renderTable();
+
+// Squarified treemap
+function worst(row, side) {
+  let sum = 0, max = 0, min = Infinity;
+  for (const r of row) { sum += r.area; max = Math.max(max, r.area); min = Math.min(min, r.area); }
+  return Math.max(side * side * max / (sum * sum), (sum * sum) / (side * side * min));
+}
+
+function place(row, box, out) {
+  const sum = row.reduce((s, r) => s + r.area, 0);
+  if (box.w >= box.h) {
+    const width = sum / box.h;
+    let y = box.y;
+    for (const r of row) { const h = r.area / width; out.push({ item: r.item, x: box.x, y, w: width, h }); y += h; }
+    return { x: box.x + width, y: box.y, w: box.w - width, h: box.h };
+  }
+  const height = sum / box.w;
+  let x = box.x;
+  for (const r of row) { const w = r.area / height; out.push({ item: r.item, x, y: box.y, w, h: height }); x += w; }
+  return { x: box.x, y: box.y + height, w: box.w, h: box.h - height };
+}
+
+function squarify(items, box) {
+  const total = items.reduce((s, i) => s + i.size, 0);
+  if (!total || box.w <= 0 || box.h <= 0) return [];
+  const scale = box.w * box.h / total;
+  const queue = items.filter(i => i.size > 0).map(item => ({ item, area: item.size * scale }));
+  const out = [];
+  let row = [];
+  while (queue.length) {
+    const side = Math.min(box.w, box.h);
+    if (row.length === 0 || worst(row.concat([queue[0]]), side) <= worst(row, side)) {
+      row.push(queue.shift());
+    } else {
+      box = place(row, box, out);
+      row = [];
+    }
+  }
+  if (row.length) place(row, box, out);
+  return out;
+}
+
+const colors = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#17becf", "#bcbd22"];
+let trail = [];
+
+function renderTreemap() {
+  const container = document.getElementById("treemap");
+  container.innerHTML = "";
+  const node = trail[trail.length - 1];
+  if (!node) { container.textContent = "No tree data in this report"; return; }
+
+  const items = node.c.map(c => ({ size: c.s, node: c }));
+  const own = node.s - node.c.reduce((s, c) => s + c.s, 0);
+  if (own > 0) items.push({ size: own, node: null });
+  items.sort((a, b) => b.size - a.size);
+
+  const rects = squarify(items, { x: 0, y: 0, w: container.clientWidth, h: container.clientHeight });
+  rects.forEach((r, i) => {
+    const cell = document.createElement("div");
+    cell.className = "cell" + (r.item.node ? "" : " files");
+    Object.assign(cell.style, { left: r.x + "px", top: r.y + "px", width: r.w + "px", height: r.h + "px",
+                                background: colors[i % colors.length] });
+    const label = (r.item.node ? r.item.node.n : "(files)") + " " + human(r.item.size);
+    cell.title = (r.item.node ? r.item.node.p : node.p + " (files)") + "\n" + human(r.item.size);
+    if (r.w > 40 && r.h > 14) cell.textContent = label;
+    if (r.item.node && r.item.node.c.length) {
+      cell.addEventListener("click", () => { trail.push(r.item.node); render(); });
+    }
+    container.appendChild(cell);
+  });
+}
+
+function renderCrumbs() {
+  const crumbs = document.getElementById("crumbs");
+  crumbs.innerHTML = "";
+  trail.forEach((node, i) => {
+    if (i > 0) crumbs.appendChild(document.createTextNode(" / "));
+    const link = text("a", i === 0 ? node.p : node.n);
+    link.addEventListener("click", () => { trail = trail.slice(0, i + 1); render(); });
+    crumbs.appendChild(link);
+  });
+}
+
+function render() { renderCrumbs(); renderTreemap(); }
+if (DATA.tree) trail = [DATA.tree];
+window.addEventListener("resize", renderTreemap);
+render();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn node(path: &str, size: u64, children: Vec<DirectoryNode>) -> DirectoryNode {
+        DirectoryNode {
+            path: PathBuf::from(path),
+            size,
+            file_count: 0,
+            dir_count: children.len(),
+            children,
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_prune_tree_keeps_largest_connected_nodes() {
+        let tree = node(
+            "/r",
+            100,
+            vec![
+                node("/r/a", 60, vec![node("/r/a/x", 50, vec![])]),
+                node("/r/b", 30, vec![node("/r/b/y", 20, vec![])]),
+            ],
+        );
+
+        let pruned = prune_tree(&tree, 3);
+        assert_eq!(pruned.c.len(), 1);
+        assert_eq!(pruned.c[0].n, "a");
+        assert_eq!(pruned.c[0].c[0].p, "/r/a/x");
+
+        let full = prune_tree(&tree, 10);
+        assert_eq!(full.c.len(), 2);
+        assert_eq!(full.c[1].c.len(), 1);
+    }
+
+    #[test]
+    fn test_write_html_report_escapes_embedded_data() {
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data/</script>"),
+            total_size: 10,
+            tree: Some(node("/data/</script>", 10, vec![])),
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_html_report(&mut out, &result, DEFAULT_MAX_NODES).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.contains("<title>DuTop report: /data/&lt;/script&gt;"));
+        assert!(html.contains("/data/<\\/script>"));
+        assert_eq!(html.matches("</script>").count(), 1);
+    }
+
+    #[test]
+    fn test_write_html_report_fills_placeholders_once() {
+        let result = AnalysisResult {
+            root_path: PathBuf::from("/data/{{DATA}}"),
+            total_size: 10,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_html_report(&mut out, &result, DEFAULT_MAX_NODES).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.contains("<title>DuTop report: /data/&#123;&#123;DATA}} (10 B)</title>"));
+        assert_eq!(html.matches("\"total\":10").count(), 1);
+        assert_eq!(html.matches("/data/{{DATA}}").count(), 1);
+    }
+}
@@ -0,0 +1,157 @@
+//! Analyzing S3 buckets
+//!
+//! Objects are listed through the S3 API and their keys split on `/`, so
+//! common prefixes show up as directories and the usual output formats apply.
+//! Credentials, region and endpoint come from the standard environment
+//! variables (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_REGION`,
+//! `AWS_ENDPOINT` for S3-compatible stores, ...).
+
+use crate::archive::{tree_result, VirtualDir};
+use crate::AnalysisResult;
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A bucket and key prefix, written `s3://bucket/prefix`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Location {
+    /// Bucket name
+    pub bucket: String,
+    /// Key prefix to analyze, without leading or trailing slashes (empty for the whole bucket)
+    pub prefix: String,
+}
+
+impl std::str::FromStr for S3Location {
+    type Err = anyhow::Error;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("s3://")
+            .with_context(|| format!("Expected s3://bucket/prefix, got: {}", url))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            anyhow::bail!("Missing bucket name in {}", url);
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+}
+
+impl fmt::Display for S3Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}", self.bucket)?;
+        if !self.prefix.is_empty() {
+            write!(f, "/{}", self.prefix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Analyze the objects under `location`, keeping the `top_n` largest prefixes
+///
+/// Object sizes are used as both disk usage and apparent size.
+pub fn analyze_s3(location: &S3Location, top_n: usize) -> Result<AnalysisResult> {
+    let store = AmazonS3Builder::from_env()
+        .with_bucket_name(&location.bucket)
+        .build()
+        .with_context(|| format!("Failed to configure S3 access to {}", location))?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let objects = runtime
+        .block_on(list_objects(&store, &location.prefix))
+        .with_context(|| format!("Failed to list {}", location))?;
+    log::info!("Listed {} objects under {}", objects.len(), location);
+    Ok(objects_result(
+        PathBuf::from(location.to_string()),
+        &objects,
+        top_n,
+    ))
+}
+
+/// List every object below `prefix` as (key relative to `prefix`, size)
+async fn list_objects(store: &dyn ObjectStore, prefix: &str) -> Result<Vec<(String, u64)>> {
+    let prefix = (!prefix.is_empty()).then(|| ObjectPath::from(prefix));
+    let mut listing = store.list(prefix.as_ref());
+
+    let mut objects = Vec::new();
+    while let Some(meta) = listing.next().await {
+        let meta = meta?;
+        let key = meta.location.as_ref();
+        let relative = prefix
+            .as_ref()
+            .and_then(|prefix| key.strip_prefix(prefix.as_ref()))
+            .unwrap_or(key)
+            .trim_start_matches('/');
+        objects.push((relative.to_string(), meta.size));
+    }
+    Ok(objects)
+}
+
+/// Build the analysis of `root` from its objects, treating each `/` in a key as a directory
+fn objects_result(root: PathBuf, objects: &[(String, u64)], top_n: usize) -> AnalysisResult {
+    let mut tree = VirtualDir::default();
+    for (key, size) in objects {
+        tree.insert(Path::new(key), *size, *size);
+    }
+    tree_result(tree.into_node(root), top_n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+    use object_store::ObjectStoreExt;
+
+    #[test]
+    fn test_parse_location() {
+        let location: S3Location = "s3://logs/2024/app/".parse().unwrap();
+        assert_eq!(location.bucket, "logs");
+        assert_eq!(location.prefix, "2024/app");
+        assert_eq!(location.to_string(), "s3://logs/2024/app");
+
+        let location: S3Location = "s3://logs".parse().unwrap();
+        assert_eq!(location.prefix, "");
+        assert_eq!(location.to_string(), "s3://logs");
+
+        assert!("logs/2024".parse::<S3Location>().is_err());
+        assert!("s3:///2024".parse::<S3Location>().is_err());
+    }
+
+    #[test]
+    fn test_aggregate_by_prefix() {
+        let store = InMemory::new();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let objects = runtime.block_on(async {
+            for (key, size) in [
+                ("data/raw/a.parquet", 5_000),
+                ("data/raw/b.parquet", 3_000),
+                ("data/index.json", 100),
+                ("data2/other", 9_999),
+            ] {
+                let path = ObjectPath::from(key);
+                store.put(&path, vec![0u8; size].into()).await.unwrap();
+            }
+            list_objects(&store, "data").await.unwrap()
+        });
+        assert_eq!(objects.len(), 3);
+
+        let root = PathBuf::from("s3://bucket/data");
+        let result = objects_result(root.clone(), &objects, 10);
+        assert_eq!(result.total_size, 8_100);
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.total_dirs, 1);
+        assert_eq!(result.top_directories[0].path, root.join("raw"));
+        assert_eq!(result.top_directories[0].size, 8_000);
+        assert_eq!(result.top_directories[1].path, root.join("index.json"));
+    }
+}
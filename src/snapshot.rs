@@ -0,0 +1,363 @@
+//! Saving and loading analysis snapshots
+//!
+//! A snapshot is the JSON encoding of a complete [`AnalysisResult`], so past
+//! scans can be inspected and re-rendered without walking the filesystem again.
+//! Paths and file names are stored as UTF-8, replacing invalid sequences.
+
+use crate::mounts::FsUsage;
+use crate::{
+    AnalysisResult, DirectoryEntry, DirectoryNode, FileNode, OtherTypes, ScanError, ScanErrorKind,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the snapshot format written by this build
+const FORMAT_VERSION: u32 = 1;
+
+/// An analysis loaded from a snapshot file
+#[derive(Debug)]
+pub struct Snapshot {
+    /// When the snapshot was written
+    pub created: SystemTime,
+    /// The saved analysis
+    pub result: AnalysisResult,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    version: u32,
+    /// Seconds since the Unix epoch
+    created: u64,
+    root_path: String,
+    total_size: u64,
+    total_files: usize,
+    total_dirs: usize,
+    cancelled: bool,
+    directories: Vec<SnapshotEntry>,
+    tree: Option<SnapshotNode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<SnapshotError>,
+    #[serde(default)]
+    hard_links: usize,
+    #[serde(default)]
+    hard_link_savings: u64,
+    #[serde(default)]
+    symlinks: usize,
+    #[serde(default)]
+    symlink_size: u64,
+    #[serde(default)]
+    sockets: usize,
+    #[serde(default)]
+    fifos: usize,
+    #[serde(default)]
+    block_devices: usize,
+    #[serde(default)]
+    char_devices: usize,
+    #[serde(default)]
+    cloned_size: u64,
+    #[serde(default)]
+    cloud_files: usize,
+    #[serde(default)]
+    cloud_size: u64,
+    #[serde(default)]
+    filesystem: Option<SnapshotFilesystem>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFilesystem {
+    total: u64,
+    free: u64,
+    available: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    path: String,
+    size: u64,
+    file_count: usize,
+    dir_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotError {
+    path: String,
+    kind: String,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotNode {
+    path: String,
+    size: u64,
+    file_count: usize,
+    dir_count: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<SnapshotNode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    files: Vec<SnapshotFileNode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFileNode {
+    name: String,
+    size: u64,
+    apparent_size: u64,
+}
+
+impl SnapshotNode {
+    fn from_node(node: &DirectoryNode) -> Self {
+        Self {
+            path: node.path.to_string_lossy().into_owned(),
+            size: node.size,
+            file_count: node.file_count,
+            dir_count: node.dir_count,
+            children: node.children.iter().map(Self::from_node).collect(),
+            files: node
+                .files
+                .iter()
+                .map(|file| SnapshotFileNode {
+                    name: file.name.to_string_lossy().into_owned(),
+                    size: file.size,
+                    apparent_size: file.apparent_size,
+                })
+                .collect(),
+        }
+    }
+
+    fn into_node(self) -> DirectoryNode {
+        DirectoryNode {
+            path: PathBuf::from(self.path),
+            size: self.size,
+            file_count: self.file_count,
+            dir_count: self.dir_count,
+            children: self.children.into_iter().map(Self::into_node).collect(),
+            files: self
+                .files
+                .into_iter()
+                .map(|file| FileNode {
+                    name: file.name.into(),
+                    size: file.size,
+                    apparent_size: file.apparent_size,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Save `result` as a snapshot file at `path`
+pub fn save_snapshot(result: &AnalysisResult, path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create snapshot: {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    write_snapshot(&mut out, result)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Write `result` as a snapshot to `out`
+pub fn write_snapshot(out: &mut impl Write, result: &AnalysisResult) -> Result<()> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let snapshot = SnapshotFile {
+        version: FORMAT_VERSION,
+        created,
+        root_path: result.root_path.to_string_lossy().into_owned(),
+        total_size: result.total_size,
+        total_files: result.total_files,
+        total_dirs: result.total_dirs,
+        cancelled: result.cancelled,
+        hard_links: result.hard_links,
+        hard_link_savings: result.hard_link_savings,
+        symlinks: result.other_types.symlinks,
+        symlink_size: result.other_types.symlink_size,
+        sockets: result.other_types.sockets,
+        fifos: result.other_types.fifos,
+        block_devices: result.other_types.block_devices,
+        char_devices: result.other_types.char_devices,
+        cloned_size: result.cloned_size,
+        cloud_files: result.cloud_files,
+        cloud_size: result.cloud_size,
+        filesystem: result.filesystem.map(|fs| SnapshotFilesystem {
+            total: fs.total,
+            free: fs.free,
+            available: fs.available,
+        }),
+        modified: result.modified,
+        directories: result
+            .top_directories
+            .iter()
+            .map(|d| SnapshotEntry {
+                path: d.path.to_string_lossy().into_owned(),
+                size: d.size,
+                file_count: d.file_count,
+                dir_count: d.dir_count,
+                modified: d.modified,
+            })
+            .collect(),
+        tree: result.tree.as_ref().map(SnapshotNode::from_node),
+        errors: result
+            .errors
+            .iter()
+            .map(|e| SnapshotError {
+                path: e.path.to_string_lossy().into_owned(),
+                kind: e.kind.as_str().to_string(),
+                message: e.message.clone(),
+            })
+            .collect(),
+    };
+
+    serde_json::to_writer(out, &snapshot)?;
+    Ok(())
+}
+
+/// Load a snapshot file written by [`save_snapshot`]
+pub fn load_snapshot(path: &Path) -> Result<Snapshot> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open snapshot: {}", path.display()))?;
+    read_snapshot(BufReader::new(file))
+        .with_context(|| format!("Failed to read snapshot: {}", path.display()))
+}
+
+/// Read a snapshot from `reader`
+pub fn read_snapshot(reader: impl io::Read) -> Result<Snapshot> {
+    let snapshot: SnapshotFile = serde_json::from_reader(reader)?;
+    if snapshot.version != FORMAT_VERSION {
+        anyhow::bail!(
+            "Unsupported snapshot version {} (expected {})",
+            snapshot.version,
+            FORMAT_VERSION
+        );
+    }
+
+    let result = AnalysisResult {
+        root_path: PathBuf::from(snapshot.root_path),
+        total_size: snapshot.total_size,
+        total_files: snapshot.total_files,
+        total_dirs: snapshot.total_dirs,
+        top_directories: snapshot
+            .directories
+            .into_iter()
+            .map(|d| DirectoryEntry {
+                path: PathBuf::from(d.path),
+                size: d.size,
+                file_count: d.file_count,
+                dir_count: d.dir_count,
+                modified: d.modified,
+            })
+            .collect(),
+        tree: snapshot.tree.map(SnapshotNode::into_node),
+        cancelled: snapshot.cancelled,
+        hard_links: snapshot.hard_links,
+        hard_link_savings: snapshot.hard_link_savings,
+        other_types: OtherTypes {
+            symlinks: snapshot.symlinks,
+            symlink_size: snapshot.symlink_size,
+            sockets: snapshot.sockets,
+            fifos: snapshot.fifos,
+            block_devices: snapshot.block_devices,
+            char_devices: snapshot.char_devices,
+        },
+        cloned_size: snapshot.cloned_size,
+        cloud_files: snapshot.cloud_files,
+        cloud_size: snapshot.cloud_size,
+        filesystem: snapshot.filesystem.map(|fs| FsUsage {
+            total: fs.total,
+            free: fs.free,
+            available: fs.available,
+        }),
+        modified: snapshot.modified,
+        link_issues: Vec::new(),
+        stats: None,
+        errors: snapshot
+            .errors
+            .into_iter()
+            .map(|e| ScanError {
+                path: PathBuf::from(e.path),
+                kind: error_kind(&e.kind),
+                message: e.message,
+            })
+            .collect(),
+    };
+
+    Ok(Snapshot {
+        created: UNIX_EPOCH + Duration::from_secs(snapshot.created),
+        result,
+    })
+}
+
+fn error_kind(name: &str) -> ScanErrorKind {
+    [
+        ScanErrorKind::PermissionDenied,
+        ScanErrorKind::NotFound,
+        ScanErrorKind::SymlinkLoop,
+        ScanErrorKind::TimedOut,
+    ]
+    .into_iter()
+    .find(|kind| kind.as_str() == name)
+    .unwrap_or(ScanErrorKind::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_disk_usage, AnalysisConfig};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/b/data.bin"), vec![0u8; 4096]).unwrap();
+        fs::write(temp_dir.path().join("root.txt"), "hello").unwrap();
+
+        let config = AnalysisConfig {
+            build_tree: true,
+            tree_files: true,
+            ..Default::default()
+        };
+        let mut result = analyze_disk_usage(temp_dir.path(), &config, 10).unwrap();
+        // As if a OneDrive placeholder had been found
+        result.cloud_files = 1;
+        result.cloud_size = 1 << 20;
+
+        let mut buffer = Vec::new();
+        write_snapshot(&mut buffer, &result).unwrap();
+        let loaded = read_snapshot(buffer.as_slice()).unwrap().result;
+
+        assert_eq!(loaded.root_path, result.root_path);
+        assert_eq!(loaded.total_size, result.total_size);
+        assert_eq!(loaded.total_files, 2);
+        assert_eq!((loaded.cloud_files, loaded.cloud_size), (1, 1 << 20));
+        assert_eq!(loaded.top_directories.len(), result.top_directories.len());
+        assert_eq!(
+            loaded.top_directories[0].path,
+            result.top_directories[0].path
+        );
+
+        let tree = loaded.tree.unwrap();
+        let nested = tree.find(&temp_dir.path().join("a/b")).unwrap();
+        assert_eq!(nested.files.len(), 1);
+        assert_eq!(nested.files[0].name, "data.bin");
+        assert_eq!(tree.files[0].name, "root.txt");
+    }
+
+    #[test]
+    fn test_snapshot_rejects_unknown_version() {
+        let data = br#"{"version":99,"created":0,"root_path":"/","total_size":0,
+            "total_files":0,"total_dirs":0,"cancelled":false,"directories":[],"tree":null}"#;
+        let err = read_snapshot(&data[..]).unwrap_err();
+        assert!(err.to_string().contains("Unsupported snapshot version"));
+    }
+}
@@ -0,0 +1,207 @@
+//! Performance figures of a scan, for tuning thread counts and backends
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How fast a scan went and what it cost
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanStats {
+    /// Time the walk took
+    pub wall_time: Duration,
+    /// Files counted
+    pub files: usize,
+    /// Directories counted
+    pub dirs: usize,
+    /// Directories counted from the cache instead of being listed
+    pub cached_dirs: usize,
+    /// Files counted from the cache instead of being stat'ed
+    pub cached_files: usize,
+    /// Time each worker thread spent in filesystem calls (listing directories
+    /// and reading metadata)
+    pub thread_busy: Vec<Duration>,
+    /// Peak resident memory of the process in bytes, when it can be queried
+    pub peak_rss: Option<u64>,
+}
+
+impl ScanStats {
+    /// Files counted per second of wall time
+    pub fn files_per_second(&self) -> f64 {
+        per_second(self.files, self.wall_time)
+    }
+
+    /// Directories counted per second of wall time
+    pub fn dirs_per_second(&self) -> f64 {
+        per_second(self.dirs, self.wall_time)
+    }
+
+    /// System calls the cache saved: one stat per file, and at least an open,
+    /// a read and a close per directory
+    pub fn syscalls_avoided(&self) -> usize {
+        self.cached_files + 3 * self.cached_dirs
+    }
+
+    /// Share of the wall time each worker thread spent in filesystem calls,
+    /// from 0 to 1
+    pub fn utilization(&self) -> Vec<f64> {
+        let wall = self.wall_time.as_secs_f64();
+        self.thread_busy
+            .iter()
+            .map(|busy| {
+                if wall > 0.0 {
+                    (busy.as_secs_f64() / wall).min(1.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Fold the figures of another scan run one after this one
+    pub fn merge(&mut self, other: &ScanStats) {
+        self.wall_time += other.wall_time;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.cached_dirs += other.cached_dirs;
+        self.cached_files += other.cached_files;
+        if self.thread_busy.len() < other.thread_busy.len() {
+            self.thread_busy
+                .resize(other.thread_busy.len(), Duration::ZERO);
+        }
+        for (busy, other) in self.thread_busy.iter_mut().zip(&other.thread_busy) {
+            *busy += *other;
+        }
+        self.peak_rss = self.peak_rss.max(other.peak_rss);
+    }
+}
+
+fn per_second(count: usize, time: Duration) -> f64 {
+    let seconds = time.as_secs_f64();
+    if seconds > 0.0 {
+        count as f64 / seconds
+    } else {
+        0.0
+    }
+}
+
+/// Counters the walker updates while collecting statistics
+pub(crate) struct StatsCollector {
+    started: Instant,
+    /// Nanoseconds spent in filesystem calls, per worker thread
+    busy: Vec<AtomicU64>,
+    cached_dirs: AtomicUsize,
+    cached_files: AtomicUsize,
+}
+
+impl StatsCollector {
+    /// Collector for a walk on a pool of `threads` workers
+    pub(crate) fn new(threads: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            busy: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+            cached_dirs: AtomicUsize::new(0),
+            cached_files: AtomicUsize::new(0),
+        }
+    }
+
+    /// Run the filesystem call `call`, adding its duration to the current
+    /// worker's busy time
+    pub(crate) fn time<T>(&self, call: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = call();
+        // Calls made outside the pool, listing the root, belong to no worker
+        if let Some(busy) = rayon::current_thread_index().and_then(|i| self.busy.get(i)) {
+            busy.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Record a directory whose `files` were counted from the cache
+    pub(crate) fn add_cached(&self, files: usize) {
+        self.cached_dirs.fetch_add(1, Ordering::Relaxed);
+        self.cached_files.fetch_add(files, Ordering::Relaxed);
+    }
+
+    /// Final figures of a walk that counted `files` and `dirs`
+    pub(crate) fn finish(&self, files: usize, dirs: usize) -> ScanStats {
+        ScanStats {
+            wall_time: self.started.elapsed(),
+            files,
+            dirs,
+            cached_dirs: self.cached_dirs.load(Ordering::Relaxed),
+            cached_files: self.cached_files.load(Ordering::Relaxed),
+            thread_busy: self
+                .busy
+                .iter()
+                .map(|nanos| Duration::from_nanos(nanos.load(Ordering::Relaxed)))
+                .collect(),
+            peak_rss: peak_rss(),
+        }
+    }
+}
+
+/// Peak resident memory of the process in bytes
+#[cfg(unix)]
+pub fn peak_rss() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: `usage` is large enough for the result
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrusage succeeded and filled the structure
+    let max_rss = u64::try_from(unsafe { usage.assume_init() }.ru_maxrss).ok()?;
+    // Linux reports kilobytes, macOS bytes
+    if cfg!(target_os = "macos") {
+        Some(max_rss)
+    } else {
+        Some(max_rss * 1024)
+    }
+}
+
+/// Peak resident memory of the process in bytes
+#[cfg(windows)]
+pub fn peak_rss() -> Option<u64> {
+    crate::windows::peak_working_set()
+}
+
+/// Peak resident memory of the process in bytes
+#[cfg(not(any(unix, windows)))]
+pub fn peak_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_stats() {
+        let stats = ScanStats {
+            wall_time: Duration::from_secs(2),
+            files: 1_000,
+            dirs: 100,
+            cached_dirs: 10,
+            cached_files: 200,
+            thread_busy: vec![Duration::from_secs(1), Duration::from_secs(3)],
+            peak_rss: Some(1 << 20),
+        };
+        assert_eq!(stats.files_per_second(), 500.0);
+        assert_eq!(stats.dirs_per_second(), 50.0);
+        assert_eq!(stats.syscalls_avoided(), 230);
+        assert_eq!(stats.utilization(), vec![0.5, 1.0]);
+
+        let mut merged = ScanStats::default();
+        merged.merge(&stats);
+        merged.merge(&stats);
+        assert_eq!(merged.files, 2_000);
+        assert_eq!(merged.thread_busy[1], Duration::from_secs(6));
+        assert_eq!(merged.peak_rss, Some(1 << 20));
+
+        let collector = StatsCollector::new(2);
+        collector.add_cached(5);
+        let finished = collector.finish(7, 1);
+        assert_eq!((finished.cached_dirs, finished.cached_files), (1, 5));
+        #[cfg(unix)]
+        assert!(finished.peak_rss.is_some_and(|rss| rss > 0));
+    }
+}
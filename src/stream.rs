@@ -0,0 +1,184 @@
+//! Streaming the entries of a scan as they are discovered
+//!
+//! The walk runs on a background thread and hands every directory it enters
+//! and every file it counts to the consumer through a bounded channel, so a
+//! slow consumer throttles the walk instead of letting entries pile up.
+//! Dropping the iterator cancels the walk.
+
+use crate::{
+    analyze_with_hooks, AnalysisConfig, CancellationToken, EntryVisitor, ScanError, ScanHooks,
+};
+use anyhow::Result;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+/// Entries buffered between the walk and the consumer
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Kind of a [`ScannedEntry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScannedEntryKind {
+    File,
+    Directory,
+}
+
+/// A file or directory reached by the walk
+#[derive(Debug, Clone)]
+pub struct ScannedEntry {
+    /// Path of the entry
+    pub path: PathBuf,
+    /// Whether the entry is a file or a directory
+    pub kind: ScannedEntryKind,
+    /// Disk usage in bytes (zero for directories, which are reported before their contents)
+    pub size: u64,
+    /// Apparent (logical) size in bytes (zero for directories)
+    pub apparent_size: u64,
+}
+
+/// Iterator over the entries of a running scan, returned by [`scan_entries`]
+pub struct ScanEntries {
+    receiver: Receiver<Result<ScannedEntry>>,
+    cancel: CancellationToken,
+}
+
+impl Iterator for ScanEntries {
+    type Item = Result<ScannedEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for ScanEntries {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Forwards the walker's entries to the channel
+struct EntrySender {
+    sender: SyncSender<Result<ScannedEntry>>,
+    cancel: CancellationToken,
+}
+
+impl EntrySender {
+    fn send(&self, item: Result<ScannedEntry>) {
+        // The consumer is gone, so there is no point in walking any further
+        if self.sender.send(item).is_err() {
+            self.cancel.cancel();
+        }
+    }
+}
+
+impl EntryVisitor for EntrySender {
+    fn visit_file(&self, path: &Path, metadata: &Metadata, size: u64) {
+        self.send(Ok(ScannedEntry {
+            path: path.to_path_buf(),
+            kind: ScannedEntryKind::File,
+            size,
+            apparent_size: metadata.len(),
+        }));
+    }
+
+    fn visit_dir(&self, path: &Path) {
+        self.send(Ok(ScannedEntry {
+            path: path.to_path_buf(),
+            kind: ScannedEntryKind::Directory,
+            size: 0,
+            apparent_size: 0,
+        }));
+    }
+
+    fn visit_error(&self, error: &ScanError) {
+        self.send(Err(error.clone().into()));
+    }
+}
+
+/// Scan `path`, yielding files and directories as the walk discovers them
+///
+/// Directories are yielded before their contents, but entries from different
+/// subtrees are interleaved in no particular order. Entries that cannot be read
+/// are yielded as errors wrapping a [`ScanError`], and the scan continues; a
+/// root that cannot be scanned at all yields a single error. Files are subject
+/// to the same filters and hard-link deduplication as [`crate::analyze_disk_usage`].
+pub fn scan_entries(path: &Path, config: &AnalysisConfig) -> ScanEntries {
+    let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    let cancel = CancellationToken::new();
+
+    let visitor = EntrySender {
+        sender,
+        cancel: cancel.clone(),
+    };
+    let path = path.to_path_buf();
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    thread::spawn(move || {
+        let hooks = ScanHooks {
+            cancel: Some(&visitor.cancel),
+            visitor: Some(&visitor),
+            ..Default::default()
+        };
+        if let Err(e) = analyze_with_hooks(&path, &config, 0, &hooks) {
+            visitor.send(Err(e));
+        }
+    });
+
+    ScanEntries { receiver, cancel }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/one"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("two"), "hello").unwrap();
+
+        let mut entries: Vec<ScannedEntry> = scan_entries(root, &AnalysisConfig::default())
+            .collect::<Result<_>>()
+            .unwrap();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let paths: Vec<_> = entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                root.join("a"),
+                root.join("a/b"),
+                root.join("a/b/one"),
+                root.join("two")
+            ]
+        );
+        assert_eq!(entries[0].kind, ScannedEntryKind::Directory);
+        assert_eq!(entries[2].kind, ScannedEntryKind::File);
+        assert_eq!(entries[2].apparent_size, 100);
+
+        // Directories come before their contents
+        let order: Vec<PathBuf> = scan_entries(root, &AnalysisConfig::default())
+            .map(|e| e.unwrap().path)
+            .collect();
+        let position = |path: PathBuf| order.iter().position(|p| *p == path).unwrap();
+        assert!(position(root.join("a")) < position(root.join("a/b")));
+        assert!(position(root.join("a/b")) < position(root.join("a/b/one")));
+    }
+
+    #[test]
+    fn test_scan_entries_missing_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut entries =
+            scan_entries(&temp_dir.path().join("missing"), &AnalysisConfig::default());
+
+        assert!(entries.next().unwrap().is_err());
+        assert!(entries.next().is_none());
+    }
+}
@@ -0,0 +1,510 @@
+//! Color themes for the terminal output
+//!
+//! A theme gives the colors of small, medium and large entries (reused for
+//! recent, aging and stale ones when coloring by age, and for good and bad news
+//! in the other reports) and of each kind of content when coloring by type.
+//! Built-in themes can be extended by `[themes.NAME]` tables in the config
+//! file, where colors are `"#rrggbb"` strings or 256-color palette indexes and
+//! anything left out comes from the default theme:
+//!
+//! ```toml
+//! theme = "mine"
+//! color_by = "age"
+//!
+//! [themes.mine]
+//! low = "#5faf5f"
+//! medium = 214
+//! high = "#d70000"
+//!
+//! [themes.mine.types]
+//! video = "#af87ff"
+//! ```
+//!
+//! Colors are written with the richest escape sequences the terminal is known
+//! to support, so RGB colors degrade to the 256-color or 16-color palette.
+
+use crate::types::Category;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Names of the built-in themes
+pub const BUILTIN_THEMES: [&str; 4] = ["default", "solarized", "colorblind", "mono"];
+
+/// A color from the 256-color palette or an RGB color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Red, green and blue components
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Ansi256(index) => palette_rgb(index),
+        }
+    }
+
+    /// Escape sequence setting this color as the foreground with `support`,
+    /// empty without colors
+    pub fn escape(self, support: ColorSupport) -> String {
+        match (support, self) {
+            (ColorSupport::None, _) => String::new(),
+            (ColorSupport::TrueColor, Color::Rgb(r, g, b)) => {
+                format!("\x1b[38;2;{};{};{}m", r, g, b)
+            }
+            (ColorSupport::TrueColor | ColorSupport::Ansi256, Color::Ansi256(index)) => {
+                format!("\x1b[38;5;{}m", index)
+            }
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => {
+                format!("\x1b[38;5;{}m", nearest_256(r, g, b))
+            }
+            (ColorSupport::Ansi16, color) => {
+                let index = nearest_16(color.rgb());
+                // 0-7 are the normal colors, 8-15 their bright variants
+                let code = if index < 8 { 30 + index } else { 82 + index };
+                format!("\x1b[{}m", code)
+            }
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    /// Parse `#rrggbb` or a palette index from 0 to 255
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid color: {} (expected #rrggbb or 0-255)", s);
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(invalid());
+            }
+            let component =
+                |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+            return Ok(Color::Rgb(component(0)?, component(2)?, component(4)?));
+        }
+        s.parse().map(Color::Ansi256).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Ansi256(index) => write!(f, "{}", index),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Index(u8),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Index(index) => Ok(Color::Ansi256(index)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// RGB value of the 256-color palette entry `index`
+fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (128, 0, 0),
+        (0, 128, 0),
+        (128, 128, 0),
+        (0, 0, 128),
+        (128, 0, 128),
+        (0, 128, 128),
+        (192, 192, 192),
+        (128, 128, 128),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (0, 0, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    match index {
+        0..=15 => BASIC[index as usize],
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Closest entry of the 6x6x6 color cube or the gray ramp
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    (16..=255)
+        .min_by_key(|&index| distance(palette_rgb(index), (r, g, b)))
+        .unwrap_or(16)
+}
+
+/// Closest of the 16 basic colors
+fn nearest_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&index| distance(palette_rgb(index), rgb))
+        .unwrap_or(7)
+}
+
+/// Colors a terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    None,
+    /// The 16 basic colors, like the Linux console
+    Ansi16,
+    /// The 256-color palette, which most terminals support
+    Ansi256,
+    /// Any RGB color
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Colors supported by the terminal, as told by `COLORTERM` and `TERM`
+    pub fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        Self::from_env(
+            &var("TERM"),
+            &var("COLORTERM"),
+            std::env::var_os("WT_SESSION").is_some(),
+        )
+    }
+
+    /// Colors supported by a terminal with these `TERM` and `COLORTERM`
+    /// values, running in Windows Terminal when `windows_terminal` is set
+    fn from_env(term: &str, colorterm: &str, windows_terminal: bool) -> Self {
+        if term == "dumb" {
+            ColorSupport::None
+        } else if matches!(colorterm, "truecolor" | "24bit") || windows_terminal {
+            ColorSupport::TrueColor
+        } else if term == "linux" || term == "ansi" || term.starts_with("vt") {
+            ColorSupport::Ansi16
+        } else {
+            ColorSupport::Ansi256
+        }
+    }
+}
+
+/// When the output is colored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// When writing to a terminal, following `NO_COLOR` and `CLICOLOR_FORCE`
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether output going to a terminal when `is_terminal` is set, or
+    /// elsewhere, is colored
+    ///
+    /// In auto mode, a non-empty `NO_COLOR` turns colors off and a
+    /// `CLICOLOR_FORCE` other than 0 turns them on even when the output is
+    /// piped, as in `dutop | less -R`; `NO_COLOR` wins when both are set.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        let no_color = std::env::var_os("NO_COLOR");
+        let force = std::env::var_os("CLICOLOR_FORCE");
+        self.resolve(
+            is_terminal,
+            no_color.as_deref().and_then(|v| v.to_str()),
+            force.as_deref().and_then(|v| v.to_str()),
+        )
+    }
+
+    fn resolve(self, is_terminal: bool, no_color: Option<&str>, force: Option<&str>) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto if no_color.is_some_and(|v| !v.is_empty()) => false,
+            ColorChoice::Auto if force.is_some_and(|v| !v.is_empty() && v != "0") => true,
+            ColorChoice::Auto => is_terminal,
+        }
+    }
+}
+
+/// What the bars and rows of the table and the tree are colored by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorBy {
+    /// Share of the largest entry: low, medium or high
+    #[default]
+    Size,
+    /// Newest modification time: within 30 days, a year, or older (table only,
+    /// the tree is colored by size)
+    Age,
+    /// Kind of content told by the extension of the name (files of the tree
+    /// only, directories are colored by size)
+    Type,
+}
+
+impl FromStr for ColorBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "size" => Ok(ColorBy::Size),
+            "age" => Ok(ColorBy::Age),
+            "type" => Ok(ColorBy::Type),
+            _ => anyhow::bail!("Invalid color mode: {} (expected size, age or type)", s),
+        }
+    }
+}
+
+/// Colors of the terminal output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Small, recent or good
+    pub low: Color,
+    /// In between
+    pub medium: Color,
+    /// Large, stale or bad
+    pub high: Color,
+    /// Colors of the kinds of content; others are left uncolored
+    pub types: BTreeMap<Category, Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        use Color::Ansi256;
+        Self {
+            low: Ansi256(34),
+            medium: Ansi256(220),
+            high: Ansi256(160),
+            types: BTreeMap::from([
+                (Category::Video, Ansi256(135)),
+                (Category::Audio, Ansi256(170)),
+                (Category::Image, Ansi256(39)),
+                (Category::Archive, Ansi256(214)),
+                (Category::DiskImage, Ansi256(208)),
+                (Category::Document, Ansi256(252)),
+                (Category::Code, Ansi256(114)),
+                (Category::Log, Ansi256(242)),
+                (Category::Database, Ansi256(75)),
+            ]),
+        }
+    }
+}
+
+impl Theme {
+    /// Built-in theme called `name`
+    pub fn builtin(name: &str) -> Option<Self> {
+        use Color::{Ansi256, Rgb};
+        let default = Self::default();
+        let theme = match name {
+            "default" => default,
+            "solarized" => Self {
+                low: Rgb(0x85, 0x99, 0x00),
+                medium: Rgb(0xb5, 0x89, 0x00),
+                high: Rgb(0xdc, 0x32, 0x2f),
+                types: BTreeMap::from([
+                    (Category::Video, Rgb(0x6c, 0x71, 0xc4)),
+                    (Category::Audio, Rgb(0xd3, 0x36, 0x82)),
+                    (Category::Image, Rgb(0x26, 0x8b, 0xd2)),
+                    (Category::Archive, Rgb(0xcb, 0x4b, 0x16)),
+                    (Category::DiskImage, Rgb(0xcb, 0x4b, 0x16)),
+                    (Category::Document, Rgb(0x93, 0xa1, 0xa1)),
+                    (Category::Code, Rgb(0x2a, 0xa1, 0x98)),
+                    (Category::Log, Rgb(0x58, 0x6e, 0x75)),
+                    (Category::Database, Rgb(0x26, 0x8b, 0xd2)),
+                ]),
+            },
+            // Okabe-Ito colors, told apart with any kind of color blindness
+            "colorblind" => Self {
+                low: Rgb(0x00, 0x72, 0xb2),
+                medium: Rgb(0xe6, 0x9f, 0x00),
+                high: Rgb(0xd5, 0x5e, 0x00),
+                types: BTreeMap::from([
+                    (Category::Video, Rgb(0xcc, 0x79, 0xa7)),
+                    (Category::Audio, Rgb(0xcc, 0x79, 0xa7)),
+                    (Category::Image, Rgb(0x56, 0xb4, 0xe9)),
+                    (Category::Archive, Rgb(0xe6, 0x9f, 0x00)),
+                    (Category::DiskImage, Rgb(0xd5, 0x5e, 0x00)),
+                    (Category::Code, Rgb(0x00, 0x9e, 0x73)),
+                    (Category::Database, Rgb(0x00, 0x72, 0xb2)),
+                    (Category::Log, Rgb(0xf0, 0xe4, 0x42)),
+                ]),
+            },
+            "mono" => Self {
+                low: Ansi256(244),
+                medium: Ansi256(250),
+                high: Ansi256(255),
+                types: BTreeMap::new(),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+}
+
+/// A theme defined in the config file, on top of the default theme
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeSpec {
+    pub low: Option<Color>,
+    pub medium: Option<Color>,
+    pub high: Option<Color>,
+    /// Colors by kind of content name, like `video` or `disk-image`
+    pub types: BTreeMap<String, Color>,
+}
+
+impl ThemeSpec {
+    /// Theme with the colors given here and the default ones elsewhere
+    pub fn theme(&self) -> Result<Theme> {
+        let mut theme = Theme::default();
+        theme.low = self.low.unwrap_or(theme.low);
+        theme.medium = self.medium.unwrap_or(theme.medium);
+        theme.high = self.high.unwrap_or(theme.high);
+        for (name, &color) in &self.types {
+            let category = CATEGORIES
+                .into_iter()
+                .find(|category| category.as_str() == name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown kind of content in theme: {}", name))?;
+            theme.types.insert(category, color);
+        }
+        Ok(theme)
+    }
+}
+
+const CATEGORIES: [Category; 10] = [
+    Category::Video,
+    Category::Audio,
+    Category::Image,
+    Category::Archive,
+    Category::DiskImage,
+    Category::Document,
+    Category::Code,
+    Category::Log,
+    Category::Database,
+    Category::Other,
+];
+
+/// Theme called `name`, looked up in the config file's `themes` before the
+/// built-in ones
+pub fn resolve(name: &str, themes: &BTreeMap<String, ThemeSpec>) -> Result<Theme> {
+    if let Some(spec) = themes.get(name) {
+        return spec.theme();
+    }
+    Theme::builtin(name).ok_or_else(|| {
+        let mut names: Vec<&str> = BUILTIN_THEMES.to_vec();
+        names.extend(themes.keys().map(String::as_str));
+        anyhow::anyhow!("Unknown theme: {} (available: {})", name, names.join(", "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!(
+            "#1e90ff".parse::<Color>().unwrap(),
+            Color::Rgb(0x1e, 0x90, 0xff)
+        );
+        assert_eq!("34".parse::<Color>().unwrap(), Color::Ansi256(34));
+        assert!("#12345".parse::<Color>().is_err());
+        assert!("256".parse::<Color>().is_err());
+        assert_eq!(Color::Rgb(1, 2, 255).to_string(), "#0102ff");
+    }
+
+    #[test]
+    fn test_escapes_follow_support() {
+        let red = Color::Rgb(255, 0, 0);
+        assert_eq!(red.escape(ColorSupport::TrueColor), "\x1b[38;2;255;0;0m");
+        assert_eq!(red.escape(ColorSupport::Ansi256), "\x1b[38;5;196m");
+        assert_eq!(red.escape(ColorSupport::Ansi16), "\x1b[91m");
+        assert_eq!(red.escape(ColorSupport::None), "");
+        assert_eq!(
+            Color::Ansi256(34).escape(ColorSupport::TrueColor),
+            "\x1b[38;5;34m"
+        );
+        assert_eq!(Color::Ansi256(4).escape(ColorSupport::Ansi16), "\x1b[34m");
+    }
+
+    #[test]
+    fn test_detect_support() {
+        assert_eq!(
+            ColorSupport::from_env("dumb", "truecolor", false),
+            ColorSupport::None
+        );
+        assert_eq!(
+            ColorSupport::from_env("xterm-256color", "truecolor", false),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env("", "", true),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env("linux", "", false),
+            ColorSupport::Ansi16
+        );
+        assert_eq!(
+            ColorSupport::from_env("xterm-256color", "", false),
+            ColorSupport::Ansi256
+        );
+    }
+
+    #[test]
+    fn test_color_choice() {
+        use ColorChoice::{Always, Auto, Never};
+        assert!(Auto.resolve(true, None, None));
+        assert!(!Auto.resolve(false, None, None));
+        assert!(!Auto.resolve(true, Some("1"), None));
+        assert!(Auto.resolve(true, Some(""), None));
+        assert!(Auto.resolve(false, None, Some("1")));
+        assert!(!Auto.resolve(false, None, Some("0")));
+        assert!(!Auto.resolve(false, Some("1"), Some("1")));
+        assert!(Always.resolve(false, Some("1"), None));
+        assert!(!Never.resolve(true, None, Some("1")));
+    }
+
+    #[test]
+    fn test_resolve_themes() {
+        let themes: BTreeMap<String, ThemeSpec> = toml::from_str(
+            r##"
+            [mine]
+            high = "#ff0000"
+            medium = 214
+
+            [mine.types]
+            video = "#af87ff"
+            "##,
+        )
+        .unwrap();
+        let theme = resolve("mine", &themes).unwrap();
+        assert_eq!(theme.high, Color::Rgb(255, 0, 0));
+        assert_eq!(theme.medium, Color::Ansi256(214));
+        assert_eq!(theme.low, Theme::default().low);
+        assert_eq!(theme.types[&Category::Video], Color::Rgb(0xaf, 0x87, 0xff));
+
+        for name in BUILTIN_THEMES {
+            assert!(resolve(name, &themes).is_ok());
+        }
+        assert!(resolve("neon", &themes).is_err());
+
+        let bad: BTreeMap<String, ThemeSpec> = toml::from_str("[bad.types]\nmovies = 1").unwrap();
+        assert!(resolve("bad", &bad).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Size limits for monitoring checks and alerts
+//!
+//! A limit is either an absolute size or a percentage of the capacity of the
+//! filesystem holding the analyzed root. It applies to the total of the
+//! analysis and to each of its top directories.
+
+use crate::format::format_size_auto;
+use crate::AnalysisResult;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Limits on disk usage; a path breaks them when it is over either one
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Thresholds {
+    /// Largest allowed size in bytes
+    pub max_size: Option<u64>,
+    /// Largest allowed share of the filesystem's capacity, in percent
+    pub max_percent: Option<f64>,
+}
+
+impl Thresholds {
+    /// Whether no limit is set
+    pub fn is_empty(&self) -> bool {
+        self.max_size.is_none() && self.max_percent.is_none()
+    }
+
+    /// Paths of `result` over the limits: the root first, then its top directories
+    pub fn check(&self, result: &AnalysisResult) -> Vec<Violation> {
+        let capacity = result
+            .filesystem
+            .map(|fs| fs.total)
+            .filter(|&total| total > 0);
+        if self.max_percent.is_some() && capacity.is_none() {
+            log::warn!(
+                "Capacity of the filesystem holding {} is unknown, percentage limits are not checked",
+                result.root_path.display()
+            );
+        }
+        std::iter::once((&result.root_path, result.total_size))
+            .chain(result.top_directories.iter().map(|d| (&d.path, d.size)))
+            .filter_map(|(path, size)| {
+                let percent = capacity.map(|total| size as f64 / total as f64 * 100.0);
+                let over_size = self.max_size.is_some_and(|max| size > max);
+                let over_percent = self
+                    .max_percent
+                    .zip(percent)
+                    .is_some_and(|(max, percent)| percent > max);
+                (over_size || over_percent).then(|| Violation {
+                    path: path.clone(),
+                    size,
+                    percent,
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Thresholds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.max_size, self.max_percent) {
+            (Some(size), Some(percent)) => write!(
+                f,
+                "{} or {}% of the filesystem",
+                format_size_auto(size),
+                percent
+            ),
+            (Some(size), None) => write!(f, "{}", format_size_auto(size)),
+            (None, Some(percent)) => write!(f, "{}% of the filesystem", percent),
+            (None, None) => write!(f, "no limit"),
+        }
+    }
+}
+
+/// A path over the limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Path of the root or top directory
+    pub path: PathBuf,
+    /// Its disk usage in bytes
+    pub size: u64,
+    /// Its share of the filesystem's capacity, when known
+    pub percent: Option<f64>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", format_size_auto(self.size), self.path.display())?;
+        if let Some(percent) = self.percent {
+            write!(f, " ({:.1}% of the filesystem)", percent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when an analysis broke its limits, so that the process can
+/// exit with a distinct code
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdExceeded {
+    /// Every path over the limits
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for ThresholdExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.violations.len() {
+            1 => write!(f, "1 path is over the threshold"),
+            count => write!(f, "{} paths are over the threshold", count),
+        }
+    }
+}
+
+impl std::error::Error for ThresholdExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mounts::FsUsage;
+    use crate::DirectoryEntry;
+
+    #[test]
+    fn test_check_thresholds() {
+        let entry = |path: &str, size| DirectoryEntry {
+            path: PathBuf::from(path),
+            size,
+            file_count: 1,
+            dir_count: 0,
+            modified: None,
+        };
+        let mut result = AnalysisResult {
+            root_path: PathBuf::from("/srv"),
+            total_size: 900,
+            top_directories: vec![entry("/srv/big", 600), entry("/srv/small", 300)],
+            ..Default::default()
+        };
+
+        let by_size = Thresholds {
+            max_size: Some(500),
+            max_percent: None,
+        };
+        let paths: Vec<_> = by_size.check(&result).into_iter().map(|v| v.path).collect();
+        assert_eq!(paths, [PathBuf::from("/srv"), PathBuf::from("/srv/big")]);
+
+        // Percentages need the filesystem's capacity
+        let by_percent = Thresholds {
+            max_size: None,
+            max_percent: Some(50.0),
+        };
+        assert!(by_percent.check(&result).is_empty());
+        result.filesystem = Some(FsUsage {
+            total: 1000,
+            free: 100,
+            available: 100,
+        });
+        let violations = by_percent.check(&result);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[1].percent, Some(60.0));
+        assert!(Thresholds::default().check(&result).is_empty());
+    }
+}
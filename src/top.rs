@@ -0,0 +1,117 @@
+//! Selecting the largest items of a stream without keeping all of them
+//!
+//! A min-heap holds the `limit` largest items seen so far, so memory stays
+//! proportional to the number of entries shown rather than to the number of
+//! directories scanned.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// An item ordered by its size alone
+struct Ranked<T> {
+    size: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Ranked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+    }
+}
+
+impl<T> Eq for Ranked<T> {}
+
+impl<T> PartialOrd for Ranked<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Ranked<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.size.cmp(&other.size)
+    }
+}
+
+/// The `limit` largest items pushed, by size
+pub(crate) struct TopN<T> {
+    limit: usize,
+    heap: BinaryHeap<Reverse<Ranked<T>>>,
+}
+
+impl<T> TopN<T> {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    /// Offer `item` of `size` bytes, dropping the smallest item when full
+    pub(crate) fn push(&mut self, size: u64, item: T) {
+        if self.heap.len() < self.limit {
+            self.heap.push(Reverse(Ranked { size, item }));
+        } else if let Some(mut smallest) = self.heap.peek_mut() {
+            if smallest.0.size < size {
+                *smallest = Reverse(Ranked { size, item });
+            }
+        }
+    }
+
+    /// Whether an item of `size` bytes would be kept, so callers can skip
+    /// building items that would not
+    pub(crate) fn accepts(&self, size: u64) -> bool {
+        self.heap.len() < self.limit
+            || self
+                .heap
+                .peek()
+                .is_some_and(|smallest| smallest.0.size < size)
+    }
+
+    /// Offer every item kept by `other`
+    pub(crate) fn merge(mut self, other: TopN<T>) -> Self {
+        for Reverse(ranked) in other.heap {
+            self.push(ranked.size, ranked.item);
+        }
+        self
+    }
+
+    /// The items kept, largest first
+    pub(crate) fn into_sorted_vec(self) -> Vec<T> {
+        // Ascending order of the reversed items is descending order of sizes
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.item)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_n() {
+        let mut top = TopN::new(3);
+        for size in [5, 1, 9, 3, 7, 2] {
+            top.push(size, size);
+        }
+        let mut other = TopN::new(3);
+        other.push(8, 8);
+        other.push(4, 4);
+        assert!(top.accepts(6) && !top.accepts(5));
+        assert_eq!(top.merge(other).into_sorted_vec(), vec![9, 8, 7]);
+
+        let mut none = TopN::new(0);
+        assert!(!none.accepts(1));
+        none.push(1, "a");
+        assert!(none.into_sorted_vec().is_empty());
+
+        let mut all = TopN::new(usize::MAX);
+        for size in [2, 3, 1] {
+            all.push(size, size);
+        }
+        assert_eq!(all.into_sorted_vec(), vec![3, 2, 1]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Directory trees keyed by path component while they are being built
+//!
+//! A node of the walk only stores its own name; its path is implied by the
+//! nodes above it. Deep trees therefore hold each component once instead of a
+//! full `PathBuf` per directory, and the public [`DirectoryNode`] with its
+//! absolute paths is materialized in a single pass when the walk of a root
+//! child completes.
+
+use crate::{DirectoryNode, FileNode};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// A directory known by its name within its parent
+#[derive(Debug, Default)]
+pub(crate) struct NameNode {
+    pub name: OsString,
+    pub size: u64,
+    pub file_count: usize,
+    pub dir_count: usize,
+    /// Immediate subdirectories, sorted by size
+    pub children: Vec<NameNode>,
+    /// Files stored directly in the directory, sorted by size
+    pub files: Vec<FileNode>,
+}
+
+impl NameNode {
+    /// Strip the paths of a node built elsewhere (an archive listing, ...)
+    pub(crate) fn from_node(node: DirectoryNode) -> Self {
+        NameNode {
+            name: node.path.file_name().unwrap_or_default().to_os_string(),
+            size: node.size,
+            file_count: node.file_count,
+            dir_count: node.dir_count,
+            children: node.children.into_iter().map(NameNode::from_node).collect(),
+            files: node.files,
+        }
+    }
+
+    /// Materialize the node as a child of `parent`
+    pub(crate) fn into_node(self, parent: &Path) -> DirectoryNode {
+        let path = parent.join(&self.name);
+        DirectoryNode {
+            children: self
+                .children
+                .into_iter()
+                .map(|child| child.into_node(&path))
+                .collect(),
+            path,
+            size: self.size,
+            file_count: self.file_count,
+            dir_count: self.dir_count,
+            files: self.files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_name_node_round_trip() {
+        let leaf = DirectoryNode {
+            path: PathBuf::from("/data/photos/2024"),
+            size: 10,
+            file_count: 1,
+            dir_count: 0,
+            children: Vec::new(),
+            files: Vec::new(),
+        };
+        let node = DirectoryNode {
+            path: PathBuf::from("/data/photos"),
+            size: 10,
+            file_count: 1,
+            dir_count: 1,
+            children: vec![leaf],
+            files: Vec::new(),
+        };
+
+        let names = NameNode::from_node(node);
+        assert_eq!(names.name, "photos");
+        assert_eq!(names.children[0].name, "2024");
+
+        let rebuilt = names.into_node(Path::new("/mnt/backup"));
+        assert_eq!(rebuilt.path, Path::new("/mnt/backup/photos"));
+        assert_eq!(
+            rebuilt.children[0].path,
+            Path::new("/mnt/backup/photos/2024")
+        );
+        assert_eq!(rebuilt.dir_count, 1);
+    }
+}
@@ -0,0 +1,641 @@
+//! Interactive terminal browser (ncdu-style)
+//!
+//! The TUI starts with an analysis of the root path and lets the user drill
+//! into subdirectories. Each directory is analyzed lazily in a background
+//! thread the first time it is opened, and results are cached so navigating
+//! back and forth is instant. `r` discards the cached result and rescans.
+//!
+//! Entries can be marked with space and deleted (or moved to the trash) with
+//! `d` after a confirmation; the affected directories are then rescanned.
+
+use crate::delete::{self, Removal};
+use crate::format::{format_percentage, format_size_auto};
+use crate::{analyze_disk_usage, AnalysisConfig, AnalysisResult, DirectoryEntry};
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// Width of the bar column in the table
+const BAR_WIDTH: usize = 20;
+
+/// Ordering applied to the entries of the current directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortMode {
+    /// Largest first
+    Size,
+    /// Alphabetical by name
+    Name,
+    /// Most files first
+    Files,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            SortMode::Size => SortMode::Name,
+            SortMode::Name => SortMode::Files,
+            SortMode::Files => SortMode::Size,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Size => "size",
+            SortMode::Name => "name",
+            SortMode::Files => "files",
+        }
+    }
+}
+
+/// A scan running in the background for a directory
+struct PendingScan {
+    path: PathBuf,
+    receiver: Receiver<Result<AnalysisResult>>,
+}
+
+/// State of the interactive browser
+pub struct App {
+    root: PathBuf,
+    config: AnalysisConfig,
+    current: PathBuf,
+    /// Parent directories and the selection to restore when returning to them
+    history: Vec<(PathBuf, usize)>,
+    cache: HashMap<PathBuf, AnalysisResult>,
+    pending: Option<PendingScan>,
+    sort: SortMode,
+    selected: usize,
+    /// Entries marked for deletion
+    marked: BTreeSet<PathBuf>,
+    /// Entries awaiting confirmation before they are deleted
+    confirm: Option<Vec<PathBuf>>,
+    removal: Removal,
+    status: Option<String>,
+    should_quit: bool,
+}
+
+impl App {
+    /// Create a browser rooted at `root`; no scan is started until requested
+    pub fn new(root: PathBuf, config: AnalysisConfig) -> Self {
+        Self {
+            current: root.clone(),
+            root,
+            config,
+            history: Vec::new(),
+            cache: HashMap::new(),
+            pending: None,
+            sort: SortMode::Size,
+            selected: 0,
+            marked: BTreeSet::new(),
+            confirm: None,
+            removal: Removal::Delete,
+            status: None,
+            should_quit: false,
+        }
+    }
+
+    /// Choose whether deleted entries are removed permanently or moved to the trash
+    pub fn with_removal(mut self, removal: Removal) -> Self {
+        self.removal = removal;
+        self
+    }
+
+    /// Entries of the current directory in display order
+    pub fn entries(&self) -> Vec<&DirectoryEntry> {
+        let Some(result) = self.cache.get(&self.current) else {
+            return Vec::new();
+        };
+
+        let mut entries: Vec<&DirectoryEntry> = result.top_directories.iter().collect();
+        match self.sort {
+            SortMode::Size => entries.sort_by_key(|e| std::cmp::Reverse(e.size)),
+            SortMode::Name => entries.sort_by_key(|e| e.path.file_name().map(|n| n.to_os_string())),
+            SortMode::Files => entries.sort_by_key(|e| std::cmp::Reverse(e.file_count)),
+        }
+        entries
+    }
+
+    /// Directory currently being displayed
+    pub fn current(&self) -> &Path {
+        &self.current
+    }
+
+    /// Index of the highlighted entry
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Store the result of an analysis, making it available for display
+    pub fn insert_result(&mut self, result: AnalysisResult) {
+        self.cache.insert(result.root_path.clone(), result);
+        self.clamp_selection();
+    }
+
+    /// Start analyzing `path` in the background unless it is already cached
+    pub fn request_scan(&mut self, path: PathBuf) {
+        if self.cache.contains_key(&path) {
+            return;
+        }
+
+        let config = self.config.clone();
+        let (sender, receiver) = mpsc::channel();
+        let scan_path = path.clone();
+        thread::spawn(move || {
+            let _ = sender.send(analyze_disk_usage(&scan_path, &config, usize::MAX));
+        });
+
+        self.status = None;
+        self.pending = Some(PendingScan { path, receiver });
+    }
+
+    /// Collect the result of the background scan if it has finished
+    pub fn poll_scan(&mut self) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+
+        match pending.receiver.try_recv() {
+            Ok(Ok(result)) => {
+                self.pending = None;
+                self.insert_result(result);
+            }
+            Ok(Err(e)) => {
+                self.status = Some(format!(
+                    "Failed to analyze {}: {:#}",
+                    pending.path.display(),
+                    e
+                ));
+                self.pending = None;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.status = Some(format!("Scan of {} was aborted", pending.path.display()));
+                self.pending = None;
+            }
+        }
+    }
+
+    /// Whether a background scan is in progress
+    pub fn is_scanning(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Whether `path` is marked for deletion
+    pub fn is_marked(&self, path: &Path) -> bool {
+        self.marked.contains(path)
+    }
+
+    /// Entries waiting for the user to confirm their deletion
+    pub fn pending_deletion(&self) -> Option<&[PathBuf]> {
+        self.confirm.as_deref()
+    }
+
+    /// Apply a key press to the browser state
+    pub fn handle_key(&mut self, code: KeyCode) {
+        if self.confirm.is_some() {
+            match code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => self.delete_confirmed(),
+                _ => {
+                    self.confirm = None;
+                    self.status = Some(" Nothing deleted".to_string());
+                }
+            }
+            return;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.select_previous(),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => {
+                self.selected = self.entries().len().saturating_sub(1)
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.enter_selected(),
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.leave(),
+            KeyCode::Char('s') => {
+                self.sort = self.sort.next();
+                self.selected = 0;
+            }
+            KeyCode::Char('r') => {
+                self.cache.remove(&self.current);
+                self.request_scan(self.current.clone());
+            }
+            KeyCode::Char(' ') => self.toggle_mark(),
+            KeyCode::Char('d') | KeyCode::Delete => self.request_deletion(),
+            _ => {}
+        }
+    }
+
+    /// Mark or unmark the highlighted entry and move to the next one
+    fn toggle_mark(&mut self) {
+        let Some(path) = self.entries().get(self.selected).map(|e| e.path.clone()) else {
+            return;
+        };
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        self.select_next();
+    }
+
+    /// Ask for confirmation to delete the marked entries, or the highlighted one
+    fn request_deletion(&mut self) {
+        let paths: Vec<PathBuf> = if self.marked.is_empty() {
+            self.entries()
+                .get(self.selected)
+                .map(|e| e.path.clone())
+                .into_iter()
+                .collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        };
+        if !paths.is_empty() {
+            self.confirm = Some(paths);
+        }
+    }
+
+    /// Delete the confirmed entries and rescan what they were part of
+    fn delete_confirmed(&mut self) {
+        let Some(paths) = self.confirm.take() else {
+            return;
+        };
+
+        let mut failures = Vec::new();
+        for path in &paths {
+            // Only entries strictly below the root are ever deleted
+            if !path.starts_with(&self.root) || path == &self.root {
+                failures.push(format!(
+                    "{}: outside of {}",
+                    path.display(),
+                    self.root.display()
+                ));
+                continue;
+            }
+            if let Err(e) = delete::remove(path, self.removal) {
+                failures.push(format!("{}: {}", path.display(), e));
+            }
+            self.marked.remove(path);
+            // Cached results of the directory and everything around it are stale now
+            self.cache
+                .retain(|dir, _| !path.starts_with(dir) && !dir.starts_with(path));
+        }
+
+        let verb = match self.removal {
+            Removal::Delete => "Deleted",
+            Removal::Trash => "Moved to trash",
+        };
+        self.request_scan(self.current.clone());
+        self.status = Some(match failures.first() {
+            None => format!(" {} {} item(s)", verb, paths.len()),
+            Some(failure) => format!(" Failed to delete {}", failure),
+        });
+    }
+
+    fn select_next(&mut self) {
+        let len = self.entries().len();
+        if self.selected + 1 < len {
+            self.selected += 1;
+        }
+    }
+
+    fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Drill into the highlighted entry if it is a directory
+    fn enter_selected(&mut self) {
+        let Some(entry) = self.entries().get(self.selected).copied() else {
+            return;
+        };
+        if !is_directory(entry) {
+            return;
+        }
+
+        let path = entry.path.clone();
+        self.history.push((self.current.clone(), self.selected));
+        self.current = path.clone();
+        self.selected = 0;
+        self.request_scan(path);
+    }
+
+    /// Return to the parent directory, never going above the root
+    fn leave(&mut self) {
+        if self.current == self.root {
+            return;
+        }
+
+        if let Some((parent, selected)) = self.history.pop() {
+            self.current = parent;
+            self.selected = selected;
+            self.clamp_selection();
+        }
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.entries().len();
+        if self.selected >= len {
+            self.selected = len.saturating_sub(1);
+        }
+    }
+}
+
+/// Entries produced for subdirectories always count the directory itself,
+/// while files directly in the analyzed path have no directory count.
+fn is_directory(entry: &DirectoryEntry) -> bool {
+    entry.dir_count > 0
+}
+
+/// Run the interactive browser on `root` until the user quits
+///
+/// `removal` decides what happens to entries the user deletes.
+pub fn run(root: &Path, config: &AnalysisConfig, removal: Removal) -> Result<()> {
+    let mut app = App::new(root.to_path_buf(), config.clone()).with_removal(removal);
+    app.request_scan(root.to_path_buf());
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    while !app.should_quit {
+        app.poll_scan();
+        terminal.draw(|frame| draw(frame, app))?;
+
+        if event::poll(Duration::from_millis(100))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key.code);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [header_area, table_area, footer_area] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Min(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let result = app.cache.get(&app.current);
+
+    let total = result
+        .map(|r| format!("  Total: {}", format_size_auto(r.total_size)))
+        .unwrap_or_default();
+    let header = Paragraph::new(vec![
+        Line::from(vec![
+            Span::styled(" DuTop ", Style::default().add_modifier(Modifier::REVERSED)),
+            Span::raw(format!(" {}", app.current.display())),
+        ]),
+        Line::from(format!(" Sorted by {}{}", app.sort.label(), total)),
+    ]);
+    frame.render_widget(header, header_area);
+
+    let block = Block::default().borders(Borders::TOP | Borders::BOTTOM);
+    if result.is_none() {
+        let message = match (&app.status, app.is_scanning()) {
+            (Some(status), _) => status.clone(),
+            (None, true) => format!("Scanning {} ...", app.current.display()),
+            (None, false) => String::new(),
+        };
+        frame.render_widget(Paragraph::new(message).block(block), table_area);
+    } else {
+        let total_size = result.map(|r| r.total_size).unwrap_or(0);
+        let entries = app.entries();
+        let max_size = entries.iter().map(|e| e.size).max().unwrap_or(0);
+
+        let rows = entries.iter().map(|entry| {
+            let mut name = entry
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| entry.path.display().to_string());
+            if is_directory(entry) {
+                name.push('/');
+            }
+            let mark = if app.is_marked(&entry.path) { "*" } else { " " };
+
+            Row::new(vec![
+                Span::raw(format_size_auto(entry.size)),
+                Span::raw(format_percentage(entry.size, total_size)),
+                bar(entry.size, max_size),
+                Span::styled(mark, Style::default().fg(Color::Red)),
+                Span::raw(name),
+            ])
+        });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(9),
+                Constraint::Length(5),
+                Constraint::Length(BAR_WIDTH as u16),
+                Constraint::Length(1),
+                Constraint::Min(10),
+            ],
+        )
+        .block(block)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        let mut state = TableState::default().with_selected(Some(app.selected));
+        frame.render_stateful_widget(table, table_area, &mut state);
+    }
+
+    let footer = match (&app.status, app.is_scanning() && result.is_some()) {
+        _ if app.confirm.is_some() => confirmation_prompt(app),
+        (Some(status), _) => status.clone(),
+        (None, true) => " Rescanning ...".to_string(),
+        (None, false) => {
+            " ↑/↓ move  ⏎/→ open  ←/⌫ back  s sort  r rescan  space mark  d delete  q quit"
+                .to_string()
+        }
+    };
+    frame.render_widget(Paragraph::new(footer), footer_area);
+}
+
+/// Footer asking to confirm the pending deletion
+fn confirmation_prompt(app: &App) -> String {
+    let paths = app.pending_deletion().unwrap_or_default();
+    let size: u64 = app
+        .entries()
+        .iter()
+        .filter(|e| paths.contains(&e.path))
+        .map(|e| e.size)
+        .sum();
+    let what = match paths {
+        [path] => path.display().to_string(),
+        _ => format!("{} marked items", paths.len()),
+    };
+    let verb = match app.removal {
+        Removal::Delete => "Permanently delete",
+        Removal::Trash => "Move to trash",
+    };
+    format!(" {} {} ({})? y/N", verb, what, format_size_auto(size))
+}
+
+/// Render a size bar scaled against the largest entry, colored like the table output
+fn bar(size: u64, max_size: u64) -> Span<'static> {
+    let filled = if max_size > 0 {
+        ((size as f64 / max_size as f64) * BAR_WIDTH as f64) as usize
+    } else {
+        0
+    }
+    .min(BAR_WIDTH);
+
+    let color = if filled >= BAR_WIDTH * 50 / 100 {
+        Color::Red
+    } else if filled >= BAR_WIDTH * 33 / 100 {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+
+    Span::styled(
+        format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled)),
+        Style::default().fg(color),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64, file_count: usize, dir_count: usize) -> DirectoryEntry {
+        DirectoryEntry {
+            path: PathBuf::from(path),
+            size,
+            file_count,
+            dir_count,
+            modified: None,
+        }
+    }
+
+    fn result(root: &str, entries: Vec<DirectoryEntry>) -> AnalysisResult {
+        AnalysisResult {
+            root_path: PathBuf::from(root),
+            total_size: entries.iter().map(|e| e.size).sum(),
+            total_files: entries.iter().map(|e| e.file_count).sum(),
+            total_dirs: entries.iter().map(|e| e.dir_count).sum(),
+            top_directories: entries,
+            ..Default::default()
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new(PathBuf::from("/data"), AnalysisConfig::default());
+        app.insert_result(result(
+            "/data",
+            vec![
+                entry("/data/b", 100, 1, 1),
+                entry("/data/a", 300, 5, 2),
+                entry("/data/c.txt", 200, 1, 0),
+            ],
+        ));
+        app
+    }
+
+    #[test]
+    fn test_sort_modes() {
+        let mut app = app();
+        let names = |app: &App| {
+            app.entries()
+                .iter()
+                .map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&app), ["a", "c.txt", "b"]);
+        app.handle_key(KeyCode::Char('s'));
+        assert_eq!(names(&app), ["a", "b", "c.txt"]);
+        app.handle_key(KeyCode::Char('s'));
+        assert_eq!(names(&app)[0], "a");
+    }
+
+    #[test]
+    fn test_selection_is_bounded() {
+        let mut app = app();
+        app.handle_key(KeyCode::Up);
+        assert_eq!(app.selected(), 0);
+        for _ in 0..10 {
+            app.handle_key(KeyCode::Down);
+        }
+        assert_eq!(app.selected(), 2);
+    }
+
+    #[test]
+    fn test_enter_and_leave_directories() {
+        let mut app = app();
+
+        // Files cannot be entered
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Enter);
+        assert_eq!(app.current(), Path::new("/data"));
+
+        // Cached directories open without a scan
+        app.insert_result(result("/data/a", vec![entry("/data/a/x", 300, 5, 1)]));
+        app.handle_key(KeyCode::Up);
+        app.handle_key(KeyCode::Enter);
+        assert_eq!(app.current(), Path::new("/data/a"));
+        assert!(!app.is_scanning());
+
+        app.handle_key(KeyCode::Backspace);
+        assert_eq!(app.current(), Path::new("/data"));
+        assert_eq!(app.selected(), 0);
+
+        // Never leave the root
+        app.handle_key(KeyCode::Backspace);
+        assert_eq!(app.current(), Path::new("/data"));
+    }
+
+    #[test]
+    fn test_mark_and_delete() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("old/nested")).unwrap();
+        std::fs::write(root.join("old/nested/file"), vec![0u8; 8192]).unwrap();
+        std::fs::write(root.join("notes.txt"), b"keep").unwrap();
+        std::fs::write(root.join("tmp.log"), b"drop").unwrap();
+
+        let config = AnalysisConfig::default();
+        let mut app = App::new(root.clone(), config.clone());
+        app.insert_result(analyze_disk_usage(&root, &config, usize::MAX).unwrap());
+        app.sort = SortMode::Name;
+
+        // notes.txt, old/, tmp.log: mark the directory and the log file
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Char(' '));
+        app.handle_key(KeyCode::Char(' '));
+        assert!(app.is_marked(&root.join("old")));
+        assert!(app.is_marked(&root.join("tmp.log")));
+
+        // Anything but `y` cancels
+        app.handle_key(KeyCode::Char('d'));
+        assert_eq!(app.pending_deletion().map(|p| p.len()), Some(2));
+        app.handle_key(KeyCode::Char('n'));
+        assert!(app.pending_deletion().is_none());
+        assert!(root.join("old").exists());
+
+        app.handle_key(KeyCode::Char('d'));
+        app.handle_key(KeyCode::Char('y'));
+        assert!(!root.join("old").exists());
+        assert!(!root.join("tmp.log").exists());
+        assert!(root.join("notes.txt").exists());
+        assert!(!app.is_marked(&root.join("old")));
+        assert!(app.is_scanning());
+    }
+}
@@ -0,0 +1,537 @@
+//! Disk usage broken down by file extension and by the kind of content the
+//! extension stands for (video, images, archives, code, logs, ...)
+//!
+//! With deep detection, files are also classified from the signature at the
+//! start of their contents: files without a known extension by whatever it
+//! tells, and files with one when it contradicts the extension, like a `.jpg`
+//! that is really a zip archive. Only a sample of the files of each extension
+//! is read, and the rest are apportioned like the sampled ones, which bounds
+//! the I/O on trees with millions of them.
+
+use crate::{analyze_with_hooks, AnalysisConfig, EntryVisitor, ScanHooks};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Kind of content a file holds, as told by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    /// Movies and recordings (mp4, mkv, ...)
+    Video,
+    /// Music and sound (mp3, flac, ...)
+    Audio,
+    /// Photos and graphics, including camera raw files
+    Image,
+    /// Compressed archives and packages (zip, tar.gz, deb, ...)
+    Archive,
+    /// ISO images and virtual machine disks
+    DiskImage,
+    /// Office documents, PDFs and text
+    Document,
+    /// Source code, configuration and build output
+    Code,
+    /// Log files, rotated ones included
+    Log,
+    /// Database and columnar data files
+    Database,
+    /// Anything else, and files without an extension
+    Other,
+}
+
+impl Category {
+    /// Category of files with `extension` (lowercase, without the dot)
+    pub fn from_extension(extension: &str) -> Self {
+        match extension {
+            "mp4" | "mkv" | "avi" | "mov" | "wmv" | "flv" | "webm" | "m4v" | "mpg" | "mpeg"
+            | "ts" | "vob" | "3gp" => Self::Video,
+            "mp3" | "flac" | "wav" | "aac" | "ogg" | "opus" | "m4a" | "wma" | "aiff" => Self::Audio,
+            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tif" | "tiff" | "webp" | "heic" | "heif"
+            | "raw" | "cr2" | "nef" | "arw" | "dng" | "psd" | "svg" | "ico" => Self::Image,
+            "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" | "lz4" | "lzma"
+            | "jar" | "war" | "deb" | "rpm" | "apk" | "whl" => Self::Archive,
+            "iso" | "img" | "dmg" | "vmdk" | "vdi" | "qcow2" | "vhd" | "vhdx" | "ova" => {
+                Self::DiskImage
+            }
+            "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp"
+            | "rtf" | "txt" | "md" | "epub" | "csv" => Self::Document,
+            "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "go" | "py" | "js" | "mjs" | "jsx"
+            | "tsx" | "java" | "kt" | "swift" | "rb" | "php" | "cs" | "sh" | "html" | "css"
+            | "json" | "yaml" | "yml" | "toml" | "xml" | "sql" | "o" | "a" | "so" | "dll"
+            | "rlib" | "class" | "pyc" | "exe" | "elf" | "wasm" => Self::Code,
+            "log" | "out" | "err" | "journal" => Self::Log,
+            "db" | "sqlite" | "sqlite3" | "mdb" | "ibd" | "frm" | "parquet" | "avro" => {
+                Self::Database
+            }
+            _ => Self::Other,
+        }
+    }
+
+    /// Lowercase name of the category
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Image => "image",
+            Self::Archive => "archive",
+            Self::DiskImage => "disk-image",
+            Self::Document => "document",
+            Self::Code => "code",
+            Self::Log => "log",
+            Self::Database => "database",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Extension of `path`, lowercased, counting rotated logs like `app.log.1` as
+/// `log`
+pub fn extension(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    // "syslog.1" is a rotated log rather than a file with extension "1"
+    if extension.chars().all(|c| c.is_ascii_digit()) {
+        let stem = Path::new(path.file_stem()?);
+        if stem
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("log"))
+        {
+            return Some("log".to_string());
+        }
+    }
+    Some(extension)
+}
+
+/// Number of files of each extension read by default when detecting types
+/// from their contents
+pub const DEFAULT_SAMPLE: usize = 1000;
+
+/// Bytes read from the start of a file to detect its type
+const HEADER_LEN: usize = 32;
+
+/// Extension usually given to the format whose signature starts `header`
+pub fn sniff(header: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "png"),
+        (b"\xff\xd8\xff", "jpg"),
+        (b"GIF87a", "gif"),
+        (b"GIF89a", "gif"),
+        (b"II*\0", "tiff"),
+        (b"MM\0*", "tiff"),
+        (b"\x1a\x45\xdf\xa3", "mkv"),
+        (b"ID3", "mp3"),
+        (b"fLaC", "flac"),
+        (b"OggS", "ogg"),
+        (b"PK\x03\x04", "zip"),
+        (b"\x1f\x8b", "gz"),
+        (b"\xfd7zXZ\0", "xz"),
+        (b"\x28\xb5\x2f\xfd", "zst"),
+        (b"7z\xbc\xaf\x27\x1c", "7z"),
+        (b"Rar!\x1a\x07", "rar"),
+        (b"BZh", "bz2"),
+        (b"QFI\xfb", "qcow2"),
+        (b"%PDF-", "pdf"),
+        (b"SQLite format 3\0", "sqlite"),
+        (b"PAR1", "parquet"),
+        (b"\x7fELF", "elf"),
+        (b"MZ", "exe"),
+        (b"\0asm", "wasm"),
+    ];
+
+    if let Some((_, extension)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| header.starts_with(signature))
+    {
+        return Some(extension);
+    }
+    // Formats whose signature is not at the very start
+    match (header.get(..4), header.get(4..8), header.get(8..12)) {
+        (_, Some(b"ftyp"), Some(brand)) => Some(match brand {
+            b"qt  " => "mov",
+            b"M4A " => "m4a",
+            b"heic" | b"heix" | b"mif1" => "heic",
+            _ => "mp4",
+        }),
+        (Some(b"RIFF"), _, Some(b"AVI ")) => Some("avi"),
+        (Some(b"RIFF"), _, Some(b"WAVE")) => Some("wav"),
+        (Some(b"RIFF"), _, Some(b"WEBP")) => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Whether contents detected as the format usually given extension `detected`
+/// contradict an extension of category `claimed`
+///
+/// Documents stored in zip containers (docx, odt, epub, ...) agree with it.
+fn contradicts(claimed: Category, detected: &str) -> bool {
+    Category::from_extension(detected) != claimed
+        && !(claimed == Category::Document && detected == "zip")
+}
+
+/// Detect the type of the file at `path` from its first bytes
+fn sniff_file(path: &Path) -> Option<&'static str> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    std::fs::File::open(path)
+        .ok()?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    sniff(&header)
+}
+
+/// Usage of one extension or category
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeUsage {
+    /// Extension without the dot (None for files without one) or category name
+    pub name: Option<String>,
+    /// Category of the files
+    pub category: Category,
+    /// Total disk usage in bytes
+    pub size: u64,
+    /// Number of files
+    pub file_count: usize,
+    /// The extension was detected from the contents of the files rather than
+    /// taken from their names
+    pub detected: bool,
+}
+
+/// Usage by extension and by category, each sorted by size
+#[derive(Debug, Clone, Default)]
+pub struct TypeReport {
+    /// Path that was analyzed
+    pub root_path: PathBuf,
+    /// Total size of all files in bytes
+    pub total_size: u64,
+    /// Total number of files
+    pub total_files: usize,
+    /// Usage per category
+    pub categories: Vec<TypeUsage>,
+    /// Usage per extension
+    pub extensions: Vec<TypeUsage>,
+    /// Sizes of detected types were extrapolated from a sample of the files
+    pub estimated: bool,
+}
+
+/// Files of one extension whose contents are being sampled
+#[derive(Default)]
+struct Sample {
+    /// Usage of the sampled files per detected extension (None if unrecognized)
+    detected: HashMap<Option<&'static str>, (u64, usize)>,
+    /// Number of files read so far, including those still being read
+    taken: usize,
+    /// Usage of the files left out of the sample
+    rest: (u64, usize),
+}
+
+#[derive(Default)]
+struct TypeTally {
+    /// Files read per extension, when detecting types from contents
+    sample_size: Option<usize>,
+    extensions: Mutex<HashMap<Option<String>, (u64, usize)>>,
+    samples: Mutex<HashMap<Option<String>, Sample>>,
+}
+
+impl EntryVisitor for TypeTally {
+    fn visit_file(&self, path: &Path, _metadata: &std::fs::Metadata, size: u64) {
+        let extension = extension(path);
+
+        if let Some(sample_size) = self.sample_size {
+            // The lock is not held while reading, so files are sniffed in parallel
+            let take = {
+                let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+                let sample = samples.entry(extension.clone()).or_default();
+                let take = sample.taken < sample_size;
+                if take {
+                    sample.taken += 1;
+                } else {
+                    sample.rest.0 += size;
+                    sample.rest.1 += 1;
+                }
+                take
+            };
+            if take {
+                // A known extension only gives way to contents contradicting it
+                let claimed = extension
+                    .as_deref()
+                    .map_or(Category::Other, Category::from_extension);
+                let detected = sniff_file(path)
+                    .filter(|kind| claimed == Category::Other || contradicts(claimed, kind));
+                let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+                let entry = samples
+                    .entry(extension)
+                    .or_default()
+                    .detected
+                    .entry(detected)
+                    .or_default();
+                entry.0 += size;
+                entry.1 += 1;
+            }
+            return;
+        }
+
+        let mut extensions = self.extensions.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = extensions.entry(extension).or_default();
+        entry.0 += size;
+        entry.1 += 1;
+    }
+}
+
+impl TypeTally {
+    /// Usage per extension, with the unsampled files of each extension split
+    /// between the detected types in the proportions of its sample
+    fn into_usage(self) -> (Vec<TypeUsage>, bool) {
+        let mut usage: HashMap<(Option<String>, bool), (u64, usize)> = self
+            .extensions
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .map(|(extension, totals)| ((extension, false), totals))
+            .collect();
+        let mut estimated = false;
+
+        for (extension, sample) in self.samples.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            let (sampled_size, sampled_files) = sample
+                .detected
+                .values()
+                .fold((0, 0), |(size, files), entry| {
+                    (size + entry.0, files + entry.1)
+                });
+            estimated |= sample.rest.1 > 0;
+
+            // Split the rest by sampled size, or by file count if that is zero
+            let mut remaining = sample.rest;
+            let mut detected: Vec<_> = sample.detected.into_iter().collect();
+            detected.sort_by_key(|(_, (size, files))| (*size, *files));
+            let last = detected.len().saturating_sub(1);
+            for (i, (kind, (size, files))) in detected.into_iter().enumerate() {
+                let share = if i == last {
+                    remaining
+                } else {
+                    let ratio = if sampled_size > 0 {
+                        size as f64 / sampled_size as f64
+                    } else {
+                        files as f64 / sampled_files as f64
+                    };
+                    (
+                        (sample.rest.0 as f64 * ratio) as u64,
+                        (sample.rest.1 as f64 * ratio) as usize,
+                    )
+                };
+                remaining.0 -= share.0;
+                remaining.1 -= share.1;
+
+                let key = match kind {
+                    Some(kind) => (Some(kind.to_string()), true),
+                    None => (extension.clone(), false),
+                };
+                let entry = usage.entry(key).or_default();
+                entry.0 += size + share.0;
+                entry.1 += files + share.1;
+            }
+        }
+
+        let usage = usage
+            .into_iter()
+            .map(|((name, detected), (size, file_count))| TypeUsage {
+                category: name
+                    .as_deref()
+                    .map_or(Category::Other, Category::from_extension),
+                name,
+                size,
+                file_count,
+                detected,
+            })
+            .collect();
+        (usage, estimated)
+    }
+}
+
+/// Analyze `path`, aggregating sizes by the extension of each file
+///
+/// With a `sample_size`, up to that many files of each extension (including
+/// files without one) are classified from their contents, which override a
+/// known extension only when they contradict it.
+pub fn analyze_by_type(
+    path: &Path,
+    config: &AnalysisConfig,
+    sample_size: Option<usize>,
+) -> Result<TypeReport> {
+    let tally = TypeTally {
+        sample_size,
+        ..Default::default()
+    };
+    let hooks = ScanHooks {
+        visitor: Some(&tally),
+        ..Default::default()
+    };
+    let config = AnalysisConfig {
+        build_tree: false,
+        ..config.clone()
+    };
+    let result = analyze_with_hooks(path, &config, 0, &hooks)?;
+
+    let (mut extensions, estimated) = tally.into_usage();
+    extensions.sort_by(|a, b| {
+        b.size
+            .cmp(&a.size)
+            .then_with(|| a.name.cmp(&b.name))
+            .then(a.detected.cmp(&b.detected))
+    });
+
+    let mut categories: HashMap<Category, (u64, usize)> = HashMap::new();
+    for usage in &extensions {
+        let entry = categories.entry(usage.category).or_default();
+        entry.0 += usage.size;
+        entry.1 += usage.file_count;
+    }
+    let mut categories: Vec<TypeUsage> = categories
+        .into_iter()
+        .map(|(category, (size, file_count))| TypeUsage {
+            name: Some(category.to_string()),
+            category,
+            size,
+            file_count,
+            detected: false,
+        })
+        .collect();
+    categories.sort_by(|a, b| b.size.cmp(&a.size).then(a.category.cmp(&b.category)));
+
+    Ok(TypeReport {
+        root_path: result.root_path,
+        total_size: result.total_size,
+        total_files: result.total_files,
+        categories,
+        extensions,
+        estimated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension(Path::new("/a/Movie.MP4")).as_deref(), Some("mp4"));
+        assert_eq!(
+            extension(Path::new("/var/log/syslog.log.3")).as_deref(),
+            Some("log")
+        );
+        assert_eq!(
+            extension(Path::new("/a/archive.tar.gz")).as_deref(),
+            Some("gz")
+        );
+        assert_eq!(extension(Path::new("/a/Makefile")), None);
+        assert_eq!(extension(Path::new("/a/.bashrc")), None);
+        assert_eq!(Category::from_extension("mkv"), Category::Video);
+        assert_eq!(Category::from_extension("xyz"), Category::Other);
+    }
+
+    #[test]
+    fn test_analyze_by_type() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("a")).unwrap();
+        fs::write(temp_dir.path().join("a/clip.mp4"), vec![1u8; 64 << 10]).unwrap();
+        fs::write(temp_dir.path().join("a/other.MKV"), vec![1u8; 8 << 10]).unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), b"hi").unwrap();
+        fs::write(temp_dir.path().join("README"), b"hi").unwrap();
+
+        let report = analyze_by_type(temp_dir.path(), &AnalysisConfig::default(), None).unwrap();
+        assert_eq!(report.total_files, 4);
+        assert_eq!(report.extensions[0].name.as_deref(), Some("mp4"));
+        assert_eq!(report.categories[0].category, Category::Video);
+        assert_eq!(report.categories[0].file_count, 2);
+        assert!(report.extensions.iter().any(|usage| usage.name.is_none()));
+        assert_eq!(
+            report.categories.iter().map(|c| c.size).sum::<u64>(),
+            report.total_size
+        );
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some("png"));
+        assert_eq!(sniff(b"\0\0\0\x18ftypisom"), Some("mp4"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WAVEfmt "), Some("wav"));
+        assert_eq!(sniff(b"SQLite format 3\0"), Some("sqlite"));
+        assert_eq!(sniff(b"hello"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_deep_types() {
+        let temp_dir = TempDir::new().unwrap();
+        let with_header = |header: &[u8]| {
+            let mut data = header.to_vec();
+            data.resize(16 << 10, 1);
+            data
+        };
+        fs::write(
+            temp_dir.path().join("picture"),
+            with_header(b"\x89PNG\r\n\x1a\n"),
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), with_header(b"%PDF-")).unwrap();
+        fs::write(
+            temp_dir.path().join("photo.jpg"),
+            with_header(b"PK\x03\x04"),
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("report.docx"),
+            with_header(b"PK\x03\x04"),
+        )
+        .unwrap();
+        for i in 0..3 {
+            fs::write(
+                temp_dir.path().join(format!("chunk{}.dat", i)),
+                with_header(b"\x1f\x8b"),
+            )
+            .unwrap();
+        }
+
+        let report = analyze_by_type(temp_dir.path(), &AnalysisConfig::default(), Some(1)).unwrap();
+        let find = |name: &str| {
+            report
+                .extensions
+                .iter()
+                .find(|usage| usage.name.as_deref() == Some(name))
+                .unwrap()
+        };
+        assert!(report.estimated);
+        assert!(find("png").detected);
+        assert_eq!(find("png").category, Category::Image);
+        assert_eq!(find("gz").file_count, 3);
+        assert_eq!(find("gz").category, Category::Archive);
+        // A known extension only gives way to contents contradicting it
+        assert!(!find("txt").detected);
+        assert!(!find("docx").detected);
+        assert!(find("zip").detected);
+        assert_eq!(find("zip").category, Category::Archive);
+        assert!(!report
+            .extensions
+            .iter()
+            .any(|usage| usage.name.as_deref() == Some("jpg")));
+        assert_eq!(
+            report.categories.iter().map(|c| c.size).sum::<u64>(),
+            report.total_size
+        );
+        assert_eq!(
+            report
+                .extensions
+                .iter()
+                .map(|c| c.file_count)
+                .sum::<usize>(),
+            report.total_files
+        );
+    }
+}
@@ -0,0 +1,1366 @@
+//! Parallel directory traversal
+//!
+//! The walker performs a fork-join traversal on the rayon thread pool: every
+//! directory is read on the worker that reached it, and its entries are then
+//! processed with `par_iter`, so subtrees are picked up by idle workers through
+//! rayon's work stealing. Each subtree returns its aggregated statistics to its
+//! parent, which means no shared map is needed for the size accounting.
+
+use crate::archive;
+use crate::cache::{ScanCache, Stamp};
+use crate::cancel::CancellationToken;
+use crate::checkpoint::Checkpointer;
+use crate::exclude::Exclusions;
+use crate::inodes::SeenInodes;
+use crate::progress::ProgressTracker;
+use crate::stats::{ScanStats, StatsCollector};
+#[cfg(target_os = "linux")]
+use crate::statx;
+use crate::top::TopN;
+use crate::trie::NameNode;
+use crate::watchdog::Watchdog;
+use crate::{
+    get_device_id, get_disk_usage, get_inode_key, get_link_count, AnalysisConfig, DirectoryEntry,
+    DirectoryNode, EntryVisitor, FileNode, LinkIssue, LinkIssueKind, OtherTypes, ScanError,
+    ScanErrorKind, ScanHooks,
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Aggregated statistics for a subtree
+#[derive(Debug, Default, Clone)]
+pub(crate) struct DirectoryStats {
+    pub size: u64,
+    pub file_count: usize,
+    pub dir_count: usize,
+    /// Most recent modification time of the files, in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Symbolic links and special files, whether or not they are counted as files
+    pub other_types: OtherTypes,
+}
+
+impl DirectoryStats {
+    fn merge(&mut self, other: &DirectoryStats) {
+        self.size += other.size;
+        self.file_count += other.file_count;
+        self.dir_count += other.dir_count;
+        self.modified = self.modified.max(other.modified);
+        self.other_types.merge(&other.other_types);
+    }
+}
+
+/// Result of scanning the contents of a directory
+#[derive(Debug, Default)]
+pub(crate) struct Subtree {
+    /// Totals for everything below the directory
+    pub stats: DirectoryStats,
+    /// Nodes for the immediate subdirectories (only when building a tree)
+    pub children: Vec<NameNode>,
+    /// Files stored directly in the directory (only when listing files in the tree)
+    pub files: Vec<FileNode>,
+    /// Totals of the files stored directly in the directory
+    own: DirectoryStats,
+    /// Names of the immediate subdirectories (only when caching)
+    subdirs: Vec<OsString>,
+    /// Whether an entry of the directory must be checked again on every scan
+    uncacheable: bool,
+}
+
+impl Subtree {
+    fn merge(mut self, other: Subtree) -> Subtree {
+        self.stats.merge(&other.stats);
+        self.children.extend(other.children);
+        self.files.extend(other.files);
+        self.own.merge(&other.own);
+        self.subdirs.extend(other.subdirs);
+        self.uncacheable |= other.uncacheable;
+        self
+    }
+
+    /// Contents of a directory whose entries cannot be cached
+    fn uncacheable() -> Subtree {
+        Subtree {
+            uncacheable: true,
+            ..Default::default()
+        }
+    }
+
+    /// Turn the contents of the directory called `name` into its node
+    fn into_name_node(mut self, name: OsString) -> NameNode {
+        self.children
+            .sort_unstable_by_key(|c| std::cmp::Reverse(c.size));
+        self.files
+            .sort_unstable_by_key(|f| std::cmp::Reverse(f.size));
+        NameNode {
+            name,
+            size: self.stats.size,
+            file_count: self.stats.file_count,
+            dir_count: self.stats.dir_count,
+            children: self.children,
+            files: self.files,
+        }
+    }
+
+    /// Turn the contents of `path` into a node for the directory itself, with
+    /// the full path of every directory below it
+    fn into_node(self, path: PathBuf) -> DirectoryNode {
+        let node = self.into_name_node(OsString::new());
+        DirectoryNode {
+            children: node
+                .children
+                .into_iter()
+                .map(|child| child.into_node(&path))
+                .collect(),
+            path,
+            size: node.size,
+            file_count: node.file_count,
+            dir_count: node.dir_count,
+            files: node.files,
+        }
+    }
+}
+
+/// Metadata of an entry, from the standard library or the statx backend
+enum Meta {
+    Std(fs::Metadata),
+    #[cfg(target_os = "linux")]
+    Statx(statx::Stat),
+}
+
+impl Meta {
+    fn is_file(&self) -> bool {
+        match self {
+            Meta::Std(metadata) => metadata.is_file(),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.is_file(),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        match self {
+            Meta::Std(metadata) => metadata.is_dir(),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.is_dir(),
+        }
+    }
+
+    fn is_symlink(&self) -> bool {
+        match self {
+            Meta::Std(metadata) => metadata.file_type().is_symlink(),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.is_symlink(),
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            Meta::Std(metadata) => metadata.len(),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.len,
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        match self {
+            Meta::Std(metadata) => metadata.modified().ok(),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.modified,
+        }
+    }
+
+    fn device(&self) -> Option<u64> {
+        match self {
+            Meta::Std(metadata) => get_device_id(metadata),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => Some(stat.device),
+        }
+    }
+
+    fn inode_key(&self) -> (u64, u64) {
+        match self {
+            Meta::Std(metadata) => get_inode_key(metadata),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => (stat.device, stat.inode),
+        }
+    }
+
+    fn link_count(&self) -> u64 {
+        match self {
+            Meta::Std(metadata) => get_link_count(metadata),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.links,
+        }
+    }
+
+    fn disk_usage(&self, path: &Path) -> u64 {
+        match self {
+            Meta::Std(metadata) => get_disk_usage(path, metadata),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => stat.disk_usage,
+        }
+    }
+
+    /// Whether the file is a placeholder whose contents are only stored in
+    /// the cloud, the disk holding at most a few blocks of it
+    #[cfg(windows)]
+    fn is_cloud_placeholder(&self) -> bool {
+        use std::os::windows::fs::MetadataExt;
+        use windows_sys::Win32::Storage::FileSystem::{
+            FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS,
+            FILE_ATTRIBUTE_RECALL_ON_OPEN,
+        };
+        let Meta::Std(metadata) = self;
+        let cloud_only = FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS
+            | FILE_ATTRIBUTE_RECALL_ON_OPEN
+            | FILE_ATTRIBUTE_OFFLINE;
+        metadata.file_attributes() & cloud_only != 0
+    }
+
+    /// Whether the file is a placeholder whose contents are only stored in
+    /// the cloud, the disk holding at most a few blocks of it
+    #[cfg(target_os = "macos")]
+    fn is_cloud_placeholder(&self) -> bool {
+        use std::os::macos::fs::MetadataExt;
+        let Meta::Std(metadata) = self;
+        metadata.st_flags() & crate::macos::SF_DATALESS != 0
+    }
+
+    #[cfg(not(any(windows, target_os = "macos")))]
+    fn is_cloud_placeholder(&self) -> bool {
+        false
+    }
+
+    /// Count the entry in `types` when it is a socket, FIFO or device node
+    #[cfg(unix)]
+    fn tally_special(&self, types: &mut OtherTypes) {
+        use std::os::unix::fs::FileTypeExt;
+        let (socket, fifo, block, char) = match self {
+            Meta::Std(metadata) => {
+                let file_type = metadata.file_type();
+                (
+                    file_type.is_socket(),
+                    file_type.is_fifo(),
+                    file_type.is_block_device(),
+                    file_type.is_char_device(),
+                )
+            }
+            #[cfg(target_os = "linux")]
+            Meta::Statx(stat) => (
+                stat.is_socket(),
+                stat.is_fifo(),
+                stat.is_block_device(),
+                stat.is_char_device(),
+            ),
+        };
+        types.sockets += usize::from(socket);
+        types.fifos += usize::from(fifo);
+        types.block_devices += usize::from(block);
+        types.char_devices += usize::from(char);
+    }
+
+    /// Windows has no sockets, FIFOs or device nodes in its directories
+    #[cfg(not(unix))]
+    fn tally_special(&self, _types: &mut OtherTypes) {}
+
+    /// The standard library's metadata, which visitors receive; the statx
+    /// backend is only used without a visitor
+    fn std(&self) -> Option<&fs::Metadata> {
+        match self {
+            Meta::Std(metadata) => Some(metadata),
+            #[cfg(target_os = "linux")]
+            Meta::Statx(_) => None,
+        }
+    }
+}
+
+/// How directories are read and entries stat'ed, apart from the walker so the
+/// watchdog's threads can make the calls
+#[derive(Clone, Copy)]
+struct Reader {
+    /// Read directories and metadata with getdents64 and statx
+    #[cfg(target_os = "linux")]
+    statx: bool,
+}
+
+impl Reader {
+    fn read_dir(self, dir: &Path) -> std::io::Result<Vec<std::io::Result<(OsString, bool)>>> {
+        #[cfg(target_os = "linux")]
+        if self.statx {
+            return Ok(statx::read_dir(dir)?.into_iter().map(Ok).collect());
+        }
+        Ok(fs::read_dir(dir)?
+            .map(|entry| {
+                let entry = entry?;
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                Ok((entry.file_name(), is_dir))
+            })
+            .collect())
+    }
+
+    fn read_metadata(self, path: &Path, follow: bool) -> std::io::Result<Meta> {
+        #[cfg(target_os = "linux")]
+        if self.statx {
+            return statx::stat(path, follow).map(Meta::Statx);
+        }
+        if follow {
+            fs::metadata(path).map(Meta::Std)
+        } else {
+            fs::symlink_metadata(path).map(Meta::Std)
+        }
+    }
+}
+
+/// Modification time of a file in seconds since the Unix epoch, when known
+fn modified_secs(metadata: &Meta) -> Option<u64> {
+    let modified = metadata.modified()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Whether resolving a path failed because of a cycle of symbolic links
+fn is_loop_error(error: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    return error.raw_os_error() == Some(libc::ELOOP);
+    // ERROR_CANT_RESOLVE_FILENAME
+    #[cfg(windows)]
+    return error.raw_os_error() == Some(1921);
+    #[cfg(not(any(unix, windows)))]
+    return false;
+}
+
+/// An immediate child of the root together with its place in the tree, if any
+pub(crate) struct RootChild {
+    pub entry: DirectoryEntry,
+    pub node: Option<DirectoryNode>,
+    pub file: Option<FileNode>,
+}
+
+/// The immediate children of the root folded together: their totals, the
+/// largest entries and, when building a tree, their nodes
+pub(crate) struct RootScan {
+    /// Totals over every child
+    pub stats: DirectoryStats,
+    /// The entries of the largest children
+    pub top: TopN<DirectoryEntry>,
+    /// Nodes of the subdirectories (only when building a tree)
+    pub nodes: Vec<DirectoryNode>,
+    /// Files directly in the root (only when listing files in the tree)
+    pub files: Vec<FileNode>,
+}
+
+impl RootScan {
+    fn new(top_n: usize) -> Self {
+        Self {
+            stats: DirectoryStats::default(),
+            top: TopN::new(top_n),
+            nodes: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    fn add(mut self, (child, other_types): (Option<RootChild>, OtherTypes)) -> Self {
+        self.stats.other_types.merge(&other_types);
+        let Some(child) = child else {
+            return self;
+        };
+        self.stats.merge(&DirectoryStats {
+            size: child.entry.size,
+            file_count: child.entry.file_count,
+            dir_count: child.entry.dir_count,
+            modified: child.entry.modified,
+            ..Default::default()
+        });
+        self.top.push(child.entry.size, child.entry);
+        self.nodes.extend(child.node);
+        self.files.extend(child.file);
+        self
+    }
+
+    fn merge(mut self, other: RootScan) -> Self {
+        self.stats.merge(&other.stats);
+        self.top = self.top.merge(other.top);
+        self.nodes.extend(other.nodes);
+        self.files.extend(other.files);
+        self
+    }
+}
+
+/// Stops the checkpoint writer once the walk is over, even when it panics
+struct FinishOnDrop<'c>(&'c Checkpointer);
+
+impl Drop for FinishOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// Ignore rules collected from `.gitignore`-style files, innermost directory first
+struct IgnoreChain {
+    matcher: Gitignore,
+    parent: Option<Arc<IgnoreChain>>,
+}
+
+impl IgnoreChain {
+    /// Files consulted in every directory when VCS ignore files are respected
+    const IGNORE_FILES: &'static [&'static str] = &[".gitignore", ".ignore"];
+
+    /// Extend `parent` with the ignore files found in `dir`
+    fn load(dir: &Path, parent: Option<Arc<IgnoreChain>>) -> Option<Arc<IgnoreChain>> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+
+        let git_exclude = dir.join(".git").join("info").join("exclude");
+        let candidates = Self::IGNORE_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .chain(std::iter::once(git_exclude));
+
+        for file in candidates {
+            if file.is_file() {
+                if let Some(e) = builder.add(&file) {
+                    log::debug!("Error reading {}: {}", file.display(), e);
+                }
+                found = true;
+            }
+        }
+
+        if !found {
+            return parent;
+        }
+
+        match builder.build() {
+            Ok(matcher) => Some(Arc::new(IgnoreChain { matcher, parent })),
+            Err(e) => {
+                log::debug!("Invalid ignore rules in {}: {}", dir.display(), e);
+                parent
+            }
+        }
+    }
+
+    /// Whether the closest rule matching `path` ignores it
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut chain = Some(self);
+        while let Some(link) = chain {
+            match link.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => chain = link.parent.as_deref(),
+            }
+        }
+        false
+    }
+}
+
+/// State inherited by a directory from its ancestors
+#[derive(Default)]
+struct Scope {
+    /// Canonical paths of the directories above (only tracked when following links)
+    ancestors: Vec<PathBuf>,
+    /// Active VCS ignore rules
+    ignores: Option<Arc<IgnoreChain>>,
+}
+
+/// Kind of an entry after resolving symbolic links (when enabled)
+enum EntryKind {
+    File(Meta),
+    Dir(Meta),
+    Other,
+}
+
+/// Shared state for a single parallel traversal
+pub(crate) struct Walker<'a> {
+    root: &'a Path,
+    max_depth: usize,
+    follow_links: bool,
+    build_tree: bool,
+    tree_files: bool,
+    expand_archives: bool,
+    respect_gitignore: bool,
+    modified_before: Option<SystemTime>,
+    modified_after: Option<SystemTime>,
+    min_file_size: u64,
+    #[cfg(windows)]
+    alternate_streams: bool,
+    #[cfg(target_os = "macos")]
+    clones: Option<crate::macos::CloneTracker>,
+    cloned_size: AtomicU64,
+    cloud_files: AtomicUsize,
+    cloud_size: AtomicU64,
+    /// Device of the root when the walk must stay on one filesystem
+    root_device: Option<u64>,
+    exclusions: &'a Exclusions,
+    /// Mount points left out of the walk, relative to the root
+    skipped_mounts: Vec<PathBuf>,
+    seen_inodes: SeenInodes,
+    /// Remember files with a single link too
+    track_all_inodes: bool,
+    reader: Reader,
+    /// Gives up on filesystem calls that hang, when a timeout is set
+    watchdog: Option<Watchdog>,
+    hard_links: AtomicUsize,
+    hard_link_savings: AtomicU64,
+    /// Count symlinks and special files as files rather than only tallying them
+    count_all_types: bool,
+    errors: Mutex<Vec<ScanError>>,
+    /// Canonical root that symlink targets are checked against, when checking links
+    link_root: Option<PathBuf>,
+    link_issues: Mutex<Vec<LinkIssue>>,
+    /// Totals of unchanged directories from the previous scan, and the file
+    /// they are saved to
+    cache: Option<(ScanCache, PathBuf)>,
+    /// Completed directories, written periodically
+    checkpoint: Option<Checkpointer>,
+    /// Performance counters, when collecting statistics
+    stats: Option<StatsCollector>,
+    progress: ProgressTracker<'a>,
+    cancel: Option<&'a CancellationToken>,
+    visitor: Option<&'a dyn EntryVisitor>,
+}
+
+impl<'a> Walker<'a> {
+    pub(crate) fn new(
+        root: &'a Path,
+        config: &AnalysisConfig,
+        exclusions: &'a Exclusions,
+        hooks: &ScanHooks<'a>,
+    ) -> Self {
+        let root_device = if config.one_file_system {
+            fs::metadata(root).ok().and_then(|m| get_device_id(&m))
+        } else {
+            None
+        };
+
+        Self {
+            root,
+            max_depth: config.max_depth.unwrap_or(usize::MAX),
+            follow_links: config.follow_links,
+            build_tree: config.build_tree,
+            tree_files: config.build_tree && config.tree_files,
+            expand_archives: config.build_tree && config.expand_archives,
+            respect_gitignore: config.respect_gitignore,
+            modified_before: config.modified_before,
+            modified_after: config.modified_after,
+            min_file_size: config.min_file_size.unwrap_or(0),
+            #[cfg(windows)]
+            alternate_streams: config.alternate_streams,
+            #[cfg(target_os = "macos")]
+            clones: config.detect_clones.then(Default::default),
+            cloned_size: AtomicU64::new(0),
+            cloud_files: AtomicUsize::new(0),
+            cloud_size: AtomicU64::new(0),
+            root_device,
+            exclusions,
+            skipped_mounts: Vec::new(),
+            seen_inodes: SeenInodes::new(config.fast_hardlinks),
+            track_all_inodes: config.track_all_inodes,
+            reader: Reader {
+                #[cfg(target_os = "linux")]
+                statx: config.backend == crate::ScanBackend::Uring && hooks.visitor.is_none(),
+            },
+            watchdog: config.entry_timeout.map(|timeout| {
+                let helpers = config
+                    .num_threads
+                    .unwrap_or_else(rayon::current_num_threads);
+                Watchdog::new(timeout, helpers)
+            }),
+            hard_links: AtomicUsize::new(0),
+            hard_link_savings: AtomicU64::new(0),
+            count_all_types: config.count_all_types,
+            errors: Mutex::new(Vec::new()),
+            link_root: config
+                .check_links
+                .then(|| fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())),
+            link_issues: Mutex::new(Vec::new()),
+            cache: None,
+            checkpoint: None,
+            stats: config.collect_stats.then(|| {
+                StatsCollector::new(
+                    config
+                        .num_threads
+                        .unwrap_or_else(rayon::current_num_threads),
+                )
+            }),
+            progress: ProgressTracker::new(hooks.progress),
+            cancel: hooks.cancel,
+            visitor: hooks.visitor,
+        }
+    }
+
+    /// Reuse the totals of the directories that did not change since the scan
+    /// that recorded `cache`, and record them again to `path`
+    pub(crate) fn with_cache(mut self, cache: ScanCache, path: &Path) -> Self {
+        self.cache = Some((cache, path.to_path_buf()));
+        self
+    }
+
+    /// Leave out the filesystems mounted on `mount_points`, relative to the root
+    pub(crate) fn with_skipped_mounts(mut self, mount_points: Vec<PathBuf>) -> Self {
+        self.skipped_mounts = mount_points;
+        self
+    }
+
+    /// Take the directories completed before `checkpoint` was interrupted
+    /// from it, and write the completed directories to it periodically
+    pub(crate) fn with_checkpoint(mut self, checkpoint: Checkpointer) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Save the directory totals recorded so far, if caching; a `partial` walk
+    /// keeps the previous totals of the directories it has not reached
+    pub(crate) fn save_cache(&self, partial: bool) {
+        // Directories taken from a checkpoint were not walked again
+        let partial = partial
+            || self
+                .checkpoint
+                .as_ref()
+                .is_some_and(Checkpointer::has_resumed);
+        if let Some((cache, path)) = &self.cache {
+            if let Err(e) = cache.save(path, partial) {
+                log::warn!("Cannot save the scan cache: {:#}", e);
+            }
+        }
+    }
+
+    /// Write the completed directories to the checkpoint
+    pub(crate) fn save_checkpoint(&self) {
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Err(e) = checkpoint.save() {
+                log::warn!("Cannot write checkpoint: {:#}", e);
+            }
+        }
+    }
+
+    /// Remove the checkpoint once the walk completed
+    pub(crate) fn remove_checkpoint(&self) {
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.remove();
+        }
+    }
+
+    /// Write a checkpoint every interval until `checkpoint` is finished
+    fn write_checkpoints(&self, checkpoint: &Checkpointer) {
+        while checkpoint.wait() {
+            // Directories completed after cancelling may be partial
+            if !self.is_cancelled() {
+                self.save_checkpoint();
+            }
+        }
+    }
+
+    /// Entries skipped because of errors, leaving none recorded
+    pub(crate) fn take_errors(&self) -> Vec<ScanError> {
+        std::mem::take(&mut *self.errors.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Problematic symbolic links found, leaving none recorded
+    pub(crate) fn take_link_issues(&self) -> Vec<LinkIssue> {
+        std::mem::take(&mut *self.link_issues.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Number of hard links skipped so far and the disk usage they would have added
+    pub(crate) fn hard_link_totals(&self) -> (usize, u64) {
+        (
+            self.hard_links.load(Ordering::Relaxed),
+            self.hard_link_savings.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Disk usage of the files found to be clones so far
+    pub(crate) fn cloned_size(&self) -> u64 {
+        self.cloned_size.load(Ordering::Relaxed)
+    }
+
+    /// Files found to be cloud-only placeholders so far, and their logical size
+    pub(crate) fn cloud_only(&self) -> (usize, u64) {
+        (
+            self.cloud_files.load(Ordering::Relaxed),
+            self.cloud_size.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Whether the analysis was cancelled before the walk completed
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.is_some_and(|token| token.is_cancelled())
+    }
+
+    /// Performance figures of the walk, when collecting statistics, given the
+    /// number of files and directories it counted
+    pub(crate) fn stats(&self, files: usize, dirs: usize) -> Option<ScanStats> {
+        self.stats.as_ref().map(|stats| stats.finish(files, dirs))
+    }
+
+    /// Send the final progress notification
+    pub(crate) fn finish(&self) {
+        self.progress.finish(self.root);
+    }
+
+    /// Walk the root, aggregating each immediate child into one entry and
+    /// keeping the `top_n` largest.
+    ///
+    /// Files directly in the root become their own entries; each subdirectory
+    /// is traversed in parallel and aggregated into a single entry. Entries are
+    /// folded as they complete, so only the largest ones are ever held, and
+    /// none are built when `top_n` is 0 and no tree is built.
+    pub(crate) fn scan_root(&self, top_n: usize) -> RootScan {
+        if self.max_depth == 0 {
+            return RootScan::new(top_n);
+        }
+
+        let Some(scope) = self.enter(self.root, &Scope::default()) else {
+            return RootScan::new(top_n);
+        };
+        self.progress.enter_dir(self.root);
+        let (children, _) = self.read_children(self.root, &scope);
+
+        std::thread::scope(|threads| {
+            let _finished = self.checkpoint.as_ref().map(|checkpoint| {
+                threads.spawn(|| self.write_checkpoints(checkpoint));
+                FinishOnDrop(checkpoint)
+            });
+            // Without entries or a tree to keep, the children are folded like
+            // the contents of any other directory
+            if top_n == 0 && !self.build_tree {
+                let subtree = children
+                    .into_par_iter()
+                    .map(|name| self.scan_entry(self.root, name, 0, &scope))
+                    .reduce(Subtree::default, Subtree::merge);
+                return RootScan {
+                    stats: subtree.stats,
+                    ..RootScan::new(top_n)
+                };
+            }
+            children
+                .par_iter()
+                .map(|name| self.scan_root_child(&self.root.join(name), &scope))
+                .inspect(|(child, _)| {
+                    if let Some(child) = child {
+                        self.progress.complete_entry(&child.entry);
+                    }
+                })
+                .fold(|| RootScan::new(top_n), RootScan::add)
+                .reduce(|| RootScan::new(top_n), RootScan::merge)
+        })
+    }
+
+    /// Walk only the given immediate children of the root
+    ///
+    /// Paths that no longer exist, or that are excluded or ignored, are left out
+    /// of the result.
+    pub(crate) fn rescan(&self, targets: &HashSet<PathBuf>) -> Vec<RootChild> {
+        if self.max_depth == 0 {
+            return Vec::new();
+        }
+
+        let Some(scope) = self.enter(self.root, &Scope::default()) else {
+            return Vec::new();
+        };
+        let (children, _) = self.read_children(self.root, &scope);
+
+        children
+            .par_iter()
+            .map(|name| self.root.join(name))
+            .filter(|child| targets.contains(child))
+            .filter_map(|child| self.scan_root_child(&child, &scope).0)
+            .collect()
+    }
+
+    /// Aggregate a single immediate child of the root into its entry, along
+    /// with the symbolic links and special files it is or holds
+    fn scan_root_child(&self, child: &Path, scope: &Scope) -> (Option<RootChild>, OtherTypes) {
+        let mut other_types = OtherTypes::default();
+        let kind = self.classify(child, &mut other_types);
+        let child = kind.and_then(|kind| self.root_child(child, kind, scope, &mut other_types));
+        (child, other_types)
+    }
+
+    /// The entry of the immediate child of the root `child`, of kind `kind`,
+    /// adding what its contents hold to `other_types`
+    fn root_child(
+        &self,
+        child: &Path,
+        kind: EntryKind,
+        scope: &Scope,
+        other_types: &mut OtherTypes,
+    ) -> Option<RootChild> {
+        match kind {
+            EntryKind::File(metadata) => {
+                let size = self.count_file(child, &metadata)?;
+                let node = self.archive_node(child, size);
+                let (file_count, dir_count, file) = match &node {
+                    Some(node) => (node.file_count, node.dir_count + 1, None),
+                    None => (1, 0, self.file_node(child, size, &metadata)),
+                };
+                Some(RootChild {
+                    entry: DirectoryEntry {
+                        path: child.to_path_buf(),
+                        size,
+                        file_count,
+                        dir_count,
+                        modified: modified_secs(&metadata),
+                    },
+                    node,
+                    file,
+                })
+            }
+            EntryKind::Dir(metadata) => {
+                let subtree = self.scan_dir(child, self.stamp(&metadata), 1, scope)?;
+                let stats = subtree.stats.clone();
+                other_types.merge(&stats.other_types);
+                let node = self
+                    .build_tree
+                    .then(|| subtree.into_node(child.to_path_buf()));
+                Some(RootChild {
+                    entry: DirectoryEntry {
+                        path: child.to_path_buf(),
+                        size: stats.size,
+                        file_count: stats.file_count,
+                        // The subdirectory itself is counted alongside its descendants
+                        dir_count: stats.dir_count + 1,
+                        modified: stats.modified,
+                    },
+                    node,
+                    file: None,
+                })
+            }
+            EntryKind::Other => None,
+        }
+    }
+
+    /// Recursively aggregate the contents of `dir`, which sits at `depth`
+    ///
+    /// When the cache holds `dir` with the same `stamp`, its files are counted
+    /// from the cache and only its subdirectories are visited.
+    ///
+    /// Returns `None` when the directory is a symlink loop and must not be counted.
+    fn scan_dir(
+        &self,
+        dir: &Path,
+        stamp: Option<Stamp>,
+        depth: usize,
+        parent: &Scope,
+    ) -> Option<Subtree> {
+        let scope = self.enter(dir, parent)?;
+        self.progress.enter_dir(dir);
+        if let Some(visitor) = self.visitor {
+            visitor.visit_dir(dir);
+        }
+
+        // Entries below max_depth are never visited, matching `du --max-depth`
+        if depth >= self.max_depth {
+            return Some(Subtree::default());
+        }
+
+        if let Some(entry) = self.checkpoint.as_ref().and_then(|c| c.resumed(dir)) {
+            self.progress.add_files(entry.file_count, entry.size);
+            return Some(Subtree {
+                stats: DirectoryStats {
+                    size: entry.size,
+                    file_count: entry.file_count,
+                    dir_count: entry.dir_count.saturating_sub(1),
+                    modified: entry.modified,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+
+        let cached = self
+            .cache
+            .as_ref()
+            .map(|(cache, _)| cache)
+            .zip(stamp)
+            .and_then(|(cache, stamp)| cache.lookup(dir, stamp));
+        let subtree = match cached {
+            Some(cached) => {
+                let own = DirectoryStats {
+                    size: cached.size,
+                    file_count: cached.file_count,
+                    dir_count: 0,
+                    modified: cached.modified,
+                    ..Default::default()
+                };
+                self.progress.add_files(own.file_count, own.size);
+                if let Some(stats) = &self.stats {
+                    stats.add_cached(own.file_count);
+                }
+                cached
+                    .subdirs
+                    .par_iter()
+                    .map(|name| self.scan_entry(dir, OsString::from(name), depth, &scope))
+                    .reduce(Subtree::default, Subtree::merge)
+                    .merge(Subtree {
+                        stats: own.clone(),
+                        own,
+                        ..Default::default()
+                    })
+            }
+            None => {
+                let (children, complete) = self.read_children(dir, &scope);
+                let subtree = children
+                    .into_par_iter()
+                    .map(|name| self.scan_entry(dir, name, depth, &scope))
+                    .reduce(Subtree::default, Subtree::merge);
+                Subtree {
+                    uncacheable: subtree.uncacheable || !complete,
+                    ..subtree
+                }
+            }
+        };
+
+        if let (Some((cache, _)), Some(stamp)) = (&self.cache, stamp) {
+            if !subtree.uncacheable {
+                let own = &subtree.own;
+                cache.record(
+                    dir,
+                    stamp,
+                    (own.size, own.file_count, own.modified),
+                    &subtree.subdirs,
+                );
+            }
+        }
+        // Directories completed after cancelling may be partial
+        if let (Some(checkpoint), false) = (&self.checkpoint, self.is_cancelled()) {
+            checkpoint.add(dir, &subtree.stats);
+        }
+        Some(subtree)
+    }
+
+    /// Aggregate the entry `name` of `dir`, which sits at `depth`, into its
+    /// share of the directory's contents
+    fn scan_entry(&self, dir: &Path, name: OsString, depth: usize, scope: &Scope) -> Subtree {
+        let child = dir.join(&name);
+        let mut other_types = OtherTypes::default();
+        let mut subtree = match self.classify(&child, &mut other_types) {
+            Some(EntryKind::File(metadata)) => {
+                // Symbolic links and special files are tallied while classifying,
+                // hard links must reach the set of seen inodes, and cloud-only
+                // files are hydrated without their directory changing
+                let uncacheable = !metadata.is_file()
+                    || other_types.symlinks > 0
+                    || metadata.link_count() > 1
+                    || metadata.is_cloud_placeholder();
+                match self.count_file(&child, &metadata) {
+                    Some(size) => {
+                        // An expanded archive counts as the directory it is shown
+                        // as, holding its entries
+                        let ((file_count, dir_count), children, files) =
+                            match self.archive_node(&child, size) {
+                                Some(node) => (
+                                    (node.file_count, node.dir_count + 1),
+                                    vec![NameNode::from_node(node)],
+                                    Vec::new(),
+                                ),
+                                None => (
+                                    (1, 0),
+                                    Vec::new(),
+                                    self.file_node(&child, size, &metadata)
+                                        .into_iter()
+                                        .collect(),
+                                ),
+                            };
+                        let stats = DirectoryStats {
+                            size,
+                            file_count,
+                            dir_count,
+                            modified: modified_secs(&metadata),
+                            ..Default::default()
+                        };
+                        Subtree {
+                            own: stats.clone(),
+                            stats,
+                            children,
+                            files,
+                            uncacheable,
+                            ..Default::default()
+                        }
+                    }
+                    None => Subtree {
+                        uncacheable,
+                        ..Default::default()
+                    },
+                }
+            }
+            Some(EntryKind::Dir(metadata)) => {
+                match self.scan_dir(&child, self.stamp(&metadata), depth + 1, scope) {
+                    Some(sub) => {
+                        let mut stats = sub.stats.clone();
+                        stats.dir_count += 1;
+                        let subdirs = if self.cache.is_some() {
+                            vec![name.clone()]
+                        } else {
+                            Vec::new()
+                        };
+                        let children = if self.build_tree {
+                            vec![sub.into_name_node(name)]
+                        } else {
+                            Vec::new()
+                        };
+                        Subtree {
+                            stats,
+                            children,
+                            subdirs,
+                            ..Default::default()
+                        }
+                    }
+                    None => Subtree::uncacheable(),
+                }
+            }
+            Some(EntryKind::Other) | None => Subtree::uncacheable(),
+        };
+        subtree.stats.other_types.merge(&other_types);
+        subtree
+    }
+
+    /// What identifies the contents of a directory with `metadata`, when caching
+    fn stamp(&self, metadata: &Meta) -> Option<Stamp> {
+        self.cache.as_ref()?;
+        Stamp::new(metadata.modified(), metadata.len())
+    }
+
+    /// Derive the scope of `dir` from its parent's
+    ///
+    /// Returns `None` when following links leads back to an ancestor.
+    fn enter(&self, dir: &Path, parent: &Scope) -> Option<Scope> {
+        let ancestors = if self.follow_links {
+            let canonical = fs::canonicalize(dir).ok()?;
+            if parent.ancestors.contains(&canonical) {
+                log::debug!("Skipping symlink loop: {}", dir.display());
+                self.push_error(ScanError {
+                    path: dir.to_path_buf(),
+                    kind: ScanErrorKind::SymlinkLoop,
+                    message: "symbolic link points to an ancestor directory".to_string(),
+                });
+                if self.link_root.is_some() {
+                    self.push_link_issue(LinkIssue {
+                        path: dir.to_path_buf(),
+                        target: fs::read_link(dir).unwrap_or(canonical),
+                        kind: LinkIssueKind::Loop,
+                    });
+                }
+                return None;
+            }
+            let mut chain = parent.ancestors.clone();
+            chain.push(canonical);
+            chain
+        } else {
+            Vec::new()
+        };
+
+        let ignores = if self.respect_gitignore {
+            IgnoreChain::load(dir, parent.ignores.clone())
+        } else {
+            None
+        };
+
+        Some(Scope { ancestors, ignores })
+    }
+
+    /// List the names of the non-excluded children of a directory, and whether
+    /// every entry could be read
+    ///
+    /// Only names are kept so the siblings of every directory on the current
+    /// path do not each hold a copy of their parent's path.
+    fn read_children(&self, dir: &Path, scope: &Scope) -> (Vec<OsString>, bool) {
+        // A cancelled walk stops reading directories, so it unwinds quickly
+        if self.is_cancelled() {
+            return (Vec::new(), false);
+        }
+
+        let entries = match self.list_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.record_error(dir, &e);
+                return (Vec::new(), false);
+            }
+        };
+
+        let mut complete = true;
+        let children = entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Ok((name, is_dir)) => {
+                    let path = dir.join(&name);
+                    let relative = path.strip_prefix(self.root).unwrap_or(&path);
+                    if self.exclusions.is_excluded(relative) {
+                        return None;
+                    }
+                    if self.skipped_mounts.iter().any(|mount| mount == relative) {
+                        log::debug!("Skipping remote filesystem: {}", path.display());
+                        return None;
+                    }
+
+                    if let Some(ignores) = &scope.ignores {
+                        if ignores.is_ignored(&path, is_dir) {
+                            log::trace!("Ignored by VCS rules: {}", path.display());
+                            return None;
+                        }
+                    }
+                    Some(name)
+                }
+                Err(e) => {
+                    self.record_error(dir, &e);
+                    complete = false;
+                    None
+                }
+            })
+            .collect();
+        (children, complete)
+    }
+
+    /// Names of the entries of `dir`, with whether each is a directory
+    fn list_dir(&self, dir: &Path) -> std::io::Result<Vec<std::io::Result<(OsString, bool)>>> {
+        let reader = self.reader;
+        self.call(dir, move |dir| reader.read_dir(dir))
+    }
+
+    /// Metadata of `path`, of the target of a final symbolic link when `follow` is set
+    fn stat(&self, path: &Path, follow: bool) -> std::io::Result<Meta> {
+        let reader = self.reader;
+        self.call(path, move |path| reader.read_metadata(path, follow))
+    }
+
+    /// Make the filesystem call `call` on `path`, timing it when collecting
+    /// statistics and giving up on it after the timeout, if any
+    fn call<T: Send + 'static>(
+        &self,
+        path: &Path,
+        call: impl FnOnce(&Path) -> std::io::Result<T> + Send + 'static,
+    ) -> std::io::Result<T> {
+        let run = || match &self.watchdog {
+            Some(watchdog) => watchdog.run(path, call),
+            None => call(path),
+        };
+        match &self.stats {
+            Some(stats) => stats.time(run),
+            None => run(),
+        }
+    }
+
+    /// Determine whether a path should be treated as a file, directory or
+    /// neither, counting it in `types` when it is a symlink or special file
+    fn classify(&self, path: &Path, types: &mut OtherTypes) -> Option<EntryKind> {
+        let metadata = match self.stat(path, false) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.record_error(path, &e);
+                return None;
+            }
+        };
+
+        if metadata.is_symlink() {
+            types.symlinks += 1;
+            types.symlink_size += metadata.len();
+            if let Some(root) = &self.link_root {
+                self.check_link(path, root);
+            }
+        }
+
+        let metadata = if metadata.is_symlink() && self.follow_links {
+            match self.stat(path, true) {
+                Ok(target) => target,
+                Err(e) => {
+                    self.record_error(path, &e);
+                    return None;
+                }
+            }
+        } else {
+            metadata
+        };
+
+        if let Some(root_device) = self.root_device {
+            if metadata
+                .device()
+                .is_some_and(|device| device != root_device)
+            {
+                log::debug!("Skipping entry on another filesystem: {}", path.display());
+                return None;
+            }
+        }
+
+        Some(if metadata.is_file() {
+            EntryKind::File(metadata)
+        } else if metadata.is_dir() {
+            EntryKind::Dir(metadata)
+        } else {
+            metadata.tally_special(types);
+            // Links that were not followed and special files take up an inode
+            // and sometimes a block, which `du` counts as well
+            if self.count_all_types {
+                EntryKind::File(metadata)
+            } else {
+                EntryKind::Other
+            }
+        })
+    }
+
+    /// Describe a counted file for the tree, when files are listed there
+    fn file_node(&self, path: &Path, size: u64, metadata: &Meta) -> Option<FileNode> {
+        if !self.tree_files {
+            return None;
+        }
+        Some(FileNode {
+            name: path.file_name()?.to_os_string(),
+            size,
+            apparent_size: metadata.len(),
+        })
+    }
+
+    /// Virtual subtree listing the contents of an archive, when archives are expanded
+    fn archive_node(&self, path: &Path, size: u64) -> Option<DirectoryNode> {
+        if !self.expand_archives || !archive::is_archive(path) {
+            return None;
+        }
+        archive::archive_tree(path, size)
+            .map_err(|e| log::debug!("Cannot list archive {}: {:#}", path.display(), e))
+            .ok()
+    }
+
+    /// Account for a file, returning its disk usage unless it is a hard link
+    /// that has already been counted
+    fn count_file(&self, path: &Path, metadata: &Meta) -> Option<u64> {
+        if metadata.len() < self.min_file_size || !self.in_time_window(metadata) {
+            return None;
+        }
+
+        // Skip if we've already counted this inode (hard link). A file with a
+        // single link cannot be met again, so the vast majority of files never
+        // reach the set, nor add false positives to the approximate filter.
+        let tracked = self.track_all_inodes || metadata.link_count() > 1;
+        let first_seen = !tracked || self.seen_inodes.insert(metadata.inode_key());
+        // Use actual disk usage (blocks) instead of apparent size
+        let size = metadata.disk_usage(path) + self.alternate_streams_size(path);
+        if !first_seen {
+            log::trace!("Skipping hard link: {}", path.display());
+            self.hard_links.fetch_add(1, Ordering::Relaxed);
+            self.hard_link_savings.fetch_add(size, Ordering::Relaxed);
+            if let (Some(visitor), Some(metadata)) = (self.visitor, metadata.std()) {
+                visitor.visit_hard_link(path, metadata, size);
+            }
+            return None;
+        }
+
+        if size > 0 && self.is_clone(path, metadata) {
+            log::trace!("Clone sharing its blocks: {}", path.display());
+            self.cloned_size.fetch_add(size, Ordering::Relaxed);
+        }
+        if metadata.is_cloud_placeholder() {
+            log::trace!("Cloud-only file: {}", path.display());
+            self.cloud_files.fetch_add(1, Ordering::Relaxed);
+            self.cloud_size.fetch_add(metadata.len(), Ordering::Relaxed);
+        }
+        self.progress.add_file(size);
+        if let (Some(visitor), Some(metadata)) = (self.visitor, metadata.std()) {
+            visitor.visit_file(path, metadata, size);
+        }
+        Some(size)
+    }
+
+    /// Size of the NTFS alternate data streams of a file, when they are counted
+    #[cfg(windows)]
+    fn alternate_streams_size(&self, path: &Path) -> u64 {
+        if self.alternate_streams {
+            crate::windows::alternate_streams_size(path)
+        } else {
+            0
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn alternate_streams_size(&self, _path: &Path) -> u64 {
+        0
+    }
+
+    /// Whether a file shares its blocks with one counted before, when clones are detected
+    #[cfg(target_os = "macos")]
+    fn is_clone(&self, path: &Path, metadata: &Meta) -> bool {
+        match (&self.clones, metadata.device()) {
+            (Some(clones), Some(device)) => clones.is_clone(path, device),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn is_clone(&self, _path: &Path, _metadata: &Meta) -> bool {
+        false
+    }
+
+    /// Whether a file's modification time passes the age filters
+    ///
+    /// Files whose modification time is unavailable are always counted.
+    fn in_time_window(&self, metadata: &Meta) -> bool {
+        if self.modified_before.is_none() && self.modified_after.is_none() {
+            return true;
+        }
+        let Some(modified) = metadata.modified() else {
+            return true;
+        };
+        self.modified_before
+            .map_or(true, |before| modified < before)
+            && self.modified_after.map_or(true, |after| modified > after)
+    }
+
+    fn record_error(&self, path: &Path, error: &std::io::Error) {
+        // Only log non-transient errors in debug mode
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            log::debug!("Error accessing {}: {}", path.display(), error);
+        }
+        self.push_error(ScanError {
+            path: path.to_path_buf(),
+            kind: error.kind().into(),
+            message: error.to_string(),
+        });
+    }
+
+    /// Record the symbolic link at `path` if it is broken, loops or leaves `root`
+    ///
+    /// Loops met while following links into directories are recorded by
+    /// [`Self::enter`] instead, as they only show up relative to the walk.
+    fn check_link(&self, path: &Path, root: &Path) {
+        let Ok(target) = fs::read_link(path) else {
+            return;
+        };
+        let kind = match fs::canonicalize(path) {
+            Ok(resolved) if resolved.starts_with(root) => return,
+            Ok(_) => LinkIssueKind::OutsideRoot,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LinkIssueKind::Broken,
+            Err(e) if is_loop_error(&e) => LinkIssueKind::Loop,
+            Err(e) => {
+                log::debug!("Cannot resolve link {}: {}", path.display(), e);
+                return;
+            }
+        };
+        self.push_link_issue(LinkIssue {
+            path: path.to_path_buf(),
+            target,
+            kind,
+        });
+    }
+
+    fn push_link_issue(&self, issue: LinkIssue) {
+        self.link_issues
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(issue);
+    }
+
+    fn push_error(&self, error: ScanError) {
+        if let Some(visitor) = self.visitor {
+            visitor.visit_error(&error);
+        }
+        self.errors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(error);
+    }
+}
@@ -0,0 +1,228 @@
+//! Keeping an analysis up to date as the filesystem changes
+//!
+//! After an initial scan, filesystem notifications (inotify, FSEvents or
+//! ReadDirectoryChangesW through the `notify` crate) are mapped to the
+//! immediate children of the root they affect, and only those children are
+//! walked again. Hard links shared between different children may be counted
+//! more than once after such a partial rescan.
+
+use crate::top::TopN;
+use crate::{
+    analyze_disk_usage, build_thread_pool, exclude, mounts, skipped_mounts, walk, AnalysisConfig,
+    AnalysisResult, CancellationToken, DirectoryEntry, RemoteFs, ScanHooks,
+};
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long to keep collecting events after the first one before rescanning
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// How often the cancellation token is checked while idle
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Sizes of every immediate child of a root, refreshed incrementally
+pub struct LiveAnalysis {
+    root: PathBuf,
+    config: AnalysisConfig,
+    entries: HashMap<PathBuf, DirectoryEntry>,
+}
+
+impl LiveAnalysis {
+    /// Perform the initial scan of `root`
+    pub fn new(root: &Path, config: &AnalysisConfig) -> Result<Self> {
+        // Only the top-level entries are kept up to date
+        let config = AnalysisConfig {
+            build_tree: false,
+            ..config.clone()
+        };
+        let result = analyze_disk_usage(root, &config, usize::MAX)?;
+        let entries = result
+            .top_directories
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            config,
+            entries,
+        })
+    }
+
+    /// Rescan the immediate children of the root containing `changed` paths
+    ///
+    /// Returns whether any child was rescanned.
+    pub fn refresh(&mut self, changed: &[PathBuf]) -> Result<bool> {
+        let targets: HashSet<PathBuf> = changed
+            .iter()
+            .filter_map(|path| self.top_level_child(path))
+            .collect();
+        if targets.is_empty() {
+            return Ok(false);
+        }
+
+        log::debug!("Rescanning {} entries", targets.len());
+        let pool = build_thread_pool(&self.config)?;
+        let exclusions = exclude::Exclusions::new(&self.config.exclude_patterns)?;
+        let remote = if self.config.remote_fs == RemoteFs::Skip {
+            mounts::remote_mounts_below(&self.root)
+        } else {
+            Vec::new()
+        };
+        let walker =
+            walk::Walker::new(&self.root, &self.config, &exclusions, &ScanHooks::default())
+                .with_skipped_mounts(skipped_mounts(&self.root, &self.config, &remote));
+        let children = match &pool {
+            Some(pool) => pool.install(|| walker.rescan(&targets)),
+            None => walker.rescan(&targets),
+        };
+
+        // Children missing from the rescan were deleted, excluded or ignored
+        for target in &targets {
+            self.entries.remove(target);
+        }
+        for child in children {
+            self.entries.insert(child.entry.path.clone(), child.entry);
+        }
+
+        Ok(true)
+    }
+
+    /// Current results, with the `top_n` largest entries
+    pub fn result(&self, top_n: usize) -> AnalysisResult {
+        let entries = self.entries.values();
+        let total_size = entries.clone().map(|d| d.size).sum();
+        let total_files = entries.clone().map(|d| d.file_count).sum();
+        let total_dirs = entries.clone().map(|d| d.dir_count).sum();
+        let modified = entries.clone().filter_map(|d| d.modified).max();
+
+        // Only the entries shown are cloned
+        let mut top = TopN::new(top_n);
+        for entry in entries {
+            top.push(entry.size, entry);
+        }
+        let directories: Vec<DirectoryEntry> = top.into_sorted_vec().into_iter().cloned().collect();
+
+        AnalysisResult {
+            root_path: self.root.clone(),
+            total_size,
+            total_files,
+            total_dirs,
+            top_directories: directories,
+            modified,
+            ..Default::default()
+        }
+    }
+
+    /// Map a changed path to the immediate child of the root that contains it
+    fn top_level_child(&self, path: &Path) -> Option<PathBuf> {
+        match path.strip_prefix(&self.root).ok()?.components().next()? {
+            Component::Normal(name) => Some(self.root.join(name)),
+            _ => None,
+        }
+    }
+}
+
+/// Scan `root`, then keep rescanning the parts that change until `cancel` is triggered
+///
+/// `on_update` receives the initial results and the results after every
+/// batch of changes.
+pub fn watch(
+    root: &Path,
+    config: &AnalysisConfig,
+    top_n: usize,
+    cancel: &CancellationToken,
+    mut on_update: impl FnMut(&AnalysisResult),
+) -> Result<()> {
+    let mut live = LiveAnalysis::new(root, config)?;
+    on_update(&live.result(top_n));
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to create file watcher")?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+    while !cancel.is_cancelled() {
+        let first = match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Batch the burst of events a single change usually produces
+        let mut changed = Vec::new();
+        let deadline = Instant::now() + DEBOUNCE;
+        let mut next = Some(first);
+        while let Some(event) = next {
+            match event {
+                // Reads, including our own rescans, don't change sizes
+                Ok(event) if event.kind.is_access() => {}
+                Ok(event) => changed.extend(event.paths),
+                Err(e) => log::debug!("Watch error: {}", e),
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            next = rx.recv_timeout(remaining).ok();
+        }
+
+        if live.refresh(&changed)? {
+            on_update(&live.result(top_n));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_refresh_rescans_changed_children() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/nested")).unwrap();
+        fs::create_dir(root.join("b")).unwrap();
+        fs::write(root.join("a/nested/one"), vec![0u8; 4096]).unwrap();
+
+        let mut live = LiveAnalysis::new(root, &AnalysisConfig::default()).unwrap();
+        assert_eq!(live.result(10).total_files, 1);
+
+        fs::write(root.join("a/nested/two"), vec![0u8; 4096]).unwrap();
+        fs::write(root.join("b/three"), vec![0u8; 4096]).unwrap();
+        fs::remove_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("top.txt"), "hello").unwrap();
+
+        // Only the changed paths are reported; "b" is stale until it changes too
+        let changed = vec![root.join("a/nested/two"), root.join("top.txt")];
+        assert!(live.refresh(&changed).unwrap());
+
+        let result = live.result(10);
+        assert_eq!(result.total_files, 1);
+        let paths: Vec<_> = result
+            .top_directories
+            .iter()
+            .map(|d| d.path.clone())
+            .collect();
+        assert!(paths.contains(&root.join("top.txt")));
+        assert!(paths.contains(&root.join("b")));
+        assert!(!paths.contains(&root.join("a")));
+
+        assert!(live.refresh(&[root.join("b/three")]).unwrap());
+        assert_eq!(live.result(10).total_files, 2);
+    }
+
+    #[test]
+    fn test_refresh_ignores_paths_outside_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut live = LiveAnalysis::new(temp_dir.path(), &AnalysisConfig::default()).unwrap();
+
+        assert!(!live.refresh(&[PathBuf::from("/elsewhere/file")]).unwrap());
+        assert!(!live.refresh(&[temp_dir.path().to_path_buf()]).unwrap());
+    }
+}
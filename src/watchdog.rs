@@ -0,0 +1,124 @@
+//! Giving up on filesystem calls that do not return
+//!
+//! Reading a directory or the metadata of a file on a dead NFS server or a
+//! stuck FUSE mount can block forever, and nothing can interrupt the call. With
+//! a timeout set, the walker makes those calls on helper threads and waits at
+//! most that long for each; a call taking longer fails as timed out, so the
+//! entry is skipped and reported, while its helper stays blocked until the call
+//! returns, if ever, and another one takes its place.
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Helper threads making filesystem calls for the walker, with a timeout
+pub(crate) struct Watchdog {
+    timeout: Duration,
+    jobs: Sender<Job>,
+    queue: Arc<Mutex<Receiver<Job>>>,
+    /// Helpers started so far, replacements included
+    started: AtomicUsize,
+}
+
+impl Watchdog {
+    /// Watchdog making calls on `helpers` threads and giving up on each after
+    /// `timeout`
+    pub(crate) fn new(timeout: Duration, helpers: usize) -> Self {
+        let (jobs, queue) = mpsc::channel();
+        let watchdog = Self {
+            timeout,
+            jobs,
+            queue: Arc::new(Mutex::new(queue)),
+            started: AtomicUsize::new(0),
+        };
+        for _ in 0..helpers.max(1) {
+            watchdog.spawn_helper();
+        }
+        watchdog
+    }
+
+    /// Start a helper taking calls until the watchdog is dropped
+    fn spawn_helper(&self) {
+        let queue = Arc::clone(&self.queue);
+        let index = self.started.fetch_add(1, Ordering::Relaxed);
+        let spawned = std::thread::Builder::new()
+            .name(format!("dutop-io-{}", index))
+            .spawn(move || loop {
+                // The queue is unlocked again before the call is made
+                let job = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Cannot start an I/O thread: {}", e);
+        }
+    }
+
+    /// Make the filesystem call `call` on `path`, failing with
+    /// [`io::ErrorKind::TimedOut`] when it does not return in time
+    pub(crate) fn run<T: Send + 'static>(
+        &self,
+        path: &Path,
+        call: impl FnOnce(&Path) -> io::Result<T> + Send + 'static,
+    ) -> io::Result<T> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let owned = path.to_path_buf();
+        let job: Job = Box::new(move || {
+            // Nobody is waiting any more when the call timed out
+            let _ = sender.send(call(&owned));
+        });
+        if self.jobs.send(job).is_err() {
+            return Err(io::Error::other("I/O threads stopped"));
+        }
+
+        match receiver.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                log::warn!(
+                    "{} did not answer within {:?}, skipping it",
+                    path.display(),
+                    self.timeout
+                );
+                // The helper stays blocked in the call
+                self.spawn_helper();
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no answer within {:?}", self.timeout),
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::other("I/O thread failed")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_watchdog_times_out() {
+        let watchdog = Watchdog::new(Duration::from_millis(50), 1);
+        let path = Path::new("/mnt/nas");
+
+        let answer = watchdog.run(path, |path| Ok(path.to_path_buf()));
+        assert_eq!(answer.unwrap(), path);
+
+        // A hanging call is given up on, and the next one still gets through
+        let start = Instant::now();
+        let hung = watchdog.run(path, |_| {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(())
+        });
+        assert_eq!(hung.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(watchdog.run(path, |_| Ok(42)).is_ok());
+    }
+}
@@ -0,0 +1,286 @@
+//! Windows-specific filesystem queries
+
+use crate::mounts::{FsUsage, Mount};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use windows_sys::Win32::Foundation::{GetLastError, INVALID_HANDLE_VALUE, NO_ERROR};
+use windows_sys::Win32::Storage::FileSystem::{
+    FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard, GetCompressedFileSizeW,
+    GetDiskFreeSpaceExW, GetDiskFreeSpaceW, GetLogicalDrives, GetVolumeInformationW,
+    GetVolumePathNameW, INVALID_FILE_SIZE, WIN32_FIND_STREAM_DATA,
+};
+
+/// Longest volume path accepted from `GetVolumePathNameW`, in UTF-16 units
+const MAX_VOLUME_PATH: usize = 1024;
+/// Length of the buffer receiving a filesystem name (MAX_PATH + 1)
+const MAX_PATH_LEN: usize = 261;
+
+/// Encode a path as a NUL-terminated wide string
+pub(crate) fn wide(path: &OsStr) -> Vec<u16> {
+    path.encode_wide().chain(std::iter::once(0)).collect()
+}
+
+/// Bytes actually allocated to the file at `path`, rounded up to whole clusters
+///
+/// For compressed and sparse files this is less than the logical size.
+pub(crate) fn allocated_size(path: &Path) -> Option<u64> {
+    let name = wide(path.as_os_str());
+    let mut high = 0u32;
+    // SAFETY: `name` is NUL-terminated and `high` is a valid out pointer
+    let low = unsafe { GetCompressedFileSizeW(name.as_ptr(), &mut high) };
+    // INVALID_FILE_SIZE is also a valid low word, so the error code decides
+    if low == INVALID_FILE_SIZE && unsafe { GetLastError() } != NO_ERROR {
+        return None;
+    }
+    let size = (u64::from(high) << 32) | u64::from(low);
+
+    let cluster = cluster_size(&name).unwrap_or(1);
+    Some(size.div_ceil(cluster) * cluster)
+}
+
+/// Total logical size of the alternate data streams of the file at `path`
+///
+/// The unnamed main stream, already counted as the file's size, is left out.
+/// Files whose streams cannot be listed contribute nothing.
+pub(crate) fn alternate_streams_size(path: &Path) -> u64 {
+    const MAIN_STREAM: &str = "::$DATA";
+
+    let name = wide(path.as_os_str());
+    // SAFETY: WIN32_FIND_STREAM_DATA is plain data, valid when zeroed
+    let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+    // SAFETY: `name` is NUL-terminated and `data` matches FindStreamInfoStandard
+    let handle = unsafe {
+        FindFirstStreamW(
+            name.as_ptr(),
+            FindStreamInfoStandard,
+            (&mut data as *mut WIN32_FIND_STREAM_DATA).cast(),
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return 0;
+    }
+
+    let mut total = 0;
+    loop {
+        let len = data.cStreamName.iter().position(|&c| c == 0).unwrap_or(0);
+        let stream = String::from_utf16_lossy(&data.cStreamName[..len]);
+        if stream != MAIN_STREAM {
+            log::trace!("Alternate data stream {}{}", path.display(), stream);
+            total += data.StreamSize.max(0) as u64;
+        }
+        let next = (&mut data as *mut WIN32_FIND_STREAM_DATA).cast();
+        // SAFETY: `handle` is an open stream search and `next` is valid for writes
+        if unsafe { FindNextStreamW(handle, next) } == 0 {
+            break;
+        }
+    }
+    // SAFETY: `handle` is an open stream search, closed exactly once
+    unsafe { FindClose(handle) };
+    total
+}
+
+/// Cluster size of the volume holding the NUL-terminated path `name`, cached per volume
+fn cluster_size(name: &[u16]) -> Option<u64> {
+    static CLUSTER_SIZES: OnceLock<Mutex<HashMap<PathBuf, u64>>> = OnceLock::new();
+
+    let mut volume = vec![0u16; MAX_VOLUME_PATH];
+    // SAFETY: `name` is NUL-terminated and `volume` holds the advertised length
+    let ok = unsafe { GetVolumePathNameW(name.as_ptr(), volume.as_mut_ptr(), volume.len() as u32) };
+    if ok == 0 {
+        return None;
+    }
+    let len = volume.iter().position(|&c| c == 0)?;
+    volume.truncate(len + 1);
+    let key = PathBuf::from(String::from_utf16_lossy(&volume[..len]));
+
+    let cache = CLUSTER_SIZES.get_or_init(Default::default);
+    if let Some(&size) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return Some(size);
+    }
+
+    let (mut sectors_per_cluster, mut bytes_per_sector, mut free, mut total) = (0, 0, 0, 0);
+    // SAFETY: `volume` is NUL-terminated and every out pointer is valid
+    let ok = unsafe {
+        GetDiskFreeSpaceW(
+            volume.as_ptr(),
+            &mut sectors_per_cluster,
+            &mut bytes_per_sector,
+            &mut free,
+            &mut total,
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    let size = u64::from(sectors_per_cluster) * u64::from(bytes_per_sector);
+    let size = size.max(1);
+
+    cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, size);
+    Some(size)
+}
+
+/// Root of the volume holding `path` (e.g. `C:\\`)
+pub(crate) fn volume_root(path: &Path) -> Option<String> {
+    let name = wide(path.as_os_str());
+    let mut volume = vec![0u16; MAX_VOLUME_PATH];
+    // SAFETY: `name` is NUL-terminated and `volume` holds the advertised length
+    let ok = unsafe { GetVolumePathNameW(name.as_ptr(), volume.as_mut_ptr(), volume.len() as u32) };
+    if ok == 0 {
+        return None;
+    }
+    let len = volume.iter().position(|&c| c == 0)?;
+    Some(String::from_utf16_lossy(&volume[..len]))
+}
+
+/// Capacity of the volume holding `path`
+pub(crate) fn filesystem_usage(path: &Path) -> io::Result<FsUsage> {
+    let name = wide(path.as_os_str());
+    let (mut available, mut total, mut free) = (0u64, 0u64, 0u64);
+    // SAFETY: `name` is NUL-terminated and every out pointer is valid
+    let ok = unsafe { GetDiskFreeSpaceExW(name.as_ptr(), &mut available, &mut total, &mut free) };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(FsUsage {
+        total,
+        free,
+        available,
+    })
+}
+
+/// The root of every drive letter in use, with its filesystem type
+pub(crate) fn logical_drives() -> Vec<Mount> {
+    // SAFETY: GetLogicalDrives takes no arguments
+    let drives = unsafe { GetLogicalDrives() };
+    (0..26u8)
+        .filter(|i| drives & (1 << i) != 0)
+        .map(|i| {
+            let root = format!("{}:\\", (b'A' + i) as char);
+            Mount {
+                fs_type: volume_fs_type(&root).unwrap_or_default(),
+                source: root.clone(),
+                mount_point: PathBuf::from(root),
+                usage: None,
+            }
+        })
+        .collect()
+}
+
+/// Name of the filesystem on the volume rooted at `root` (e.g. `NTFS`)
+pub(crate) fn volume_fs_type(root: &str) -> Option<String> {
+    let root = wide(OsStr::new(root));
+    let mut fs_name = [0u16; MAX_PATH_LEN];
+    // SAFETY: `root` is NUL-terminated, `fs_name` holds the advertised length and
+    // the other outputs are optional
+    let ok = unsafe {
+        GetVolumeInformationW(
+            root.as_ptr(),
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            fs_name.as_mut_ptr(),
+            fs_name.len() as u32,
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    let len = fs_name.iter().position(|&c| c == 0)?;
+    Some(String::from_utf16_lossy(&fs_name[..len]))
+}
+
+/// Peak working set of the process in bytes
+pub(crate) fn peak_working_set() -> Option<u64> {
+    use windows_sys::Win32::System::ProcessStatus::{
+        GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+    };
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    // SAFETY: the structure is plain data, valid when zeroed
+    let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    // SAFETY: the pseudo handle of the current process needs no closing, and
+    // `counters` is as large as advertised
+    let ok = unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) };
+    (ok != 0).then_some(counters.PeakWorkingSetSize as u64)
+}
+
+/// Kind of storage holding `path`: remote drives are network storage, and
+/// local ones spinning disks when their device reports a seek penalty
+pub(crate) fn storage_kind(path: &Path) -> crate::mounts::StorageKind {
+    use crate::mounts::StorageKind;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, GetDriveTypeW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::{
+        PropertyStandardQuery, StorageDeviceSeekPenaltyProperty, DEVICE_SEEK_PENALTY_DESCRIPTOR,
+        IOCTL_STORAGE_QUERY_PROPERTY, STORAGE_PROPERTY_QUERY,
+    };
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    let Some(root) = volume_root(path) else {
+        return StorageKind::Unknown;
+    };
+    // SAFETY: the root is NUL-terminated
+    if unsafe { GetDriveTypeW(wide(OsStr::new(&root)).as_ptr()) } == DRIVE_REMOTE {
+        return StorageKind::Network;
+    }
+
+    // Querying the device needs no access rights to the volume
+    let device = wide(OsStr::new(&format!(r"\\.\{}", root.trim_end_matches('\\'))));
+    // SAFETY: `device` is NUL-terminated and the other arguments are optional
+    let handle = unsafe {
+        CreateFileW(
+            device.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return StorageKind::Unknown;
+    }
+    let query = STORAGE_PROPERTY_QUERY {
+        PropertyId: StorageDeviceSeekPenaltyProperty,
+        QueryType: PropertyStandardQuery,
+        AdditionalParameters: [0],
+    };
+    // SAFETY: the descriptor is plain data, valid when zeroed
+    let mut penalty: DEVICE_SEEK_PENALTY_DESCRIPTOR = unsafe { std::mem::zeroed() };
+    let mut returned = 0u32;
+    // SAFETY: `handle` is open and both buffers are as large as advertised
+    let ok = unsafe {
+        DeviceIoControl(
+            handle,
+            IOCTL_STORAGE_QUERY_PROPERTY,
+            (&query as *const STORAGE_PROPERTY_QUERY).cast(),
+            std::mem::size_of::<STORAGE_PROPERTY_QUERY>() as u32,
+            (&mut penalty as *mut DEVICE_SEEK_PENALTY_DESCRIPTOR).cast(),
+            std::mem::size_of::<DEVICE_SEEK_PENALTY_DESCRIPTOR>() as u32,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    // SAFETY: the handle was opened above and is closed once
+    unsafe { CloseHandle(handle) };
+    match (ok != 0, penalty.IncursSeekPenalty != 0) {
+        (false, _) => StorageKind::Unknown,
+        (true, true) => StorageKind::Rotational,
+        (true, false) => StorageKind::SolidState,
+    }
+}